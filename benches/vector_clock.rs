@@ -0,0 +1,53 @@
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use ops_crdt_rust::NodeType;
+use ops_crdt_rust::vector_clock::VectorClock;
+use ops_crdt_rust::dense_vector_clock::{DenseVectorClock, Membership};
+
+const CLUSTER_SIZE_LIST: [NodeType; 3] = [5, 50, 500];
+const BENCH_TIME_MS: u64 = 200;
+const BENCH_BATCH: u64   = 1000;
+
+fn bench<F: FnMut()>(name: &str, nodes: NodeType, mut f: F) {
+    let budget = Duration::from_millis(BENCH_TIME_MS);
+    let start = Instant::now();
+    let mut iter: u64 = 0;
+    while start.elapsed() < budget {
+        for _ in 0..BENCH_BATCH {
+            f();
+        }
+        iter += BENCH_BATCH;
+    }
+    let ns = start.elapsed().as_nanos() / iter as u128;
+    println!("{:<24} nodes {:>4} {:>10} ns/iter ({} iters)", name, nodes, ns, iter);
+}
+
+fn main() {
+    for nodes in CLUSTER_SIZE_LIST {
+        let node_list: Vec<NodeType> = (0..nodes).collect();
+
+        let mut vc1 = VectorClock::new(node_list.clone()).unwrap();
+        let mut vc2 = vc1.clone();
+        for node in node_list.iter().step_by(2) {
+            vc1.next_vc(node).unwrap();
+        }
+        for node in node_list.iter().skip(1).step_by(2) {
+            vc2.next_vc(node).unwrap();
+        }
+
+        let membership = Membership::new(node_list.clone()).unwrap();
+        let dvc1 = DenseVectorClock::from_vector_clock(&membership, &vc1).unwrap();
+        let dvc2 = DenseVectorClock::from_vector_clock(&membership, &vc2).unwrap();
+
+        bench("hashmap cmp_vc", nodes, || { black_box(vc1.cmp_vc(black_box(&vc2)).unwrap()); });
+        bench("dense cmp_vc", nodes, || { black_box(dvc1.cmp_vc(black_box(&dvc2)).unwrap()); });
+        bench("hashmap max_vc", nodes, || { black_box(vc1.max_vc(black_box(&vc2)).unwrap()); });
+        bench("dense max_vc", nodes, || { black_box(dvc1.max_vc(black_box(&dvc2)).unwrap()); });
+        bench("hashmap check_vc", nodes, || { black_box(vc1.check_vc(0, black_box(&vc2)).unwrap()); });
+        bench("dense check_vc", nodes, || { black_box(dvc1.check_vc(0, black_box(&dvc2)).unwrap()); });
+        bench("hashmap clone", nodes, || { black_box(vc1.clone()); });
+        bench("dense clone", nodes, || { black_box(dvc1.clone()); });
+        println!();
+    }
+}
@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use anyhow::Result;
+
+use crate::NodeType;
+use crate::message_data::{PeerMsgMap, PeerNodeMsg, UserUpdateMsg};
+use crate::replica::Replica;
+use crate::scheduler::{Clock, SystemClock};
+use crate::error::CrdtError;
+
+// sends what a replica produced to one peer; a lost send is fine, anti-entropy repairs it
+pub trait Transport<OpsValue: Clone+PartialEq>: Send+'static {
+    fn send(&mut self, pnode: NodeType, pmsg_list: Vec<PeerNodeMsg<OpsValue>>) -> impl Future<Output = Result<(), CrdtError>>+Send;
+}
+
+// errors nobody waits on, from undelivered peer batches and ticks; the oldest go first
+pub const MAX_ERROR_LIST_LEN: usize = 64;
+
+pub enum Command<CrdtValue, OpsValue: Clone+PartialEq> {
+    Update(UserUpdateMsg<OpsValue>, oneshot::Sender<Result<(), CrdtError>>),
+    Peer(Vec<PeerNodeMsg<OpsValue>>, Option<oneshot::Sender<Result<(), CrdtError>>>),
+    Query(oneshot::Sender<CrdtValue>),
+    TakeErrors(oneshot::Sender<Vec<CrdtError>>),
+    Stop
+}
+
+pub struct Inbox<CrdtValue, OpsValue: Clone+PartialEq> {
+    pub rx: mpsc::Receiver<Command<CrdtValue, OpsValue>>
+}
+
+pub struct ReplicaHandle<CrdtValue, OpsValue: Clone+PartialEq> {
+    pub node: NodeType,
+    pub tx: mpsc::Sender<Command<CrdtValue, OpsValue>>
+}
+
+impl <CrdtValue, OpsValue: Clone+PartialEq> Clone for ReplicaHandle<CrdtValue, OpsValue> {
+    fn clone(&self) -> Self {
+        Self{node: self.node, tx: self.tx.clone()}
+    }
+}
+
+impl <CrdtValue: Send, OpsValue: Clone+PartialEq+Send> ReplicaHandle<CrdtValue, OpsValue> {
+    // handles exist before the actors, so a transport can be wired to all of them first
+    pub fn channel(node: NodeType, capacity: usize) -> (Self, Inbox<CrdtValue, OpsValue>) {
+        let (tx, rx) = mpsc::channel(capacity);
+        (Self{node, tx}, Inbox{rx})
+    }
+
+    pub async fn update(&self, umsg: UserUpdateMsg<OpsValue>) -> Result<(), CrdtError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.send(Command::Update(umsg, reply_tx)).await?;
+        reply_rx.await.map_err(|_| CrdtError::ActorStopped(self.node))?
+    }
+
+    pub async fn peer_update(&self, pmsg_list: Vec<PeerNodeMsg<OpsValue>>) -> Result<(), CrdtError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.send(Command::Peer(pmsg_list, Some(reply_tx))).await?;
+        reply_rx.await.map_err(|_| CrdtError::ActorStopped(self.node))?
+    }
+
+    // no reply, for peer traffic nobody waits on
+    pub async fn deliver(&self, pmsg_list: Vec<PeerNodeMsg<OpsValue>>) -> Result<(), CrdtError> {
+        self.send(Command::Peer(pmsg_list, None)).await
+    }
+
+    pub async fn query(&self) -> Result<CrdtValue, CrdtError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.send(Command::Query(reply_tx)).await?;
+        reply_rx.await.map_err(|_| CrdtError::ActorStopped(self.node))
+    }
+
+    pub async fn take_errors(&self) -> Result<Vec<CrdtError>, CrdtError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.send(Command::TakeErrors(reply_tx)).await?;
+        reply_rx.await.map_err(|_| CrdtError::ActorStopped(self.node))
+    }
+
+    pub async fn stop(&self) -> Result<(), CrdtError> {
+        self.send(Command::Stop).await
+    }
+
+    async fn send(&self, command: Command<CrdtValue, OpsValue>) -> Result<(), CrdtError> {
+        self.tx.send(command).await.map_err(|_| CrdtError::ActorStopped(self.node))
+    }
+}
+
+// in-process transport between actors of the same runtime; a full inbox drops the batch
+// rather than wait, since two actors sending to each other could otherwise both block
+pub struct ChannelTransport<CrdtValue, OpsValue: Clone+PartialEq> {
+    pub handle_list: HashMap<NodeType, ReplicaHandle<CrdtValue, OpsValue>>
+}
+
+impl <CrdtValue: Send, OpsValue: Clone+PartialEq+Send> ChannelTransport<CrdtValue, OpsValue> {
+    pub fn new(handle_list: &[ReplicaHandle<CrdtValue, OpsValue>]) -> Self {
+        Self{handle_list: handle_list.iter().map(|handle| (handle.node, handle.clone())).collect()}
+    }
+}
+
+impl <CrdtValue: Send+'static, OpsValue: Clone+PartialEq+Send+'static> Transport<OpsValue> for ChannelTransport<CrdtValue, OpsValue> {
+    async fn send(&mut self, pnode: NodeType, pmsg_list: Vec<PeerNodeMsg<OpsValue>>) -> Result<(), CrdtError> {
+        match self.handle_list.get(&pnode) {
+            Some(handle) => handle.tx.try_send(Command::Peer(pmsg_list, None))
+                                     .map_err(|e| CrdtError::TransportError(format!("node {}: {}", pnode, e))),
+            None         => Err(CrdtError::UnknownNode(pnode))
+        }
+    }
+}
+
+pub fn spawn_replica<R: Replica, T: Transport<R::Ops>>(replica: R, inbox: Inbox<R::Value, R::Ops>, transport: T, tick_ms: u64) ->
+    JoinHandle<R> {
+    tokio::spawn(run_replica(replica, inbox, transport, tick_ms))
+}
+
+// owns the replica until stopped or every handle is dropped, then hands it back
+pub async fn run_replica<R: Replica, T: Transport<R::Ops>>(mut replica: R, mut inbox: Inbox<R::Value, R::Ops>, mut transport: T, tick_ms: u64) -> R {
+    let mut interval = tokio::time::interval(Duration::from_millis(tick_ms.max(1)));
+    let mut error_list = Vec::new();
+    loop {
+        let msg_map = tokio::select! {
+            command = inbox.rx.recv() => match command {
+                Some(Command::Update(umsg, reply_tx))    => reply(replica.update(umsg), reply_tx),
+                Some(Command::Peer(pmsg_list, reply_tx)) => {
+                    let result = replica.peer_update(pmsg_list);
+                    // skipped messages are kept for the caller whether or not it waits on the reply
+                    for e in replica.take_rejection_list() {
+                        record_error(&mut error_list, e);
+                    }
+                    match (reply_tx, result) {
+                        (Some(reply_tx), result) => reply(result, reply_tx),
+                        (None, Ok(msg_map))      => msg_map,
+                        (None, Err(e))           => {
+                            record_error(&mut error_list, e);
+                            HashMap::new()
+                        }
+                    }
+                }
+                Some(Command::Query(reply_tx))           => {
+                    let _ = reply_tx.send(replica.value());
+                    HashMap::new()
+                }
+                Some(Command::TakeErrors(reply_tx))      => {
+                    let _ = reply_tx.send(std::mem::take(&mut error_list));
+                    HashMap::new()
+                }
+                Some(Command::Stop) | None               => break
+            },
+            _ = interval.tick(), if tick_ms > 0 => match replica.tick(SystemClock.now_ms()) {
+                Ok(msg_map) => msg_map,
+                Err(e)      => {
+                    record_error(&mut error_list, e);
+                    HashMap::new()
+                }
+            }
+        };
+        for (pnode, pmsg_list) in msg_map {
+            let _ = transport.send(pnode, pmsg_list).await;
+        }
+    }
+    replica
+}
+
+fn record_error(error_list: &mut Vec<CrdtError>, e: CrdtError) {
+    if error_list.len() >= MAX_ERROR_LIST_LEN {
+        error_list.remove(0);
+    }
+    error_list.push(e);
+}
+
+fn reply<OpsValue: Clone+PartialEq>(result: Result<PeerMsgMap<OpsValue>, CrdtError>, reply_tx: oneshot::Sender<Result<(), CrdtError>>) ->
+    PeerMsgMap<OpsValue> {
+    match result {
+        Ok(msg_map) => {
+            let _ = reply_tx.send(Ok(()));
+            msg_map
+        }
+        Err(e)      => {
+            let _ = reply_tx.send(Err(e));
+            HashMap::new()
+        }
+    }
+}
@@ -1,56 +1,117 @@
 use std::collections::HashMap;
 use anyhow::Result;
 
-use crate::{NodeType, IntMultCrdtValue, IntMultOpsValue};
-use crate::crdt::CRDT;
+use crate::{EpochType, NodeType, IntMultCrdtValue, IntMultOpsValue};
+use crate::crdt::{CRDT, CrdtBehavior, CrdtType};
+use crate::anti_entropy;
 use crate::message_data::{NodeUpdateMsg, PeerNodeMsg, SDPOpsType, OpsInstance};
-use crate::vector_clock::{VCStatus, VectorClockError};
-use crate::message_list;
+use crate::vector_clock::{VCStatus, VectorClock};
+use crate::error::CrdtError;
 
 #[derive(Debug)]
 pub struct AddMult;
 
+impl CrdtBehavior<IntMultCrdtValue, IntMultOpsValue> for AddMult {
+    const CRDT_TYPE: CrdtType = CrdtType::AddMultCrdt;
+
+    // an add is cancelled by the negated add, which concurrent mults scale the same way;
+    // only a mult by 1 or -1 has an integer inverse
+    fn inverse_ops(_crdt_value: &IntMultCrdtValue, ops_instance: &OpsInstance<IntMultOpsValue>) -> 
+        Result<Option<OpsInstance<IntMultOpsValue>>, CrdtError> {
+        match (&ops_instance.ops_type, ops_instance.ops_value) {
+            (SDPOpsType::SDPAdd, 0)      => Ok(None),
+            (SDPOpsType::SDPAdd, value)  => value.checked_neg()
+                                                 .map(|value| Some(OpsInstance::new(SDPOpsType::SDPAdd, value)))
+                                                 .ok_or(CrdtError::Overflow("add mult value")),
+            (SDPOpsType::SDPMult, 1)     => Ok(None),
+            (SDPOpsType::SDPMult, -1)    => Ok(Some(ops_instance.clone())),
+            (SDPOpsType::SDPMult, _)     => Err(CrdtError::UndoUnsupported("multiplication"))
+        }
+    }
+
+    // a local operation follows everything delivered, so no mult is concurrent with its add
+    fn check_ops(crdt_value: &IntMultCrdtValue, ops_instance: &OpsInstance<IntMultOpsValue>) -> Result<(), CrdtError> {
+        let value = match ops_instance.ops_type {
+                        SDPOpsType::SDPAdd  => crdt_value.checked_add(ops_instance.ops_value),
+                        SDPOpsType::SDPMult => crdt_value.checked_mul(ops_instance.ops_value)
+                    };
+        value.map(|_| ()).ok_or(CrdtError::Overflow("add mult value"))
+    }
+}
+
 impl CRDT<IntMultCrdtValue, IntMultOpsValue, AddMult> {
     pub fn process_local_msg(&mut self, msg: NodeUpdateMsg<IntMultOpsValue>) -> 
-        Result<HashMap<NodeType, Vec<PeerNodeMsg<IntMultOpsValue>>>, VectorClockError> {
-        self.process_msg(&msg)?;
+        Result<HashMap<NodeType, Vec<PeerNodeMsg<IntMultOpsValue>>>, CrdtError> {
+        self.check_local_msg(&msg)?;
+        let undo = self.prepare_undo(&msg)?;
+        self.process_msg_with(&msg, Self::process_msg)?;
+        self.record_undo(undo);
         self.general_process_local_msg(msg)
     }
 
+    pub fn undo(&mut self, msg: &NodeUpdateMsg<IntMultOpsValue>) -> 
+        Result<HashMap<NodeType, Vec<PeerNodeMsg<IntMultOpsValue>>>, CrdtError> {
+        self.undo_with(msg, Self::process_local_msg)
+    }
+
+    pub fn redo(&mut self, msg: &NodeUpdateMsg<IntMultOpsValue>) -> 
+        Result<HashMap<NodeType, Vec<PeerNodeMsg<IntMultOpsValue>>>, CrdtError> {
+        self.redo_with(msg, Self::process_local_msg)
+    }
+
     pub fn process_peer_msg(&mut self, pmsg_list: Vec<PeerNodeMsg<IntMultOpsValue>>) ->
-        Result<HashMap<NodeType, Vec<PeerNodeMsg<IntMultOpsValue>>>, VectorClockError> {
+        Result<HashMap<NodeType, Vec<PeerNodeMsg<IntMultOpsValue>>>, CrdtError> {
+        let mut ctrl_msg_map = HashMap::new();
         for msg in pmsg_list {
+            let msg = match self.accept_peer_msg(msg)? {
+                Some(msg) => msg,
+                None      => continue
+            };
             match msg {
                 PeerNodeMsg::VectorClockNodeMsg(vmsg) =>  
                     self.general_process_vc_msg(vmsg)?,
                 PeerNodeMsg::UpdateNodeMsg(umsg)      =>  
                     {   let vc_status = self.general_process_peer_msg(umsg.clone())?;
                          if vc_status == VCStatus::INORDER {
-                            self.process_msg(&umsg)?
+                            self.process_msg_with(&umsg, Self::process_msg)?
                         }
                     }
+                cmsg                                  =>
+                    self.general_process_ctrl_msg(cmsg, &mut ctrl_msg_map)?
             }
         }
         self.causally_stable()?;
-        let msg_list = self.create_peer_msg_list(false)?;
-        if msg_list.len() > 0 {
+        let mut msg_list = self.create_peer_msg_list(false)?;
+        if !msg_list.is_empty() {
             self.msg_count_vc = 0;
         }
+        anti_entropy::merge_peer_msg_map(&mut msg_list, ctrl_msg_map);
         Ok(msg_list)
     }
 
-    pub fn process_msg(&mut self, msg: &NodeUpdateMsg<IntMultOpsValue>) -> Result<(), VectorClockError> {
+    pub fn query_at(&self, vc: &VectorClock) -> Result<IntMultCrdtValue, CrdtError> {
+        self.query_at_with(self.get_epoch(), vc, Self::process_msg)
+    }
+
+    pub fn query_at_epoch(&self, epoch: EpochType, vc: &VectorClock) -> Result<IntMultCrdtValue, CrdtError> {
+        self.query_at_with(epoch, vc, Self::process_msg)
+    }
+
+    // local overflow is refused by check_ops; a peer operation is already delivered when it
+    // is applied, so arithmetic wraps instead, which still commutes and keeps replicas equal
+    pub fn process_msg(&mut self, msg: &NodeUpdateMsg<IntMultOpsValue>) -> Result<(), CrdtError> {
         match msg.user_update_msg.ops_instance.ops_type {
             SDPOpsType::SDPAdd  =>  {   let clist 
-                                            = message_list::concurrent_msg_list(&msg.node_vector_clock, 
-                                                    &self.msg_list, self.get_option_value())?;
+                                            = self.concurrent_msg_list(&msg.node_vector_clock, 
+                                                    self.get_option_value())?;
                                         let m = clist.iter()
-                                                        .fold(1, 
+                                                        .fold(1 as IntMultOpsValue, 
                                                            |acc, cmsg| 
-                                                              acc*cmsg.user_update_msg.ops_instance.ops_value);
-                                        self.crdt_value += m*msg.user_update_msg.ops_instance.ops_value
+                                                              acc.wrapping_mul(cmsg.user_update_msg.ops_instance.ops_value))
+                                                        .wrapping_mul(msg.user_update_msg.ops_instance.ops_value);
+                                        self.crdt_value = self.crdt_value.wrapping_add(m)
                                     },
-            SDPOpsType::SDPMult =>  self.crdt_value *= msg.user_update_msg.ops_instance.ops_value
+            SDPOpsType::SDPMult =>  self.crdt_value = self.crdt_value.wrapping_mul(msg.user_update_msg.ops_instance.ops_value)
         };
         Ok(())
     }
@@ -1,41 +1,41 @@
 use std::collections::HashMap;
 use std::fmt::Debug;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
 use anyhow::Result;
 
-use crate::NodeType;
-use crate::message_data::{PeerNodeMsg, NodeVectorClockMsg};
-use crate::crdt::CRDT;
-use crate::vector_clock::VectorClockError;
+use crate::{LCType, NodeType};
+use crate::message_data::{PeerNodeMsg, NodeUpdateMsg, NodeVectorClockMsg};
+use crate::message_list;
+use crate::crdt::{CRDT, CrdtBehavior};
+use crate::vector_clock::VectorClock;
+use crate::error::CrdtError;
 
-impl <CrdtValue: Clone+Debug, OpsValue: Clone+PartialEq+Debug, State: Debug> CRDT<CrdtValue, OpsValue, State> {
-    pub fn create_peer_msg_list(&self, msg_flag: bool) -> 
-        Result<HashMap<NodeType, Vec<PeerNodeMsg<OpsValue>>>, VectorClockError> {
+impl <CrdtValue: Clone+Debug, 
+      OpsValue: Clone+PartialEq+Debug+Serialize+DeserializeOwned, 
+      State: Debug+CrdtBehavior<CrdtValue, OpsValue>> CRDT<CrdtValue, OpsValue, State> {
+    pub fn create_peer_msg_list(&mut self, msg_flag: bool) -> 
+        Result<HashMap<NodeType, Vec<PeerNodeMsg<OpsValue>>>, CrdtError> {
         let mut msg_map = HashMap::<NodeType, Vec<PeerNodeMsg<OpsValue>>>::new();
         let msg_vec = Vec::<PeerNodeMsg<OpsValue>>::new();
-        let vc_flag = !msg_flag && self.msg_count_vc >= self.max_msg_count_vc;
+        let vc_flag = !msg_flag && self.max_msg_count_vc > 0 && self.msg_count_vc >= self.max_msg_count_vc;
         if vc_flag || msg_flag {
+            let spill_list = self.spill_store.load()?;
             let node_trcb = self.trcb.node_trcb.clone();
             for (pnode_key, pvc) in node_trcb {
+                if self.trcb.is_evicted(&pnode_key) {
+                    continue;
+                }
                 let mut msg_vec1 = msg_vec.clone();
                 if vc_flag {
-                    let vc_msg = PeerNodeMsg::VectorClockNodeMsg(NodeVectorClockMsg::new(self.trcb.node, self.trcb.node_vector_clock.clone()));
+                    let vc_msg = PeerNodeMsg::VectorClockNodeMsg(self.create_vc_msg());
                     msg_vec1.push(vc_msg);
                 }
 
                 if msg_flag {
-                    for (pvc_node_key, pvc_lc) in pvc.vcmap {
-                        if pnode_key != pvc_node_key {
-                            let lc0 = 
-                                self.trcb.node_vector_clock.vcmap.get(&pvc_node_key).ok_or(VectorClockError::UnexpectedError("anti_entropy.create_peer_msg_list 29".to_owned()))?;
-                            for lc1 in pvc_lc+1..=*lc0 {
-                                let msg_key = (pvc_node_key, lc1);
-                                if let Some(msg) = self.msg_list.get(&msg_key) {
-                                    let msg = msg.clone();
-                                    msg_vec1.push(PeerNodeMsg::UpdateNodeMsg(msg));
-                                }
-                            }
-                        }
-                    }
+                    let missing_list = self.missing_msg_list(pnode_key, &pvc, &spill_list)?;
+                    let missing_list = self.repair_msg_list(pnode_key, missing_list, false)?;
+                    msg_vec1.extend(missing_list.into_iter().map(|msg| self.outbound_update_msg(msg)));
                 }
                 msg_map.insert(pnode_key, msg_vec1);
             }
@@ -43,6 +43,102 @@ impl <CrdtValue: Clone+Debug, OpsValue: Clone+PartialEq+Debug, State: Debug> CRD
 
         Ok(msg_map)
     }
-}
 
+    pub fn create_repair_msg_list_for(&mut self, pnode: NodeType) -> Result<Vec<PeerNodeMsg<OpsValue>>, CrdtError> {
+        let pvc = self.trcb.node_trcb.get(&pnode).ok_or(CrdtError::UnknownNode(pnode))?.clone();
+        let missing_list = self.missing_msg_list(pnode, &pvc, &self.spill_store.load()?)?;
+        let missing_list = self.repair_msg_list(pnode, missing_list, false)?;
+        Ok(missing_list.into_iter().map(|msg| self.outbound_update_msg(msg)).collect())
+    }
+
+    // with repair limits set, a local operation still goes out whole to every live peer;
+    // pages, cursor and budget only pace the older operations sent by repair rounds
+    pub fn create_push_msg_list(&self, msg: NodeUpdateMsg<OpsValue>) -> HashMap<NodeType, Vec<PeerNodeMsg<OpsValue>>> {
+        let pmsg = self.outbound_update_msg(msg);
+        self.trcb.node_trcb.keys()
+                           .filter(|pnode| !self.trcb.is_evicted(pnode))
+                           .map(|pnode| (*pnode, vec![pmsg.clone()]))
+                           .collect()
+    }
+
+    pub fn create_digest_request(&self) -> HashMap<NodeType, Vec<PeerNodeMsg<OpsValue>>> {
+        self.trcb.node_trcb.keys()
+                           .filter(|pnode| !self.trcb.is_evicted(pnode))
+                           .map(|pnode| (*pnode, vec![PeerNodeMsg::DigestRequestMsg(self.create_vc_msg())]))
+                           .collect()
+    }
+
+    pub fn create_digest_request_for(&self, pnode: NodeType) -> Result<HashMap<NodeType, Vec<PeerNodeMsg<OpsValue>>>, CrdtError> {
+        if !self.trcb.node_trcb.contains_key(&pnode) {
+            return Err(CrdtError::UnknownNode(pnode));
+        }
+        Ok(HashMap::from([(pnode, vec![PeerNodeMsg::DigestRequestMsg(self.create_vc_msg())])]))
+    }
+
+    // request -> missing updates followed by a reply carrying our clock -> ack carrying the requester's new clock
+    pub fn general_process_ctrl_msg(&mut self, msg: PeerNodeMsg<OpsValue>, ctrl_msg_map: &mut HashMap<NodeType, Vec<PeerNodeMsg<OpsValue>>>) -> 
+        Result<(), CrdtError> {
+        match msg {
+            PeerNodeMsg::VectorClockNodeMsg(vmsg) |
+            PeerNodeMsg::DigestAckMsg(vmsg)       => self.general_process_vc_msg(vmsg)?,
+            PeerNodeMsg::DigestRequestMsg(vmsg)   => {
+                if self.trcb.is_evicted(&vmsg.node) {
+                    self.failure_detector.heartbeat(vmsg.node);
+                    return Ok(());
+                }
+                let missing_list = self.missing_msg_list(vmsg.node, &vmsg.node_vector_clock, &self.spill_store.load()?)?;
+                let pnode = vmsg.node;
+                let missing_list = self.repair_msg_list(pnode, missing_list, true)?;
+                self.general_process_vc_msg(vmsg)?;
+                let reply_list = ctrl_msg_map.entry(pnode).or_default();
+                reply_list.extend(missing_list.into_iter().map(|msg| self.outbound_update_msg(msg)));
+                reply_list.push(PeerNodeMsg::DigestReplyMsg(self.create_vc_msg()));
+            },
+            PeerNodeMsg::DigestReplyMsg(vmsg)     => {
+                let pnode = vmsg.node;
+                self.scheduler.unanswered_set.remove(&pnode);
+                self.general_process_vc_msg(vmsg)?;
+                ctrl_msg_map.entry(pnode).or_default().push(PeerNodeMsg::DigestAckMsg(self.create_vc_msg()));
+            },
+            PeerNodeMsg::MerkleRequestMsg(_)      |
+            PeerNodeMsg::MerkleReplyMsg(_)        => self.general_process_merkle_msg(msg, ctrl_msg_map)?,
+            PeerNodeMsg::DeltaUpdateNodeMsg(_)    => self.msg_count_vc += 1,
+            PeerNodeMsg::UpdateNodeMsg(_)         => 
+                return Err(CrdtError::MisroutedMsg("update message"))
+        }
+        Ok(())
+    }
+
+    pub fn create_vc_msg(&self) -> NodeVectorClockMsg {
+        let mut msg = NodeVectorClockMsg::new(self.trcb.node, self.trcb.node_vector_clock.clone());
+        msg.epoch = self.epoch.epoch;
+        msg.reset_pending = self.epoch.reset_pending;
+        self.sign_vc_msg(&mut msg);
+        msg
+    }
 
+    pub fn missing_msg_list(&self, pnode: NodeType, pvc: &VectorClock, spill_list: &HashMap<(NodeType, LCType), NodeUpdateMsg<OpsValue>>) -> 
+        Result<Vec<NodeUpdateMsg<OpsValue>>, CrdtError> {
+        let mut missing_list = Vec::new();
+        for (pvc_node_key, pvc_lc) in pvc.vcmap.iter() {
+            if pnode != *pvc_node_key {
+                let lc0 = self.trcb.node_vector_clock.vcmap.get(pvc_node_key).ok_or(CrdtError::NonCompatibleVC)?;
+                for lc1 in pvc_lc+1..=*lc0 {
+                    let msg_key = (*pvc_node_key, lc1);
+                    if let Some(msg) = self.msg_list.get(&msg_key).or(spill_list.get(&msg_key)) {
+                        missing_list.push(msg.clone());
+                    }
+                }
+            }
+        }
+        message_list::causal_sort(&mut missing_list);
+        Ok(missing_list)
+    }
+}
+
+pub fn merge_peer_msg_map<OpsValue: Clone+PartialEq>(msg_map: &mut HashMap<NodeType, Vec<PeerNodeMsg<OpsValue>>>, 
+                                                     other_map: HashMap<NodeType, Vec<PeerNodeMsg<OpsValue>>>) {
+    for (pnode, msg_list) in other_map {
+        msg_map.entry(pnode).or_default().extend(msg_list);
+    }
+}
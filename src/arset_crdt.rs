@@ -1,58 +1,137 @@
 use std::collections::{HashMap, HashSet};
 use anyhow::Result;
 
-use crate::{NodeType, ARSetOpsValue};
-use crate::crdt::CRDT;
-use crate::message_list;
+use crate::{EpochType, NodeType, ARSetOpsValue};
+use crate::crdt::{CRDT, CrdtBehavior, CrdtType};
+use crate::anti_entropy;
 use crate::message_data::{NodeUpdateMsg, PeerNodeMsg, OpsInstance, SDPOpsType};
-use crate::vector_clock::{VCStatus, VectorClockError};
+use crate::vector_clock::{VCStatus, VectorClock};
+use crate::subscription::ValueChange;
+use crate::error::CrdtError;
 
 #[derive(Debug)]
 pub struct AWSet;
 #[derive(Debug)]
 pub struct RWSet;
 
+impl CrdtBehavior<HashSet<ARSetOpsValue>, ARSetOpsValue> for AWSet {
+    const CRDT_TYPE: CrdtType = CrdtType::AWSetCrdt;
+
+    // an add the set already held, or a remove of a missing value, leaves nothing to undo
+    fn inverse_ops(crdt_value: &HashSet<ARSetOpsValue>, ops_instance: &OpsInstance<ARSetOpsValue>) -> 
+        Result<Option<OpsInstance<ARSetOpsValue>>, CrdtError> {
+        let value = ops_instance.ops_value;
+        match (&ops_instance.ops_type, crdt_value.contains(&value)) {
+            (SDPOpsType::SDPMult, false) => Ok(Some(OpsInstance::new(SDPOpsType::SDPAdd, value))),
+            (SDPOpsType::SDPAdd, true)   => Ok(Some(OpsInstance::new(SDPOpsType::SDPMult, value))),
+            _                            => Ok(None)
+        }
+    }
+
+    fn change_list(old_value: &HashSet<ARSetOpsValue>, new_value: &HashSet<ARSetOpsValue>) -> Vec<ValueChange<ARSetOpsValue>> {
+        set_change_list(old_value, new_value)
+    }
+}
+
+impl CrdtBehavior<HashSet<ARSetOpsValue>, ARSetOpsValue> for RWSet {
+    const CRDT_TYPE: CrdtType = CrdtType::RWSetCrdt;
+
+    fn inverse_ops(crdt_value: &HashSet<ARSetOpsValue>, ops_instance: &OpsInstance<ARSetOpsValue>) -> 
+        Result<Option<OpsInstance<ARSetOpsValue>>, CrdtError> {
+        let value = ops_instance.ops_value;
+        match (&ops_instance.ops_type, crdt_value.contains(&value)) {
+            (SDPOpsType::SDPAdd, false) => Ok(Some(OpsInstance::new(SDPOpsType::SDPMult, value))),
+            (SDPOpsType::SDPMult, true) => Ok(Some(OpsInstance::new(SDPOpsType::SDPAdd, value))),
+            _                           => Ok(None)
+        }
+    }
+
+    fn change_list(old_value: &HashSet<ARSetOpsValue>, new_value: &HashSet<ARSetOpsValue>) -> Vec<ValueChange<ARSetOpsValue>> {
+        set_change_list(old_value, new_value)
+    }
+}
+
+// removals first, each part in value order so listeners see a stable sequence
+pub fn set_change_list(old_value: &HashSet<ARSetOpsValue>, new_value: &HashSet<ARSetOpsValue>) -> Vec<ValueChange<ARSetOpsValue>> {
+    let mut removed_list: Vec<ARSetOpsValue> = old_value.difference(new_value).copied().collect();
+    let mut added_list: Vec<ARSetOpsValue> = new_value.difference(old_value).copied().collect();
+    removed_list.sort();
+    added_list.sort();
+    removed_list.into_iter().map(ValueChange::Removed)
+                .chain(added_list.into_iter().map(ValueChange::Added))
+                .collect()
+}
+
 impl CRDT<HashSet<ARSetOpsValue>, ARSetOpsValue, AWSet> {
     pub fn process_local_msg(&mut self, msg: NodeUpdateMsg<ARSetOpsValue>) -> 
-        Result<HashMap<NodeType, Vec<PeerNodeMsg<ARSetOpsValue>>>, VectorClockError> {
-        self.process_msg(&msg)?;
+        Result<HashMap<NodeType, Vec<PeerNodeMsg<ARSetOpsValue>>>, CrdtError> {
+        self.check_local_msg(&msg)?;
+        let undo = self.prepare_undo(&msg)?;
+        self.process_msg_with(&msg, Self::process_msg)?;
+        self.record_undo(undo);
         self.general_process_local_msg(msg)
      }
 
+    pub fn undo(&mut self, msg: &NodeUpdateMsg<ARSetOpsValue>) -> 
+        Result<HashMap<NodeType, Vec<PeerNodeMsg<ARSetOpsValue>>>, CrdtError> {
+        self.undo_with(msg, Self::process_local_msg)
+    }
+
+    pub fn redo(&mut self, msg: &NodeUpdateMsg<ARSetOpsValue>) -> 
+        Result<HashMap<NodeType, Vec<PeerNodeMsg<ARSetOpsValue>>>, CrdtError> {
+        self.redo_with(msg, Self::process_local_msg)
+    }
+
     pub fn process_peer_msg(&mut self, pmsg_list: Vec<PeerNodeMsg<ARSetOpsValue>>) ->
-        Result<HashMap<NodeType, Vec<PeerNodeMsg<ARSetOpsValue>>>, VectorClockError> {
+        Result<HashMap<NodeType, Vec<PeerNodeMsg<ARSetOpsValue>>>, CrdtError> {
+        let mut ctrl_msg_map = HashMap::new();
         for msg in pmsg_list {
+            let msg = match self.accept_peer_msg(msg)? {
+                Some(msg) => msg,
+                None      => continue
+            };
             match msg {
                 PeerNodeMsg::VectorClockNodeMsg(vmsg) =>  
                     self.general_process_vc_msg(vmsg)?,
                 PeerNodeMsg::UpdateNodeMsg(umsg)      =>  
                     {   let vc_status = self.general_process_peer_msg(umsg.clone())?;
                         if vc_status == VCStatus::INORDER {
-                                                            self.process_msg(&umsg)?
+                                                            self.process_msg_with(&umsg, Self::process_msg)?
                                                           }
                     }
+                cmsg                                  =>
+                    self.general_process_ctrl_msg(cmsg, &mut ctrl_msg_map)?
             }
         }
         self.causally_stable()?;
-        let msg_list = self.create_peer_msg_list(false)?;
-        if msg_list.len() > 0 {
+        let mut msg_list = self.create_peer_msg_list(false)?;
+        if !msg_list.is_empty() {
             self.msg_count_vc = 0;
         }
+        anti_entropy::merge_peer_msg_map(&mut msg_list, ctrl_msg_map);
         Ok(msg_list)
     }
 
-    pub fn process_msg(&mut self, msg: &NodeUpdateMsg<ARSetOpsValue>) -> Result<(), VectorClockError>{
-        let value = msg.user_update_msg.ops_instance.ops_value.clone();
+    pub fn query_at(&self, vc: &VectorClock) -> Result<HashSet<ARSetOpsValue>, CrdtError> {
+        self.query_at_with(self.get_epoch(), vc, Self::process_msg)
+    }
+
+    pub fn query_at_epoch(&self, epoch: EpochType, vc: &VectorClock) -> Result<HashSet<ARSetOpsValue>, CrdtError> {
+        self.query_at_with(epoch, vc, Self::process_msg)
+    }
+
+    pub fn process_msg(&mut self, msg: &NodeUpdateMsg<ARSetOpsValue>) -> Result<(), CrdtError>{
+        let value = msg.user_update_msg.ops_instance.ops_value;
         match msg.user_update_msg.ops_instance.ops_type {
             SDPOpsType::SDPAdd  =>  {   let clist 
-                                            = message_list::concurrent_msg_list(&msg.node_vector_clock, 
-                                                    &self.msg_list, self.get_option_value(value.clone()))?;
-                                        if clist.len() == 0 {
+                                            = self.concurrent_msg_list(&msg.node_vector_clock, 
+                                                    self.get_option_value(value))?;
+                                        if clist.is_empty() {
                                             self.crdt_value.remove(&value);
                                         };
                                         true
                                     }
-            SDPOpsType::SDPMult =>  self.crdt_value.insert(value.clone())
+            SDPOpsType::SDPMult =>  self.crdt_value.insert(value)
         };
         Ok(())
     }
@@ -72,41 +151,70 @@ impl CRDT<HashSet<ARSetOpsValue>, ARSetOpsValue, AWSet> {
 
 impl CRDT<HashSet<ARSetOpsValue>, ARSetOpsValue, RWSet> {
     pub fn process_local_msg(&mut self, msg: NodeUpdateMsg<ARSetOpsValue>) -> 
-        Result<HashMap<NodeType, Vec<PeerNodeMsg<ARSetOpsValue>>>, VectorClockError> {
-        self.process_msg(&msg)?;
+        Result<HashMap<NodeType, Vec<PeerNodeMsg<ARSetOpsValue>>>, CrdtError> {
+        self.check_local_msg(&msg)?;
+        let undo = self.prepare_undo(&msg)?;
+        self.process_msg_with(&msg, Self::process_msg)?;
+        self.record_undo(undo);
         self.general_process_local_msg(msg)
      }
 
+    pub fn undo(&mut self, msg: &NodeUpdateMsg<ARSetOpsValue>) -> 
+        Result<HashMap<NodeType, Vec<PeerNodeMsg<ARSetOpsValue>>>, CrdtError> {
+        self.undo_with(msg, Self::process_local_msg)
+    }
+
+    pub fn redo(&mut self, msg: &NodeUpdateMsg<ARSetOpsValue>) -> 
+        Result<HashMap<NodeType, Vec<PeerNodeMsg<ARSetOpsValue>>>, CrdtError> {
+        self.redo_with(msg, Self::process_local_msg)
+    }
+
     pub fn process_peer_msg(&mut self, pmsg_list: Vec<PeerNodeMsg<ARSetOpsValue>>) ->
-        Result<HashMap<NodeType, Vec<PeerNodeMsg<ARSetOpsValue>>>, VectorClockError> {
+        Result<HashMap<NodeType, Vec<PeerNodeMsg<ARSetOpsValue>>>, CrdtError> {
+        let mut ctrl_msg_map = HashMap::new();
         for msg in pmsg_list {
+            let msg = match self.accept_peer_msg(msg)? {
+                Some(msg) => msg,
+                None      => continue
+            };
             match msg {
                 PeerNodeMsg::VectorClockNodeMsg(vmsg) => 
                     self.general_process_vc_msg(vmsg)?,
                 PeerNodeMsg::UpdateNodeMsg(umsg)      =>  
                     {   let vc_status = self.general_process_peer_msg(umsg.clone())?;
                         if vc_status == VCStatus::INORDER {
-                            self.process_msg(&umsg)?
+                            self.process_msg_with(&umsg, Self::process_msg)?
                         }
                     }
+                cmsg                                  =>
+                    self.general_process_ctrl_msg(cmsg, &mut ctrl_msg_map)?
             }
         }
         self.causally_stable()?;
-        let msg_list = self.create_peer_msg_list(false)?;
-        if msg_list.len() > 0 {
+        let mut msg_list = self.create_peer_msg_list(false)?;
+        if !msg_list.is_empty() {
             self.msg_count_vc = 0;
         }
+        anti_entropy::merge_peer_msg_map(&mut msg_list, ctrl_msg_map);
         Ok(msg_list)
     }
 
-    pub fn process_msg(&mut self, msg: &NodeUpdateMsg<ARSetOpsValue>)  -> Result<(), VectorClockError>{
-        let value = msg.user_update_msg.ops_instance.ops_value.clone();
+    pub fn query_at(&self, vc: &VectorClock) -> Result<HashSet<ARSetOpsValue>, CrdtError> {
+        self.query_at_with(self.get_epoch(), vc, Self::process_msg)
+    }
+
+    pub fn query_at_epoch(&self, epoch: EpochType, vc: &VectorClock) -> Result<HashSet<ARSetOpsValue>, CrdtError> {
+        self.query_at_with(epoch, vc, Self::process_msg)
+    }
+
+    pub fn process_msg(&mut self, msg: &NodeUpdateMsg<ARSetOpsValue>)  -> Result<(), CrdtError>{
+        let value = msg.user_update_msg.ops_instance.ops_value;
         match msg.user_update_msg.ops_instance.ops_type {
             SDPOpsType::SDPAdd  =>  {   let clist 
-                                            = message_list::concurrent_msg_list(&msg.node_vector_clock, 
-                                                    &self.msg_list, self.get_option_value(value.clone()))?;
-                                        if clist.len() == 0 {
-                                            self.crdt_value.insert(value.clone());
+                                            = self.concurrent_msg_list(&msg.node_vector_clock, 
+                                                    self.get_option_value(value))?;
+                                        if clist.is_empty() {
+                                            self.crdt_value.insert(value);
                                         };
                                         true
                                     }
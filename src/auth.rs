@@ -0,0 +1,231 @@
+#[cfg(any(feature = "hmac", feature = "ed25519"))]
+use std::collections::HashMap;
+use std::fmt::Debug;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use anyhow::Result;
+
+#[cfg(feature = "hmac")]
+use hmac::{Hmac, Mac};
+#[cfg(feature = "hmac")]
+use sha2::Sha256;
+#[cfg(feature = "ed25519")]
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+
+use crate::{EpochType, NodeType};
+use crate::crdt::{CRDT, CrdtBehavior};
+use crate::message_data::{NodeUpdateMsg, NodeVectorClockMsg, PeerNodeMsg};
+use crate::vector_clock::VectorClock;
+use crate::error::CrdtError;
+
+pub const MAX_REJECTION_LIST_LEN: usize = 64;
+
+// signs for the local node and verifies messages claiming to come from any node
+pub trait MsgAuth: Debug+Send+Sync {
+    fn sign(&self, bytes: &[u8]) -> Vec<u8>;
+    fn verify(&self, node: NodeType, bytes: &[u8], signature: &[u8]) -> bool;
+}
+
+// every node signs with its own secret; a replica holding the secret of another node
+// can verify its messages but also forge them, which the ed25519 mode rules out
+#[cfg(feature = "hmac")]
+pub struct HmacAuth {
+    node: NodeType,
+    secret_list: HashMap<NodeType, Vec<u8>>
+}
+
+#[cfg(feature = "hmac")]
+impl HmacAuth {
+    pub fn new(node: NodeType, secret_list: HashMap<NodeType, Vec<u8>>) -> Result<Self, CrdtError> {
+        if !secret_list.contains_key(&node) {
+            return Err(CrdtError::UnknownNode(node));
+        }
+        Ok(Self{node, secret_list})
+    }
+
+    fn mac(secret: &[u8], bytes: &[u8]) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("hmac accepts keys of any length");
+        mac.update(bytes);
+        mac
+    }
+}
+
+#[cfg(feature = "hmac")]
+impl Debug for HmacAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HmacAuth").field("node", &self.node).field("node_list", &self.secret_list.keys()).finish()
+    }
+}
+
+#[cfg(feature = "hmac")]
+impl MsgAuth for HmacAuth {
+    fn sign(&self, bytes: &[u8]) -> Vec<u8> {
+        Self::mac(&self.secret_list[&self.node], bytes).finalize().into_bytes().to_vec()
+    }
+
+    fn verify(&self, node: NodeType, bytes: &[u8], signature: &[u8]) -> bool {
+        self.secret_list.get(&node).is_some_and(|secret| Self::mac(secret, bytes).verify_slice(signature).is_ok())
+    }
+}
+
+#[cfg(feature = "ed25519")]
+pub struct Ed25519Auth {
+    signing_key: SigningKey,
+    verifying_key_list: HashMap<NodeType, VerifyingKey>
+}
+
+#[cfg(feature = "ed25519")]
+impl Ed25519Auth {
+    pub fn new(secret_key: &[u8; 32], public_key_list: HashMap<NodeType, [u8; 32]>) -> Result<Self, CrdtError> {
+        let verifying_key_list = public_key_list.iter()
+                                                .map(|(node, key)| VerifyingKey::from_bytes(key)
+                                                                       .map(|key| (*node, key))
+                                                                       .map_err(|e| CrdtError::ConfigError(format!("public key of node {}", node), e.to_string())))
+                                                .collect::<Result<HashMap<_, _>, _>>()?;
+        Ok(Self{signing_key: SigningKey::from_bytes(secret_key), verifying_key_list})
+    }
+
+    pub fn public_key(secret_key: &[u8; 32]) -> [u8; 32] {
+        SigningKey::from_bytes(secret_key).verifying_key().to_bytes()
+    }
+}
+
+#[cfg(feature = "ed25519")]
+impl Debug for Ed25519Auth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Ed25519Auth").field("node_list", &self.verifying_key_list.keys()).finish()
+    }
+}
+
+#[cfg(feature = "ed25519")]
+impl MsgAuth for Ed25519Auth {
+    fn sign(&self, bytes: &[u8]) -> Vec<u8> {
+        self.signing_key.sign(bytes).to_bytes().to_vec()
+    }
+
+    fn verify(&self, node: NodeType, bytes: &[u8], signature: &[u8]) -> bool {
+        let signature = match Signature::from_slice(signature) {
+            Ok(signature) => signature,
+            Err(_)        => return false
+        };
+        self.verifying_key_list.get(&node).is_some_and(|key| key.verify(bytes, &signature).is_ok())
+    }
+}
+
+fn put_clock(bytes: &mut Vec<u8>, vc: &VectorClock) {
+    let mut lc_list: Vec<_> = vc.vcmap.iter().collect();
+    lc_list.sort();
+    for (node, lc) in lc_list {
+        bytes.extend_from_slice(&node.to_le_bytes());
+        bytes.extend_from_slice(&lc.to_le_bytes());
+    }
+}
+
+// the first epoch adds nothing, so signatures from releases without epochs still verify
+fn put_epoch(bytes: &mut Vec<u8>, epoch: EpochType) {
+    if epoch != 0 {
+        bytes.extend_from_slice(&epoch.to_le_bytes());
+    }
+}
+
+// a delta update is signed as the full update it expands to, so the signature survives relaying
+pub fn update_signed_bytes<OpsValue: Clone+PartialEq+Serialize>(msg: &NodeUpdateMsg<OpsValue>) -> Result<Vec<u8>, CrdtError> {
+    let mut bytes = b"update".to_vec();
+    bytes.extend_from_slice(&msg.node.to_le_bytes());
+    put_clock(&mut bytes, &msg.node_vector_clock);
+    bytes.extend(serde_json::to_vec(&msg.user_update_msg).map_err(|e| CrdtError::EncodeError(e.to_string()))?);
+    for (node, lc, hash) in msg.pred_hash_list.iter() {
+        bytes.extend_from_slice(&node.to_le_bytes());
+        bytes.extend_from_slice(&lc.to_le_bytes());
+        bytes.extend_from_slice(hash);
+    }
+    put_epoch(&mut bytes, msg.epoch);
+    Ok(bytes)
+}
+
+pub fn clock_signed_bytes(msg: &NodeVectorClockMsg) -> Vec<u8> {
+    let mut bytes = b"clock".to_vec();
+    bytes.extend_from_slice(&msg.node.to_le_bytes());
+    put_clock(&mut bytes, &msg.node_vector_clock);
+    put_epoch(&mut bytes, msg.epoch);
+    if msg.reset_pending {
+        bytes.extend_from_slice(b"reset");
+    }
+    bytes
+}
+
+impl <CrdtValue: Clone+Debug,
+      OpsValue: Clone+PartialEq+Debug+Serialize+DeserializeOwned,
+      State: Debug+CrdtBehavior<CrdtValue, OpsValue>> CRDT<CrdtValue, OpsValue, State> {
+    pub fn set_auth(&mut self, auth: Option<Box<dyn MsgAuth>>) {
+        self.auth = auth;
+    }
+
+    pub fn sign_update_msg(&self, msg: &mut NodeUpdateMsg<OpsValue>) -> Result<(), CrdtError> {
+        if let Some(auth) = self.auth.as_ref() {
+            msg.signature = Some(auth.sign(&update_signed_bytes(msg)?));
+        }
+        Ok(())
+    }
+
+    pub fn sign_vc_msg(&self, msg: &mut NodeVectorClockMsg) {
+        if let Some(auth) = self.auth.as_ref() {
+            msg.signature = Some(auth.sign(&clock_signed_bytes(msg)));
+        }
+    }
+
+    fn verify_signature(&self, node: NodeType, bytes: &[u8], signature: &Option<Vec<u8>>) -> Result<(), CrdtError> {
+        match (self.auth.as_ref(), signature) {
+            (None, _)                                                            => Ok(()),
+            (Some(auth), Some(signature)) if auth.verify(node, bytes, signature) => Ok(()),
+            _                                                                    => Err(CrdtError::AuthRejected(node))
+        }
+    }
+
+    // updates are checked against their origin and clock messages against their sender;
+    // merkle messages only steer repair and stay unsigned, and a delta left unexpanded is
+    // dropped unapplied
+    pub fn authenticate_peer_msg(&self, msg: &PeerNodeMsg<OpsValue>) -> Result<(), CrdtError> {
+        match msg {
+            PeerNodeMsg::VectorClockNodeMsg(vmsg) |
+            PeerNodeMsg::DigestRequestMsg(vmsg)   |
+            PeerNodeMsg::DigestReplyMsg(vmsg)     |
+            PeerNodeMsg::DigestAckMsg(vmsg)       =>
+                self.verify_signature(vmsg.node, &clock_signed_bytes(vmsg), &vmsg.signature),
+            PeerNodeMsg::UpdateNodeMsg(umsg)      => match self.auth {
+                Some(_) => self.verify_signature(umsg.node, &update_signed_bytes(umsg)?, &umsg.signature),
+                None    => Ok(())
+            },
+            PeerNodeMsg::DeltaUpdateNodeMsg(_)    |
+            PeerNodeMsg::MerkleRequestMsg(_)      |
+            PeerNodeMsg::MerkleReplyMsg(_)        => Ok(())
+        }
+    }
+
+    // a message is only checked against the epoch once it is authenticated, since one of
+    // the next epoch ends the current epoch here
+    pub fn accept_peer_msg(&mut self, msg: PeerNodeMsg<OpsValue>) -> Result<Option<PeerNodeMsg<OpsValue>>, CrdtError> {
+        let msg = self.expand_peer_msg(msg);
+        match self.authenticate_peer_msg(&msg) {
+            Ok(())                              => self.accept_peer_epoch(msg),
+            Err(e @ CrdtError::AuthRejected(_)) => {
+                self.reject_peer_msg(e);
+                Ok(None)
+            }
+            Err(e)                              => Err(e)
+        }
+    }
+
+    // a rejected message is skipped so the rest of its batch still applies and the replies
+    // still go out; the caller learns about it from take_rejection_list
+    pub fn reject_peer_msg(&mut self, e: CrdtError) {
+        if self.rejection_list.len() >= MAX_REJECTION_LIST_LEN {
+            self.rejection_list.remove(0);
+        }
+        self.rejection_list.push(e);
+    }
+
+    pub fn take_rejection_list(&mut self) -> Vec<CrdtError> {
+        std::mem::take(&mut self.rejection_list)
+    }
+}
@@ -0,0 +1,17 @@
+use ops_crdt_rust::http_api;
+use ops_crdt_rust::error::CrdtError;
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match http_api::run(&args) {
+        Ok(())                              => (),
+        Err(e @ CrdtError::ConfigError(..)) => {
+            eprintln!("{}\n\n{}", e, http_api::USAGE);
+            std::process::exit(2);
+        }
+        Err(e)                              => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,454 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::collections::btree_map::Entry;
+use std::fmt::Debug;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use anyhow::Result;
+
+use crate::{NodeType, CRDTNumType};
+use crate::crdt::{CRDT, CrdtBehavior, CrdtInstance, CrdtType};
+use crate::message_data::{OpsInstance, PeerMsgMap, SDPOpsType, StateTransferMsg, UserUpdateMsg, sorted_msg_list};
+use crate::node_instance::NodeInstance;
+use crate::replica::Replica;
+use crate::scheduler::{Clock, SystemClock};
+use crate::simulation::{self, Scenario};
+use crate::error::CrdtError;
+
+pub const USAGE: &str = "usage:
+  ops_crdt_rust start --node <node> --peers <node>=<host:port>,... --data-dir <dir>
+  ops_crdt_rust submit --addr <host:port> --crdt <crdt> [--instance <num>] --op add|mult --value <value>
+  ops_crdt_rust query --addr <host:port> --crdt <crdt> [--instance <num>]
+  ops_crdt_rust dump --addr <host:port> --crdt <crdt> [--instance <num>]
+  ops_crdt_rust simulate --scenario <file.json> [--report <file.json>]
+  ops_crdt_rust random
+crdt is one of add_mult, ewflag, dwflag, awset, rwset, pncnt; instance defaults to 0";
+
+pub const TICK_MS: u64 = 100;
+pub const PEER_TIMEOUT_MS: u64 = 1000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CliCommand {
+    Start{node: NodeType, peer_list: BTreeMap<NodeType, String>, data_dir: PathBuf},
+    Submit{addr: String, crdt_type: CrdtType, instance_num: CRDTNumType, ops_type: SDPOpsType, value: Value},
+    Query{addr: String, crdt_type: CrdtType, instance_num: CRDTNumType},
+    Dump{addr: String, crdt_type: CrdtType, instance_num: CRDTNumType},
+    Simulate{scenario_path: PathBuf, report_path: Option<PathBuf>},
+    Random
+}
+
+// one request per line, answered by one response line on the same connection
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum CliRequest {
+    Submit{crdt_type: CrdtType, #[serde(default)] instance_num: CRDTNumType, ops_type: SDPOpsType, value: Value},
+    Query{crdt_type: CrdtType, #[serde(default)] instance_num: CRDTNumType},
+    Dump{crdt_type: CrdtType, #[serde(default)] instance_num: CRDTNumType},
+    Peer{crdt_type: CrdtType, #[serde(default)] instance_num: CRDTNumType, msg_list: Value}
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum CliResponse {
+    Ok(Value),
+    Err(String)
+}
+
+pub fn parse_crdt_type(name: &str) -> Result<CrdtType, CrdtError> {
+    match name {
+        "add_mult" => Ok(CrdtType::AddMultCrdt),
+        "ewflag"   => Ok(CrdtType::EWFlagCrdt),
+        "dwflag"   => Ok(CrdtType::DWFlagCrdt),
+        "awset"    => Ok(CrdtType::AWSetCrdt),
+        "rwset"    => Ok(CrdtType::RWSetCrdt),
+        "pncnt"    => Ok(CrdtType::PNCounterCrdt),
+        _          => Err(CrdtError::ConfigError("--crdt".to_owned(), name.to_owned()))
+    }
+}
+
+pub fn parse_peer_list(value: &str) -> Result<BTreeMap<NodeType, String>, CrdtError> {
+    value.split(",")
+         .filter(|s| !s.trim().is_empty())
+         .map(|s| match s.split_once("=") {
+                    Some((node, addr)) => Ok((node.trim().parse::<NodeType>()
+                                                          .map_err(|_| CrdtError::ConfigError("--peers".to_owned(), s.to_owned()))?,
+                                              addr.trim().to_owned())),
+                    None               => Err(CrdtError::ConfigError("--peers".to_owned(), s.to_owned()))
+                  })
+         .collect()
+}
+
+pub fn parse_args(args: &[String]) -> Result<CliCommand, CrdtError> {
+    let (command, flag_list) = match args.split_first() {
+        Some((command, flag_list)) => (command.as_str(), flag_list),
+        None                       => return Err(CrdtError::ConfigError("command".to_owned(), "".to_owned()))
+    };
+    let mut flag_map = HashMap::new();
+    for pair in flag_list.chunks(2) {
+        match pair {
+            [flag, value] if flag.starts_with("--") => flag_map.insert(flag.as_str(), value.as_str()),
+            _                                       => return Err(CrdtError::ConfigError(command.to_owned(), pair.join(" ")))
+        };
+    }
+    let flag = |name: &str| flag_map.get(name).copied().ok_or(CrdtError::ConfigError(name.to_owned(), "".to_owned()));
+    let instance_num = match flag_map.get("--instance") {
+                           Some(num) => num.parse::<CRDTNumType>().map_err(|_| CrdtError::ConfigError("--instance".to_owned(), num.to_string()))?,
+                           None      => 0
+                       };
+
+    match command {
+        "start"    => {
+            let node = flag("--node")?;
+            let node = node.parse::<NodeType>().map_err(|_| CrdtError::ConfigError("--node".to_owned(), node.to_owned()))?;
+            Ok(CliCommand::Start{node, peer_list: parse_peer_list(flag("--peers")?)?, data_dir: PathBuf::from(flag("--data-dir")?)})
+        }
+        "submit"   => {
+            let ops_type = match flag("--op")? {
+                               "add"  => SDPOpsType::SDPAdd,
+                               "mult" => SDPOpsType::SDPMult,
+                               op     => return Err(CrdtError::ConfigError("--op".to_owned(), op.to_owned()))
+                           };
+            // anything that is not json, like Enabled, is taken as a string
+            let value = flag("--value")?;
+            let value = serde_json::from_str(value).unwrap_or(Value::String(value.to_owned()));
+            Ok(CliCommand::Submit{addr: flag("--addr")?.to_owned(), crdt_type: parse_crdt_type(flag("--crdt")?)?, instance_num, ops_type, value})
+        }
+        "query"    => Ok(CliCommand::Query{addr: flag("--addr")?.to_owned(), crdt_type: parse_crdt_type(flag("--crdt")?)?, instance_num}),
+        "dump"     => Ok(CliCommand::Dump{addr: flag("--addr")?.to_owned(), crdt_type: parse_crdt_type(flag("--crdt")?)?, instance_num}),
+        "simulate" => Ok(CliCommand::Simulate{scenario_path: PathBuf::from(flag("--scenario")?),
+                                                report_path: flag_map.get("--report").map(PathBuf::from)}),
+        "random"   => Ok(CliCommand::Random),
+        _          => Err(CrdtError::ConfigError("command".to_owned(), command.to_owned()))
+    }
+}
+
+pub fn run(args: &[String]) -> Result<(), CrdtError> {
+    match parse_args(args)? {
+        CliCommand::Start{node, peer_list, data_dir}                       => {
+            let addr = peer_list.get(&node).ok_or(CrdtError::UnknownNode(node))?.clone();
+            let listener = TcpListener::bind(&addr).map_err(|e| CrdtError::TransportError(format!("{}: {}", addr, e)))?;
+            let server = Arc::new(ReplicaServer::new(node, peer_list, data_dir)?);
+            println!("node {} listening on {}", node, addr);
+            server.serve(listener)
+        }
+        CliCommand::Submit{addr, crdt_type, instance_num, ops_type, value} =>
+            print_value(request(&addr, &CliRequest::Submit{crdt_type, instance_num, ops_type, value})?),
+        CliCommand::Query{addr, crdt_type, instance_num}                   => print_value(request(&addr, &CliRequest::Query{crdt_type, instance_num})?),
+        CliCommand::Dump{addr, crdt_type, instance_num}                    => print_value(request(&addr, &CliRequest::Dump{crdt_type, instance_num})?),
+        CliCommand::Simulate{scenario_path, report_path}                   => {
+            let json = fs::read_to_string(&scenario_path).map_err(|e| CrdtError::StorageError(format!("{}: {}", scenario_path.display(), e)))?;
+            let report = serde_json::to_value(simulation::simulate(&Scenario::from_json(&json)?)?)
+                             .map_err(|e| CrdtError::EncodeError(e.to_string()))?;
+            match report_path {
+                Some(report_path) => fs::write(&report_path, report.to_string())
+                                         .map_err(|e| CrdtError::StorageError(format!("{}: {}", report_path.display(), e))),
+                None              => print_value(report)
+            }
+        }
+        CliCommand::Random                                                 => {
+            crate::rand_add_mult::test_random();
+            crate::rand_ewflag::test_random();
+            crate::rand_pncnt::test_random();
+            crate::rand_awset::test_random();
+            crate::rand_gossip::test_random();
+            Ok(())
+        }
+    }
+}
+
+fn print_value(value: Value) -> Result<(), CrdtError> {
+    println!("{}", serde_json::to_string_pretty(&value).map_err(|e| CrdtError::EncodeError(e.to_string()))?);
+    Ok(())
+}
+
+pub fn request(addr: &str, request: &CliRequest) -> Result<Value, CrdtError> {
+    match send_line(addr, request)? {
+        CliResponse::Ok(value) => Ok(value),
+        CliResponse::Err(e)    => Err(CrdtError::RemoteError(e))
+    }
+}
+
+fn send_line(addr: &str, request: &CliRequest) -> Result<CliResponse, CrdtError> {
+    let transport_error = |e: std::io::Error| CrdtError::TransportError(format!("{}: {}", addr, e));
+    let timeout = Duration::from_millis(PEER_TIMEOUT_MS);
+    let sock_addr = addr.to_socket_addrs()
+                        .map_err(transport_error)?
+                        .next()
+                        .ok_or(CrdtError::TransportError(format!("{}: no address", addr)))?;
+    let mut stream = TcpStream::connect_timeout(&sock_addr, timeout).map_err(transport_error)?;
+    stream.set_read_timeout(Some(timeout)).map_err(transport_error)?;
+    let mut line = serde_json::to_string(request).map_err(|e| CrdtError::EncodeError(e.to_string()))?;
+    line.push('\n');
+    stream.write_all(line.as_bytes()).map_err(transport_error)?;
+
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line).map_err(transport_error)?;
+    serde_json::from_str(&line).map_err(|e| CrdtError::DecodeError(e.to_string()))
+}
+
+pub fn dump<CrdtValue: Clone+Debug+Serialize,
+            OpsValue: Clone+PartialEq+Debug+Serialize+DeserializeOwned,
+            State: Debug+CrdtBehavior<CrdtValue, OpsValue>>(crdt: &CRDT<CrdtValue, OpsValue, State>) -> Result<Value, CrdtError> {
+    let msg_list: BTreeMap<_, _> = crdt.all_msg_list()?.into_iter().collect();
+    let node_trcb: BTreeMap<_, _> = crdt.trcb.node_trcb.iter().collect();
+    let mut evicted: Vec<_> = crdt.trcb.evicted.iter().collect();
+    evicted.sort();
+    Ok(json!({"node": crdt.trcb.node,
+              "value": crdt.crdt_value,
+              "node_vector_clock": crdt.trcb.node_vector_clock,
+              "stable_vector_clock": crdt.trcb.stable_vector_clock,
+              "node_trcb": node_trcb,
+              "stable_dots": crdt.trcb.stable_dots,
+              "evicted": evicted,
+              "msg_list": msg_list.into_values().collect::<Vec<_>>()}))
+}
+
+// a replica with every crdt type per instance number, reachable over tcp with one json request
+// per line; each crdt is saved to the data directory after it changed and reloaded on start
+pub struct ReplicaServer {
+    pub node: NodeType,
+    pub peer_list: BTreeMap<NodeType, String>,
+    pub data_dir: PathBuf,
+    pub node_instance_list: Mutex<BTreeMap<CRDTNumType, NodeInstance>>
+}
+
+impl ReplicaServer {
+    pub fn new(node: NodeType, peer_list: BTreeMap<NodeType, String>, data_dir: PathBuf) -> Result<Self, CrdtError> {
+        fs::create_dir_all(&data_dir).map_err(|e| CrdtError::StorageError(e.to_string()))?;
+        let mut node_instance_list = BTreeMap::new();
+        for instance_num in saved_instance_list(&data_dir)? {
+            node_instance_list.insert(instance_num, load_instance(node, &peer_list, &data_dir, instance_num)?);
+        }
+        Ok(Self{node, peer_list, data_dir, node_instance_list: Mutex::new(node_instance_list)})
+    }
+
+    pub fn handle_request(&self, request: CliRequest) -> CliResponse {
+        let (response, out_list) = self.process_request(request);
+        self.send_peer_list(out_list);
+        response
+    }
+
+    fn process_request(&self, request: CliRequest) -> (CliResponse, Vec<(NodeType, CliRequest)>) {
+        match self.try_process_request(request) {
+            Ok((value, out_list)) => (CliResponse::Ok(value), out_list),
+            Err(e)                => (CliResponse::Err(e.to_string()), Vec::new())
+        }
+    }
+
+    // an instance comes into being with its first submit or peer message, on every node
+    fn try_process_request(&self, request: CliRequest) -> Result<(Value, Vec<(NodeType, CliRequest)>), CrdtError> {
+        let mut ni_list = self.node_instance_list.lock().unwrap_or_else(|e| e.into_inner());
+        let instance_num = request_instance_num(&request);
+        let ni = match (ni_list.entry(instance_num), &request) {
+                     (Entry::Occupied(entry), _)                                      => entry.into_mut(),
+                     (Entry::Vacant(entry), CliRequest::Submit{..} | CliRequest::Peer{..}) =>
+                         entry.insert(load_instance(self.node, &self.peer_list, &self.data_dir, instance_num)?),
+                     (Entry::Vacant(_), _)                                            => return Err(CrdtError::NotFound(format!("instance {}", instance_num)))
+                 };
+        let data_dir = &self.data_dir;
+        match request_crdt_type(&request) {
+            CrdtType::AddMultCrdt   => handle(&mut ni.add_mult_crdt, instance_num, request, data_dir),
+            CrdtType::EWFlagCrdt    => handle(&mut ni.ewflag_crdt, instance_num, request, data_dir),
+            CrdtType::DWFlagCrdt    => handle(&mut ni.dwflag_crdt, instance_num, request, data_dir),
+            CrdtType::AWSetCrdt     => handle(&mut ni.awset_crdt, instance_num, request, data_dir),
+            CrdtType::RWSetCrdt     => handle(&mut ni.rwset_crdt, instance_num, request, data_dir),
+            CrdtType::PNCounterCrdt => handle(&mut ni.pncnt_crdt, instance_num, request, data_dir)
+        }
+    }
+
+    // whatever the other crdts produced still goes out when one of them fails
+    pub fn tick(&self) -> Vec<CrdtError> {
+        let now = SystemClock.now_ms();
+        let mut out_list = Vec::new();
+        let mut error_list = Vec::new();
+        {
+            let mut ni_list = self.node_instance_list.lock().unwrap_or_else(|e| e.into_inner());
+            for (instance_num, ni) in ni_list.iter_mut() {
+                let result_list = [tick(&mut ni.add_mult_crdt, *instance_num, now),
+                                   tick(&mut ni.ewflag_crdt, *instance_num, now),
+                                   tick(&mut ni.dwflag_crdt, *instance_num, now),
+                                   tick(&mut ni.awset_crdt, *instance_num, now),
+                                   tick(&mut ni.rwset_crdt, *instance_num, now),
+                                   tick(&mut ni.pncnt_crdt, *instance_num, now)];
+                for result in result_list {
+                    match result {
+                        Ok(request_list) => out_list.extend(request_list),
+                        Err(e)           => error_list.push(e)
+                    }
+                }
+            }
+        }
+        self.send_peer_list(out_list);
+        error_list
+    }
+
+    // sent outside the lock, so a slow or down peer does not hold up other requests
+    fn send_peer_list(&self, out_list: Vec<(NodeType, CliRequest)>) {
+        for (pnode, request) in out_list {
+            if let Some(addr) = self.peer_list.get(&pnode) {
+                let _ = send_line(addr, &request);
+            }
+        }
+    }
+
+    pub fn serve(self: Arc<Self>, listener: TcpListener) -> Result<(), CrdtError> {
+        let server = self.clone();
+        thread::spawn(move || loop {
+            thread::sleep(Duration::from_millis(TICK_MS));
+            for e in server.tick() {
+                eprintln!("node {} tick: {}", server.node, e);
+            }
+        });
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(_)     => continue
+            };
+            let server = self.clone();
+            thread::spawn(move || server.serve_stream(stream));
+        }
+        Ok(())
+    }
+
+    fn serve_stream(&self, stream: TcpStream) {
+        let mut writer = match stream.try_clone() {
+            Ok(writer) => writer,
+            Err(_)     => return
+        };
+        for line in BufReader::new(stream).lines() {
+            let line = match line {
+                Ok(line) if line.trim().is_empty() => continue,
+                Ok(line)                           => line,
+                Err(_)                             => return
+            };
+            let (response, out_list) = match serde_json::from_str(&line) {
+                                           Ok(request) => self.process_request(request),
+                                           Err(e)      => (CliResponse::Err(CrdtError::DecodeError(e.to_string()).to_string()), Vec::new())
+                                       };
+            // answer first, so a peer waiting on this reply is not held up by the forwarding
+            let mut line = serde_json::to_string(&response).unwrap_or_default();
+            line.push('\n');
+            if writer.write_all(line.as_bytes()).is_err() {
+                return;
+            }
+            self.send_peer_list(out_list);
+        }
+    }
+}
+
+fn request_crdt_type(request: &CliRequest) -> CrdtType {
+    match request {
+        CliRequest::Submit{crdt_type, ..} | CliRequest::Query{crdt_type, ..} |
+        CliRequest::Dump{crdt_type, ..}   | CliRequest::Peer{crdt_type, ..}  => crdt_type.clone()
+    }
+}
+
+fn request_instance_num(request: &CliRequest) -> CRDTNumType {
+    match request {
+        CliRequest::Submit{instance_num, ..} | CliRequest::Query{instance_num, ..} |
+        CliRequest::Dump{instance_num, ..}   | CliRequest::Peer{instance_num, ..}  => *instance_num
+    }
+}
+
+fn handle<CrdtValue: Clone+Debug+Serialize+DeserializeOwned,
+          OpsValue: Clone+PartialEq+Debug+Serialize+DeserializeOwned,
+          State: Debug+CrdtBehavior<CrdtValue, OpsValue>>(crdt: &mut CRDT<CrdtValue, OpsValue, State>, instance_num: CRDTNumType, request: CliRequest,
+                                                           data_dir: &Path) ->
+    Result<(Value, Vec<(NodeType, CliRequest)>), CrdtError>
+    where CRDT<CrdtValue, OpsValue, State>: Replica<Value = CrdtValue, Ops = OpsValue> {
+    let msg_map = match request {
+        CliRequest::Submit{crdt_type, ops_type, value, ..} => {
+            let ops_value = serde_json::from_value(value).map_err(|e| CrdtError::DecodeError(e.to_string()))?;
+            crdt.update(UserUpdateMsg::new(CrdtInstance::new(0, instance_num, crdt_type), OpsInstance::new(ops_type, ops_value)))?
+        }
+        CliRequest::Peer{msg_list, ..}                     => {
+            let msg_list = serde_json::from_value(msg_list).map_err(|e| CrdtError::DecodeError(e.to_string()))?;
+            crdt.peer_update(msg_list)?
+        }
+        CliRequest::Query{..}                              =>
+            return Ok((serde_json::to_value(crdt.value()).map_err(|e| CrdtError::EncodeError(e.to_string()))?, Vec::new())),
+        CliRequest::Dump{..}                               =>
+            return Ok((dump(crdt)?, Vec::new()))
+    };
+    save(crdt, instance_num, data_dir)?;
+    let value = serde_json::to_value(crdt.value()).map_err(|e| CrdtError::EncodeError(e.to_string()))?;
+    Ok((value, peer_request_list(State::CRDT_TYPE, instance_num, msg_map)?))
+}
+
+fn tick<CrdtValue: Clone+Debug+Serialize,
+        OpsValue: Clone+PartialEq+Debug+Serialize+DeserializeOwned,
+        State: Debug+CrdtBehavior<CrdtValue, OpsValue>>(crdt: &mut CRDT<CrdtValue, OpsValue, State>, instance_num: CRDTNumType, now: u64) ->
+    Result<Vec<(NodeType, CliRequest)>, CrdtError>
+    where CRDT<CrdtValue, OpsValue, State>: Replica<Value = CrdtValue, Ops = OpsValue> {
+    peer_request_list(State::CRDT_TYPE, instance_num, crdt.tick(now)?)
+}
+
+fn peer_request_list<OpsValue: Clone+PartialEq+Serialize>(crdt_type: CrdtType, instance_num: CRDTNumType, msg_map: PeerMsgMap<OpsValue>) ->
+    Result<Vec<(NodeType, CliRequest)>, CrdtError> {
+    sorted_msg_list(msg_map).into_iter()
+                            .map(|(pnode, pmsg_list)| Ok((pnode, CliRequest::Peer{crdt_type: crdt_type.clone(),
+                                                                                   instance_num,
+                                                                                   msg_list: serde_json::to_value(pmsg_list)
+                                                                                                 .map_err(|e| CrdtError::EncodeError(e.to_string()))?})))
+                            .collect()
+}
+
+fn snapshot_path(data_dir: &Path, crdt_type: CrdtType, instance_num: CRDTNumType) -> PathBuf {
+    data_dir.join(format!("{:?}_{}.json", crdt_type, instance_num))
+}
+
+// instance 0 is always there, the others only once something was saved for them
+fn saved_instance_list(data_dir: &Path) -> Result<BTreeSet<CRDTNumType>, CrdtError> {
+    let mut instance_list = BTreeSet::from([0]);
+    for entry in fs::read_dir(data_dir).map_err(|e| CrdtError::StorageError(e.to_string()))? {
+        let name = entry.map_err(|e| CrdtError::StorageError(e.to_string()))?.file_name();
+        let instance_num = name.to_str()
+                               .and_then(|name| name.strip_suffix(".json"))
+                               .and_then(|name| name.rsplit_once("_"))
+                               .and_then(|(_, num)| num.parse::<CRDTNumType>().ok());
+        instance_list.extend(instance_num);
+    }
+    Ok(instance_list)
+}
+
+fn load_instance(node: NodeType, peer_list: &BTreeMap<NodeType, String>, data_dir: &Path, instance_num: CRDTNumType) -> Result<NodeInstance, CrdtError> {
+    let mut ni = NodeInstance::new_with_node_list(node, peer_list.keys().copied().collect())?;
+    load(&mut ni.add_mult_crdt, instance_num, data_dir)?;
+    load(&mut ni.ewflag_crdt, instance_num, data_dir)?;
+    load(&mut ni.dwflag_crdt, instance_num, data_dir)?;
+    load(&mut ni.awset_crdt, instance_num, data_dir)?;
+    load(&mut ni.rwset_crdt, instance_num, data_dir)?;
+    load(&mut ni.pncnt_crdt, instance_num, data_dir)?;
+    Ok(ni)
+}
+
+fn save<CrdtValue: Clone+Debug+Serialize,
+        OpsValue: Clone+PartialEq+Debug+Serialize+DeserializeOwned,
+        State: Debug+CrdtBehavior<CrdtValue, OpsValue>>(crdt: &CRDT<CrdtValue, OpsValue, State>, instance_num: CRDTNumType, data_dir: &Path) ->
+    Result<(), CrdtError> {
+    let path = snapshot_path(data_dir, State::CRDT_TYPE, instance_num);
+    let tmp_path = path.with_extension("json.tmp");
+    let bytes = serde_json::to_vec(&crdt.create_state_transfer()?).map_err(|e| CrdtError::EncodeError(e.to_string()))?;
+    fs::write(&tmp_path, bytes).map_err(|e| CrdtError::StorageError(e.to_string()))?;
+    fs::rename(&tmp_path, &path).map_err(|e| CrdtError::StorageError(e.to_string()))
+}
+
+// a restart resumes from its own last snapshot the way a joining node resumes from a donor
+fn load<CrdtValue: Clone+Debug+DeserializeOwned,
+        OpsValue: Clone+PartialEq+Debug+Serialize+DeserializeOwned,
+        State: Debug+CrdtBehavior<CrdtValue, OpsValue>>(crdt: &mut CRDT<CrdtValue, OpsValue, State>, instance_num: CRDTNumType, data_dir: &Path) ->
+    Result<(), CrdtError> {
+    let path = snapshot_path(data_dir, State::CRDT_TYPE, instance_num);
+    if !path.exists() {
+        return Ok(());
+    }
+    let bytes = fs::read(&path).map_err(|e| CrdtError::StorageError(e.to_string()))?;
+    let msg: StateTransferMsg<CrdtValue, OpsValue> = serde_json::from_slice(&bytes).map_err(|e| CrdtError::DecodeError(e.to_string()))?;
+    crdt.apply_state_transfer(msg)?;
+    Ok(())
+}
@@ -1,44 +1,179 @@
 use dotenvy::dotenv;
 use lazy_static::lazy_static;
 
+use crate::LCType;
+use crate::merkle::MERKLE_MAX_DEPTH;
+use crate::error::CrdtError;
+
 pub mod env {
     pub const MAX_MSG_COUNT_VC_VAR: &str   = "MAX_MSG_COUNT_VC";
     pub const MAX_MSG_COUNT_CS_VAR: &str   = "MAX_MSG_COUNT_CS";
     pub const NODE_LIST_VAR: &str          = "NODE_LIST";
+    pub const NODE_ID_LIST_VAR: &str       = "NODE_ID_LIST";
+    pub const FD_SUSPECT_TIMEOUT_MS_VAR: &str = "FD_SUSPECT_TIMEOUT_MS";
+    pub const FD_EVICT_AFTER_MS_VAR: &str     = "FD_EVICT_AFTER_MS";
+    pub const MSG_LIST_MAX_COUNT_VAR: &str       = "MSG_LIST_MAX_COUNT";
+    pub const MSG_LIST_MAX_BYTES_VAR: &str       = "MSG_LIST_MAX_BYTES";
+    pub const MSG_LIST_OVERFLOW_ACTION_VAR: &str = "MSG_LIST_OVERFLOW_ACTION";
+    pub const MSG_LIST_SPILL_DIR_VAR: &str       = "MSG_LIST_SPILL_DIR";
+    pub const GOSSIP_FANOUT_VAR: &str    = "GOSSIP_FANOUT";
+    pub const GOSSIP_PERIOD_MS_VAR: &str = "GOSSIP_PERIOD_MS";
+    pub const GOSSIP_MODE_VAR: &str      = "GOSSIP_MODE";
+    pub const TICK_VC_INTERVAL_MS_VAR: &str     = "TICK_VC_INTERVAL_MS";
+    pub const TICK_REPAIR_INTERVAL_MS_VAR: &str = "TICK_REPAIR_INTERVAL_MS";
+    pub const TICK_JITTER_PCT_VAR: &str         = "TICK_JITTER_PCT";
+    pub const REPAIR_MAX_BATCH_COUNT_VAR: &str      = "REPAIR_MAX_BATCH_COUNT";
+    pub const REPAIR_MAX_BATCH_BYTES_VAR: &str      = "REPAIR_MAX_BATCH_BYTES";
+    pub const REPAIR_BUDGET_BYTES_PER_SEC_VAR: &str = "REPAIR_BUDGET_BYTES_PER_SEC";
+    pub const MERKLE_DEPTH_VAR: &str = "MERKLE_DEPTH";
+    pub const DELTA_VC_MSG_VAR: &str = "DELTA_VC_MSG";
+    pub const PROTOCOL_MAX_VERSION_VAR: &str = "PROTOCOL_MAX_VERSION";
+    pub const HASH_CHAIN_VAR: &str = "HASH_CHAIN";
+    pub const EPOCH_RESET_LC_VAR: &str         = "EPOCH_RESET_LC";
+    pub const EPOCH_RESET_TIMEOUT_MS_VAR: &str = "EPOCH_RESET_TIMEOUT_MS";
+    pub const UNDO_HISTORY_LEN_VAR: &str = "UNDO_HISTORY_LEN";
+    pub const HISTORY_VAR: &str = "HISTORY";
     pub const TEST_MSG_COUNT_VAR: &str     = "TEST_MSG_COUNT";
     pub const TEST_MSG_RANGE_PCT_VAR: &str = "TEST_MSG_RANGE_PCT";
     pub const TEST_MSG_RATE_PCT_VAR: &str  = "TEST_MSG_RATE_PCT";
     pub const TEST_SLEEP_TIME_MS_VAR: &str = "TEST_SLEEP_TIME_MS";
 }
 
+// a malformed value reads as 0 here and is reported by check_env
 fn set_int_mode(param: &str) -> u64 {
+    get_int(param).unwrap_or_default()
+}
+
+fn set_str_mode(param: &str, default: &str) -> String {
     dotenv().ok();
-    let value = std::env::var(param).unwrap_or("0".to_owned());
-    parse_int(&value)
+    std::env::var(param).unwrap_or(default.to_owned())
 }
 
 fn set_u16_mode(param: &str) -> u16 {
-    set_int_mode(param) as u16
+    get_u16(param).unwrap_or_default()
+}
+
+fn set_lc_mode(param: &str) -> LCType {
+    get_lc(param).unwrap_or_default()
+}
+
+fn set_depth_mode(param: &str) -> u8 {
+    get_depth(param).unwrap_or_default()
 }
 
 fn set_list_mode(param: &str) -> Vec<u16> {
+    get_list(param).unwrap_or_default()
+}
+
+fn set_str_list_mode(param: &str) -> Vec<String> {
+    set_str_mode(param, "").split(",").map(|s| s.trim().to_owned()).filter(|s| !s.is_empty()).collect()
+}
+
+fn parse_int(param: &str, value: &str) -> Result<u64, CrdtError> {
+    value.trim().parse::<u64>().map_err(|_| CrdtError::ConfigError(param.to_owned(), value.to_owned()))
+}
+
+fn parse_u16(param: &str, value: &str) -> Result<u16, CrdtError> {
+    u16::try_from(parse_int(param, value)?).map_err(|_| CrdtError::ConfigError(param.to_owned(), value.to_owned()))
+}
+
+fn get_int(param: &str) -> Result<u64, CrdtError> {
+    dotenv().ok();
+    match std::env::var(param) {
+        Ok(value) => parse_int(param, &value),
+        Err(_)    => Ok(0)
+    }
+}
+
+fn get_u16(param: &str) -> Result<u16, CrdtError> {
+    dotenv().ok();
+    match std::env::var(param) {
+        Ok(value) => parse_u16(param, &value),
+        Err(_)    => Ok(0)
+    }
+}
+
+fn get_lc(param: &str) -> Result<LCType, CrdtError> {
+    let value = get_int(param)?;
+    LCType::try_from(value).map_err(|_| CrdtError::ConfigError(param.to_owned(), value.to_string()))
+}
+
+fn get_depth(param: &str) -> Result<u8, CrdtError> {
+    let value = get_int(param)?;
+    match u8::try_from(value) {
+        Ok(depth) if depth <= MERKLE_MAX_DEPTH => Ok(depth),
+        _                                      => Err(CrdtError::ConfigError(param.to_owned(), value.to_string()))
+    }
+}
+
+fn get_list(param: &str) -> Result<Vec<u16>, CrdtError> {
     dotenv().ok();
     let value = std::env::var(param).unwrap_or("".to_owned());
-    value.split(",").map(|x| parse_u16(x)).collect()
+    value.split(",").filter(|s| !s.trim().is_empty()).map(|s| parse_u16(param, s)).collect()
 }
 
-fn parse_int(s: &str) -> u64 {
-    s.parse::<u64>().unwrap()
+const U16_VAR_LIST: [&str; 7] = [env::MAX_MSG_COUNT_VC_VAR, env::MAX_MSG_COUNT_CS_VAR, env::TICK_JITTER_PCT_VAR,
+                                 env::PROTOCOL_MAX_VERSION_VAR, env::TEST_MSG_COUNT_VAR, env::TEST_MSG_RANGE_PCT_VAR,
+                                 env::TEST_MSG_RATE_PCT_VAR];
+const INT_VAR_LIST: [&str; 17] = [env::FD_SUSPECT_TIMEOUT_MS_VAR, env::FD_EVICT_AFTER_MS_VAR, env::MSG_LIST_MAX_COUNT_VAR,
+                                  env::MSG_LIST_MAX_BYTES_VAR, env::GOSSIP_FANOUT_VAR, env::GOSSIP_PERIOD_MS_VAR,
+                                  env::TICK_VC_INTERVAL_MS_VAR, env::TICK_REPAIR_INTERVAL_MS_VAR,
+                                  env::REPAIR_MAX_BATCH_COUNT_VAR, env::REPAIR_MAX_BATCH_BYTES_VAR,
+                                  env::REPAIR_BUDGET_BYTES_PER_SEC_VAR, env::DELTA_VC_MSG_VAR, env::HASH_CHAIN_VAR,
+                                  env::EPOCH_RESET_TIMEOUT_MS_VAR, env::UNDO_HISTORY_LEN_VAR, env::HISTORY_VAR,
+                                  env::TEST_SLEEP_TIME_MS_VAR];
+
+fn validate_env() -> Result<(), CrdtError> {
+    get_list(env::NODE_LIST_VAR)?;
+    get_lc(env::EPOCH_RESET_LC_VAR)?;
+    get_depth(env::MERKLE_DEPTH_VAR)?;
+    for param in U16_VAR_LIST {
+        get_u16(param)?;
+    }
+    for param in INT_VAR_LIST {
+        get_int(param)?;
+    }
+    Ok(())
 }
 
-fn parse_u16(s: &str) -> u16 {
-    parse_int(s) as u16
+// the statics below cannot return an error, so constructors reading them call this first
+pub fn check_env() -> Result<(), CrdtError> {
+    match ENV_ERROR.as_ref() {
+        Some(e) => Err(e.clone()),
+        None    => Ok(())
+    }
 }
 
 lazy_static! {
+    static ref ENV_ERROR: Option<CrdtError> = validate_env().err();
     pub static ref MAX_MSG_COUNT_VC: u16   = set_u16_mode(env::MAX_MSG_COUNT_VC_VAR);
+    // no longer read, kept so existing configs and callers still build
     pub static ref MAX_MSG_COUNT_CS: u16   = set_u16_mode(env::MAX_MSG_COUNT_CS_VAR);
     pub static ref NODE_LIST: Vec<u16>     = set_list_mode(env::NODE_LIST_VAR);
+    pub static ref NODE_ID_LIST: Vec<String> = set_str_list_mode(env::NODE_ID_LIST_VAR);
+    pub static ref FD_SUSPECT_TIMEOUT_MS: u64 = set_int_mode(env::FD_SUSPECT_TIMEOUT_MS_VAR);
+    pub static ref FD_EVICT_AFTER_MS: u64     = set_int_mode(env::FD_EVICT_AFTER_MS_VAR);
+    pub static ref MSG_LIST_MAX_COUNT: u64          = set_int_mode(env::MSG_LIST_MAX_COUNT_VAR);
+    pub static ref MSG_LIST_MAX_BYTES: u64          = set_int_mode(env::MSG_LIST_MAX_BYTES_VAR);
+    pub static ref MSG_LIST_OVERFLOW_ACTION: String = set_str_mode(env::MSG_LIST_OVERFLOW_ACTION_VAR, "reject_local");
+    pub static ref MSG_LIST_SPILL_DIR: String       = set_str_mode(env::MSG_LIST_SPILL_DIR_VAR, "spill");
+    pub static ref GOSSIP_FANOUT: u64    = set_int_mode(env::GOSSIP_FANOUT_VAR);
+    pub static ref GOSSIP_PERIOD_MS: u64 = set_int_mode(env::GOSSIP_PERIOD_MS_VAR);
+    pub static ref GOSSIP_MODE: String   = set_str_mode(env::GOSSIP_MODE_VAR, "random");
+    pub static ref TICK_VC_INTERVAL_MS: u64     = set_int_mode(env::TICK_VC_INTERVAL_MS_VAR);
+    pub static ref TICK_REPAIR_INTERVAL_MS: u64 = set_int_mode(env::TICK_REPAIR_INTERVAL_MS_VAR);
+    pub static ref TICK_JITTER_PCT: u16         = set_u16_mode(env::TICK_JITTER_PCT_VAR);
+    pub static ref REPAIR_MAX_BATCH_COUNT: u64      = set_int_mode(env::REPAIR_MAX_BATCH_COUNT_VAR);
+    pub static ref REPAIR_MAX_BATCH_BYTES: u64      = set_int_mode(env::REPAIR_MAX_BATCH_BYTES_VAR);
+    pub static ref REPAIR_BUDGET_BYTES_PER_SEC: u64 = set_int_mode(env::REPAIR_BUDGET_BYTES_PER_SEC_VAR);
+    pub static ref MERKLE_DEPTH: u8 = set_depth_mode(env::MERKLE_DEPTH_VAR);
+    pub static ref DELTA_VC_MSG: bool = set_int_mode(env::DELTA_VC_MSG_VAR) != 0;
+    pub static ref PROTOCOL_MAX_VERSION: u16 = set_u16_mode(env::PROTOCOL_MAX_VERSION_VAR);
+    pub static ref HASH_CHAIN: bool = set_int_mode(env::HASH_CHAIN_VAR) != 0;
+    pub static ref EPOCH_RESET_LC: LCType      = set_lc_mode(env::EPOCH_RESET_LC_VAR);
+    pub static ref EPOCH_RESET_TIMEOUT_MS: u64 = set_int_mode(env::EPOCH_RESET_TIMEOUT_MS_VAR);
+    pub static ref UNDO_HISTORY_LEN: u64 = set_int_mode(env::UNDO_HISTORY_LEN_VAR);
+    pub static ref HISTORY: bool = set_int_mode(env::HISTORY_VAR) != 0;
     pub static ref TEST_MSG_COUNT: u16     = set_u16_mode(env::TEST_MSG_COUNT_VAR);
     pub static ref TEST_MSG_RANGE_PCT: u16 = set_u16_mode(env::TEST_MSG_RANGE_PCT_VAR);
     pub static ref TEST_MSG_RATE_PCT: u16  = set_u16_mode(env::TEST_MSG_RATE_PCT_VAR);
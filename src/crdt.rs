@@ -1,22 +1,42 @@
 use std::collections::HashMap;
 use std::fmt::Debug;
+use std::path::PathBuf;
 use anyhow::Result;
 
 use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
 
 use crate::{LCType, 
             NodeType, 
             CRDTNumType};
 use crate::trcb;
-use crate::vector_clock::{VCStatus, VectorClockError, VectorClock, peer_vc_status};
+use crate::vector_clock::{VCStatus, VectorClock, peer_vc_status, INITIAL_LC};
 use crate::message_data::{NodeUpdateMsg, 
                           NodeVectorClockMsg, 
+                          OpsInstance,
                           PeerNodeMsg, 
+                          StateTransferMsg,
                           UserUpdateMsg};
 use crate::message_list;
-use crate::constants::{MAX_MSG_COUNT_CS, MAX_MSG_COUNT_VC, NODE_LIST};
+use crate::failure_detector::{FailureDetector, EvictionPolicy, PeerStatus};
+use crate::memory_policy::{self, MemoryPolicy, OverflowAction, SpillStore};
+use crate::gossip::{GossipConfig, GossipState};
+use crate::scheduler::{Scheduler, TickConfig, TickTask};
+use crate::repair_limit::{RepairConfig, RepairLimiter};
+use crate::merkle::MerkleState;
+use crate::delta_vc::DeltaClockState;
+use crate::protocol::{ProtocolState, PROTOCOL_VERSION};
+use crate::auth::MsgAuth;
+use crate::hash_chain::HashChainState;
+use crate::epoch::EpochState;
+use crate::undo::UndoState;
+use crate::history::HistoryState;
+use crate::subscription::{SubscriptionState, ValueChange};
+use crate::constants::{check_env, MAX_MSG_COUNT_VC, MAX_MSG_COUNT_CS, NODE_LIST, FD_SUSPECT_TIMEOUT_MS, FD_EVICT_AFTER_MS, MERKLE_DEPTH, DELTA_VC_MSG,
+                       HASH_CHAIN, EPOCH_RESET_LC, EPOCH_RESET_TIMEOUT_MS, UNDO_HISTORY_LEN, HISTORY};
+use crate::error::CrdtError;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub enum CrdtType {
     AddMultCrdt,
     EWFlagCrdt,
@@ -26,11 +46,11 @@ pub enum CrdtType {
     PNCounterCrdt
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct CrdtInstance {
-    instance_node_id: NodeType,
-    instance_num: CRDTNumType,
-    instance_type: CrdtType
+    pub instance_node_id: NodeType,
+    pub instance_num: CRDTNumType,
+    pub instance_type: CrdtType
 }
 impl CrdtInstance {
     pub fn new(instance_node_id: NodeType, instance_num: CRDTNumType, instance_type: CrdtType) -> Self {
@@ -42,91 +62,348 @@ impl CrdtInstance {
     }
 }
 
+pub trait CrdtBehavior<CrdtValue, OpsValue: Clone+PartialEq> {
+    const CRDT_TYPE: CrdtType;
+
+    // called once per message, in per-node causal order, when it is dropped from msg_list;
+    // the built-in sets and flags keep their concurrency metadata in msg_list itself, so
+    // only a type holding tombstones in its value needs it
+    fn on_causally_stable(_crdt_value: &mut CrdtValue, _msg: &NodeUpdateMsg<OpsValue>) {}
+
+    // the operation cancelling ops_instance on crdt_value as it was just before, or none
+    // when the operation left it unchanged
+    fn inverse_ops(_crdt_value: &CrdtValue, _ops_instance: &OpsInstance<OpsValue>) -> Result<Option<OpsInstance<OpsValue>>, CrdtError> {
+        Err(CrdtError::UndoUnsupported("operation"))
+    }
+
+    // refuses a local operation before it takes a clock, since a clock taken by an operation
+    // that is then dropped leaves a gap peers wait on forever
+    fn check_ops(_crdt_value: &CrdtValue, _ops_instance: &OpsInstance<OpsValue>) -> Result<(), CrdtError> {
+        Ok(())
+    }
+
+    // the elements an operation added to or removed from the value, for types holding any
+    fn change_list(_old_value: &CrdtValue, _new_value: &CrdtValue) -> Vec<ValueChange<OpsValue>> {
+        Vec::new()
+    }
+}
+
 #[derive(Debug)]
 pub struct CRDT <CrdtValue: Clone+Debug, OpsValue: Clone+PartialEq+Debug, State> {
     pub trcb: trcb::TRCBData,
     pub msg_list: HashMap<(NodeType, LCType), NodeUpdateMsg<OpsValue>>,
     pub crdt_value: CrdtValue,
     pub max_msg_count_vc: u16,
+    #[deprecated(note = "causal stability is tracked incrementally, this is no longer read")]
     pub max_msg_count_cs: u16,
     pub msg_count_vc: u16,
+    #[deprecated(note = "causal stability is tracked incrementally, this is no longer updated")]
     pub msg_count_cs: u16,
+    pub failure_detector: FailureDetector,
+    pub memory_policy: MemoryPolicy,
+    pub msg_bytes: usize,
+    pub spill_store: SpillStore,
+    pub gossip: Option<GossipState>,
+    pub scheduler: Scheduler,
+    pub repair_limiter: RepairLimiter,
+    pub merkle: MerkleState,
+    pub delta_vc: DeltaClockState,
+    pub protocol: ProtocolState,
+    pub auth: Option<Box<dyn MsgAuth>>,
+    pub rejection_list: Vec<CrdtError>,
+    pub hash_chain: HashChainState,
+    pub epoch: EpochState,
+    pub undo: UndoState<OpsValue>,
+    pub history: HistoryState<CrdtValue, OpsValue>,
+    pub subscription: SubscriptionState<CrdtValue, OpsValue>,
     pub state: std::marker::PhantomData<State>
 }
 
-impl <CrdtValue: Clone+Debug, OpsValue: Clone+PartialEq+Debug, State: Debug> CRDT<CrdtValue, OpsValue, State> {
-    pub fn new(node: NodeType, crdt_value: CrdtValue) -> Result<Self, VectorClockError> {
-        let trcb = trcb::TRCBData::new(node, NODE_LIST.to_owned().clone())?;
-        let msg_list = HashMap::new();
+impl <CrdtValue: Clone+Debug, 
+      OpsValue: Clone+PartialEq+Debug+Serialize+DeserializeOwned, 
+      State: Debug+CrdtBehavior<CrdtValue, OpsValue>> CRDT<CrdtValue, OpsValue, State> {
+    pub fn new(node: NodeType, crdt_value: CrdtValue) -> Result<Self, CrdtError> {
+        Self::new_with_node_list(node, NODE_LIST.to_owned().clone(), crdt_value)
+    }
+
+    #[allow(deprecated)]
+    pub fn new_with_node_list(node: NodeType, node_list: Vec<NodeType>, crdt_value: CrdtValue) -> Result<Self, CrdtError> {
+        check_env()?;
+        let mut crdt = Self::new_bare(node, node_list, crdt_value)?;
+        let eviction_policy = match FD_EVICT_AFTER_MS.to_owned() {
+                                    0         => EvictionPolicy::Manual,
+                                    evict_ms  => EvictionPolicy::AfterSuspectedMs(evict_ms)
+                              };
+        crdt.failure_detector = FailureDetector::new(crdt.trcb.node_trcb.keys().copied().collect(),
+                                                     FD_SUSPECT_TIMEOUT_MS.to_owned(),
+                                                     eviction_policy);
+        crdt.memory_policy = MemoryPolicy::from_env()?;
+        crdt.spill_store = SpillStore::new(crdt.memory_policy.spill_dir.clone(), node);
+        crdt.gossip = GossipConfig::from_env()?.map(GossipState::new);
+        crdt.scheduler = Scheduler::new(TickConfig::from_env());
+        if let Some(gossip) = crdt.gossip.as_ref() {
+            crdt.scheduler.set_interval(TickTask::Repair, gossip.config.period_ms);
+        }
+        crdt.repair_limiter = RepairLimiter::new(RepairConfig::from_env());
+        crdt.protocol = ProtocolState::from_env()?;
+        crdt.max_msg_count_vc = MAX_MSG_COUNT_VC.to_owned();
+        crdt.max_msg_count_cs = MAX_MSG_COUNT_CS.to_owned();
+        crdt.merkle = MerkleState::new(MERKLE_DEPTH.to_owned());
+        crdt.delta_vc = DeltaClockState::new(DELTA_VC_MSG.to_owned(), &crdt.trcb.node_vector_clock);
+        crdt.hash_chain = HashChainState::new(HASH_CHAIN.to_owned());
+        crdt.epoch = EpochState::new(EPOCH_RESET_LC.to_owned(), EPOCH_RESET_TIMEOUT_MS.to_owned());
+        crdt.undo = UndoState::new(UNDO_HISTORY_LEN.to_owned() as usize);
+        crdt.history = HistoryState::new(HISTORY.to_owned(), crdt.crdt_value.clone());
+        Ok(crdt)
+    }
+
+    // just the clocks, the value and the operations to apply against, with every optional
+    // part off and nothing read from the env config or the disk
+    pub fn new_bare(node: NodeType, node_list: Vec<NodeType>, crdt_value: CrdtValue) -> Result<Self, CrdtError> {
+        let trcb = trcb::TRCBData::new(node, node_list)?;
+        let failure_detector = FailureDetector::new(trcb.node_trcb.keys().copied().collect(), 0, EvictionPolicy::Manual);
+        let delta_vc = DeltaClockState::new(false, &trcb.node_vector_clock);
+        let history = HistoryState::new(false, crdt_value.clone());
+        #[allow(deprecated)]
         Ok(Self{trcb, 
-                msg_list, 
+                msg_list: HashMap::new(), 
                 crdt_value, 
-                max_msg_count_vc: MAX_MSG_COUNT_VC.to_owned(),
-                max_msg_count_cs: MAX_MSG_COUNT_CS.to_owned(),
+                max_msg_count_vc: 0,
+                max_msg_count_cs: 0,
                 msg_count_vc: 0,
                 msg_count_cs: 0,
+                failure_detector,
+                memory_policy: MemoryPolicy::new(None, None, OverflowAction::RejectLocal, PathBuf::new()),
+                msg_bytes: 0,
+                spill_store: SpillStore::new(PathBuf::new(), node),
+                gossip: None,
+                scheduler: Scheduler::new_with_seed(TickConfig::new(0, 0, 0), 0),
+                repair_limiter: RepairLimiter::new(RepairConfig::default()),
+                merkle: MerkleState::new(0),
+                delta_vc,
+                protocol: ProtocolState::new(PROTOCOL_VERSION),
+                auth: None,
+                rejection_list: Vec::new(),
+                hash_chain: HashChainState::new(false),
+                epoch: EpochState::new(0, 0),
+                undo: UndoState::new(0),
+                history,
+                subscription: SubscriptionState::new(),
                 state: std::marker::PhantomData::<State>})
     }
 
-    pub fn next_vc(&mut self) -> Result<VectorClock, VectorClockError> {
+    pub fn next_vc(&mut self) -> Result<VectorClock, CrdtError> {
         self.trcb.next_vc()
     }
 
     pub fn get_node(&self) -> NodeType {
-        self.trcb.node.clone()
+        self.trcb.node
     }
 
     pub fn create_local_msg(&mut self, user_update_msg: UserUpdateMsg<OpsValue>) -> 
-        Result<NodeUpdateMsg<OpsValue>, VectorClockError> {
+        Result<NodeUpdateMsg<OpsValue>, CrdtError> {
+        self.check_local_capacity()?;
+        self.check_local_epoch()?;
+        self.check_instance(&user_update_msg)?;
+        State::check_ops(&self.crdt_value, &user_update_msg.ops_instance)?;
         let node = self.get_node();
         let node_vector_clock = self.next_vc()?.clone();
-        Ok(NodeUpdateMsg::new(node, node_vector_clock, user_update_msg))
+        let mut msg = NodeUpdateMsg::new(node, node_vector_clock, user_update_msg);
+        msg.epoch = self.epoch.epoch;
+        msg.pred_hash_list = self.pred_hash_list(node, &msg.node_vector_clock)?;
+        self.sign_update_msg(&mut msg)?;
+        Ok(msg)
+    }
+
+    // an update must name this crdt type
+    pub fn check_instance(&self, user_update_msg: &UserUpdateMsg<OpsValue>) -> Result<(), CrdtError> {
+        match user_update_msg.crdt_instance.instance_type == State::CRDT_TYPE {
+            true  => Ok(()),
+            false => Err(CrdtError::UnknownInstance(user_update_msg.crdt_instance.clone()))
+        }
     }
 
-    pub fn add_msg(&mut self, msg: NodeUpdateMsg<OpsValue>) -> Result<(), VectorClockError> {
-        let lc = msg.node_vector_clock.vcmap.get(&msg.node).ok_or(VectorClockError::NodeNotFound)?;
-        self.msg_list.insert((msg.node, lc.clone()), msg);
+    // a local update must be the latest operation created here and not yet processed, or
+    // it would be applied twice
+    pub fn check_local_msg(&self, msg: &NodeUpdateMsg<OpsValue>) -> Result<(), CrdtError> {
+        self.check_instance(&msg.user_update_msg)?;
+        let node = self.get_node();
+        let lc = *msg.node_vector_clock.vcmap.get(&msg.node).ok_or(CrdtError::UnknownNode(msg.node))?;
+        let local_lc = *self.trcb.node_vector_clock.vcmap.get(&node).ok_or(CrdtError::UnknownNode(node))?;
+        let last_lc = self.delta_vc.op_clock_list.get(&node)
+                                                 .and_then(|op_clock| op_clock.last_vc.vcmap.get(&node))
+                                                 .copied()
+                                                 .unwrap_or(INITIAL_LC);
+        if msg.node != node || lc != local_lc || lc <= last_lc {
+            return Err(CrdtError::StaleMessage(msg.node, lc));
+        }
         Ok(())
     }
 
+    pub fn add_msg(&mut self, msg: NodeUpdateMsg<OpsValue>) -> Result<(), CrdtError> {
+        let lc = msg.node_vector_clock.vcmap.get(&msg.node).ok_or(CrdtError::UnknownNode(msg.node))?;
+        self.msg_bytes += memory_policy::msg_size(&msg);
+        if let Some(old_msg) = self.msg_list.insert((msg.node, *lc), msg) {
+            self.msg_bytes -= memory_policy::msg_size(&old_msg);
+        }
+        self.enforce_memory_policy()
+    }
+
     pub fn general_process_local_msg(&mut self, msg: NodeUpdateMsg<OpsValue>) -> 
-        Result<HashMap<NodeType, Vec<PeerNodeMsg<OpsValue>>>, VectorClockError> {
+        Result<HashMap<NodeType, Vec<PeerNodeMsg<OpsValue>>>, CrdtError> {
         self.msg_count_vc = 0;
-        self.msg_count_cs += 1;
+        self.record_op_vc(&msg);
+        self.record_op_hash(&msg)?;
+        self.record_history(&msg);
         self.add_msg(msg.clone())?;
         self.causally_stable()?;         
-        self.create_peer_msg_list(true)
+        self.check_epoch_reset_lc()?;
+        if self.gossip.is_some() {
+            self.create_gossip_msg_list()
+        } else if self.repair_limiter.config.is_unlimited() {
+            self.create_peer_msg_list(true)
+        } else {
+            Ok(self.create_push_msg_list(msg))
+        }
     }
 
-    pub fn general_process_peer_msg(&mut self, msg: NodeUpdateMsg<OpsValue>) -> Result<VCStatus, VectorClockError>  {
+    pub fn general_process_peer_msg(&mut self, msg: NodeUpdateMsg<OpsValue>) -> Result<VCStatus, CrdtError>  {
+        self.check_instance(&msg.user_update_msg)?;
         self.msg_count_vc += 1;
+        if self.trcb.is_evicted(&msg.node) && !self.trcb.is_after_stable(&msg.node_vector_clock)? {
+            return Ok(VCStatus::EVICTED);
+        }
+
         let vc_ord = self.trcb.node_vector_clock.check_vc(msg.node, &msg.node_vector_clock)?;
-        let vc_status = peer_vc_status(vc_ord);
+        let mut vc_status = peer_vc_status(vc_ord);
+        if vc_status == VCStatus::INORDER && !self.trcb.node_vector_clock.check_deps(msg.node, &msg.node_vector_clock)? {
+            vc_status = VCStatus::OUTOFORDER;
+        }
     
+        if vc_status == VCStatus::DUPLICATE {
+            self.check_duplicate_op(&msg)?;
+        }
         if vc_status == VCStatus::INORDER {
-            self.msg_count_cs += 1;
+            self.check_pred_hash(&msg)?;
+            self.record_op_vc(&msg);
+            self.record_history(&msg);
             self.add_msg(msg.clone())?;
             self.trcb.add_peer_vc(msg.node, msg.node_vector_clock.clone())?;
         }
         Ok(vc_status)
     }
 
-    pub fn general_process_vc_msg(&mut self, msg: NodeVectorClockMsg) -> Result<(), VectorClockError> {
+    pub fn general_process_vc_msg(&mut self, msg: NodeVectorClockMsg) -> Result<(), CrdtError> {
+        self.failure_detector.heartbeat(msg.node);
+        if self.trcb.is_evicted(&msg.node) {
+            return Ok(());
+        }
         self.trcb.add_peer_vcmsg(msg.node, msg.node_vector_clock.clone())?;
-        self.msg_count_cs += 1;
         self.causally_stable()
     }
 
-    pub fn causally_stable(&mut self) -> Result<(), VectorClockError> {
-        if self.msg_count_cs >= self.max_msg_count_cs{
-            let cs_vc = self.trcb.causally_stable()?;
-            let new_list = message_list::remove_causally_stable(&cs_vc, &self.msg_list)?;
-            self.msg_list = new_list;
-            self.msg_count_cs = 0;
+    pub fn check_peers(&mut self, now: u64) -> Result<Vec<(NodeType, PeerStatus)>, CrdtError> {
+        let status_list = self.failure_detector.check(now);
+        for (peer, status) in status_list.iter() {
+            if *status == PeerStatus::Evicted {
+                self.trcb.evict_peer(*peer)?;
+            }
+        }
+        self.causally_stable()?;
+        Ok(status_list)
+    }
+
+    pub fn evict_peer(&mut self, peer: NodeType) -> Result<(), CrdtError> {
+        self.failure_detector.evict(peer)?;
+        self.trcb.evict_peer(peer)?;
+        self.causally_stable()
+    }
+
+    pub fn readmit_peer(&mut self, peer: NodeType, peer_vc: VectorClock) -> Result<(), CrdtError> {
+        self.failure_detector.readmit(peer)?;
+        self.repair_limiter.reset_cursor(peer);
+        self.trcb.readmit_peer(peer, peer_vc)
+    }
+
+    pub fn peer_status(&self, peer: &NodeType) -> Option<PeerStatus> {
+        self.failure_detector.status(peer)
+    }
+
+    pub fn peer_status_list(&self) -> HashMap<NodeType, PeerStatus> {
+        self.failure_detector.status_list()
+    }
+
+    pub fn create_state_transfer(&self) -> Result<StateTransferMsg<CrdtValue, OpsValue>, CrdtError> {
+        Ok(StateTransferMsg{node: self.get_node(),
+                            node_vector_clock: self.trcb.node_vector_clock.clone(),
+                            stable_vector_clock: self.trcb.stable_vector_clock.clone(),
+                            crdt_value: self.crdt_value.clone(),
+                            msg_list: self.all_msg_list()?.into_values().collect(),
+                            op_clock_list: self.delta_vc.op_clock_list.clone(),
+                            epoch: self.epoch.epoch})
+    }
+
+    // local operations the donor never delivered are handed back so the caller can resubmit them
+    pub fn apply_state_transfer(&mut self, msg: StateTransferMsg<CrdtValue, OpsValue>) -> 
+        Result<Vec<UserUpdateMsg<OpsValue>>, CrdtError> {
+        let node = self.get_node();
+        let donor_lc = *msg.node_vector_clock.vcmap.get(&node).ok_or(CrdtError::UnknownNode(node))?;
+        let mut lost_list: Vec<(LCType, UserUpdateMsg<OpsValue>)> = 
+            self.all_msg_list()?
+                         .iter()
+                         .filter(|((mnode, mlc), _)| *mnode == node && *mlc > donor_lc)
+                         .map(|((_, mlc), umsg)| (*mlc, umsg.user_update_msg.clone()))
+                         .collect();
+        lost_list.sort_by_key(|(mlc, _)| *mlc);
+        self.history.rebase(&msg);
+        let old_value = self.watched_value();
+        let donor = msg.node;
+
+        self.trcb.reset_from_state(msg.node, msg.node_vector_clock, msg.stable_vector_clock)?;
+        self.failure_detector.reset();
+        self.repair_limiter.reset();
+        self.crdt_value = msg.crdt_value;
+        self.delta_vc.op_clock_list = msg.op_clock_list;
+        self.hash_chain.last_hash_list.clear();
+        self.merkle.clear_cache();
+        self.epoch.reset(msg.epoch);
+        self.msg_list = HashMap::new();
+        self.msg_bytes = 0;
+        self.spill_store.clear()?;
+        for umsg in msg.msg_list {
+            self.add_msg(umsg)?;
+        }
+        self.msg_count_vc = 0;
+        self.notify_replaced_value(old_value, donor);
+
+        Ok(lost_list.into_iter().map(|(_, umsg)| umsg).collect())
+    }
+
+    pub fn causally_stable(&mut self) -> Result<(), CrdtError> {
+        let stable_dots = self.trcb.take_stable_dots();
+        let mut stable_list = message_list::remove_stable_dots(&stable_dots, &mut self.msg_list);
+        for msg in stable_list.iter() {
+            self.msg_bytes -= memory_policy::msg_size(msg);
+        }
+        stable_list.extend(self.spill_store.remove(&stable_dots)?);
+        for msg in stable_list.iter() {
+            State::on_causally_stable(&mut self.crdt_value, msg);
+        }
+        if self.can_finish_epoch_reset() {
+            self.finish_epoch_reset()?;
         }
         Ok(())
     }
 
+    pub fn concurrent_msg_list(&self, msg_vc: &VectorClock, check_value: Option<OpsValue>) -> 
+        Result<Vec<NodeUpdateMsg<OpsValue>>, CrdtError> {
+        let mut clist = message_list::concurrent_msg_list(msg_vc, &self.msg_list, check_value.clone())?;
+        if !self.spill_store.is_empty() {
+            clist.extend(message_list::concurrent_msg_list(msg_vc, &self.spill_store.load_concurrent(msg_vc)?, check_value)?);
+        }
+        Ok(clist)
+    }
+
     pub fn query(&self) -> CrdtValue {
         self.crdt_value.clone()
     }
@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+
+use crate::{LCType, NodeType};
+use crate::crdt::{CRDT, CrdtBehavior};
+use crate::message_data::{DeltaNodeUpdateMsg, NodeUpdateMsg, PeerNodeMsg};
+use crate::vector_clock::VectorClock;
+
+// clocks of the last two delivered operations of one origin
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpClock {
+    pub prev_vc: Option<VectorClock>,
+    pub last_vc: VectorClock
+}
+
+#[derive(Debug)]
+pub struct DeltaClockState {
+    pub enabled: bool,
+    pub op_clock_list: HashMap<NodeType, OpClock>
+}
+
+impl DeltaClockState {
+    pub fn new(enabled: bool, zero_vc: &VectorClock) -> Self {
+        let op_clock_list = zero_vc.vcmap.keys()
+                                         .map(|node| (*node, OpClock{prev_vc: None, last_vc: zero_vc.clone()}))
+                                         .collect();
+        Self{enabled, op_clock_list}
+    }
+
+    pub fn record(&mut self, node: NodeType, vc: &VectorClock) {
+        self.op_clock_list.entry(node)
+                          .and_modify(|op_clock| op_clock.prev_vc = Some(std::mem::replace(&mut op_clock.last_vc, vc.clone())))
+                          .or_insert_with(|| OpClock{prev_vc: None, last_vc: vc.clone()});
+    }
+
+    pub fn base_vc(&self, node: NodeType, lc: LCType) -> Option<&VectorClock> {
+        let op_clock = self.op_clock_list.get(&node)?;
+        let is_base = |vc: &VectorClock| vc.vcmap.get(&node).is_some_and(|blc| blc.checked_add(1) == Some(lc));
+        if is_base(&op_clock.last_vc) {
+            return Some(&op_clock.last_vc);
+        }
+        op_clock.prev_vc.as_ref().filter(|vc| is_base(vc))
+    }
+}
+
+pub fn delta_entry_list(vc: &VectorClock, base_vc: &VectorClock, node: NodeType) -> Vec<(NodeType, LCType)> {
+    let mut entry_list: Vec<(NodeType, LCType)> = vc.vcmap.iter()
+                                                           .filter(|(vnode, lc)| **vnode != node && base_vc.vcmap.get(vnode) != Some(lc))
+                                                           .map(|(vnode, lc)| (*vnode, *lc))
+                                                           .collect();
+    entry_list.sort();
+    entry_list
+}
+
+impl <CrdtValue: Clone+Debug,
+      OpsValue: Clone+PartialEq+Debug+Serialize+DeserializeOwned,
+      State: Debug+CrdtBehavior<CrdtValue, OpsValue>> CRDT<CrdtValue, OpsValue, State> {
+    pub fn set_delta_vc(&mut self, enabled: bool) {
+        self.delta_vc.enabled = enabled;
+    }
+
+    // the base is the origin's previous operation rather than the last message on the link,
+    // so a delta stays decodable after loss or relaying: the receiver only delivers operation
+    // lc of an origin right after lc-1, whose clock it keeps
+    pub fn delta_base_vc(&self, node: NodeType, lc: LCType) -> Option<&VectorClock> {
+        let key = (node, lc.wrapping_sub(1));
+        self.delta_vc.base_vc(node, lc)
+                     .or(self.msg_list.get(&key).map(|msg| &msg.node_vector_clock))
+                     .or(self.spill_store.vc(&key))
+    }
+
+    pub fn outbound_update_msg(&self, msg: NodeUpdateMsg<OpsValue>) -> PeerNodeMsg<OpsValue> {
+        if !self.delta_vc.enabled {
+            return PeerNodeMsg::UpdateNodeMsg(msg);
+        }
+        let lc = match msg.node_vector_clock.vcmap.get(&msg.node) {
+            Some(lc) => *lc,
+            None     => return PeerNodeMsg::UpdateNodeMsg(msg)
+        };
+        match self.delta_base_vc(msg.node, lc) {
+            Some(base_vc) if base_vc.len() == msg.node_vector_clock.len() =>
+                PeerNodeMsg::DeltaUpdateNodeMsg(DeltaNodeUpdateMsg{node: msg.node,
+                                                                   lc,
+                                                                   entry_list: delta_entry_list(&msg.node_vector_clock, base_vc, msg.node),
+                                                                   user_update_msg: msg.user_update_msg,
+                                                                   pred_hash_list: msg.pred_hash_list,
+                                                                   epoch: msg.epoch,
+                                                                   signature: msg.signature}),
+            _                                                             =>
+                PeerNodeMsg::UpdateNodeMsg(msg)
+        }
+    }
+
+    // a delta whose base is unknown cannot be the next operation of its origin, so it is
+    // handed back unchanged and dropped like any duplicate or out of order message
+    pub fn expand_peer_msg(&self, msg: PeerNodeMsg<OpsValue>) -> PeerNodeMsg<OpsValue> {
+        let dmsg = match msg {
+            PeerNodeMsg::DeltaUpdateNodeMsg(dmsg) => dmsg,
+            msg                                   => return msg
+        };
+        let mut node_vector_clock = match self.delta_base_vc(dmsg.node, dmsg.lc) {
+            Some(base_vc) => base_vc.clone(),
+            None          => return PeerNodeMsg::DeltaUpdateNodeMsg(dmsg)
+        };
+        node_vector_clock.vcmap.insert(dmsg.node, dmsg.lc);
+        node_vector_clock.vcmap.extend(dmsg.entry_list);
+        let mut msg = NodeUpdateMsg::new(dmsg.node, node_vector_clock, dmsg.user_update_msg);
+        msg.pred_hash_list = dmsg.pred_hash_list;
+        msg.epoch = dmsg.epoch;
+        msg.signature = dmsg.signature;
+        PeerNodeMsg::UpdateNodeMsg(msg)
+    }
+
+    pub fn record_op_vc(&mut self, msg: &NodeUpdateMsg<OpsValue>) {
+        self.delta_vc.record(msg.node, &msg.node_vector_clock);
+    }
+}
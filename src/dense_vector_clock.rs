@@ -0,0 +1,259 @@
+use std::{cmp::max, cmp::min, collections::HashMap, collections::hash_map::Entry};
+use std::sync::Arc;
+use serde::{Serialize, Deserialize};
+use anyhow::Result;
+
+use crate::{LCType, NodeType};
+use crate::vector_clock::{VectorClock, VCOrdering, VCStatus, INITIAL_LC, INC_LC, cmp_lc, cmp_next_lc, vc_order, peer_vc_status};
+use crate::error::CrdtError;
+
+// an alternative to VectorClock for callers with a large fixed membership; the replicas
+// themselves keep VectorClock, whose map tolerates evicted and readmitted peers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VCSerdeVersion {
+    V1,
+    V2
+}
+
+pub const VC_SERDE_V2: u8 = 2;
+
+#[derive(Debug)]
+pub struct Membership {
+    node_list: Vec<NodeType>,
+    slot_map: HashMap<NodeType, usize>,
+    serde_version: VCSerdeVersion
+}
+
+impl Membership {
+    pub fn new(node_list: Vec<NodeType>) -> Result<Arc<Self>, CrdtError> {
+        Self::new_with_version(node_list, VCSerdeVersion::V1)
+    }
+
+    pub fn new_with_version(node_list: Vec<NodeType>, serde_version: VCSerdeVersion) -> Result<Arc<Self>, CrdtError> {
+        if node_list.is_empty() {
+            return Err(CrdtError::EmptyNodeList);
+        }
+
+        let mut slot_list = Vec::with_capacity(node_list.len());
+        let mut slot_map = HashMap::with_capacity(node_list.len());
+
+        for node in node_list {
+            if let Entry::Vacant(entry) = slot_map.entry(node) {
+                entry.insert(slot_list.len());
+                slot_list.push(node);
+            }
+        }
+
+        Ok(Arc::new(Self{node_list: slot_list, slot_map, serde_version}))
+    }
+
+    pub fn slot(&self, node: &NodeType) -> Option<usize> {
+        self.slot_map.get(node).copied()
+    }
+
+    pub fn node_list(&self) -> &[NodeType] {
+        &self.node_list
+    }
+
+    pub fn len(&self) -> usize {
+        self.node_list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.node_list.is_empty()
+    }
+
+    pub fn serde_version(&self) -> VCSerdeVersion {
+        self.serde_version
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(into = "DenseVectorClockRepr", try_from = "DenseVectorClockRepr")]
+pub struct DenseVectorClock {
+    membership: Arc<Membership>,
+    counters: Vec<LCType>
+}
+
+impl DenseVectorClock {
+    pub fn new(membership: &Arc<Membership>) -> Self {
+        Self{membership: membership.clone(), counters: vec![INITIAL_LC; membership.len()]}
+    }
+
+    pub fn from_vector_clock(membership: &Arc<Membership>, vc: &VectorClock) -> Result<Self, CrdtError> {
+        if vc.len() != membership.len() {
+            return Err(CrdtError::NonCompatibleVC);
+        }
+
+        let mut counters = Vec::with_capacity(membership.len());
+        for node in membership.node_list() {
+            let lc = vc.vcmap.get(node).ok_or(CrdtError::NonCompatibleVC)?;
+            counters.push(*lc);
+        }
+
+        Ok(Self{membership: membership.clone(), counters})
+    }
+
+    pub fn to_vector_clock(&self) -> VectorClock {
+        let vcmap = self.membership.node_list().iter().copied().zip(self.counters.iter().copied()).collect();
+        VectorClock{vcmap}
+    }
+
+    pub fn membership(&self) -> &Arc<Membership> {
+        &self.membership
+    }
+
+    // a decoded clock carries a table of its own; the caller rebinds it to the table its
+    // other clocks use so they share one and compare slot by slot
+    pub fn rebind(&mut self, membership: &Arc<Membership>) -> Result<(), CrdtError> {
+        if !Arc::ptr_eq(&self.membership, membership) {
+            *self = Self::from_vector_clock(membership, &self.to_vector_clock())?;
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.counters.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counters.is_empty()
+    }
+
+    pub fn get(&self, node: &NodeType) -> Option<LCType> {
+        self.membership.slot(node).map(|slot| self.counters[slot])
+    }
+
+    pub fn next_vc(&mut self, node: &NodeType) -> Result<(), CrdtError> {
+        let slot = self.membership.slot(node).ok_or(CrdtError::UnknownNode(*node))?;
+        self.counters[slot] = self.counters[slot].checked_add(INC_LC).ok_or(CrdtError::ClockOverflow(*node))?;
+        Ok(())
+    }
+
+    pub fn is_next_vc(&self, node: &NodeType, peer_vc: &DenseVectorClock) -> Result<VCStatus, CrdtError> {
+        let nlc = self.get(node).ok_or(CrdtError::UnknownNode(*node))?;
+        let plc = peer_vc.get(node).ok_or(CrdtError::UnknownNode(*node))?;
+        let vc_status = cmp_next_lc(nlc, plc);
+        Ok(peer_vc_status(vc_status))
+    }
+
+    pub fn check_deps(&self, node: NodeType, other: &DenseVectorClock) -> Result<bool, CrdtError> {
+        for (onode, olc) in other.membership.node_list().iter().zip(other.counters.iter()) {
+            let lc = self.get(onode).ok_or(CrdtError::NonCompatibleVC)?;
+            if *onode != node && *olc > lc {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    pub fn cmp_vc(&self, other: &DenseVectorClock) -> Result<VCOrdering, CrdtError> {
+        if self.len() != other.len() {
+            return Err(CrdtError::NonCompatibleVC);
+        }
+
+        let mut vcords = VCOrdering::VCEQ;
+        if self.same_layout(other) {
+            for (lc1, lc2) in self.counters.iter().zip(other.counters.iter()) {
+                vcords = vc_order(vcords, cmp_lc(*lc1, *lc2));
+            }
+        } else {
+            for (node, lc1) in self.membership.node_list().iter().zip(self.counters.iter()) {
+                let lc2 = other.get(node).ok_or(CrdtError::NonCompatibleVC)?;
+                vcords = vc_order(vcords, cmp_lc(*lc1, lc2));
+            }
+        }
+
+        Ok(vcords)
+    }
+
+    pub fn check_vc(&self, node: NodeType, other: &DenseVectorClock) -> Result<VCOrdering, CrdtError> {
+        let lc1 = self.get(&node).ok_or(CrdtError::UnknownNode(node))?;
+        let lc2 = other.get(&node).ok_or(CrdtError::UnknownNode(node))?;
+
+        Ok(cmp_next_lc(lc1, lc2))
+    }
+
+    pub fn min_max_vc(&self, other: &DenseVectorClock, f: fn(LCType, LCType) -> LCType) -> Result<DenseVectorClock, CrdtError> {
+        let mut counters = Vec::with_capacity(self.len());
+        if self.same_layout(other) {
+            for (lc1, lc2) in self.counters.iter().zip(other.counters.iter()) {
+                counters.push(f(*lc1, *lc2));
+            }
+        } else {
+            for (node, lc1) in self.membership.node_list().iter().zip(self.counters.iter()) {
+                let lc2 = other.get(node).ok_or(CrdtError::NonCompatibleVC)?;
+                counters.push(f(*lc1, lc2));
+            }
+        }
+
+        Ok(DenseVectorClock{membership: self.membership.clone(), counters})
+    }
+
+    pub fn max_vc(&self, other: &DenseVectorClock) -> Result<DenseVectorClock, CrdtError> {
+        self.min_max_vc(other, max)
+    }
+
+    pub fn min_vc(&self, other: &DenseVectorClock) -> Result<DenseVectorClock, CrdtError> {
+        self.min_max_vc(other, min)
+    }
+
+    fn same_layout(&self, other: &DenseVectorClock) -> bool {
+        Arc::ptr_eq(&self.membership, &other.membership) ||
+            self.membership.node_list() == other.membership.node_list()
+    }
+}
+
+// V1 is the `VectorClock` encoding, so both representations stay wire compatible;
+// V2 sends the membership order once and the counters as a plain array.
+#[derive(Serialize, Deserialize)]
+struct DenseVectorClockRepr {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    version: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    vcmap: Option<HashMap<NodeType, LCType>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    nodes: Option<Vec<NodeType>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    counters: Option<Vec<LCType>>
+}
+
+impl From<DenseVectorClock> for DenseVectorClockRepr {
+    fn from(dvc: DenseVectorClock) -> Self {
+        match dvc.membership.serde_version() {
+            VCSerdeVersion::V1 => DenseVectorClockRepr{version: None,
+                                                       vcmap: Some(dvc.to_vector_clock().vcmap),
+                                                       nodes: None,
+                                                       counters: None},
+            VCSerdeVersion::V2 => DenseVectorClockRepr{version: Some(VC_SERDE_V2),
+                                                       vcmap: None,
+                                                       nodes: Some(dvc.membership.node_list().to_vec()),
+                                                       counters: Some(dvc.counters)}
+        }
+    }
+}
+
+impl TryFrom<DenseVectorClockRepr> for DenseVectorClock {
+    type Error = String;
+
+    fn try_from(repr: DenseVectorClockRepr) -> Result<Self, Self::Error> {
+        match repr {
+            DenseVectorClockRepr{version: Some(VC_SERDE_V2), nodes: Some(nodes), counters: Some(counters), ..} => {
+                let membership = Membership::new_with_version(nodes, VCSerdeVersion::V2).map_err(|e| format!("{:?}", e))?;
+                if membership.len() != counters.len() {
+                    return Err(format!("dense vector clock has {} nodes and {} counters",
+                                       membership.len(), counters.len()));
+                }
+                Ok(DenseVectorClock{membership, counters})
+            },
+            DenseVectorClockRepr{version: None, vcmap: Some(vcmap), ..} => {
+                let mut node_list: Vec<NodeType> = vcmap.keys().copied().collect();
+                node_list.sort();
+                let membership = Membership::new_with_version(node_list, VCSerdeVersion::V1).map_err(|e| format!("{:?}", e))?;
+                DenseVectorClock::from_vector_clock(&membership, &VectorClock{vcmap})
+                    .map_err(|e| format!("{:?}", e))
+            },
+            DenseVectorClockRepr{version, ..} => Err(format!("unsupported dense vector clock version {:?}", version))
+        }
+    }
+}
@@ -4,11 +4,12 @@ use anyhow::Result;
 
 use crate::NodeType;
 
-use crate::crdt::CRDT;
-use crate::{EDFlagCrdtValue, EDFlagOpsValue};
+use crate::crdt::{CRDT, CrdtBehavior, CrdtType};
+use crate::anti_entropy;
+use crate::{EpochType, EDFlagCrdtValue, EDFlagOpsValue};
 use crate::message_data::{NodeUpdateMsg, PeerNodeMsg, OpsInstance, SDPOpsType};
-use crate::vector_clock::{VCStatus, VectorClockError};
-use crate::message_list;
+use crate::vector_clock::{VCStatus, VectorClock};
+use crate::error::CrdtError;
 
 #[derive(Debug)]
 pub struct EWFlag;
@@ -21,41 +22,97 @@ pub enum EDFlag {
     Disabled
 }
 
+impl CrdtBehavior<EDFlagCrdtValue, EDFlagOpsValue> for EWFlag {
+    const CRDT_TYPE: CrdtType = CrdtType::EWFlagCrdt;
+
+    // the flag goes back to its previous value, enabling winning over concurrent disables
+    fn inverse_ops(crdt_value: &EDFlag, ops_instance: &OpsInstance<EDFlagOpsValue>) -> 
+        Result<Option<OpsInstance<EDFlagOpsValue>>, CrdtError> {
+        match (crdt_value, *crdt_value == ops_instance.ops_value) {
+            (_, true)                 => Ok(None),
+            (EDFlag::Enabled, false)  => Ok(Some(OpsInstance::new(SDPOpsType::SDPMult, EDFlag::Enabled))),
+            (EDFlag::Disabled, false) => Ok(Some(OpsInstance::new(SDPOpsType::SDPAdd, EDFlag::Disabled)))
+        }
+    }
+}
+
+impl CrdtBehavior<EDFlagCrdtValue, EDFlagOpsValue> for DWFlag {
+    const CRDT_TYPE: CrdtType = CrdtType::DWFlagCrdt;
+
+    fn inverse_ops(crdt_value: &EDFlag, ops_instance: &OpsInstance<EDFlagOpsValue>) -> 
+        Result<Option<OpsInstance<EDFlagOpsValue>>, CrdtError> {
+        match (crdt_value, *crdt_value == ops_instance.ops_value) {
+            (_, true)                 => Ok(None),
+            (EDFlag::Disabled, false) => Ok(Some(OpsInstance::new(SDPOpsType::SDPMult, EDFlag::Disabled))),
+            (EDFlag::Enabled, false)  => Ok(Some(OpsInstance::new(SDPOpsType::SDPAdd, EDFlag::Enabled)))
+        }
+    }
+}
+
 impl CRDT<EDFlagCrdtValue, EDFlagOpsValue, EWFlag> {
     pub fn process_local_msg(&mut self, msg: NodeUpdateMsg<EDFlagOpsValue>) -> 
-        Result<HashMap<NodeType, Vec<PeerNodeMsg<EDFlagOpsValue>>>, VectorClockError> {
-        self.process_msg(&msg)?;
+        Result<HashMap<NodeType, Vec<PeerNodeMsg<EDFlagOpsValue>>>, CrdtError> {
+        self.check_local_msg(&msg)?;
+        let undo = self.prepare_undo(&msg)?;
+        self.process_msg_with(&msg, Self::process_msg)?;
+        self.record_undo(undo);
         self.general_process_local_msg(msg)
     }
 
+    pub fn undo(&mut self, msg: &NodeUpdateMsg<EDFlagOpsValue>) -> 
+        Result<HashMap<NodeType, Vec<PeerNodeMsg<EDFlagOpsValue>>>, CrdtError> {
+        self.undo_with(msg, Self::process_local_msg)
+    }
+
+    pub fn redo(&mut self, msg: &NodeUpdateMsg<EDFlagOpsValue>) -> 
+        Result<HashMap<NodeType, Vec<PeerNodeMsg<EDFlagOpsValue>>>, CrdtError> {
+        self.redo_with(msg, Self::process_local_msg)
+    }
+
     pub fn process_peer_msg(&mut self, pmsg_list: Vec<PeerNodeMsg<EDFlagOpsValue>>) ->
-        Result<HashMap<NodeType, Vec<PeerNodeMsg<EDFlagOpsValue>>>, VectorClockError> {
+        Result<HashMap<NodeType, Vec<PeerNodeMsg<EDFlagOpsValue>>>, CrdtError> {
+        let mut ctrl_msg_map = HashMap::new();
         for msg in pmsg_list {
+            let msg = match self.accept_peer_msg(msg)? {
+                Some(msg) => msg,
+                None      => continue
+            };
             match msg {
                 PeerNodeMsg::VectorClockNodeMsg(vmsg) =>  
                     self.general_process_vc_msg(vmsg)?,
                 PeerNodeMsg::UpdateNodeMsg(umsg)   =>  
                     {   let vc_status = self.general_process_peer_msg(umsg.clone())?;
                         if vc_status == VCStatus::INORDER {
-                                                            self.process_msg(&umsg)?
+                                                            self.process_msg_with(&umsg, Self::process_msg)?
                                                           }
                     }
+                cmsg                                  =>
+                    self.general_process_ctrl_msg(cmsg, &mut ctrl_msg_map)?
             }
         }
         self.causally_stable()?;
-        let msg_list = self.create_peer_msg_list(false)?;
-        if msg_list.len() > 0 {
+        let mut msg_list = self.create_peer_msg_list(false)?;
+        if !msg_list.is_empty() {
             self.msg_count_vc = 0;
         }
+        anti_entropy::merge_peer_msg_map(&mut msg_list, ctrl_msg_map);
         Ok(msg_list)
     } 
 
-    pub fn process_msg(&mut self, msg: &NodeUpdateMsg<EDFlag>) -> Result<(), VectorClockError> {
+    pub fn query_at(&self, vc: &VectorClock) -> Result<EDFlagCrdtValue, CrdtError> {
+        self.query_at_with(self.get_epoch(), vc, Self::process_msg)
+    }
+
+    pub fn query_at_epoch(&self, epoch: EpochType, vc: &VectorClock) -> Result<EDFlagCrdtValue, CrdtError> {
+        self.query_at_with(epoch, vc, Self::process_msg)
+    }
+
+    pub fn process_msg(&mut self, msg: &NodeUpdateMsg<EDFlag>) -> Result<(), CrdtError> {
         match msg.user_update_msg.ops_instance.ops_type {
             SDPOpsType::SDPAdd  =>      {   let clist 
-                                                = message_list::concurrent_msg_list(&msg.node_vector_clock, 
-                                                    &self.msg_list, self.get_option_value())?;
-                                            if clist.len() == 0 {
+                                                = self.concurrent_msg_list(&msg.node_vector_clock, 
+                                                    self.get_option_value())?;
+                                            if clist.is_empty() {
                                                 self.crdt_value = msg.user_update_msg.ops_instance.ops_value.clone();
                                             }
                                         },
@@ -79,39 +136,68 @@ impl CRDT<EDFlagCrdtValue, EDFlagOpsValue, EWFlag> {
 
 impl CRDT<EDFlagCrdtValue, EDFlagOpsValue, DWFlag> {
     pub fn process_local_msg(&mut self, msg: NodeUpdateMsg<EDFlagOpsValue>) -> 
-        Result<HashMap<NodeType, Vec<PeerNodeMsg<EDFlagOpsValue>>>, VectorClockError> {
-        self.process_msg(&msg)?;
+        Result<HashMap<NodeType, Vec<PeerNodeMsg<EDFlagOpsValue>>>, CrdtError> {
+        self.check_local_msg(&msg)?;
+        let undo = self.prepare_undo(&msg)?;
+        self.process_msg_with(&msg, Self::process_msg)?;
+        self.record_undo(undo);
         self.general_process_local_msg(msg)
     }
 
+    pub fn undo(&mut self, msg: &NodeUpdateMsg<EDFlagOpsValue>) -> 
+        Result<HashMap<NodeType, Vec<PeerNodeMsg<EDFlagOpsValue>>>, CrdtError> {
+        self.undo_with(msg, Self::process_local_msg)
+    }
+
+    pub fn redo(&mut self, msg: &NodeUpdateMsg<EDFlagOpsValue>) -> 
+        Result<HashMap<NodeType, Vec<PeerNodeMsg<EDFlagOpsValue>>>, CrdtError> {
+        self.redo_with(msg, Self::process_local_msg)
+    }
+
     pub fn process_peer_msg(&mut self, pmsg_list: Vec<PeerNodeMsg<EDFlagOpsValue>>) ->
-        Result<HashMap<NodeType, Vec<PeerNodeMsg<EDFlagOpsValue>>>, VectorClockError> {
+        Result<HashMap<NodeType, Vec<PeerNodeMsg<EDFlagOpsValue>>>, CrdtError> {
+        let mut ctrl_msg_map = HashMap::new();
         for msg in pmsg_list {
+            let msg = match self.accept_peer_msg(msg)? {
+                Some(msg) => msg,
+                None      => continue
+            };
             match msg {
                 PeerNodeMsg::VectorClockNodeMsg(vmsg) =>  
                     self.general_process_vc_msg(vmsg)?,
                 PeerNodeMsg::UpdateNodeMsg(umsg)   =>  
                     {   let vc_status = self.general_process_peer_msg(umsg.clone())?;
                         if vc_status == VCStatus::INORDER {
-                                                            self.process_msg(&umsg)?
+                                                            self.process_msg_with(&umsg, Self::process_msg)?
                                                           }
                     }
+                cmsg                                  =>
+                    self.general_process_ctrl_msg(cmsg, &mut ctrl_msg_map)?
             }
         }
         self.causally_stable()?;
-        let msg_list = self.create_peer_msg_list(false)?;
-        if msg_list.len() > 0 {
+        let mut msg_list = self.create_peer_msg_list(false)?;
+        if !msg_list.is_empty() {
             self.msg_count_vc = 0;
         }
+        anti_entropy::merge_peer_msg_map(&mut msg_list, ctrl_msg_map);
         Ok(msg_list)
     }
 
-    pub fn process_msg(&mut self, msg: &NodeUpdateMsg<EDFlag>) -> Result<(), VectorClockError>{
+    pub fn query_at(&self, vc: &VectorClock) -> Result<EDFlagCrdtValue, CrdtError> {
+        self.query_at_with(self.get_epoch(), vc, Self::process_msg)
+    }
+
+    pub fn query_at_epoch(&self, epoch: EpochType, vc: &VectorClock) -> Result<EDFlagCrdtValue, CrdtError> {
+        self.query_at_with(epoch, vc, Self::process_msg)
+    }
+
+    pub fn process_msg(&mut self, msg: &NodeUpdateMsg<EDFlag>) -> Result<(), CrdtError>{
         match msg.user_update_msg.ops_instance.ops_type {
             SDPOpsType::SDPAdd  =>  {   let clist 
-                                            = message_list::concurrent_msg_list(&msg.node_vector_clock, 
-                                                    &self.msg_list, self.get_option_value())?;
-                                        if clist.len() == 0 {
+                                            = self.concurrent_msg_list(&msg.node_vector_clock, 
+                                                    self.get_option_value())?;
+                                        if clist.is_empty() {
                                             self.crdt_value = msg.user_update_msg.ops_instance.ops_value.clone();
                                         }
                                    },
@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use anyhow::Result;
+
+use crate::{EpochType, LCType, NodeType};
+use crate::crdt::{CRDT, CrdtBehavior};
+use crate::delta_vc::DeltaClockState;
+use crate::message_list;
+use crate::message_data::{NodeUpdateMsg, PeerNodeMsg};
+use crate::error::CrdtError;
+
+// clocks restart from zero in the next epoch once every operation of the current one is
+// stable everywhere; a node closing the epoch stops creating operations and says so in
+// its clock messages, which makes its own entry final, and peers hearing it do the same
+#[derive(Debug)]
+pub struct EpochState {
+    pub epoch: EpochType,
+    pub reset_lc: LCType,
+    pub reset_pending: bool,
+    pub final_lc_list: HashMap<NodeType, LCType>,
+    pub timeout_ms: u64,
+    pub pending_since: Option<u64>
+}
+
+impl EpochState {
+    pub fn new(reset_lc: LCType, timeout_ms: u64) -> Self {
+        Self{epoch: 0, reset_lc, reset_pending: false, final_lc_list: HashMap::new(), timeout_ms, pending_since: None}
+    }
+
+    pub fn reset(&mut self, epoch: EpochType) {
+        self.epoch = epoch;
+        self.reset_pending = false;
+        self.final_lc_list.clear();
+        self.pending_since = None;
+    }
+}
+
+// merkle messages carry no epoch; one crossing a reset only costs a useless repair round
+pub fn peer_msg_epoch<OpsValue: Clone+PartialEq>(msg: &PeerNodeMsg<OpsValue>) -> Option<EpochType> {
+    match msg {
+        PeerNodeMsg::VectorClockNodeMsg(vmsg) |
+        PeerNodeMsg::DigestRequestMsg(vmsg)   |
+        PeerNodeMsg::DigestReplyMsg(vmsg)     |
+        PeerNodeMsg::DigestAckMsg(vmsg)       => Some(vmsg.epoch),
+        PeerNodeMsg::UpdateNodeMsg(umsg)      => Some(umsg.epoch),
+        PeerNodeMsg::DeltaUpdateNodeMsg(dmsg) => Some(dmsg.epoch),
+        PeerNodeMsg::MerkleRequestMsg(_)      |
+        PeerNodeMsg::MerkleReplyMsg(_)        => None
+    }
+}
+
+impl <CrdtValue: Clone+Debug,
+      OpsValue: Clone+PartialEq+Debug+Serialize+DeserializeOwned,
+      State: Debug+CrdtBehavior<CrdtValue, OpsValue>> CRDT<CrdtValue, OpsValue, State> {
+    pub fn set_epoch_reset_lc(&mut self, reset_lc: LCType) {
+        self.epoch.reset_lc = reset_lc;
+    }
+
+    pub fn set_epoch_reset_timeout(&mut self, timeout_ms: u64) {
+        self.epoch.timeout_ms = timeout_ms;
+    }
+
+    pub fn get_epoch(&self) -> EpochType {
+        self.epoch.epoch
+    }
+
+    pub fn is_epoch_reset_pending(&self) -> bool {
+        self.epoch.reset_pending
+    }
+
+    // local operations are refused from here on; the reset itself happens in
+    // causally_stable once the condition holds
+    pub fn start_epoch_reset(&mut self) -> Result<(), CrdtError> {
+        self.epoch.reset_pending = true;
+        self.causally_stable()
+    }
+
+    pub fn check_epoch_reset_lc(&mut self) -> Result<(), CrdtError> {
+        let node = self.get_node();
+        let lc = *self.trcb.node_vector_clock.vcmap.get(&node).ok_or(CrdtError::UnknownNode(node))?;
+        if self.epoch.reset_lc > 0 && lc >= self.epoch.reset_lc && !self.epoch.reset_pending {
+            self.start_epoch_reset()?;
+        }
+        Ok(())
+    }
+
+    pub fn check_local_epoch(&self) -> Result<(), CrdtError> {
+        match self.epoch.reset_pending {
+            true  => Err(CrdtError::EpochResetPending(self.epoch.epoch)),
+            false => Ok(())
+        }
+    }
+
+    // a message of an older epoch is a duplicate, since the reset waited for every operation
+    // to be delivered everywhere; one of the next epoch means its sender saw the same, so a
+    // node that is closing the epoch can follow at once
+    pub fn accept_peer_epoch(&mut self, msg: PeerNodeMsg<OpsValue>) -> Result<Option<PeerNodeMsg<OpsValue>>, CrdtError> {
+        let peer_epoch = match peer_msg_epoch(&msg) {
+            Some(peer_epoch) => peer_epoch,
+            None             => return Ok(Some(msg))
+        };
+        if peer_epoch < self.epoch.epoch {
+            return Ok(None);
+        }
+        if peer_epoch > self.epoch.epoch {
+            // a delta of the next epoch that could not be expanded carries no authenticated clock
+            if matches!(msg, PeerNodeMsg::DeltaUpdateNodeMsg(_)) {
+                return Ok(None);
+            }
+            if !self.epoch.reset_pending || peer_epoch != self.epoch.epoch+1 {
+                self.reject_peer_msg(CrdtError::EpochMismatch(self.epoch.epoch, peer_epoch));
+                return Ok(None);
+            }
+            self.finish_epoch_reset()?;
+        }
+        if let PeerNodeMsg::VectorClockNodeMsg(vmsg) = &msg {
+            if vmsg.reset_pending {
+                let final_lc = *vmsg.node_vector_clock.vcmap.get(&vmsg.node).ok_or(CrdtError::UnknownNode(vmsg.node))?;
+                self.epoch.final_lc_list.insert(vmsg.node, final_lc);
+                self.epoch.reset_pending = true;
+            }
+        }
+        Ok(Some(msg))
+    }
+
+    // every live peer has announced its final entry and all operations up to those entries
+    // are stable, so no operation of this epoch is missing anywhere
+    pub fn can_finish_epoch_reset(&self) -> bool {
+        self.epoch.reset_pending &&
+        self.trcb.node_vector_clock.vcmap == self.trcb.stable_vector_clock.vcmap &&
+        self.epoch_reset_blocker_list().is_empty()
+    }
+
+    // the live peers that have not announced a final entry, that hold back operations up to
+    // it, or that have not yet acknowledged every operation delivered here
+    pub fn epoch_reset_blocker_list(&self) -> Vec<NodeType> {
+        let node_vcmap = &self.trcb.node_vector_clock.vcmap;
+        let is_blocker = |pnode: &&NodeType| match (self.epoch.final_lc_list.get(pnode), node_vcmap.get(pnode), self.trcb.node_trcb.get(pnode)) {
+            (Some(final_lc), Some(lc), Some(pvc)) => lc < final_lc || node_vcmap.iter().any(|(node, lc)| pvc.vcmap.get(node).is_none_or(|plc| plc < lc)),
+            _                                     => true
+        };
+        let mut blocker_list: Vec<NodeType> = self.trcb.node_trcb.keys()
+                                                                 .filter(|pnode| !self.trcb.is_evicted(pnode))
+                                                                 .filter(is_blocker)
+                                                                 .copied()
+                                                                 .collect();
+        blocker_list.sort();
+        blocker_list
+    }
+
+    // a silent peer would hold the reset, and with it every local operation, forever; like
+    // the failure detector the wait is stamped at the first check that sees it. the evicted
+    // peers rejoin through a state transfer
+    pub fn check_epoch_reset_timeout(&mut self, now: u64) -> Result<Vec<NodeType>, CrdtError> {
+        if !self.epoch.reset_pending || self.epoch.timeout_ms == 0 {
+            return Ok(Vec::new());
+        }
+        let pending_since = *self.epoch.pending_since.get_or_insert(now);
+        if now.saturating_sub(pending_since) < self.epoch.timeout_ms {
+            return Ok(Vec::new());
+        }
+        let blocker_list = self.epoch_reset_blocker_list();
+        for pnode in blocker_list.iter() {
+            self.evict_peer(*pnode)?;
+        }
+        Ok(blocker_list)
+    }
+
+    pub fn finish_epoch_reset(&mut self) -> Result<(), CrdtError> {
+        let old_value = self.watched_value();
+        let mut stable_list: Vec<NodeUpdateMsg<OpsValue>> = self.all_msg_list()?.into_values().collect();
+        message_list::causal_sort(&mut stable_list);
+        for msg in stable_list.iter() {
+            State::on_causally_stable(&mut self.crdt_value, msg);
+        }
+        self.msg_list.clear();
+        self.msg_bytes = 0;
+        self.spill_store.clear()?;
+        self.trcb.reset_epoch();
+        self.delta_vc = DeltaClockState::new(self.delta_vc.enabled, &self.trcb.node_vector_clock);
+        self.hash_chain.last_hash_list.clear();
+        self.repair_limiter.reset();
+        self.msg_count_vc = 0;
+        self.epoch.reset(self.epoch.epoch+1);
+        self.notify_replaced_value(old_value, self.get_node());
+        Ok(())
+    }
+}
@@ -0,0 +1,74 @@
+use std::fmt;
+
+use crate::{EpochType, LCType, NodeType};
+use crate::crdt::CrdtInstance;
+
+#[derive(Debug, Clone)]
+pub enum CrdtError {
+    EmptyNodeList,
+    UnknownNode(NodeType),
+    UnknownNodeId(String),
+    NodeIdConflict(String, NodeType),
+    UnknownInstance(CrdtInstance),
+    NonCompatibleVC,
+    InconsistentInputTRBC(NodeType, Vec<NodeType>),
+    StaleMessage(NodeType, LCType),
+    Overflow(&'static str),
+    ClockOverflow(NodeType),
+    EpochResetPending(EpochType),
+    EpochMismatch(EpochType, EpochType),
+    NoUndoEntry(NodeType, LCType),
+    UndoUnsupported(&'static str),
+    HistoryUnavailable,
+    MsgListFull(usize, usize),
+    SpillError(String),
+    EncodeError(String),
+    DecodeError(String),
+    UnsupportedVersion(u16),
+    AuthRejected(NodeType),
+    MisroutedMsg(&'static str),
+    ActorStopped(NodeType),
+    TransportError(String),
+    RemoteError(String),
+    StorageError(String),
+    NotFound(String),
+    ConfigError(String, String)
+}
+
+impl fmt::Display for CrdtError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CrdtError::EmptyNodeList                          => write!(f, "empty node list"),
+            CrdtError::UnknownNode(node)                      => write!(f, "unknown node {}", node),
+            CrdtError::UnknownNodeId(id)                      => write!(f, "unknown node id {}", id),
+            CrdtError::NodeIdConflict(id, node)               => write!(f, "node id {} or node {} already mapped elsewhere", id, node),
+            CrdtError::UnknownInstance(instance)              => write!(f, "update for {:?} instance {} of node {} sent to another crdt type",
+                                                                        instance.instance_type, instance.instance_num, instance.instance_node_id),
+            CrdtError::NonCompatibleVC                        => write!(f, "vector clocks cover different nodes"),
+            CrdtError::InconsistentInputTRBC(node, node_list) => write!(f, "node {} not in node list {:?}", node, node_list),
+            CrdtError::StaleMessage(node, lc)                 => write!(f, "message {} of node {} already processed or out of turn", lc, node),
+            CrdtError::Overflow(what)                         => write!(f, "{} overflow", what),
+            CrdtError::ClockOverflow(node)                    => write!(f, "logical clock of node {} overflowed", node),
+            CrdtError::EpochResetPending(epoch)               => write!(f, "epoch {} is closing, no new operations until the reset", epoch),
+            CrdtError::EpochMismatch(epoch, peer_epoch)       => write!(f, "peer in epoch {} while this node is in epoch {}", peer_epoch, epoch),
+            CrdtError::NoUndoEntry(node, lc)                  => write!(f, "no undo history for operation {} of node {}", lc, node),
+            CrdtError::UndoUnsupported(what)                  => write!(f, "{} cannot be undone", what),
+            CrdtError::HistoryUnavailable                     => write!(f, "history off or not reaching back to that clock"),
+            CrdtError::MsgListFull(count, bytes)              => write!(f, "message list full with {} messages of {} bytes", count, bytes),
+            CrdtError::SpillError(e)                          => write!(f, "spill store: {}", e),
+            CrdtError::EncodeError(e)                         => write!(f, "encode: {}", e),
+            CrdtError::DecodeError(e)                         => write!(f, "decode: {}", e),
+            CrdtError::UnsupportedVersion(version)            => write!(f, "unsupported protocol version {}", version),
+            CrdtError::AuthRejected(node)                     => write!(f, "message claiming node {} failed authentication", node),
+            CrdtError::MisroutedMsg(kind)                     => write!(f, "{} routed to the wrong handler", kind),
+            CrdtError::ActorStopped(node)                     => write!(f, "replica actor of node {} stopped", node),
+            CrdtError::TransportError(e)                      => write!(f, "transport: {}", e),
+            CrdtError::RemoteError(e)                         => write!(f, "remote: {}", e),
+            CrdtError::StorageError(e)                        => write!(f, "storage: {}", e),
+            CrdtError::NotFound(what)                         => write!(f, "{} not found", what),
+            CrdtError::ConfigError(param, value)              => write!(f, "invalid value {:?} for {}", value, param)
+        }
+    }
+}
+
+impl std::error::Error for CrdtError {}
@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+use anyhow::Result;
+
+use crate::NodeType;
+use crate::error::CrdtError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum PeerStatus {
+    Alive,
+    Suspected,
+    Evicted
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EvictionPolicy {
+    Manual,
+    AfterSuspectedMs(u64)
+}
+
+#[derive(Debug, Clone)]
+struct PeerState {
+    status: PeerStatus,
+    heard: bool,
+    last_heard: Option<u64>,
+    suspected_at: u64
+}
+
+#[derive(Debug)]
+pub struct FailureDetector {
+    pub suspect_timeout_ms: u64,
+    pub eviction_policy: EvictionPolicy,
+    peer_list: HashMap<NodeType, PeerState>
+}
+
+impl FailureDetector {
+    pub fn new(peer_list: Vec<NodeType>, suspect_timeout_ms: u64, eviction_policy: EvictionPolicy) -> Self {
+        let peer_list = peer_list.into_iter()
+                                 .map(|peer| (peer, PeerState{status: PeerStatus::Alive, heard: false, last_heard: None, suspected_at: 0}))
+                                 .collect();
+        Self{suspect_timeout_ms, eviction_policy, peer_list}
+    }
+
+    pub fn heartbeat(&mut self, peer: NodeType) {
+        if let Some(pstate) = self.peer_list.get_mut(&peer) {
+            pstate.heard = true;
+        }
+    }
+
+    // heartbeats are stamped with the time of the check that observes them,
+    // so detection granularity is the interval between calls to check
+    pub fn check(&mut self, now: u64) -> Vec<(NodeType, PeerStatus)> {
+        let mut status_list = Vec::new();
+
+        for (peer, pstate) in self.peer_list.iter_mut() {
+            let old_status = pstate.status;
+            let last_heard = *pstate.last_heard.get_or_insert(now);
+
+            if pstate.heard {
+                pstate.heard = false;
+                pstate.last_heard = Some(now);
+                if pstate.status == PeerStatus::Suspected {
+                    pstate.status = PeerStatus::Alive;
+                }
+            } else if pstate.status == PeerStatus::Alive && now.saturating_sub(last_heard) > self.suspect_timeout_ms {
+                pstate.status = PeerStatus::Suspected;
+                pstate.suspected_at = now;
+            }
+
+            if let (PeerStatus::Suspected, EvictionPolicy::AfterSuspectedMs(evict_ms)) = (pstate.status, self.eviction_policy) {
+                if now.saturating_sub(pstate.suspected_at) >= evict_ms {
+                    pstate.status = PeerStatus::Evicted;
+                }
+            }
+
+            if pstate.status != old_status {
+                status_list.push((*peer, pstate.status));
+            }
+        }
+
+        status_list
+    }
+
+    pub fn evict(&mut self, peer: NodeType) -> Result<(), CrdtError> {
+        let pstate = self.peer_list.get_mut(&peer).ok_or(CrdtError::UnknownNode(peer))?;
+        pstate.status = PeerStatus::Evicted;
+        Ok(())
+    }
+
+    pub fn readmit(&mut self, peer: NodeType) -> Result<(), CrdtError> {
+        let pstate = self.peer_list.get_mut(&peer).ok_or(CrdtError::UnknownNode(peer))?;
+        pstate.status = PeerStatus::Alive;
+        pstate.heard = false;
+        pstate.last_heard = None;
+        Ok(())
+    }
+
+    pub fn reset(&mut self) {
+        for pstate in self.peer_list.values_mut() {
+            pstate.status = PeerStatus::Alive;
+            pstate.heard = false;
+            pstate.last_heard = None;
+        }
+    }
+
+    pub fn status(&self, peer: &NodeType) -> Option<PeerStatus> {
+        self.peer_list.get(peer).map(|pstate| pstate.status)
+    }
+
+    pub fn status_list(&self) -> HashMap<NodeType, PeerStatus> {
+        self.peer_list.iter().map(|(peer, pstate)| (*peer, pstate.status)).collect()
+    }
+}
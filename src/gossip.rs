@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::str::FromStr;
+use rand::SeedableRng;
+use rand::rngs::SmallRng;
+use rand::seq::SliceRandom;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use strum::EnumString;
+use anyhow::Result;
+
+use crate::NodeType;
+use crate::crdt::{CRDT, CrdtBehavior};
+use crate::message_data::PeerNodeMsg;
+use crate::scheduler::TickTask;
+use crate::error::CrdtError;
+use crate::constants::{env, GOSSIP_FANOUT, GOSSIP_PERIOD_MS, GOSSIP_MODE};
+
+#[derive(Debug, Clone, Copy, PartialEq, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum GossipMode {
+    Random,
+    RoundRobin
+}
+
+#[derive(Debug, Clone)]
+pub struct GossipConfig {
+    pub fanout: usize,
+    pub period_ms: u64,
+    pub mode: GossipMode
+}
+
+impl GossipConfig {
+    pub fn new(fanout: usize, period_ms: u64, mode: GossipMode) -> Self {
+        Self{fanout, period_ms, mode}
+    }
+
+    pub fn from_env() -> Result<Option<Self>, CrdtError> {
+        let mode = GossipMode::from_str(&GOSSIP_MODE)
+                    .map_err(|_| CrdtError::ConfigError(env::GOSSIP_MODE_VAR.to_owned(), GOSSIP_MODE.to_owned()))?;
+        match *GOSSIP_FANOUT {
+            0      => Ok(None),
+            fanout => Ok(Some(Self::new(fanout as usize, *GOSSIP_PERIOD_MS, mode)))
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct GossipState {
+    pub config: GossipConfig,
+    cursor: usize,
+    rng: SmallRng
+}
+
+impl GossipState {
+    pub fn new(config: GossipConfig) -> Self {
+        Self{config, cursor: 0, rng: SmallRng::from_entropy()}
+    }
+
+    pub fn new_with_seed(config: GossipConfig, seed: u64) -> Self {
+        Self{config, cursor: 0, rng: SmallRng::seed_from_u64(seed)}
+    }
+
+    // round robin walks a sorted peer list so every peer is contacted at least
+    // once every ceil(peers/fanout) rounds; random mode only does so with high probability
+    pub fn select_peers(&mut self, mut peer_list: Vec<NodeType>) -> Vec<NodeType> {
+        peer_list.sort();
+        let fanout = self.config.fanout.min(peer_list.len());
+        match self.config.mode {
+            GossipMode::Random     => {
+                peer_list.shuffle(&mut self.rng);
+                peer_list.truncate(fanout);
+                peer_list
+            },
+            GossipMode::RoundRobin => {
+                if peer_list.is_empty() {
+                    return peer_list;
+                }
+                let start = self.cursor % peer_list.len();
+                self.cursor = (start + fanout) % peer_list.len();
+                peer_list.iter().cycle().skip(start).take(fanout).copied().collect()
+            }
+        }
+    }
+}
+
+impl <CrdtValue: Clone+Debug,
+      OpsValue: Clone+PartialEq+Debug+Serialize+DeserializeOwned,
+      State: Debug+CrdtBehavior<CrdtValue, OpsValue>> CRDT<CrdtValue, OpsValue, State> {
+    pub fn set_gossip(&mut self, gossip: Option<GossipState>) {
+        if let Some(gossip) = gossip.as_ref() {
+            self.scheduler.set_interval(TickTask::Repair, gossip.config.period_ms);
+        }
+        self.gossip = gossip;
+    }
+
+    // each selected peer gets what we believe it lacks, including operations we hold
+    // for third parties, followed by a digest request so its reply repairs our own gaps
+    // and its ack corrects our estimate of what it holds
+    pub fn create_gossip_msg_list(&mut self) ->
+        Result<HashMap<NodeType, Vec<PeerNodeMsg<OpsValue>>>, CrdtError> {
+        let peer_list: Vec<NodeType> = self.trcb.node_trcb.keys()
+                                                          .filter(|pnode| !self.trcb.is_evicted(pnode))
+                                                          .copied()
+                                                          .collect();
+        let peer_list = match self.gossip.as_mut() {
+            Some(gossip) => gossip.select_peers(peer_list),
+            None         => peer_list
+        };
+
+        let spill_list = self.spill_store.load()?;
+        let mut msg_map = HashMap::new();
+        for pnode in peer_list {
+            let pvc = self.trcb.node_trcb.get(&pnode).ok_or(CrdtError::UnknownNode(pnode))?;
+            let missing_list = self.missing_msg_list(pnode, pvc, &spill_list)?;
+            let mut msg_list: Vec<PeerNodeMsg<OpsValue>> = self.repair_msg_list(pnode, missing_list, false)?
+                                                               .into_iter()
+                                                               .map(|msg| self.outbound_update_msg(msg))
+                                                               .collect();
+            msg_list.push(PeerNodeMsg::DigestRequestMsg(self.create_vc_msg()));
+            msg_map.insert(pnode, msg_list);
+        }
+        Ok(msg_map)
+    }
+}
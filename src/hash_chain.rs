@@ -0,0 +1,178 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+use sha2::{Digest, Sha256};
+use anyhow::Result;
+
+use crate::{LCType, NodeType};
+use crate::auth::update_signed_bytes;
+use crate::crdt::{CRDT, CrdtBehavior};
+use crate::delta_vc::delta_entry_list;
+use crate::message_data::{NodeUpdateMsg, OpHash, PredHash};
+use crate::vector_clock::VectorClock;
+use crate::error::CrdtError;
+
+// the hash covers the predecessor hashes, so it pins the whole causal history of the operation
+pub fn op_hash<OpsValue: Clone+PartialEq+Serialize>(msg: &NodeUpdateMsg<OpsValue>) -> Result<OpHash, CrdtError> {
+    Ok(Sha256::digest(update_signed_bytes(msg)?).into())
+}
+
+// two versions of one operation; witness is the operation whose predecessor hash disagreed
+// with the local version, none when the other version itself arrived; signed when both
+// versions carry the signature of their origin
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EquivocationReport {
+    pub node: NodeType,
+    pub lc: LCType,
+    pub local_hash: OpHash,
+    pub peer_hash: OpHash,
+    pub witness: Option<(NodeType, LCType)>,
+    pub signed: bool
+}
+
+// an operation, the witness and whether the report was signed; a signed report of an
+// operation first reported unsigned still goes out since only it proves the equivocation
+pub type ReportKey = (NodeType, LCType, Option<(NodeType, LCType)>, bool);
+
+#[derive(Debug)]
+pub struct HashChainState {
+    pub enabled: bool,
+    pub last_hash_list: HashMap<NodeType, (LCType, OpHash)>,
+    pub report_list: Vec<EquivocationReport>,
+    pub reported_set: HashSet<ReportKey>,
+    pub equivocator_set: HashSet<NodeType>,
+    pub suspect_set: HashSet<NodeType>
+}
+
+impl HashChainState {
+    pub fn new(enabled: bool) -> Self {
+        Self{enabled, last_hash_list: HashMap::new(), report_list: Vec::new(), reported_set: HashSet::new(),
+             equivocator_set: HashSet::new(), suspect_set: HashSet::new()}
+    }
+
+    // only two signed versions prove the origin equivocated; a witness naming another hash
+    // may be lying itself, and an unsigned version may have been forged on the way, so those
+    // leave suspects until the conflicting version is fetched
+    fn report(&mut self, report: EquivocationReport) {
+        // the report list is drained by the caller, so a redelivered version is checked here
+        if !self.reported_set.insert((report.node, report.lc, report.witness, report.signed)) {
+            return;
+        }
+        match (report.witness, report.signed) {
+            (None, true)              => {
+                self.equivocator_set.insert(report.node);
+            },
+            (Some((wnode, _)), _)     => {
+                self.suspect_set.insert(report.node);
+                self.suspect_set.insert(wnode);
+            },
+            (None, false)             => {
+                self.suspect_set.insert(report.node);
+            }
+        }
+        self.report_list.push(report);
+    }
+}
+
+impl <CrdtValue: Clone+Debug,
+      OpsValue: Clone+PartialEq+Debug+Serialize+DeserializeOwned,
+      State: Debug+CrdtBehavior<CrdtValue, OpsValue>> CRDT<CrdtValue, OpsValue, State> {
+    pub fn set_hash_chain(&mut self, enabled: bool) {
+        self.hash_chain.enabled = enabled;
+    }
+
+    // retained operations are hashed on demand; once an operation is stable only the latest
+    // of its origin is remembered, and a reference to an older one cannot be checked
+    pub fn known_op_hash(&self, node: NodeType, lc: LCType) -> Result<Option<OpHash>, CrdtError> {
+        if let Some(msg) = self.msg_list.get(&(node, lc)) {
+            return Ok(Some(op_hash(msg)?));
+        }
+        if let Some(msg) = self.spill_store.get::<OpsValue>(&(node, lc))? {
+            return Ok(Some(op_hash(&msg)?));
+        }
+        Ok(self.hash_chain.last_hash_list.get(&node).filter(|(last_lc, _)| *last_lc == lc).map(|(_, hash)| *hash))
+    }
+
+    // the origin's previous operation and the operations it covers for the first time;
+    // everything else in the causal past is reachable through their hashes
+    pub fn pred_hash_list(&self, node: NodeType, vc: &VectorClock) -> Result<Vec<PredHash>, CrdtError> {
+        if !self.hash_chain.enabled {
+            return Ok(Vec::new());
+        }
+        let lc = *vc.vcmap.get(&node).ok_or(CrdtError::UnknownNode(node))?;
+        let mut entry_list = match self.delta_base_vc(node, lc) {
+            Some(base_vc) => delta_entry_list(vc, base_vc, node),
+            None          => delta_entry_list(vc, &VectorClock{vcmap: HashMap::new()}, node)
+        };
+        if lc > 1 {
+            entry_list.push((node, lc-1));
+        }
+
+        let mut pred_hash_list = Vec::new();
+        for (pnode, plc) in entry_list {
+            if let Some(hash) = self.known_op_hash(pnode, plc)? {
+                pred_hash_list.push((pnode, plc, hash));
+            }
+        }
+        Ok(pred_hash_list)
+    }
+
+    pub fn record_op_hash(&mut self, msg: &NodeUpdateMsg<OpsValue>) -> Result<(), CrdtError> {
+        if self.hash_chain.enabled {
+            let lc = *msg.node_vector_clock.vcmap.get(&msg.node).ok_or(CrdtError::UnknownNode(msg.node))?;
+            self.hash_chain.last_hash_list.insert(msg.node, (lc, op_hash(msg)?));
+        }
+        Ok(())
+    }
+
+    // called on delivery, before the operation is added to msg_list
+    pub fn check_pred_hash(&mut self, msg: &NodeUpdateMsg<OpsValue>) -> Result<(), CrdtError> {
+        if !self.hash_chain.enabled {
+            return Ok(());
+        }
+        let lc = *msg.node_vector_clock.vcmap.get(&msg.node).ok_or(CrdtError::UnknownNode(msg.node))?;
+        for (pnode, plc, peer_hash) in msg.pred_hash_list.iter() {
+            match self.known_op_hash(*pnode, *plc)? {
+                Some(local_hash) if local_hash != *peer_hash =>
+                    self.hash_chain.report(EquivocationReport{node: *pnode, lc: *plc, local_hash, peer_hash: *peer_hash,
+                                                              witness: Some((msg.node, lc)), signed: false}),
+                _                                            => ()
+            }
+        }
+        self.record_op_hash(msg)
+    }
+
+    // a duplicate delivered during anti-entropy must be the operation already held; with
+    // authentication on, both versions were verified against the origin key on arrival
+    pub fn check_duplicate_op(&mut self, msg: &NodeUpdateMsg<OpsValue>) -> Result<(), CrdtError> {
+        if !self.hash_chain.enabled {
+            return Ok(());
+        }
+        let lc = *msg.node_vector_clock.vcmap.get(&msg.node).ok_or(CrdtError::UnknownNode(msg.node))?;
+        if let Some(local_hash) = self.known_op_hash(msg.node, lc)? {
+            let peer_hash = op_hash(msg)?;
+            if local_hash != peer_hash {
+                self.hash_chain.report(EquivocationReport{node: msg.node, lc, local_hash, peer_hash, witness: None,
+                                                          signed: self.auth.is_some()});
+            }
+        }
+        Ok(())
+    }
+
+    pub fn take_equivocation_list(&mut self) -> Vec<EquivocationReport> {
+        std::mem::take(&mut self.hash_chain.report_list)
+    }
+
+    pub fn equivocator_list(&self) -> Vec<NodeType> {
+        let mut node_list: Vec<NodeType> = self.hash_chain.equivocator_set.iter().copied().collect();
+        node_list.sort();
+        node_list
+    }
+
+    pub fn suspect_list(&self) -> Vec<NodeType> {
+        let mut node_list: Vec<NodeType> = self.hash_chain.suspect_set.difference(&self.hash_chain.equivocator_set).copied().collect();
+        node_list.sort();
+        node_list
+    }
+}
@@ -0,0 +1,113 @@
+use std::cmp::Ordering;
+use std::fmt::Debug;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use anyhow::Result;
+
+use crate::{EpochType, LCType, NodeType};
+use crate::crdt::{CRDT, CrdtBehavior};
+use crate::message_data::{NodeUpdateMsg, StateTransferMsg};
+use crate::vector_clock::{VectorClock, VCOrdering};
+use crate::error::CrdtError;
+
+pub type ProcessMsgFn<CrdtValue, OpsValue, State> =
+    fn(&mut CRDT<CrdtValue, OpsValue, State>, &NodeUpdateMsg<OpsValue>) -> Result<(), CrdtError>;
+
+// every delivered operation in delivery order, which is causal order, on top of a base
+// value: the initial one, or the one a state transfer brought along with the donor's clock
+#[derive(Debug)]
+pub struct HistoryState<CrdtValue: Clone, OpsValue: Clone+PartialEq> {
+    pub enabled: bool,
+    pub base_value: CrdtValue,
+    pub base_epoch: EpochType,
+    pub base_vc: Option<VectorClock>,
+    pub base_msg_list: Vec<NodeUpdateMsg<OpsValue>>,
+    pub op_list: Vec<NodeUpdateMsg<OpsValue>>
+}
+
+impl <CrdtValue: Clone, OpsValue: Clone+PartialEq> HistoryState<CrdtValue, OpsValue> {
+    pub fn new(enabled: bool, base_value: CrdtValue) -> Self {
+        Self{enabled, base_value, base_epoch: 0, base_vc: None, base_msg_list: Vec::new(), op_list: Vec::new()}
+    }
+
+    pub fn rebase(&mut self, msg: &StateTransferMsg<CrdtValue, OpsValue>) {
+        self.base_value = msg.crdt_value.clone();
+        self.base_epoch = msg.epoch;
+        self.base_vc = Some(msg.node_vector_clock.clone());
+        self.base_msg_list = msg.msg_list.clone();
+        self.op_list.clear();
+    }
+
+    // a clock the base already covers in part cannot be replayed from it
+    pub fn reaches(&self, epoch: EpochType, vc: &VectorClock) -> Result<bool, CrdtError> {
+        match (&self.base_vc, epoch.cmp(&self.base_epoch)) {
+            (None, _)                        => Ok(true),
+            (Some(_), Ordering::Less)        => Ok(false),
+            (Some(_), Ordering::Greater)     => Ok(true),
+            (Some(base_vc), Ordering::Equal) => is_covered(base_vc, vc)
+        }
+    }
+}
+
+pub fn is_covered(msg_vc: &VectorClock, vc: &VectorClock) -> Result<bool, CrdtError> {
+    Ok(matches!(msg_vc.cmp_vc(vc)?, VCOrdering::VCLE | VCOrdering::VCEQ))
+}
+
+impl <CrdtValue: Clone+Debug,
+      OpsValue: Clone+PartialEq+Debug+Serialize+DeserializeOwned,
+      State: Debug+CrdtBehavior<CrdtValue, OpsValue>> CRDT<CrdtValue, OpsValue, State> {
+    // turning history on later only covers operations delivered from then on
+    pub fn set_history(&mut self, enabled: bool) -> Result<(), CrdtError> {
+        if enabled && !self.history.enabled {
+            self.history.base_msg_list = self.all_msg_list()?.into_values().collect();
+            self.history.base_value = self.crdt_value.clone();
+            self.history.base_epoch = self.epoch.epoch;
+            self.history.base_vc = Some(self.trcb.node_vector_clock.clone());
+            self.history.op_list.clear();
+        }
+        self.history.enabled = enabled;
+        Ok(())
+    }
+
+    pub fn record_history(&mut self, msg: &NodeUpdateMsg<OpsValue>) {
+        if self.history.enabled {
+            self.history.op_list.push(msg.clone());
+        }
+    }
+
+    pub fn history_len(&self) -> usize {
+        self.history.op_list.len()
+    }
+
+    // operations of earlier epochs were all stable before the reset, so none of them is
+    // concurrent with an operation of a later one
+    pub fn query_at_with(&self, epoch: EpochType, vc: &VectorClock, process_msg: ProcessMsgFn<CrdtValue, OpsValue, State>) ->
+        Result<CrdtValue, CrdtError> {
+        if !self.history.enabled || !self.history.reaches(epoch, vc)? {
+            return Err(CrdtError::HistoryUnavailable);
+        }
+        let node_list: Vec<NodeType> = self.trcb.node_vector_clock.vcmap.keys().copied().collect();
+        let mut replica = Self::new_bare(self.get_node(), node_list, self.history.base_value.clone())?;
+        replica.msg_list = self.history.base_msg_list.iter()
+                                                     .map(|msg| Ok((msg_key(msg)?, msg.clone())))
+                                                     .collect::<Result<_, CrdtError>>()?;
+        let mut replica_epoch = self.history.base_epoch;
+        for msg in self.history.op_list.iter() {
+            if msg.epoch > epoch || (msg.epoch == epoch && !is_covered(&msg.node_vector_clock, vc)?) {
+                continue;
+            }
+            if msg.epoch != replica_epoch {
+                replica.msg_list.clear();
+                replica_epoch = msg.epoch;
+            }
+            process_msg(&mut replica, msg)?;
+            replica.msg_list.insert(msg_key(msg)?, msg.clone());
+        }
+        Ok(replica.crdt_value)
+    }
+}
+
+fn msg_key<OpsValue: Clone+PartialEq>(msg: &NodeUpdateMsg<OpsValue>) -> Result<(NodeType, LCType), CrdtError> {
+    let lc = msg.node_vector_clock.vcmap.get(&msg.node).ok_or(CrdtError::UnknownNode(msg.node))?;
+    Ok((msg.node, *lc))
+}
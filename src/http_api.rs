@@ -0,0 +1,440 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt::Debug;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use anyhow::Result;
+use dotenvy::dotenv;
+
+use crate::{CRDTNumType, NodeType};
+use crate::cli::dump;
+use crate::constants::{check_env, NODE_LIST};
+use crate::crdt::{CRDT, CrdtBehavior, CrdtInstance, CrdtType};
+use crate::message_data::{OpsInstance, PeerMsgMap, PeerNodeMsg, SDPOpsType, UserUpdateMsg, sorted_msg_list};
+use crate::node_instance::NodeInstance;
+use crate::node_state::NodeState;
+use crate::replica::Replica;
+use crate::error::CrdtError;
+
+pub const USAGE: &str = "usage:
+  http_server --addr <host:port> [--nodes 0,1,2]
+
+routes:
+  GET  /cluster                   nodes and a summary of every instance
+  GET  /instances                 instance numbers and crdt types
+  POST /instances                 {\"crdt_type\": \"AWSetCrdt\"}
+  GET  /instances/<num>[?node=n]  values on every node, or on node n
+  POST /instances/<num>/ops       {\"node\": 0, \"ops_type\": \"SDPMult\", \"value\": 3}
+  GET  /instances/<num>/state     clocks and message lists on every node
+
+every instance is a cluster of replicas inside this process, kept in memory only; the
+nodes are not reachable over the network, use the ops_crdt_rust start command for that";
+
+pub const MAX_BODY_BYTES: usize = 1 << 20;
+pub const MAX_LINE_BYTES: usize = 8 << 10;
+pub const MAX_HEADER_COUNT: usize = 100;
+pub const READ_TIMEOUT_MS: u64 = 5000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpRequest {
+    pub method: String,
+    pub path: String,
+    pub query: HashMap<String, String>,
+    pub body: String
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub body: Value
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct CreateInstanceReq {
+    pub crdt_type: CrdtType
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ApplyOpsReq {
+    pub node: NodeType,
+    pub ops_type: SDPOpsType,
+    pub value: Value
+}
+
+enum InstanceRoute {
+    Value(Option<NodeType>),
+    Ops(ApplyOpsReq),
+    State,
+    Summary
+}
+
+impl HttpResponse {
+    pub fn new(status: u16, body: Value) -> Self {
+        Self{status, body}
+    }
+
+    pub fn from_error(e: &CrdtError) -> Self {
+        Self{status: error_status(e), body: json!({"error": e.to_string()})}
+    }
+}
+
+pub fn error_status(e: &CrdtError) -> u16 {
+    match e {
+        CrdtError::NotFound(_) | CrdtError::UnknownNode(_)       => 404,
+        CrdtError::DecodeError(_) | CrdtError::ConfigError(_, _) => 400,
+        CrdtError::EncodeError(_) | CrdtError::SpillError(_) |
+        CrdtError::StorageError(_)                               => 500,
+        _                                                        => 409
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        400 => "Bad Request",
+        404 => "Not Found",
+        409 => "Conflict",
+        _   => "Internal Server Error"
+    }
+}
+
+// one instance is a whole cluster of replicas living in this process; its nodes exchange
+// messages by direct delivery and are lost when the server stops
+#[derive(Debug)]
+pub struct ApiInstance {
+    pub crdt_instance: CrdtInstance,
+    pub node_state: NodeState
+}
+
+#[derive(Debug)]
+pub struct ApiState {
+    pub node_list: Vec<NodeType>,
+    pub instance_list: BTreeMap<CRDTNumType, ApiInstance>
+}
+
+impl ApiState {
+    pub fn new(node_list: Vec<NodeType>) -> Result<Self, CrdtError> {
+        if node_list.is_empty() {
+            return Err(CrdtError::EmptyNodeList);
+        }
+        Ok(Self{node_list, instance_list: BTreeMap::new()})
+    }
+
+    pub fn handle(&mut self, request: &HttpRequest) -> HttpResponse {
+        match self.route(request) {
+            Ok(response) => response,
+            Err(e)       => HttpResponse::from_error(&e)
+        }
+    }
+
+    fn route(&mut self, request: &HttpRequest) -> Result<HttpResponse, CrdtError> {
+        let segment_list: Vec<&str> = request.path.split('/').filter(|segment| !segment.is_empty()).collect();
+        let ok = |body| Ok(HttpResponse::new(200, body));
+        match (request.method.as_str(), segment_list.as_slice()) {
+            ("GET", ["cluster"])                 => ok(self.cluster()?),
+            ("GET", ["instances"])               => ok(json!(self.instance_list.iter()
+                                                                   .map(|(num, instance)| instance_json(*num, instance))
+                                                                   .collect::<Vec<_>>())),
+            ("POST", ["instances"])              => {
+                let create_req: CreateInstanceReq = decode_body(&request.body)?;
+                let instance_num = self.create_instance(create_req.crdt_type)?;
+                Ok(HttpResponse::new(201, instance_json(instance_num, &self.instance_list[&instance_num])))
+            }
+            ("GET", ["instances", num])          => {
+                let node = match request.query.get("node") {
+                               Some(node) => Some(node.parse::<NodeType>()
+                                                      .map_err(|_| CrdtError::ConfigError("node".to_owned(), node.clone()))?),
+                               None       => None
+                           };
+                ok(self.handle_instance(num, InstanceRoute::Value(node))?)
+            }
+            ("POST", ["instances", num, "ops"])  => ok(self.handle_instance(num, InstanceRoute::Ops(decode_body(&request.body)?))?),
+            ("GET", ["instances", num, "state"]) => ok(self.handle_instance(num, InstanceRoute::State)?),
+            _                                    => Err(CrdtError::NotFound(format!("route {} {}", request.method, request.path)))
+        }
+    }
+
+    pub fn create_instance(&mut self, crdt_type: CrdtType) -> Result<CRDTNumType, CrdtError> {
+        let instance_num = match self.instance_list.last_key_value() {
+                               Some((num, _)) => num.checked_add(1).ok_or(CrdtError::Overflow("instance number"))?,
+                               None           => 0
+                           };
+        let node_state = NodeState::new_with_node_list(self.node_list.clone())?;
+        self.instance_list.insert(instance_num, ApiInstance{crdt_instance: CrdtInstance::new(0, instance_num, crdt_type), node_state});
+        Ok(instance_num)
+    }
+
+    pub fn cluster(&mut self) -> Result<Value, CrdtError> {
+        let num_list: Vec<CRDTNumType> = self.instance_list.keys().copied().collect();
+        let mut summary_list = Vec::new();
+        for num in num_list {
+            summary_list.push(self.handle_instance(&num.to_string(), InstanceRoute::Summary)?);
+        }
+        Ok(json!({"node_list": self.node_list, "instance_list": summary_list}))
+    }
+
+    fn handle_instance(&mut self, num: &str, route: InstanceRoute) -> Result<Value, CrdtError> {
+        let not_found = || CrdtError::NotFound(format!("instance {}", num));
+        let instance_num = num.parse::<CRDTNumType>().map_err(|_| not_found())?;
+        let instance = self.instance_list.get_mut(&instance_num).ok_or_else(not_found)?;
+        let crdt_instance = instance.crdt_instance.clone();
+        let ns = &mut instance.node_state;
+        match crdt_instance.instance_type {
+            CrdtType::AddMultCrdt   => handle_instance(ns, |ni| &mut ni.add_mult_crdt, crdt_instance, route),
+            CrdtType::EWFlagCrdt    => handle_instance(ns, |ni| &mut ni.ewflag_crdt, crdt_instance, route),
+            CrdtType::DWFlagCrdt    => handle_instance(ns, |ni| &mut ni.dwflag_crdt, crdt_instance, route),
+            CrdtType::AWSetCrdt     => handle_instance(ns, |ni| &mut ni.awset_crdt, crdt_instance, route),
+            CrdtType::RWSetCrdt     => handle_instance(ns, |ni| &mut ni.rwset_crdt, crdt_instance, route),
+            CrdtType::PNCounterCrdt => handle_instance(ns, |ni| &mut ni.pncnt_crdt, crdt_instance, route)
+        }
+    }
+}
+
+fn instance_json(instance_num: CRDTNumType, instance: &ApiInstance) -> Value {
+    json!({"instance_num": instance_num, "crdt_type": instance.crdt_instance.instance_type})
+}
+
+fn decode_body<T: DeserializeOwned>(body: &str) -> Result<T, CrdtError> {
+    serde_json::from_str(body).map_err(|e| CrdtError::DecodeError(e.to_string()))
+}
+
+fn encode<T: Serialize>(value: &T) -> Result<Value, CrdtError> {
+    serde_json::to_value(value).map_err(|e| CrdtError::EncodeError(e.to_string()))
+}
+
+fn handle_instance<CrdtValue: Clone+Debug+PartialEq+Serialize,
+                   OpsValue: Clone+PartialEq+Debug+Serialize+DeserializeOwned,
+                   State: Debug+CrdtBehavior<CrdtValue, OpsValue>,
+                   Select: Fn(&mut NodeInstance) -> &mut CRDT<CrdtValue, OpsValue, State>>(node_state: &mut NodeState,
+                                                                                           select: Select,
+                                                                                           crdt_instance: CrdtInstance,
+                                                                                           route: InstanceRoute) -> Result<Value, CrdtError>
+    where CRDT<CrdtValue, OpsValue, State>: Replica<Value = CrdtValue, Ops = OpsValue> {
+    match route {
+        InstanceRoute::Value(Some(node)) => {
+            let value = select(node_state.get_node_instance_mut(node)?).value();
+            Ok(json!({"node": node, "value": encode(&value)?}))
+        }
+        InstanceRoute::Value(None)       => {
+            let (converged, value_list) = value_list(node_state, &select)?;
+            Ok(json!({"converged": converged, "value_list": value_list}))
+        }
+        InstanceRoute::Ops(ops_req)      => {
+            let ops_value: OpsValue = serde_json::from_value(ops_req.value).map_err(|e| CrdtError::DecodeError(e.to_string()))?;
+            let umsg = UserUpdateMsg::new(crdt_instance, OpsInstance::new(ops_req.ops_type, ops_value));
+            let msg_map = select(node_state.get_node_instance_mut(ops_req.node)?).update(umsg)?;
+            // the operation is applied once update returns, so a failed delivery is reported
+            // alongside the value rather than as an error
+            let delivery_error_list = deliver(node_state, &select, msg_map);
+            let value = select(node_state.get_node_instance_mut(ops_req.node)?).value();
+            Ok(json!({"node": ops_req.node, "value": encode(&value)?, "delivery_error_list": delivery_error_list}))
+        }
+        InstanceRoute::State             => {
+            let mut state_list = BTreeMap::new();
+            for (node, ni) in node_state.node_instance_list.iter_mut() {
+                state_list.insert(*node, dump(select(ni))?);
+            }
+            Ok(json!(state_list))
+        }
+        InstanceRoute::Summary           => {
+            let (converged, _) = value_list(node_state, &select)?;
+            let mut vc_list = BTreeMap::new();
+            let mut msg_count = 0;
+            for (node, ni) in node_state.node_instance_list.iter_mut() {
+                let crdt = select(ni);
+                vc_list.insert(*node, encode(&crdt.trcb.node_vector_clock)?);
+                msg_count += crdt.all_msg_list()?.len();
+            }
+            Ok(json!({"instance_num": crdt_instance.instance_num,
+                      "crdt_type": crdt_instance.instance_type,
+                      "converged": converged,
+                      "msg_count": msg_count,
+                      "node_vector_clock_list": vc_list}))
+        }
+    }
+}
+
+fn value_list<CrdtValue: Clone+Debug+PartialEq+Serialize,
+              OpsValue: Clone+PartialEq+Debug+Serialize+DeserializeOwned,
+              State: Debug+CrdtBehavior<CrdtValue, OpsValue>,
+              Select: Fn(&mut NodeInstance) -> &mut CRDT<CrdtValue, OpsValue, State>>(node_state: &mut NodeState, select: &Select) ->
+    Result<(bool, BTreeMap<NodeType, Value>), CrdtError>
+    where CRDT<CrdtValue, OpsValue, State>: Replica<Value = CrdtValue, Ops = OpsValue> {
+    let mut crdt_value_list = Vec::new();
+    let mut value_list = BTreeMap::new();
+    for (node, ni) in node_state.node_instance_list.iter_mut() {
+        let value = select(ni).value();
+        value_list.insert(*node, encode(&value)?);
+        crdt_value_list.push(value);
+    }
+    // compared as values, since set json comes out in any order
+    let converged = crdt_value_list.windows(2).all(|pair| pair[0] == pair[1]);
+    Ok((converged, value_list))
+}
+
+// the replicas share the process, so peer messages are handed over at once until none are left
+fn deliver<CrdtValue: Clone+Debug,
+           OpsValue: Clone+PartialEq+Debug+Serialize+DeserializeOwned,
+           State: Debug+CrdtBehavior<CrdtValue, OpsValue>,
+           Select: Fn(&mut NodeInstance) -> &mut CRDT<CrdtValue, OpsValue, State>>(node_state: &mut NodeState,
+                                                                                   select: &Select,
+                                                                                   msg_map: PeerMsgMap<OpsValue>) -> Vec<String>
+    where CRDT<CrdtValue, OpsValue, State>: Replica<Value = CrdtValue, Ops = OpsValue> {
+    let mut msg_queue: VecDeque<(NodeType, Vec<PeerNodeMsg<OpsValue>>)> = sorted_msg_list(msg_map).into();
+    let mut error_list = Vec::new();
+    while let Some((pnode, pmsg_list)) = msg_queue.pop_front() {
+        let replica = match node_state.get_node_instance_mut(pnode) {
+                          Ok(ni) => select(ni),
+                          Err(e) => {
+                              error_list.push(format!("node {}: {}", pnode, e));
+                              continue;
+                          }
+                      };
+        let result = replica.peer_update(pmsg_list);
+        error_list.extend(replica.take_rejection_list().iter().map(|e| format!("node {}: {}", pnode, e)));
+        match result {
+            Ok(msg_map) => msg_queue.extend(sorted_msg_list(msg_map)),
+            Err(e)      => error_list.push(format!("node {}: {}", pnode, e))
+        }
+    }
+    error_list
+}
+
+// reads at most MAX_LINE_BYTES, so a client can not grow a line without end
+fn read_line<R: BufRead>(reader: &mut R) -> Result<String, CrdtError> {
+    let mut line = String::new();
+    reader.take(MAX_LINE_BYTES as u64 + 1).read_line(&mut line).map_err(|e| CrdtError::TransportError(e.to_string()))?;
+    match line.len() > MAX_LINE_BYTES {
+        true  => Err(CrdtError::DecodeError(format!("line over {} bytes", MAX_LINE_BYTES))),
+        false => Ok(line)
+    }
+}
+
+pub fn read_request<R: BufRead>(reader: &mut R) -> Result<HttpRequest, CrdtError> {
+    let line = read_line(reader)?;
+    let mut part_list = line.split_whitespace();
+    let (method, target) = match (part_list.next(), part_list.next()) {
+                               (Some(method), Some(target)) => (method.to_owned(), target.to_owned()),
+                               _                            => return Err(CrdtError::DecodeError(format!("request line {:?}", line.trim_end())))
+                           };
+
+    let mut content_len = 0;
+    for header_count in 0.. {
+        let line = read_line(reader)?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if header_count >= MAX_HEADER_COUNT {
+            return Err(CrdtError::DecodeError(format!("over {} headers", MAX_HEADER_COUNT)));
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_len = value.trim().parse::<usize>().map_err(|_| CrdtError::DecodeError(format!("content-length {}", value.trim())))?;
+            }
+        }
+    }
+    if content_len > MAX_BODY_BYTES {
+        return Err(CrdtError::DecodeError(format!("body of {} bytes over {}", content_len, MAX_BODY_BYTES)));
+    }
+    let mut body = vec![0; content_len];
+    reader.read_exact(&mut body).map_err(|e| CrdtError::TransportError(e.to_string()))?;
+    let body = String::from_utf8(body).map_err(|e| CrdtError::DecodeError(e.to_string()))?;
+
+    let (path, query) = match target.split_once('?') {
+                            Some((path, query)) => (path.to_owned(), query),
+                            None                => (target.clone(), "")
+                        };
+    let query = query.split('&')
+                     .filter_map(|pair| pair.split_once('='))
+                     .map(|(name, value)| (name.to_owned(), value.to_owned()))
+                     .collect();
+    Ok(HttpRequest{method, path, query, body})
+}
+
+pub fn write_response<W: Write>(writer: &mut W, response: &HttpResponse) -> Result<(), CrdtError> {
+    let body = response.body.to_string();
+    write!(writer, "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+           response.status, reason(response.status), body.len(), body).map_err(|e| CrdtError::TransportError(e.to_string()))
+}
+
+// one request per connection; requests are served one at a time against the shared state
+pub struct ApiServer {
+    pub state: Mutex<ApiState>
+}
+
+impl ApiServer {
+    pub fn new(node_list: Vec<NodeType>) -> Result<Self, CrdtError> {
+        Ok(Self{state: Mutex::new(ApiState::new(node_list)?)})
+    }
+
+    pub fn handle(&self, request: &HttpRequest) -> HttpResponse {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).handle(request)
+    }
+
+    pub fn serve(self: Arc<Self>, listener: TcpListener) -> Result<(), CrdtError> {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(_)     => continue
+            };
+            let server = self.clone();
+            thread::spawn(move || server.serve_stream(stream));
+        }
+        Ok(())
+    }
+
+    // a client that stops sending gets dropped after READ_TIMEOUT_MS instead of holding a thread
+    fn serve_stream(&self, stream: TcpStream) {
+        let timeout = Some(Duration::from_millis(READ_TIMEOUT_MS));
+        if stream.set_read_timeout(timeout).and_then(|_| stream.set_write_timeout(timeout)).is_err() {
+            return;
+        }
+        let mut writer = match stream.try_clone() {
+            Ok(writer) => writer,
+            Err(_)     => return
+        };
+        let response = match read_request(&mut BufReader::new(stream)) {
+                           Ok(request) => self.handle(&request),
+                           Err(e)      => HttpResponse::from_error(&e)
+                       };
+        let _ = write_response(&mut writer, &response);
+    }
+}
+
+pub fn parse_node_list(value: &str) -> Result<Vec<NodeType>, CrdtError> {
+    value.split(',')
+         .map(|node| node.trim().parse::<NodeType>().map_err(|_| CrdtError::ConfigError("--nodes".to_owned(), value.to_owned())))
+         .collect()
+}
+
+pub fn run(args: &[String]) -> Result<(), CrdtError> {
+    let mut addr = None;
+    let mut node_list = None;
+    for pair in args.chunks(2) {
+        match pair {
+            [flag, value] if flag == "--addr"  => addr = Some(value.clone()),
+            [flag, value] if flag == "--nodes" => node_list = Some(parse_node_list(value)?),
+            _                                  => return Err(CrdtError::ConfigError("argument".to_owned(), pair.join(" ")))
+        }
+    }
+    let addr = addr.ok_or(CrdtError::ConfigError("--addr".to_owned(), "".to_owned()))?;
+    let node_list = match node_list {
+                        Some(node_list) => node_list,
+                        None            => {
+                            dotenv().ok();
+                            check_env()?;
+                            NODE_LIST.to_owned()
+                        }
+                    };
+    let listener = TcpListener::bind(&addr).map_err(|e| CrdtError::TransportError(format!("{}: {}", addr, e)))?;
+    let server = Arc::new(ApiServer::new(node_list)?);
+    println!("http api for nodes {:?} listening on {}", server.state.lock().unwrap_or_else(|e| e.into_inner()).node_list, addr);
+    server.serve(listener)
+}
@@ -1,6 +1,10 @@
 use edflag_crdt::EDFlag;
 
+#[cfg(not(feature = "lc64"))]
 pub type LCType            = u32;
+#[cfg(feature = "lc64")]
+pub type LCType            = u64;
+pub type EpochType         = u32;
 pub type NodeType          = u16; //must implement Copy trait
 pub type CRDTNumType       = u16;
 
@@ -11,8 +15,12 @@ pub type EDFlagOpsValue    = EDFlag;
 pub type PNCntOpsValue     = u32;
 pub type ARSetOpsValue     = i32;
 
+pub mod error;
+
 pub mod vector_clock;
 
+pub mod dense_vector_clock;
+
 pub mod trcb;
 
 pub mod message_data;
@@ -21,10 +29,52 @@ pub mod message_list;
 
 pub mod anti_entropy;
 
+pub mod failure_detector;
+
+pub mod memory_policy;
+
+pub mod gossip;
+
+pub mod scheduler;
+
+pub mod repair_limit;
+
+pub mod merkle;
+
+pub mod wire;
+
+pub mod delta_vc;
+
+pub mod protocol;
+
+pub mod auth;
+
+pub mod hash_chain;
+
+pub mod epoch;
+
+pub mod undo;
+
+pub mod history;
+
+pub mod subscription;
+
+pub mod replica;
+
+#[cfg(feature = "tokio")]
+pub mod actor;
+
+pub mod node_id;
+
 pub mod node_state;
 
 pub mod node_instance;
 
+pub mod cli;
+
+#[cfg(feature = "http")]
+pub mod http_api;
+
 pub mod crdt;
 
 pub mod add_mult_crdt;
@@ -47,6 +97,10 @@ pub mod rand_awset;
 
 pub mod rand_pncnt;
 
+pub mod rand_gossip;
+
+pub mod simulation;
+
 
 
 
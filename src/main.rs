@@ -1,13 +1,17 @@
+use ops_crdt_rust::cli;
+use ops_crdt_rust::error::CrdtError;
+
 fn main() {
-    println!("\n\n\n");
-    ops_crdt_rust::rand_add_mult::test_random();
-    println!("\n\n");
-    ops_crdt_rust::rand_ewflag::test_random();
-    println!("\n\n");
-    ops_crdt_rust::rand_pncnt::test_random();
-    println!("\n\n");
-    ops_crdt_rust::rand_awset::test_random();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match cli::run(&args) {
+        Ok(())                              => (),
+        Err(e @ CrdtError::ConfigError(..)) => {
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        }
+        Err(e)                              => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
 }
-
-
-
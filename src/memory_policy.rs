@@ -0,0 +1,391 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+use strum::EnumString;
+use anyhow::Result;
+
+use crate::{LCType, NodeType};
+use crate::crdt::{CRDT, CrdtBehavior, CrdtInstance};
+use crate::message_data::NodeUpdateMsg;
+use crate::vector_clock::{VectorClock, VCOrdering, lc_u64};
+use crate::constants::{env, MSG_LIST_MAX_COUNT, MSG_LIST_MAX_BYTES, MSG_LIST_OVERFLOW_ACTION, MSG_LIST_SPILL_DIR};
+use crate::error::CrdtError;
+
+#[derive(Debug, Clone, Copy, PartialEq, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum OverflowAction {
+    RejectLocal,
+    SpillToDisk,
+    StateTransfer
+}
+
+#[derive(Debug, Clone)]
+pub struct MemoryPolicy {
+    pub max_msg_count: Option<usize>,
+    pub max_msg_bytes: Option<usize>,
+    pub overflow_action: OverflowAction,
+    pub spill_dir: PathBuf
+}
+
+impl MemoryPolicy {
+    pub fn new(max_msg_count: Option<usize>, max_msg_bytes: Option<usize>, overflow_action: OverflowAction, spill_dir: PathBuf) -> Self {
+        Self{max_msg_count, max_msg_bytes, overflow_action, spill_dir}
+    }
+
+    pub fn from_env() -> Result<Self, CrdtError> {
+        let overflow_action = OverflowAction::from_str(&MSG_LIST_OVERFLOW_ACTION)
+                                .map_err(|_| CrdtError::ConfigError(env::MSG_LIST_OVERFLOW_ACTION_VAR.to_owned(), MSG_LIST_OVERFLOW_ACTION.to_owned()))?;
+        Ok(Self{max_msg_count: limit(*MSG_LIST_MAX_COUNT),
+                max_msg_bytes: limit(*MSG_LIST_MAX_BYTES),
+                overflow_action,
+                spill_dir: PathBuf::from(MSG_LIST_SPILL_DIR.as_str())})
+    }
+
+    pub fn is_over(&self, msg_count: usize, msg_bytes: usize) -> bool {
+        self.max_msg_count.is_some_and(|max_count| msg_count > max_count) ||
+            self.max_msg_bytes.is_some_and(|max_bytes| msg_bytes > max_bytes)
+    }
+
+    pub fn fill_pct(&self, msg_count: usize, msg_bytes: usize) -> u16 {
+        let count_pct = self.max_msg_count.map_or(0, |max_count| pct(msg_count, max_count));
+        let bytes_pct = self.max_msg_bytes.map_or(0, |max_bytes| pct(msg_bytes, max_bytes));
+        count_pct.max(bytes_pct)
+    }
+}
+
+// replicas of several instances may share a process and a spill directory
+pub fn spill_path(spill_dir: &Path, node: NodeType, crdt_instance: &CrdtInstance) -> PathBuf {
+    spill_dir.join(format!("msg_list_{}_{:?}_{}_{}.jsonl", node, crdt_instance.instance_type,
+                           crdt_instance.instance_node_id, crdt_instance.instance_num).to_lowercase())
+}
+
+fn limit(value: u64) -> Option<usize> {
+    match value {
+        0     => None,
+        value => Some(value as usize)
+    }
+}
+
+fn pct(value: usize, max_value: usize) -> u16 {
+    (value.saturating_mul(100) / max_value.max(1)).min(u16::MAX as usize) as u16
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MemoryMetrics {
+    pub msg_count: usize,
+    pub msg_bytes: usize,
+    pub spilled_count: usize,
+    pub max_msg_count: Option<usize>,
+    pub max_msg_bytes: Option<usize>,
+    pub fill_pct: u16,
+    pub evicted_count: usize
+}
+
+type SpillKey = (NodeType, LCType);
+type SpillEntry = (u64, usize);
+
+pub fn msg_size<OpsValue: Clone+PartialEq+Serialize>(msg: &NodeUpdateMsg<OpsValue>) -> usize {
+    serde_json::to_vec(msg).map_or(0, |bytes| bytes.len())
+}
+
+// one json line per message, found through the offset and length kept for its key; lines
+// of removed messages stay in the file until they make up half of it. the clocks stay in
+// memory so lookups by clock only read the lines they return
+#[derive(Debug)]
+pub struct SpillStore {
+    spill_dir: PathBuf,
+    node: NodeType,
+    path: Option<PathBuf>,
+    key_list: HashMap<SpillKey, SpillEntry>,
+    vc_list: HashMap<SpillKey, VectorClock>,
+    file_len: u64,
+    dead_len: u64
+}
+
+impl SpillStore {
+    pub fn new(spill_dir: PathBuf, node: NodeType) -> Self {
+        Self{spill_dir, node, path: None, key_list: HashMap::new(), vc_list: HashMap::new(), file_len: 0, dead_len: 0}
+    }
+
+    // the file is named after the instance of the first message spilled, since a replica
+    // only learns its instance from its operations
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn file_len(&self) -> u64 {
+        self.file_len
+    }
+
+    pub fn len(&self) -> usize {
+        self.key_list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.key_list.is_empty()
+    }
+
+    pub fn contains(&self, key: &(NodeType, LCType)) -> bool {
+        self.key_list.contains_key(key)
+    }
+
+    pub fn vc(&self, key: &(NodeType, LCType)) -> Option<&VectorClock> {
+        self.vc_list.get(key)
+    }
+
+    pub fn spill<OpsValue: Clone+PartialEq+Serialize>(&mut self, msg_list: Vec<NodeUpdateMsg<OpsValue>>) -> Result<(), CrdtError> {
+        let path = match (&self.path, msg_list.first()) {
+            (Some(path), _)   => path.clone(),
+            (None, Some(msg)) => spill_path(&self.spill_dir, self.node, &msg.user_update_msg.crdt_instance),
+            (None, None)      => return Ok(())
+        };
+        fs::create_dir_all(&self.spill_dir).map_err(spill_error)?;
+        let mut file = OpenOptions::new().create(true)
+                                         .append(!self.key_list.is_empty())
+                                         .write(true)
+                                         .truncate(self.key_list.is_empty())
+                                         .open(&path).map_err(spill_error)?;
+        if self.key_list.is_empty() {
+            self.file_len = 0;
+            self.dead_len = 0;
+        }
+        self.path = Some(path);
+        for msg in msg_list {
+            let lc = msg.node_vector_clock.vcmap.get(&msg.node).ok_or(CrdtError::UnknownNode(msg.node))?;
+            let line = serde_json::to_string(&msg).map_err(spill_error)?;
+            writeln!(file, "{}", line).map_err(spill_error)?;
+            if let Some((_, old_len)) = self.key_list.insert((msg.node, *lc), (self.file_len, line.len())) {
+                self.dead_len += old_len as u64 + 1;
+            }
+            self.vc_list.insert((msg.node, *lc), msg.node_vector_clock.clone());
+            self.file_len += line.len() as u64 + 1;
+        }
+        Ok(())
+    }
+
+    pub fn get<OpsValue: Clone+PartialEq+DeserializeOwned>(&self, key: &(NodeType, LCType)) ->
+        Result<Option<NodeUpdateMsg<OpsValue>>, CrdtError> {
+        let (offset, len) = match self.key_list.get(key) {
+            Some(entry) => *entry,
+            None        => return Ok(None)
+        };
+        let mut file = self.open()?;
+        read_msg(&mut file, offset, len).map(Some)
+    }
+
+    pub fn load<OpsValue: Clone+PartialEq+DeserializeOwned>(&self) ->
+        Result<HashMap<(NodeType, LCType), NodeUpdateMsg<OpsValue>>, CrdtError> {
+        let mut msg_list = HashMap::new();
+        if self.key_list.is_empty() {
+            return Ok(msg_list);
+        }
+
+        let mut entry_list: Vec<(&SpillKey, &SpillEntry)> = self.key_list.iter().collect();
+        entry_list.sort_by_key(|(_, (offset, _))| *offset);
+        let mut file = self.open()?;
+        for (key, (offset, len)) in entry_list {
+            msg_list.insert(*key, read_msg(&mut file, *offset, *len)?);
+        }
+        Ok(msg_list)
+    }
+
+    pub fn load_concurrent<OpsValue: Clone+PartialEq+DeserializeOwned>(&self, msg_vc: &VectorClock) ->
+        Result<HashMap<(NodeType, LCType), NodeUpdateMsg<OpsValue>>, CrdtError> {
+        let mut entry_list = Vec::new();
+        for (key, vc) in self.vc_list.iter() {
+            if msg_vc.cmp_vc(vc)? == VCOrdering::VCCN {
+                entry_list.push((*key, self.key_list[key]));
+            }
+        }
+        let mut msg_list = HashMap::new();
+        if entry_list.is_empty() {
+            return Ok(msg_list);
+        }
+
+        entry_list.sort_by_key(|(_, (offset, _))| *offset);
+        let mut file = self.open()?;
+        for (key, (offset, len)) in entry_list {
+            msg_list.insert(key, read_msg(&mut file, offset, len)?);
+        }
+        Ok(msg_list)
+    }
+
+    pub fn remove<OpsValue: Clone+PartialEq+DeserializeOwned>(&mut self, key_list: &[(NodeType, LCType)]) ->
+        Result<Vec<NodeUpdateMsg<OpsValue>>, CrdtError> {
+        if !key_list.iter().any(|key| self.key_list.contains_key(key)) {
+            return Ok(Vec::new());
+        }
+
+        let mut removed = Vec::new();
+        let mut file = self.open()?;
+        for key in key_list {
+            if let Some((offset, len)) = self.key_list.remove(key) {
+                self.vc_list.remove(key);
+                removed.push(read_msg(&mut file, offset, len)?);
+                self.dead_len += len as u64 + 1;
+            }
+        }
+        if self.key_list.is_empty() {
+            self.clear()?;
+        } else if self.dead_len*2 > self.file_len {
+            self.compact()?;
+        }
+        Ok(removed)
+    }
+
+    // copies the live lines to a new file and swaps it in, so a crash leaves one of the two
+    pub fn compact(&mut self) -> Result<(), CrdtError> {
+        let path = match &self.path {
+            Some(path) => path.clone(),
+            None       => return Ok(())
+        };
+        let mut entry_list: Vec<(SpillKey, SpillEntry)> = self.key_list.iter().map(|(key, entry)| (*key, *entry)).collect();
+        entry_list.sort_by_key(|(_, (offset, _))| *offset);
+
+        let mut old_file = self.open()?;
+        let compact_path = path.with_extension("jsonl.compact");
+        let mut file = File::create(&compact_path).map_err(spill_error)?;
+        let mut key_list = HashMap::with_capacity(entry_list.len());
+        let mut file_len = 0;
+        for (key, (offset, len)) in entry_list {
+            let line = read_line(&mut old_file, offset, len)?;
+            file.write_all(&line).and_then(|_| file.write_all(b"\n")).map_err(spill_error)?;
+            key_list.insert(key, (file_len, len));
+            file_len += len as u64 + 1;
+        }
+        file.sync_all().map_err(spill_error)?;
+        fs::rename(&compact_path, &path).map_err(spill_error)?;
+        self.key_list = key_list;
+        self.file_len = file_len;
+        self.dead_len = 0;
+        Ok(())
+    }
+
+    pub fn clear(&mut self) -> Result<(), CrdtError> {
+        self.key_list.clear();
+        self.vc_list.clear();
+        self.file_len = 0;
+        self.dead_len = 0;
+        match self.path.as_ref().map(fs::remove_file) {
+            Some(Err(e)) if e.kind() != std::io::ErrorKind::NotFound => Err(spill_error(e)),
+            _                                                          => Ok(())
+        }
+    }
+
+    fn open(&self) -> Result<File, CrdtError> {
+        let path = self.path.as_ref().ok_or(CrdtError::SpillError("nothing spilled".to_owned()))?;
+        File::open(path).map_err(spill_error)
+    }
+}
+
+fn read_line(file: &mut File, offset: u64, len: usize) -> Result<Vec<u8>, CrdtError> {
+    let mut line = vec![0; len];
+    file.seek(SeekFrom::Start(offset)).map_err(spill_error)?;
+    file.read_exact(&mut line).map_err(spill_error)?;
+    Ok(line)
+}
+
+fn read_msg<OpsValue: Clone+PartialEq+DeserializeOwned>(file: &mut File, offset: u64, len: usize) -> Result<NodeUpdateMsg<OpsValue>, CrdtError> {
+    serde_json::from_slice(&read_line(file, offset, len)?).map_err(spill_error)
+}
+
+fn spill_error<E: std::fmt::Display>(e: E) -> CrdtError {
+    CrdtError::SpillError(e.to_string())
+}
+
+impl <CrdtValue: Clone+Debug,
+      OpsValue: Clone+PartialEq+Debug+Serialize+DeserializeOwned,
+      State: Debug+CrdtBehavior<CrdtValue, OpsValue>> CRDT<CrdtValue, OpsValue, State> {
+    pub fn set_memory_policy(&mut self, memory_policy: MemoryPolicy) -> Result<(), CrdtError> {
+        if self.spill_store.is_empty() {
+            self.spill_store = SpillStore::new(memory_policy.spill_dir.clone(), self.get_node());
+        }
+        self.memory_policy = memory_policy;
+        self.enforce_memory_policy()
+    }
+
+    pub fn memory_metrics(&self) -> MemoryMetrics {
+        MemoryMetrics{msg_count: self.msg_list.len(),
+                      msg_bytes: self.msg_bytes,
+                      spilled_count: self.spill_store.len(),
+                      max_msg_count: self.memory_policy.max_msg_count,
+                      max_msg_bytes: self.memory_policy.max_msg_bytes,
+                      fill_pct: self.memory_policy.fill_pct(self.msg_list.len(), self.msg_bytes),
+                      evicted_count: self.trcb.evicted.len()}
+    }
+
+    pub fn check_local_capacity(&self) -> Result<(), CrdtError> {
+        let msg_count = self.msg_list.len();
+        if self.memory_policy.overflow_action == OverflowAction::RejectLocal &&
+            self.memory_policy.is_over(msg_count+1, self.msg_bytes) {
+            return Err(CrdtError::MsgListFull(msg_count, self.msg_bytes));
+        }
+        Ok(())
+    }
+
+    pub fn enforce_memory_policy(&mut self) -> Result<(), CrdtError> {
+        if !self.memory_policy.is_over(self.msg_list.len(), self.msg_bytes) {
+            return Ok(());
+        }
+
+        match self.memory_policy.overflow_action {
+            OverflowAction::RejectLocal   => Ok(()),
+            OverflowAction::SpillToDisk   => self.spill_msg_list(),
+            OverflowAction::StateTransfer => self.evict_lagging_peers()
+        }
+    }
+
+    pub fn all_msg_list(&self) -> Result<HashMap<(NodeType, LCType), NodeUpdateMsg<OpsValue>>, CrdtError> {
+        let mut msg_list = self.spill_store.load()?;
+        msg_list.extend(self.msg_list.iter().map(|(key, msg)| (*key, msg.clone())));
+        Ok(msg_list)
+    }
+
+    fn spill_msg_list(&mut self) -> Result<(), CrdtError> {
+        let mut key_list: Vec<(NodeType, LCType)> = self.msg_list.keys().copied().collect();
+        key_list.sort_by_key(|(node, lc)| (*lc, *node));
+
+        let mut spill_list = Vec::new();
+        for key in key_list {
+            if !self.memory_policy.is_over(self.msg_list.len(), self.msg_bytes) {
+                break;
+            }
+            if let Some(msg) = self.msg_list.remove(&key) {
+                self.msg_bytes -= msg_size(&msg);
+                spill_list.push(msg);
+            }
+        }
+        self.spill_store.spill(spill_list)
+    }
+
+    // peers furthest behind hold the stable clock down, so they are evicted first
+    // and must come back through a state transfer
+    fn evict_lagging_peers(&mut self) -> Result<(), CrdtError> {
+        let mut lag_list: Vec<(u64, NodeType)> = Vec::new();
+        for (pnode, pvc) in self.trcb.node_trcb.iter() {
+            if !self.trcb.is_evicted(pnode) {
+                lag_list.push((clock_lag(&self.trcb.node_vector_clock, pvc), *pnode));
+            }
+        }
+        lag_list.sort_by(|a, b| b.cmp(a));
+
+        for (_, pnode) in lag_list {
+            if !self.memory_policy.is_over(self.msg_list.len(), self.msg_bytes) {
+                break;
+            }
+            self.evict_peer(pnode)?;
+        }
+        Ok(())
+    }
+}
+
+fn clock_lag(node_vc: &VectorClock, peer_vc: &VectorClock) -> u64 {
+    node_vc.vcmap.iter()
+                 .map(|(node, lc)| lc_u64(lc.saturating_sub(*peer_vc.vcmap.get(node).unwrap_or(lc))))
+                 .sum()
+}
@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::Arc;
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+use anyhow::Result;
+
+use crate::{EpochType, LCType, NodeType};
+use crate::crdt::{CRDT, CrdtBehavior};
+use crate::message_data::{MerklePath, MerkleReplyMsg, MerkleRequestMsg, NodeUpdateMsg, PeerNodeMsg};
+use crate::vector_clock::VectorClock;
+use crate::error::CrdtError;
+
+pub const MERKLE_FANOUT_BITS: u8 = 4;
+pub const MERKLE_FANOUT: u64     = 1 << MERKLE_FANOUT_BITS;
+pub const MERKLE_MAX_DEPTH: u8   = 15;
+pub const MERKLE_ROOT: MerklePath = (0, 0);
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64  = 0x100000001b3;
+
+pub fn fnv_hash(bytes: &[u8]) -> u64 {
+    fnv_extend(FNV_OFFSET, bytes)
+}
+
+fn fnv_extend(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, byte| (hash ^ *byte as u64).wrapping_mul(FNV_PRIME))
+}
+
+// the clock is hashed in node order so the digest does not depend on map iteration order
+pub fn msg_hash<OpsValue: Clone+PartialEq+Serialize>(msg: &NodeUpdateMsg<OpsValue>) -> Result<u64, CrdtError> {
+    let mut vc_list: Vec<(&NodeType, &LCType)> = msg.node_vector_clock.vcmap.iter().collect();
+    vc_list.sort();
+    let mut hash = fnv_extend(FNV_OFFSET, &msg.node.to_le_bytes());
+    for (node, lc) in vc_list {
+        hash = fnv_extend(hash, &node.to_le_bytes());
+        hash = fnv_extend(hash, &lc.to_le_bytes());
+    }
+    let ops_bytes = serde_json::to_vec(&msg.user_update_msg)
+                        .map_err(|e| CrdtError::EncodeError(e.to_string()))?;
+    Ok(fnv_extend(hash, &ops_bytes))
+}
+
+// a tree of depth 0 is its root alone, which every operation falls under
+pub fn leaf_index(node: NodeType, lc: LCType, depth: u8) -> u64 {
+    if depth == 0 {
+        return 0;
+    }
+    let hash = fnv_extend(fnv_hash(&node.to_le_bytes()), &lc.to_le_bytes());
+    hash >> (64 - (depth.min(MERKLE_MAX_DEPTH)*MERKLE_FANOUT_BITS) as u32)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum DivergenceKind {
+    MissingLocal,
+    MissingPeer,
+    Mismatch
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MerkleDivergence {
+    pub peer: NodeType,
+    pub node: NodeType,
+    pub lc: LCType,
+    pub kind: DivergenceKind
+}
+
+#[derive(Debug)]
+pub struct MerkleTree {
+    pub depth: u8,
+    hash_list: HashMap<MerklePath, u64>,
+    leaf_list: HashMap<u64, Vec<(NodeType, LCType, u64)>>
+}
+
+impl MerkleTree {
+    // only operations in (low, high] per origin are summarised, a range both replicas
+    // are expected to retain whatever their own stable clocks are
+    pub fn new<OpsValue: Clone+PartialEq+Serialize>(depth: u8,
+                                                     low_vc: &VectorClock,
+                                                     high_vc: &VectorClock,
+                                                     msg_list: &HashMap<(NodeType, LCType), NodeUpdateMsg<OpsValue>>) ->
+        Result<Self, CrdtError> {
+        let mut leaf_list: HashMap<u64, Vec<(NodeType, LCType, u64)>> = HashMap::new();
+        for ((node, lc), msg) in msg_list.iter() {
+            let low_lc = low_vc.vcmap.get(node).ok_or(CrdtError::NonCompatibleVC)?;
+            let high_lc = high_vc.vcmap.get(node).ok_or(CrdtError::NonCompatibleVC)?;
+            if lc > low_lc && lc <= high_lc {
+                leaf_list.entry(leaf_index(*node, *lc, depth)).or_default().push((*node, *lc, msg_hash(msg)?));
+            }
+        }
+
+        let mut hash_list = HashMap::new();
+        for (index, entry_list) in leaf_list.iter_mut() {
+            entry_list.sort();
+            let hash = entry_list.iter().fold(FNV_OFFSET, |hash, (node, lc, msg_hash)| {
+                let hash = fnv_extend(hash, &node.to_le_bytes());
+                let hash = fnv_extend(hash, &lc.to_le_bytes());
+                fnv_extend(hash, &msg_hash.to_le_bytes())
+            });
+            hash_list.insert((depth, *index), hash);
+        }
+
+        for level in (0..depth).rev() {
+            let mut parent_list: Vec<u64> = hash_list.keys()
+                                                     .filter(|(clevel, _)| *clevel == level+1)
+                                                     .map(|(_, index)| index >> MERKLE_FANOUT_BITS)
+                                                     .collect();
+            parent_list.sort();
+            parent_list.dedup();
+            for parent in parent_list {
+                let hash = (0..MERKLE_FANOUT).fold(FNV_OFFSET, |hash, child| {
+                    let child_hash = hash_list.get(&(level+1, (parent << MERKLE_FANOUT_BITS) | child)).copied().unwrap_or(0);
+                    fnv_extend(hash, &child_hash.to_le_bytes())
+                });
+                hash_list.insert((level, parent), hash);
+            }
+        }
+
+        Ok(Self{depth, hash_list, leaf_list})
+    }
+
+    pub fn hash(&self, path: &MerklePath) -> u64 {
+        self.hash_list.get(path).copied().unwrap_or(0)
+    }
+
+    pub fn root_hash(&self) -> u64 {
+        self.hash(&MERKLE_ROOT)
+    }
+
+    pub fn child_list(&self, (level, index): &MerklePath) -> Vec<MerklePath> {
+        (0..MERKLE_FANOUT).map(|child| (level+1, (index << MERKLE_FANOUT_BITS) | child)).collect()
+    }
+
+    pub fn leaf_entry_list(&self, (level, index): &MerklePath) -> Vec<(NodeType, LCType, u64)> {
+        if *level != self.depth {
+            return Vec::new();
+        }
+        self.leaf_list.get(index).cloned().unwrap_or_default()
+    }
+}
+
+type TreeKey = (EpochType, HashMap<NodeType, LCType>, HashMap<NodeType, LCType>);
+
+// operations never change once delivered, and those in the window are all retained, so a
+// tree only depends on its window and stays valid for the whole descent over it
+#[derive(Debug, Default)]
+pub struct MerkleState {
+    pub depth: u8,
+    pub divergence_list: Vec<MerkleDivergence>,
+    tree_cache: Option<(TreeKey, Arc<MerkleTree>)>
+}
+
+impl MerkleState {
+    pub fn new(depth: u8) -> Self {
+        Self{depth: depth.min(MERKLE_MAX_DEPTH), divergence_list: Vec::new(), tree_cache: None}
+    }
+
+    pub fn clear_cache(&mut self) {
+        self.tree_cache = None;
+    }
+}
+
+impl <CrdtValue: Clone+Debug,
+      OpsValue: Clone+PartialEq+Debug+Serialize+DeserializeOwned,
+      State: Debug+CrdtBehavior<CrdtValue, OpsValue>> CRDT<CrdtValue, OpsValue, State> {
+    pub fn set_merkle_depth(&mut self, depth: u8) {
+        self.merkle = MerkleState::new(depth);
+    }
+
+    pub fn merkle_tree(&self, low_vc: &VectorClock, high_vc: &VectorClock) -> Result<MerkleTree, CrdtError> {
+        MerkleTree::new(self.merkle.depth, low_vc, high_vc, &self.all_msg_list()?)
+    }
+
+    pub fn cached_merkle_tree(&mut self, low_vc: &VectorClock, high_vc: &VectorClock) -> Result<Arc<MerkleTree>, CrdtError> {
+        let key = (self.epoch.epoch, low_vc.vcmap.clone(), high_vc.vcmap.clone());
+        if let Some((cache_key, tree)) = self.merkle.tree_cache.as_ref() {
+            if *cache_key == key {
+                return Ok(tree.clone());
+            }
+        }
+        let tree = Arc::new(self.merkle_tree(low_vc, high_vc)?);
+        self.merkle.tree_cache = Some((key, tree.clone()));
+        Ok(tree)
+    }
+
+    pub fn take_merkle_divergence(&mut self) -> Vec<MerkleDivergence> {
+        std::mem::take(&mut self.merkle.divergence_list)
+    }
+
+    pub fn create_merkle_request(&self, pnode: NodeType) -> Result<HashMap<NodeType, Vec<PeerNodeMsg<OpsValue>>>, CrdtError> {
+        if !self.trcb.node_trcb.contains_key(&pnode) {
+            return Err(CrdtError::UnknownNode(pnode));
+        }
+        if self.merkle.depth == 0 {
+            return Ok(HashMap::new());
+        }
+        Ok(HashMap::from([(pnode, vec![self.create_merkle_request_msg()])]))
+    }
+
+    pub fn create_merkle_request_msg(&self) -> PeerNodeMsg<OpsValue> {
+        PeerNodeMsg::MerkleRequestMsg(MerkleRequestMsg{node: self.get_node(),
+                                                       depth: self.merkle.depth,
+                                                       low_vector_clock: self.trcb.stable_vector_clock.clone(),
+                                                       high_vector_clock: self.trcb.node_vector_clock.clone(),
+                                                       path_list: vec![MERKLE_ROOT]})
+    }
+
+    // the responder narrows the window to what it retains and echoes it, so both sides
+    // hash the same range; each round trip descends one level below the differing paths
+    pub fn general_process_merkle_msg(&mut self, msg: PeerNodeMsg<OpsValue>, ctrl_msg_map: &mut HashMap<NodeType, Vec<PeerNodeMsg<OpsValue>>>) ->
+        Result<(), CrdtError> {
+        match msg {
+            PeerNodeMsg::MerkleRequestMsg(rmsg) => {
+                if rmsg.depth != self.merkle.depth || self.merkle.depth == 0 || self.trcb.is_evicted(&rmsg.node) {
+                    return Ok(());
+                }
+                let low_vc = rmsg.low_vector_clock.max_vc(&self.trcb.stable_vector_clock)?;
+                let high_vc = rmsg.high_vector_clock.min_vc(&self.trcb.node_vector_clock)?;
+                let tree = self.cached_merkle_tree(&low_vc, &high_vc)?;
+                let mut hash_list = Vec::new();
+                let mut leaf_list = Vec::new();
+                for path in rmsg.path_list.iter() {
+                    if path.0 < tree.depth {
+                        hash_list.extend(tree.child_list(path)
+                                             .into_iter()
+                                             .map(|child| (child, tree.hash(&child)))
+                                             .filter(|(_, hash)| *hash != 0));
+                    } else {
+                        leaf_list.extend(tree.leaf_entry_list(path));
+                    }
+                }
+                let reply_msg = MerkleReplyMsg{node: self.get_node(),
+                                               depth: tree.depth,
+                                               low_vector_clock: low_vc,
+                                               high_vector_clock: high_vc,
+                                               path_list: rmsg.path_list,
+                                               hash_list,
+                                               leaf_list};
+                ctrl_msg_map.entry(rmsg.node).or_default().push(PeerNodeMsg::MerkleReplyMsg(reply_msg));
+            },
+            PeerNodeMsg::MerkleReplyMsg(rmsg)   => {
+                if rmsg.depth != self.merkle.depth || self.merkle.depth == 0 {
+                    return Ok(());
+                }
+                let low_vc = rmsg.low_vector_clock.max_vc(&self.trcb.stable_vector_clock)?;
+                let high_vc = rmsg.high_vector_clock.min_vc(&self.trcb.node_vector_clock)?;
+                let tree = self.cached_merkle_tree(&low_vc, &high_vc)?;
+                let peer_hash_list: HashMap<MerklePath, u64> = rmsg.hash_list.into_iter().collect();
+                let mut peer_leaf_list: HashMap<(NodeType, LCType), u64> = rmsg.leaf_list
+                                                                              .into_iter()
+                                                                              .map(|(node, lc, hash)| ((node, lc), hash))
+                                                                              .collect();
+                let mut path_list = Vec::new();
+                for path in rmsg.path_list.iter() {
+                    if path.0 < tree.depth {
+                        path_list.extend(tree.child_list(path)
+                                             .into_iter()
+                                             .filter(|child| tree.hash(child) != peer_hash_list.get(child).copied().unwrap_or(0)));
+                        continue;
+                    }
+                    for (node, lc, hash) in tree.leaf_entry_list(path) {
+                        match peer_leaf_list.remove(&(node, lc)) {
+                            None                                => self.record_divergence(rmsg.node, node, lc, DivergenceKind::MissingPeer),
+                            Some(peer_hash) if peer_hash != hash => self.record_divergence(rmsg.node, node, lc, DivergenceKind::Mismatch),
+                            Some(_)                             => ()
+                        }
+                    }
+                }
+                // a peer entry may fall outside our narrower window when our stable clock moved on
+                for ((node, lc), _) in peer_leaf_list {
+                    let low_lc = low_vc.vcmap.get(&node).ok_or(CrdtError::NonCompatibleVC)?;
+                    let high_lc = high_vc.vcmap.get(&node).ok_or(CrdtError::NonCompatibleVC)?;
+                    if lc > *low_lc && lc <= *high_lc {
+                        self.record_divergence(rmsg.node, node, lc, DivergenceKind::MissingLocal);
+                    }
+                }
+
+                if !path_list.is_empty() {
+                    let request_msg = MerkleRequestMsg{node: self.get_node(),
+                                                       depth: tree.depth,
+                                                       low_vector_clock: low_vc,
+                                                       high_vector_clock: high_vc,
+                                                       path_list};
+                    ctrl_msg_map.entry(rmsg.node).or_default().push(PeerNodeMsg::MerkleRequestMsg(request_msg));
+                }
+            },
+            _                                   =>
+                return Err(CrdtError::MisroutedMsg("non merkle message"))
+        }
+        Ok(())
+    }
+
+    fn record_divergence(&mut self, peer: NodeType, node: NodeType, lc: LCType, kind: DivergenceKind) {
+        let divergence = MerkleDivergence{peer, node, lc, kind};
+        if !self.merkle.divergence_list.contains(&divergence) {
+            self.merkle.divergence_list.push(divergence);
+        }
+    }
+}
@@ -1,8 +1,10 @@
+use std::collections::HashMap;
 use serde::{Serialize, Deserialize};
 
 use crate::vector_clock::VectorClock;
-use crate::NodeType;
+use crate::{EpochType, LCType, NodeType};
 use crate::crdt::CrdtInstance;
+use crate::delta_vc::OpClock;
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub enum SDPOpsType {
@@ -39,33 +41,120 @@ impl <OpsValue: Clone+PartialEq> UserUpdateMsg<OpsValue> {
     }
 }
 
+fn is_first_epoch(epoch: &EpochType) -> bool {
+    *epoch == 0
+}
+
+pub type OpHash = [u8; 32];
+
+// an operation the origin had not yet covered when it sent its previous one, with its hash
+pub type PredHash = (NodeType, LCType, OpHash);
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct NodeUpdateMsg <OpsValue: Clone+PartialEq> {
     pub node: NodeType,
     pub node_vector_clock: VectorClock,
-    pub user_update_msg: UserUpdateMsg<OpsValue>
+    pub user_update_msg: UserUpdateMsg<OpsValue>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pred_hash_list: Vec<PredHash>,
+    #[serde(default, skip_serializing_if = "is_first_epoch")]
+    pub epoch: EpochType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<Vec<u8>>
 }
 impl <OpsValue: Clone+PartialEq> NodeUpdateMsg<OpsValue> {
     pub fn new(node:NodeType, node_vector_clock: VectorClock, user_update_msg: UserUpdateMsg<OpsValue>) -> Self {
-        Self {node, node_vector_clock, user_update_msg}
+        Self {node, node_vector_clock, user_update_msg, pred_hash_list: Vec::new(), epoch: 0, signature: None}
     }
 }
 
+// clock entries other than the origin's that changed since the origin's previous operation
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DeltaNodeUpdateMsg <OpsValue: Clone+PartialEq> {
+    pub node: NodeType,
+    pub lc: LCType,
+    pub entry_list: Vec<(NodeType, LCType)>,
+    pub user_update_msg: UserUpdateMsg<OpsValue>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pred_hash_list: Vec<PredHash>,
+    #[serde(default, skip_serializing_if = "is_first_epoch")]
+    pub epoch: EpochType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<Vec<u8>>
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct NodeVectorClockMsg {
     pub node: NodeType,
-    pub node_vector_clock: VectorClock
+    pub node_vector_clock: VectorClock,
+    #[serde(default, skip_serializing_if = "is_first_epoch")]
+    pub epoch: EpochType,
+    // the sender creates no more operations in this epoch, so its own entry is final
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub reset_pending: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<Vec<u8>>
 }
 impl NodeVectorClockMsg {
     pub fn new(node: NodeType, node_vector_clock: VectorClock) -> Self {
-        Self {node, node_vector_clock}
+        Self {node, node_vector_clock, epoch: 0, reset_pending: false, signature: None}
     }
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StateTransferMsg <CrdtValue: Clone, OpsValue: Clone+PartialEq> {
+    pub node: NodeType,
+    pub node_vector_clock: VectorClock,
+    pub stable_vector_clock: VectorClock,
+    pub crdt_value: CrdtValue,
+    pub msg_list: Vec<NodeUpdateMsg<OpsValue>>,
+    #[serde(default)]
+    pub op_clock_list: HashMap<NodeType, OpClock>,
+    #[serde(default)]
+    pub epoch: EpochType
+}
+
+pub type MerklePath = (u8, u64);
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MerkleRequestMsg {
+    pub node: NodeType,
+    pub depth: u8,
+    pub low_vector_clock: VectorClock,
+    pub high_vector_clock: VectorClock,
+    pub path_list: Vec<MerklePath>
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MerkleReplyMsg {
+    pub node: NodeType,
+    pub depth: u8,
+    pub low_vector_clock: VectorClock,
+    pub high_vector_clock: VectorClock,
+    pub path_list: Vec<MerklePath>,
+    pub hash_list: Vec<(MerklePath, u64)>,
+    pub leaf_list: Vec<(NodeType, LCType, u64)>
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum PeerNodeMsg <OpsValue: Clone+PartialEq> {
     VectorClockNodeMsg(NodeVectorClockMsg),
-    UpdateNodeMsg(NodeUpdateMsg<OpsValue>)
+    UpdateNodeMsg(NodeUpdateMsg<OpsValue>),
+    DeltaUpdateNodeMsg(DeltaNodeUpdateMsg<OpsValue>),
+    DigestRequestMsg(NodeVectorClockMsg),
+    DigestReplyMsg(NodeVectorClockMsg),
+    DigestAckMsg(NodeVectorClockMsg),
+    MerkleRequestMsg(MerkleRequestMsg),
+    MerkleReplyMsg(MerkleReplyMsg)
+}
+
+pub type PeerMsgMap<OpsValue> = HashMap<NodeType, Vec<PeerNodeMsg<OpsValue>>>;
+
+// in peer order, so runs and replies do not depend on hash map order
+pub fn sorted_msg_list<OpsValue: Clone+PartialEq>(msg_map: PeerMsgMap<OpsValue>) -> Vec<(NodeType, Vec<PeerNodeMsg<OpsValue>>)> {
+    let mut msg_list: Vec<_> = msg_map.into_iter().collect();
+    msg_list.sort_by_key(|(pnode, _)| *pnode);
+    msg_list
 }
 
 
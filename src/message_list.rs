@@ -1,28 +1,26 @@
 use std::collections::HashMap;
 use anyhow::Result;
 
-use crate::vector_clock::{VectorClock, VectorClockError, VCOrdering};
+use crate::vector_clock::{VectorClock, VCOrdering, lc_u64};
 use crate::message_data::NodeUpdateMsg;
 use crate::{NodeType, LCType};
+use crate::error::CrdtError;
 
-pub fn remove_causally_stable<OpsValue: Clone+PartialEq>
-    (cs_vc: &VectorClock, msg_list: &HashMap<(NodeType, LCType), NodeUpdateMsg<OpsValue>>) -> 
-    Result<HashMap<(NodeType, LCType), NodeUpdateMsg<OpsValue>>, VectorClockError> {
+pub fn remove_stable_dots<OpsValue: Clone+PartialEq>
+    (stable_dots: &[(NodeType, LCType)], msg_list: &mut HashMap<(NodeType, LCType), NodeUpdateMsg<OpsValue>>) -> 
+    Vec<NodeUpdateMsg<OpsValue>> {
 
-    let mut new_list = HashMap::new();
+    stable_dots.iter().filter_map(|key| msg_list.remove(key)).collect()
+}
 
-    for (key, msg) in msg_list.iter() {
-        let cmp_csvc = cs_vc.cmp_vc(&msg.node_vector_clock)?;
-        if cmp_csvc == VCOrdering::VCCN || cmp_csvc == VCOrdering::VCLE {
-            new_list.insert(*key, msg.clone());
-        }
-    }
-    Ok(new_list)    
+// a message's clock sum is larger than that of everything it causally follows
+pub fn causal_sort<OpsValue: Clone+PartialEq>(msg_list: &mut [NodeUpdateMsg<OpsValue>]) {
+    msg_list.sort_by_cached_key(|msg| (msg.node_vector_clock.vcmap.values().map(|lc| lc_u64(*lc)).sum::<u64>(), msg.node));
 }
 
 pub fn concurrent_msg_list<OpsValue: Clone+PartialEq>
     (msg_vc: &VectorClock, msg_list: &HashMap<(NodeType, LCType), NodeUpdateMsg<OpsValue>>, check_value: Option<OpsValue>) ->
-    Result<Vec<NodeUpdateMsg<OpsValue>>, VectorClockError> {
+    Result<Vec<NodeUpdateMsg<OpsValue>>, CrdtError> {
 
     let mut clist = Vec::new();
 
@@ -0,0 +1,205 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Debug;
+use std::hash::Hash;
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+use anyhow::Result;
+
+use crate::NodeType;
+use crate::crdt::{CRDT, CrdtBehavior};
+use crate::constants::{env, NODE_ID_LIST, NODE_LIST};
+use crate::error::CrdtError;
+
+// each allocator hands out nodes from its own block, so concurrent adds never pick the same node
+pub const NODE_ID_BLOCK: u32 = 256;
+
+// replicas known by a uuid or hostname get a compact node; clocks and messages only carry
+// the node, so the external id never leaves this map
+pub trait NodeId: Eq+Hash+Ord+Clone+Debug+Serialize+DeserializeOwned {}
+impl <T: Eq+Hash+Ord+Clone+Debug+Serialize+DeserializeOwned> NodeId for T {}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NodeIdMsg<Id> {
+    pub node: NodeType,
+    pub id_list: Vec<(NodeType, Id)>,
+    pub removed_list: Vec<NodeType>
+}
+
+// entries and removals only grow, so maps merge by union in any order; a node is never
+// handed out again once removed. an id added concurrently by two allocators keeps the
+// lower node everywhere
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeIdMap<Id: NodeId> {
+    pub id_list: BTreeMap<Id, NodeType>,
+    pub node_list: BTreeMap<NodeType, Id>,
+    pub removed_set: BTreeSet<NodeType>
+}
+
+impl <Id: NodeId> Default for NodeIdMap<Id> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl <Id: NodeId> NodeIdMap<Id> {
+    pub fn new() -> Self {
+        Self{id_list: BTreeMap::new(), node_list: BTreeMap::new(), removed_set: BTreeSet::new()}
+    }
+
+    // sorted first, so every replica configured with the same ids derives the same map
+    pub fn from_id_list(mut id_list: Vec<Id>) -> Result<Self, CrdtError> {
+        id_list.sort();
+        id_list.dedup();
+        let mut id_map = Self::new();
+        for (index, id) in id_list.into_iter().enumerate() {
+            let node = NodeType::try_from(index).map_err(|_| CrdtError::Overflow("node id"))?;
+            id_map.insert(node, id)?;
+        }
+        Ok(id_map)
+    }
+
+    pub fn from_pair_list(pair_list: Vec<(NodeType, Id)>) -> Result<Self, CrdtError> {
+        let mut id_map = Self::new();
+        for (node, id) in pair_list {
+            id_map.insert(node, id)?;
+        }
+        Ok(id_map)
+    }
+
+    pub fn insert(&mut self, node: NodeType, id: Id) -> Result<(), CrdtError> {
+        self.check_pair(node, &id)?;
+        self.node_list.insert(node, id.clone());
+        self.id_list.insert(id, node);
+        Ok(())
+    }
+
+    // an id already known keeps its node; a new one takes the next node of the allocator block.
+    // running replicas keep their clocks, the node only joins those recreated over live_node_list
+    pub fn add(&mut self, allocator: NodeType, id: Id) -> Result<NodeType, CrdtError> {
+        if let Some(node) = self.id_list.get(&id) {
+            return Ok(*node);
+        }
+        let block_start = allocator as u32 * NODE_ID_BLOCK;
+        let block_end = block_start + NODE_ID_BLOCK;
+        let next_node = match self.node_list.keys().map(|node| *node as u32).rfind(|node| (block_start..block_end).contains(node)) {
+            Some(last_node) => last_node + 1,
+            None            => block_start
+        };
+        let node = match next_node < block_end {
+            true  => NodeType::try_from(next_node).map_err(|_| CrdtError::Overflow("node id"))?,
+            false => return Err(CrdtError::Overflow("node id"))
+        };
+        self.insert(node, id)?;
+        Ok(node)
+    }
+
+    pub fn remove(&mut self, id: &Id) -> Result<NodeType, CrdtError> {
+        let node = self.node(id)?;
+        self.removed_set.insert(node);
+        Ok(node)
+    }
+
+    pub fn node(&self, id: &Id) -> Result<NodeType, CrdtError> {
+        self.id_list.get(id).copied().ok_or(CrdtError::UnknownNodeId(format!("{:?}", id)))
+    }
+
+    pub fn id(&self, node: NodeType) -> Result<&Id, CrdtError> {
+        self.node_list.get(&node).ok_or(CrdtError::UnknownNode(node))
+    }
+
+    pub fn is_removed(&self, node: &NodeType) -> bool {
+        self.removed_set.contains(node)
+    }
+
+    // nodes not removed, the list a replica's clocks are built over
+    pub fn live_node_list(&self) -> Vec<NodeType> {
+        self.node_list.keys().filter(|node| !self.is_removed(node)).copied().collect()
+    }
+
+    pub fn create_msg(&self, node: NodeType) -> NodeIdMsg<Id> {
+        NodeIdMsg{node,
+                  id_list: self.node_list.iter().map(|(node, id)| (*node, id.clone())).collect(),
+                  removed_list: self.removed_set.iter().copied().collect()}
+    }
+
+    // the whole message is checked before anything is merged; returns the nodes it removed
+    pub fn apply_msg(&mut self, msg: NodeIdMsg<Id>) -> Result<Vec<NodeType>, CrdtError> {
+        for (node, id) in msg.id_list.iter() {
+            if self.node_list.get(node).is_some_and(|mid| mid != id) {
+                return Err(CrdtError::NodeIdConflict(format!("{:?}", id), *node));
+            }
+        }
+        // the node that loses an id stays mapped but removed, so its block never reuses it
+        let mut superseded_list = Vec::new();
+        for (node, id) in msg.id_list {
+            self.node_list.insert(node, id.clone());
+            match self.id_list.get(&id).copied() {
+                Some(mnode) if mnode < node => superseded_list.push(node),
+                Some(mnode) if mnode > node => { superseded_list.push(mnode); self.id_list.insert(id, node); },
+                Some(_)                     => (),
+                None                        => { self.id_list.insert(id, node); }
+            }
+        }
+        let removed_list: Vec<NodeType> = msg.removed_list.into_iter()
+                                                          .chain(superseded_list)
+                                                          .filter(|node| self.node_list.contains_key(node))
+                                                          .filter(|node| self.removed_set.insert(*node))
+                                                          .collect();
+        Ok(removed_list)
+    }
+
+    fn check_pair(&self, node: NodeType, id: &Id) -> Result<(), CrdtError> {
+        let node_ok = self.id_list.get(id).is_none_or(|mnode| *mnode == node);
+        let id_ok = self.node_list.get(&node).is_none_or(|mid| mid == id);
+        match node_ok && id_ok {
+            true  => Ok(()),
+            false => Err(CrdtError::NodeIdConflict(format!("{:?}", id), node))
+        }
+    }
+}
+
+impl NodeIdMap<String> {
+    // NODE_ID_LIST names the replicas of NODE_LIST in the same order
+    pub fn from_env() -> Result<Option<Self>, CrdtError> {
+        let id_list = NODE_ID_LIST.to_owned();
+        if id_list.is_empty() {
+            return Ok(None);
+        }
+        let node_list = NODE_LIST.to_owned();
+        if node_list.len() != id_list.len() {
+            return Err(CrdtError::ConfigError(env::NODE_ID_LIST_VAR.to_owned(), id_list.join(",")));
+        }
+        Self::from_pair_list(node_list.into_iter().zip(id_list).collect()).map(Some)
+    }
+}
+
+// clocks are sized at creation, so a node added to a running map only takes part once the
+// replicas are recreated over the larger list; removals take effect at once as evictions
+impl <CrdtValue: Clone+Debug,
+      OpsValue: Clone+PartialEq+Debug+Serialize+DeserializeOwned,
+      State: Debug+CrdtBehavior<CrdtValue, OpsValue>> CRDT<CrdtValue, OpsValue, State> {
+    pub fn new_with_node_id_map<Id: NodeId>(id: &Id, id_map: &NodeIdMap<Id>, crdt_value: CrdtValue) -> Result<Self, CrdtError> {
+        Self::new_with_node_list(id_map.node(id)?, id_map.live_node_list(), crdt_value)
+    }
+
+    pub fn remove_node_id<Id: NodeId>(&mut self, id_map: &mut NodeIdMap<Id>, id: &Id) -> Result<NodeIdMsg<Id>, CrdtError> {
+        let node = id_map.remove(id)?;
+        self.evict_node(node)?;
+        Ok(id_map.create_msg(self.get_node()))
+    }
+
+    pub fn apply_node_id_msg<Id: NodeId>(&mut self, id_map: &mut NodeIdMap<Id>, msg: NodeIdMsg<Id>) -> Result<Vec<NodeType>, CrdtError> {
+        let removed_list = id_map.apply_msg(msg)?;
+        for node in removed_list.iter() {
+            self.evict_node(*node)?;
+        }
+        Ok(removed_list)
+    }
+
+    fn evict_node(&mut self, node: NodeType) -> Result<(), CrdtError> {
+        match node != self.get_node() && self.trcb.node_trcb.contains_key(&node) && !self.trcb.is_evicted(&node) {
+            true  => self.evict_peer(node),
+            false => Ok(())
+        }
+    }
+}
@@ -9,7 +9,8 @@ use crate::{NodeType,
 use crate::crdt::CRDT;
 use crate::pncnt_crdt::{PNCounter, PNCounterData};
 use crate::arset_crdt::{AWSet, RWSet};
-use crate::vector_clock::VectorClockError;
+use crate::error::CrdtError;
+use crate::constants::NODE_LIST;
 use crate::edflag_crdt::{EDFlag, EWFlag, DWFlag};
 use crate::add_mult_crdt::AddMult;
 
@@ -25,13 +26,17 @@ pub struct NodeInstance {
 }
 
 impl NodeInstance {
-    pub fn new(node: NodeType) -> Result<Self, VectorClockError> {
-        let add_mult_crdt: CRDT<IntMultCrdtValue, IntMultOpsValue, AddMult> = CRDT::new(node, 0)?;
-        let ewflag_crdt:   CRDT<EDFlagCrdtValue, EDFlagOpsValue, EWFlag> = CRDT::new(node, EDFlag::Enabled)?;
-        let dwflag_crdt:   CRDT<EDFlagCrdtValue, EDFlagOpsValue, DWFlag> = CRDT::new(node, EDFlag::Disabled)?;
-        let awset_crdt:    CRDT<HashSet<ARSetOpsValue>, ARSetOpsValue, AWSet> = CRDT::new(node, HashSet::<ARSetOpsValue>::new())?;
-        let rwset_crdt:    CRDT<HashSet<ARSetOpsValue>, ARSetOpsValue, RWSet> = CRDT::new(node, HashSet::<ARSetOpsValue>::new())?;
-        let pncnt_crdt:    CRDT<PNCounterData, PNCntOpsValue, PNCounter> = CRDT::new(node, PNCounterData::new())?;
+    pub fn new(node: NodeType) -> Result<Self, CrdtError> {
+        Self::new_with_node_list(node, NODE_LIST.to_owned())
+    }
+
+    pub fn new_with_node_list(node: NodeType, node_list: Vec<NodeType>) -> Result<Self, CrdtError> {
+        let add_mult_crdt: CRDT<IntMultCrdtValue, IntMultOpsValue, AddMult> = CRDT::new_with_node_list(node, node_list.clone(), 0)?;
+        let ewflag_crdt:   CRDT<EDFlagCrdtValue, EDFlagOpsValue, EWFlag> = CRDT::new_with_node_list(node, node_list.clone(), EDFlag::Enabled)?;
+        let dwflag_crdt:   CRDT<EDFlagCrdtValue, EDFlagOpsValue, DWFlag> = CRDT::new_with_node_list(node, node_list.clone(), EDFlag::Disabled)?;
+        let awset_crdt:    CRDT<HashSet<ARSetOpsValue>, ARSetOpsValue, AWSet> = CRDT::new_with_node_list(node, node_list.clone(), HashSet::<ARSetOpsValue>::new())?;
+        let rwset_crdt:    CRDT<HashSet<ARSetOpsValue>, ARSetOpsValue, RWSet> = CRDT::new_with_node_list(node, node_list.clone(), HashSet::<ARSetOpsValue>::new())?;
+        let pncnt_crdt:    CRDT<PNCounterData, PNCntOpsValue, PNCounter> = CRDT::new_with_node_list(node, node_list, PNCounterData::new())?;
         Ok(Self{node, add_mult_crdt, ewflag_crdt, dwflag_crdt, awset_crdt, rwset_crdt, pncnt_crdt})
     }
 }
@@ -3,8 +3,8 @@ use anyhow::Result;
 use dotenvy::dotenv;
 
 use crate::NodeType;
-use crate::constants::NODE_LIST;
-use crate::vector_clock::VectorClockError;
+use crate::constants::{check_env, NODE_LIST};
+use crate::error::CrdtError;
 
 use crate::node_instance::NodeInstance;
 
@@ -14,19 +14,27 @@ pub struct NodeState{
 }
 
 impl NodeState {
-    pub fn new() -> Result<Self, VectorClockError> {
+    pub fn new() -> Result<Self, CrdtError> {
         dotenv().ok();
-        let node_list = NODE_LIST.to_owned();
+        check_env()?;
+        Self::new_with_node_list(NODE_LIST.to_owned())
+    }
+
+    pub fn new_with_node_list(node_list: Vec<NodeType>) -> Result<Self, CrdtError> {
         let mut node_instance_list = HashMap::new();
-        for node in node_list {
-            let node_instance = NodeInstance::new(node)?;
-            node_instance_list.insert(node, node_instance);
+        for node in node_list.iter() {
+            let node_instance = NodeInstance::new_with_node_list(*node, node_list.clone())?;
+            node_instance_list.insert(*node, node_instance);
         }
         Ok(Self{node_instance_list})
     }
 
-    pub fn get_node_instance(&self, node: NodeType) -> &NodeInstance {
-        self.node_instance_list.get(&node).unwrap()
+    pub fn get_node_instance(&self, node: NodeType) -> Result<&NodeInstance, CrdtError> {
+        self.node_instance_list.get(&node).ok_or(CrdtError::UnknownNode(node))
+    }
+
+    pub fn get_node_instance_mut(&mut self, node: NodeType) -> Result<&mut NodeInstance, CrdtError> {
+        self.node_instance_list.get_mut(&node).ok_or(CrdtError::UnknownNode(node))
     }
 
     pub fn get_node_len(&self) -> u16 {
@@ -2,61 +2,123 @@ use serde::{Serialize, Deserialize};
 use std::collections::HashMap;
 use anyhow::Result;
 
-use crate::{NodeType, PNCntOpsValue};
-use crate::crdt::CRDT;
+use crate::{EpochType, NodeType, PNCntOpsValue};
+use crate::crdt::{CRDT, CrdtBehavior, CrdtType};
+use crate::anti_entropy;
 use crate::message_data::{NodeUpdateMsg, PeerNodeMsg, OpsInstance, SDPOpsType};
-use crate::vector_clock::{VectorClockError, VCStatus};
+use crate::vector_clock::{VCStatus, VectorClock};
+use crate::error::CrdtError;
 
 #[derive(Debug)]
 pub struct PNCounter;
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct PNCounterData {
     pcount: PNCntOpsValue,
     ncount: PNCntOpsValue
 }
+impl Default for PNCounterData {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl PNCounterData {
     pub fn new() -> Self {
         Self{pcount:0, ncount:0}
     }
 }
 
+impl CrdtBehavior<PNCounterData, PNCntOpsValue> for PNCounter {
+    const CRDT_TYPE: CrdtType = CrdtType::PNCounterCrdt;
+
+    fn check_ops(crdt_value: &PNCounterData, ops_instance: &OpsInstance<PNCntOpsValue>) -> Result<(), CrdtError> {
+        let count = match ops_instance.ops_type {
+                        SDPOpsType::SDPAdd  => crdt_value.pcount,
+                        SDPOpsType::SDPMult => crdt_value.ncount
+                    };
+        count.checked_add(ops_instance.ops_value).map(|_| ()).ok_or(CrdtError::Overflow("pn counter"))
+    }
+
+    fn inverse_ops(_crdt_value: &PNCounterData, ops_instance: &OpsInstance<PNCntOpsValue>) -> 
+        Result<Option<OpsInstance<PNCntOpsValue>>, CrdtError> {
+        let ops_value = ops_instance.ops_value;
+        match (&ops_instance.ops_type, ops_value) {
+            (_, 0)                   => Ok(None),
+            (SDPOpsType::SDPAdd, _)  => Ok(Some(OpsInstance::new(SDPOpsType::SDPMult, ops_value))),
+            (SDPOpsType::SDPMult, _) => Ok(Some(OpsInstance::new(SDPOpsType::SDPAdd, ops_value)))
+        }
+    }
+}
+
 impl CRDT<PNCounterData, PNCntOpsValue, PNCounter> {
     pub fn process_local_msg(&mut self, msg: NodeUpdateMsg<PNCntOpsValue>) -> 
-        Result<HashMap<NodeType, Vec<PeerNodeMsg<PNCntOpsValue>>>, VectorClockError> {
-        self.process_msg(&msg)?;
+        Result<HashMap<NodeType, Vec<PeerNodeMsg<PNCntOpsValue>>>, CrdtError> {
+        self.check_local_msg(&msg)?;
+        let undo = self.prepare_undo(&msg)?;
+        self.process_msg_with(&msg, Self::process_msg)?;
+        self.record_undo(undo);
         self.general_process_local_msg(msg)
     }
 
+    pub fn undo(&mut self, msg: &NodeUpdateMsg<PNCntOpsValue>) -> 
+        Result<HashMap<NodeType, Vec<PeerNodeMsg<PNCntOpsValue>>>, CrdtError> {
+        self.undo_with(msg, Self::process_local_msg)
+    }
+
+    pub fn redo(&mut self, msg: &NodeUpdateMsg<PNCntOpsValue>) -> 
+        Result<HashMap<NodeType, Vec<PeerNodeMsg<PNCntOpsValue>>>, CrdtError> {
+        self.redo_with(msg, Self::process_local_msg)
+    }
+
     pub fn process_peer_msg(&mut self, pmsg_list: Vec<PeerNodeMsg<PNCntOpsValue>>) ->
-        Result<HashMap<NodeType, Vec<PeerNodeMsg<PNCntOpsValue>>>, VectorClockError> {
+        Result<HashMap<NodeType, Vec<PeerNodeMsg<PNCntOpsValue>>>, CrdtError> {
+        let mut ctrl_msg_map = HashMap::new();
         for msg in pmsg_list {
+            let msg = match self.accept_peer_msg(msg)? {
+                Some(msg) => msg,
+                None      => continue
+            };
             match msg {
                 PeerNodeMsg::VectorClockNodeMsg(vmsg) =>    
                     self.general_process_vc_msg(vmsg)?,
                 PeerNodeMsg::UpdateNodeMsg(umsg)      =>    
                     {   let vc_status = self.general_process_peer_msg(umsg.clone())?;
                         if vc_status == VCStatus::INORDER {
-                            self.process_msg(&umsg)?
+                            self.process_msg_with(&umsg, Self::process_msg)?
                         }
                     }
+                cmsg                                  =>
+                    self.general_process_ctrl_msg(cmsg, &mut ctrl_msg_map)?
             }
         }
         self.causally_stable()?;
-        let msg_list = self.create_peer_msg_list(false)?;
-        if msg_list.len() > 0 {
+        let mut msg_list = self.create_peer_msg_list(false)?;
+        if !msg_list.is_empty() {
             self.msg_count_vc = 0;
         }
+        anti_entropy::merge_peer_msg_map(&mut msg_list, ctrl_msg_map);
         Ok(msg_list)
     }
 
-    pub fn process_msg(&mut self, msg: &NodeUpdateMsg<PNCntOpsValue>) -> Result<(), VectorClockError>{
+    pub fn query_at(&self, vc: &VectorClock) -> Result<PNCounterData, CrdtError> {
+        self.query_at_with(self.get_epoch(), vc, Self::process_msg)
+    }
+
+    pub fn query_at_epoch(&self, epoch: EpochType, vc: &VectorClock) -> Result<PNCounterData, CrdtError> {
+        self.query_at_with(epoch, vc, Self::process_msg)
+    }
+
+    // local overflow is refused by check_ops; a peer operation is already delivered when it
+    // is applied, so counts wrap instead, the same way on every replica and in any order
+    pub fn process_msg(&mut self, msg: &NodeUpdateMsg<PNCntOpsValue>) -> Result<(), CrdtError>{
+        let ops_value = msg.user_update_msg.ops_instance.ops_value;
         match msg.user_update_msg.ops_instance.ops_type {
             SDPOpsType::SDPAdd  => self.crdt_value = 
-                                   PNCounterData{pcount: self.crdt_value.pcount+msg.user_update_msg.ops_instance.ops_value,
+                                   PNCounterData{pcount: self.crdt_value.pcount.wrapping_add(ops_value),
                                                  ncount: self.crdt_value.ncount},
             SDPOpsType::SDPMult => self.crdt_value = 
                                    PNCounterData{pcount: self.crdt_value.pcount,
-                                                 ncount: self.crdt_value.ncount+msg.user_update_msg.ops_instance.ops_value}
+                                                 ncount: self.crdt_value.ncount.wrapping_add(ops_value)}
         };
         Ok(())
     }
@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::NodeType;
+use crate::crdt::{CRDT, CrdtBehavior};
+use crate::message_data::{DeltaNodeUpdateMsg, NodeUpdateMsg, NodeVectorClockMsg, PeerNodeMsg};
+use crate::error::CrdtError;
+use crate::wire::{self, WireOptions, WireValue, WIRE_VERSION, WIRE_MIN_VERSION};
+use crate::constants::PROTOCOL_MAX_VERSION;
+
+// one version number covers both encodings:
+// 1 - json messages as a bare array, binary frames without message lengths; only clock
+//     and full update messages
+// 2 - json messages in an envelope, binary frames with a length per message; delta,
+//     digest and merkle messages
+// 3 - update and clock messages may carry signatures
+// 4 - updates may carry the hashes of their causal predecessors
+// 5 - update and clock messages may carry the clock epoch
+pub const PROTOCOL_VERSION: u16     = WIRE_VERSION as u16;
+pub const PROTOCOL_MIN_VERSION: u16 = WIRE_MIN_VERSION as u16;
+
+const PEER_MSG_KIND_LIST: [&str; 8] = ["VectorClockNodeMsg", "UpdateNodeMsg", "DeltaUpdateNodeMsg", "DigestRequestMsg",
+                                       "DigestReplyMsg", "DigestAckMsg", "MerkleRequestMsg", "MerkleReplyMsg"];
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct HelloMsg {
+    pub node: NodeType,
+    pub min_version: u16,
+    pub max_version: u16
+}
+impl HelloMsg {
+    pub fn new(node: NodeType, min_version: u16, max_version: u16) -> Self {
+        Self{node, min_version, max_version}
+    }
+}
+
+// the highest version both sides speak
+pub fn negotiate(local: &HelloMsg, peer: &HelloMsg) -> Option<u16> {
+    let version = local.max_version.min(peer.max_version);
+    (version >= local.min_version.max(peer.min_version)).then_some(version)
+}
+
+#[derive(Debug)]
+pub struct ProtocolState {
+    pub min_version: u16,
+    pub max_version: u16,
+    pub peer_version_list: HashMap<NodeType, u16>
+}
+
+impl ProtocolState {
+    pub fn new(max_version: u16) -> Self {
+        Self{min_version: PROTOCOL_MIN_VERSION, max_version, peer_version_list: HashMap::new()}
+    }
+
+    pub fn from_env() -> Result<Self, CrdtError> {
+        let max_version = match PROTOCOL_MAX_VERSION.to_owned() {
+                              0       => PROTOCOL_VERSION,
+                              version => version
+                          };
+        if !(PROTOCOL_MIN_VERSION..=PROTOCOL_VERSION).contains(&max_version) {
+            return Err(CrdtError::UnsupportedVersion(max_version));
+        }
+        Ok(Self::new(max_version))
+    }
+
+    // until a peer says hello it is sent the oldest version, which every release reads
+    pub fn version_for(&self, pnode: &NodeType) -> u16 {
+        self.peer_version_list.get(pnode).copied().unwrap_or(self.min_version)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct MsgEnvelope {
+    version: u16,
+    node: NodeType,
+    msg_list: Vec<Value>
+}
+
+#[derive(Debug, Clone)]
+pub struct DecodedMsgList<OpsValue: Clone+PartialEq> {
+    pub version: u16,
+    pub node: Option<NodeType>,
+    pub msg_list: Vec<PeerNodeMsg<OpsValue>>,
+    pub skipped_count: usize
+}
+
+fn encode_error(e: serde_json::Error) -> CrdtError {
+    CrdtError::EncodeError(e.to_string())
+}
+
+fn decode_error(e: serde_json::Error) -> CrdtError {
+    CrdtError::DecodeError(e.to_string())
+}
+
+fn check_version(version: u16) -> Result<(), CrdtError> {
+    match (PROTOCOL_MIN_VERSION..=PROTOCOL_VERSION).contains(&version) {
+        true  => Ok(()),
+        false => Err(CrdtError::UnsupportedVersion(version))
+    }
+}
+
+// a message kind added by a newer release is skipped; anything else that fails to
+// decode is an error, since unknown fields are already ignored by serde
+fn is_unknown_kind(value: &Value) -> bool {
+    match value {
+        Value::String(kind) => !PEER_MSG_KIND_LIST.contains(&kind.as_str()),
+        Value::Object(map)  => map.len() == 1 && map.keys().all(|kind| !PEER_MSG_KIND_LIST.contains(&kind.as_str())),
+        _                   => false
+    }
+}
+
+fn decode_value_list<OpsValue: Clone+PartialEq+DeserializeOwned>(value_list: Vec<Value>) ->
+    Result<(Vec<PeerNodeMsg<OpsValue>>, usize), CrdtError> {
+    let mut msg_list = Vec::with_capacity(value_list.len());
+    let mut skipped_count = 0;
+    for value in value_list {
+        if is_unknown_kind(&value) {
+            skipped_count += 1;
+            continue;
+        }
+        msg_list.push(serde_json::from_value(value).map_err(decode_error)?);
+    }
+    Ok((msg_list, skipped_count))
+}
+
+fn downgrade_vc_msg(version: u16, mut vmsg: NodeVectorClockMsg) -> NodeVectorClockMsg {
+    if version < 3 {
+        vmsg.signature = None;
+    }
+    if version < 5 {
+        vmsg.epoch = 0;
+        vmsg.reset_pending = false;
+    }
+    vmsg
+}
+
+fn downgrade_update_msg<OpsValue: Clone+PartialEq>(version: u16, mut umsg: NodeUpdateMsg<OpsValue>) -> NodeUpdateMsg<OpsValue> {
+    if version < 3 {
+        umsg.signature = None;
+    }
+    if version < 4 {
+        umsg.pred_hash_list.clear();
+    }
+    if version < 5 {
+        umsg.epoch = 0;
+    }
+    umsg
+}
+
+fn downgrade_delta_msg<OpsValue: Clone+PartialEq>(version: u16, mut dmsg: DeltaNodeUpdateMsg<OpsValue>) -> DeltaNodeUpdateMsg<OpsValue> {
+    if version < 3 {
+        dmsg.signature = None;
+    }
+    if version < 4 {
+        dmsg.pred_hash_list.clear();
+    }
+    if version < 5 {
+        dmsg.epoch = 0;
+    }
+    dmsg
+}
+
+// a peer on an older version gets only what it decodes: fields it predates are cleared and
+// kinds it predates are dropped, anti-entropy covering for a dropped delta; a signature
+// covers the predecessor hashes and the epoch, so peers before those fields cannot verify
+// updates carrying them, and a mixed cluster keeps them off until every node upgrades
+pub fn downgrade_msg_list<OpsValue: Clone+PartialEq>(version: u16, msg_list: &[PeerNodeMsg<OpsValue>]) -> Vec<PeerNodeMsg<OpsValue>> {
+    msg_list.iter()
+            .cloned()
+            .filter_map(|msg| match msg {
+                PeerNodeMsg::VectorClockNodeMsg(vmsg) => Some(PeerNodeMsg::VectorClockNodeMsg(downgrade_vc_msg(version, vmsg))),
+                PeerNodeMsg::UpdateNodeMsg(umsg)      => Some(PeerNodeMsg::UpdateNodeMsg(downgrade_update_msg(version, umsg))),
+                _ if version < 2                      => None,
+                PeerNodeMsg::DeltaUpdateNodeMsg(dmsg) => Some(PeerNodeMsg::DeltaUpdateNodeMsg(downgrade_delta_msg(version, dmsg))),
+                PeerNodeMsg::DigestRequestMsg(vmsg)   => Some(PeerNodeMsg::DigestRequestMsg(downgrade_vc_msg(version, vmsg))),
+                PeerNodeMsg::DigestReplyMsg(vmsg)     => Some(PeerNodeMsg::DigestReplyMsg(downgrade_vc_msg(version, vmsg))),
+                PeerNodeMsg::DigestAckMsg(vmsg)       => Some(PeerNodeMsg::DigestAckMsg(downgrade_vc_msg(version, vmsg))),
+                msg                                   => Some(msg)
+            })
+            .collect()
+}
+
+pub fn encode_json_msg_list<OpsValue: Clone+PartialEq+Serialize>(version: u16, node: NodeType, msg_list: &[PeerNodeMsg<OpsValue>]) ->
+    Result<Vec<u8>, CrdtError> {
+    check_version(version)?;
+    let msg_list = match version < PROTOCOL_VERSION {
+                       true  => downgrade_msg_list(version, msg_list),
+                       false => msg_list.to_vec()
+                   };
+    if version == 1 {
+        return serde_json::to_vec(&msg_list).map_err(encode_error);
+    }
+    let msg_list = msg_list.iter().map(serde_json::to_value).collect::<Result<Vec<_>, _>>().map_err(encode_error)?;
+    serde_json::to_vec(&MsgEnvelope{version, node, msg_list}).map_err(encode_error)
+}
+
+pub fn decode_json_msg_list<OpsValue: Clone+PartialEq+DeserializeOwned>(bytes: &[u8]) -> Result<DecodedMsgList<OpsValue>, CrdtError> {
+    let (version, node, value_list) = match serde_json::from_slice(bytes).map_err(decode_error)? {
+        Value::Array(value_list) => (1, None, value_list),
+        value                    => {
+            let envelope: MsgEnvelope = serde_json::from_value(value).map_err(decode_error)?;
+            (envelope.version, Some(envelope.node), envelope.msg_list)
+        }
+    };
+    check_version(version)?;
+    let (msg_list, skipped_count) = decode_value_list(value_list)?;
+    Ok(DecodedMsgList{version, node, msg_list, skipped_count})
+}
+
+pub fn encode_binary_msg_list<OpsValue: WireValue>(version: u16, msg_list: &[PeerNodeMsg<OpsValue>], options: WireOptions) ->
+    Result<Vec<u8>, CrdtError> {
+    check_version(version)?;
+    wire::encode_peer_msg_list(msg_list, options.with_version(version as u8))
+}
+
+pub fn decode_binary_msg_list<OpsValue: WireValue>(bytes: &[u8]) -> Result<DecodedMsgList<OpsValue>, CrdtError> {
+    let frame = wire::decode_peer_frame(bytes)?;
+    Ok(DecodedMsgList{version: frame.version as u16, node: None, msg_list: frame.msg_list, skipped_count: frame.skipped_count})
+}
+
+impl <CrdtValue: Clone+Debug,
+      OpsValue: Clone+PartialEq+Debug+Serialize+DeserializeOwned,
+      State: Debug+CrdtBehavior<CrdtValue, OpsValue>> CRDT<CrdtValue, OpsValue, State> {
+    pub fn hello_msg(&self) -> HelloMsg {
+        HelloMsg::new(self.trcb.node, self.protocol.min_version, self.protocol.max_version)
+    }
+
+    pub fn process_hello_msg(&mut self, msg: &HelloMsg) -> Result<u16, CrdtError> {
+        if !self.trcb.node_trcb.contains_key(&msg.node) {
+            return Err(CrdtError::UnknownNode(msg.node));
+        }
+        let version = negotiate(&self.hello_msg(), msg).ok_or(CrdtError::UnsupportedVersion(msg.max_version))?;
+        self.protocol.peer_version_list.insert(msg.node, version);
+        Ok(version)
+    }
+
+    pub fn peer_version(&self, pnode: &NodeType) -> u16 {
+        self.protocol.version_for(pnode)
+    }
+
+    // deltas go out whole to a peer predating them, since this replica knows their base
+    pub fn encode_msg_list_for(&self, pnode: &NodeType, msg_list: &[PeerNodeMsg<OpsValue>]) -> Result<Vec<u8>, CrdtError> {
+        let version = self.peer_version(pnode);
+        if version >= 2 {
+            return encode_json_msg_list(version, self.trcb.node, msg_list);
+        }
+        let msg_list: Vec<PeerNodeMsg<OpsValue>> = msg_list.iter().map(|msg| self.expand_peer_msg(msg.clone())).collect();
+        encode_json_msg_list(version, self.trcb.node, &msg_list)
+    }
+
+    pub fn wire_options_for(&self, pnode: &NodeType, delta_clock: bool) -> WireOptions {
+        WireOptions::new(delta_clock).with_version(self.peer_version(pnode) as u8)
+    }
+}
@@ -53,7 +53,7 @@ pub fn test_random(){
         if process_msg {
             let mut vc_result = Vec::new();
             for (pnode, pmsg_list) in result {
-                let _ = match pnode {
+                match pnode {
                     0 => vc_result.push(ni0.awset_crdt.process_peer_msg(pmsg_list).unwrap()),
                     1 => vc_result.push(ni1.awset_crdt.process_peer_msg(pmsg_list).unwrap()),
                     2 => vc_result.push(ni2.awset_crdt.process_peer_msg(pmsg_list).unwrap()),
@@ -6,7 +6,7 @@ pub fn get_rand(low: u16, high: u16) -> u16 {
 }
 
 pub fn get_bool_index() -> bool {
-    get_rand(0, 100) % 2 == 0
+    get_rand(0, 100).is_multiple_of(2)
 }
 
 pub fn get_node_index(node_list: u16) -> u16 {
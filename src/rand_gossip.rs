@@ -0,0 +1,117 @@
+use std::collections::VecDeque;
+use rand::{Rng, SeedableRng};
+use rand::rngs::SmallRng;
+use anyhow::Result;
+
+use crate::{NodeType, PNCntOpsValue};
+use crate::crdt::{CRDT, CrdtInstance, CrdtType};
+use crate::gossip::{GossipConfig, GossipMode, GossipState};
+use crate::message_data::{PeerNodeMsg, UserUpdateMsg};
+use crate::pncnt_crdt::{PNCounter, PNCounterData};
+use crate::vector_clock::VCOrdering;
+use crate::error::CrdtError;
+
+#[derive(Debug, Clone)]
+pub struct GossipSimConfig {
+    pub node_count: NodeType,
+    pub op_rounds: u64,
+    pub op_rate_pct: u16,
+    pub loss_pct: u16,
+    pub max_rounds: u64,
+    pub seed: u64
+}
+
+#[derive(Debug, Clone)]
+pub struct GossipSimReport {
+    pub fanout: usize,
+    pub mode: GossipMode,
+    pub converge_round: Option<u64>,
+    pub converge_ms: Option<u64>,
+    pub msg_count: u64,
+    pub byte_count: u64
+}
+
+type PNCounterCrdt = CRDT<PNCounterData, PNCntOpsValue, PNCounter>;
+
+pub fn simulate(gossip_config: GossipConfig, sim_config: &GossipSimConfig) -> Result<GossipSimReport, CrdtError> {
+    let node_list: Vec<NodeType> = (0..sim_config.node_count).collect();
+    let mut rng = SmallRng::seed_from_u64(sim_config.seed);
+    let mut node_crdt_list = Vec::new();
+    for node in node_list.iter() {
+        let mut crdt: PNCounterCrdt = CRDT::new_with_node_list(*node, node_list.clone(), PNCounterData::new())?;
+        crdt.set_gossip(Some(GossipState::new_with_seed(gossip_config.clone(), sim_config.seed.wrapping_add(*node as u64))));
+        node_crdt_list.push(crdt);
+    }
+
+    let crdt_instance = CrdtInstance::new_default(CrdtType::PNCounterCrdt);
+    let mut report = GossipSimReport{fanout: gossip_config.fanout,
+                                     mode: gossip_config.mode,
+                                     converge_round: None,
+                                     converge_ms: None,
+                                     msg_count: 0,
+                                     byte_count: 0};
+
+    for round in 0..sim_config.max_rounds {
+        let mut queue = VecDeque::new();
+        for crdt in node_crdt_list.iter_mut() {
+            if round < sim_config.op_rounds && rng.gen_range(0..100) < sim_config.op_rate_pct {
+                let user_update_msg = UserUpdateMsg::new(crdt_instance.clone(), crdt.get_add_ops(1));
+                let node_update_msg = crdt.create_local_msg(user_update_msg)?;
+                queue.extend(crdt.process_local_msg(node_update_msg)?);
+            }
+            queue.extend(crdt.create_gossip_msg_list()?);
+        }
+
+        while let Some((pnode, pmsg_list)) = queue.pop_front() {
+            if rng.gen_range(0..100) < sim_config.loss_pct {
+                continue;
+            }
+            report.msg_count += pmsg_list.len() as u64;
+            report.byte_count += msg_bytes(&pmsg_list);
+            queue.extend(node_crdt_list[pnode as usize].process_peer_msg(pmsg_list)?);
+        }
+
+        if round >= sim_config.op_rounds && converged(&node_crdt_list)? {
+            report.converge_round = Some(round - sim_config.op_rounds);
+            report.converge_ms = Some((round - sim_config.op_rounds)*gossip_config.period_ms);
+            break;
+        }
+    }
+
+    Ok(report)
+}
+
+fn msg_bytes(pmsg_list: &[PeerNodeMsg<PNCntOpsValue>]) -> u64 {
+    pmsg_list.iter()
+             .map(|pmsg| serde_json::to_vec(pmsg).map_or(0, |bytes| bytes.len() as u64))
+             .sum()
+}
+
+fn converged(node_crdt_list: &[PNCounterCrdt]) -> Result<bool, CrdtError> {
+    let first = &node_crdt_list[0];
+    for crdt in node_crdt_list.iter().skip(1) {
+        if crdt.trcb.node_vector_clock.cmp_vc(&first.trcb.node_vector_clock)? != VCOrdering::VCEQ ||
+            crdt.query() != first.query() {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+pub fn test_random() {
+    for node_count in [5, 25] {
+        let sim_config = GossipSimConfig{node_count, op_rounds: 20, op_rate_pct: 30, loss_pct: 5, max_rounds: 200, seed: 7};
+        let mut fanout_list = vec![1, 2, 3];
+        fanout_list.push(node_count as usize - 1);
+        for mode in [GossipMode::Random, GossipMode::RoundRobin] {
+            for fanout in fanout_list.iter() {
+                let report = simulate(GossipConfig::new(*fanout, 1000, mode), &sim_config).unwrap();
+                assert!(report.converge_ms.is_some(), "{} nodes {:?} fanout {} never converged", node_count, mode, fanout);
+                println!("nodes {:>3} mode {:<10} fanout {:>2} converge_ms {:>8} msgs {:>8} bytes {:>10}",
+                    node_count, format!("{:?}", report.mode), report.fanout,
+                    report.converge_ms.map_or("never".to_owned(), |ms| ms.to_string()),
+                    report.msg_count, report.byte_count);
+            }
+        }
+    }
+}
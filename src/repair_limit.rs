@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use anyhow::Result;
+
+use crate::{LCType, NodeType};
+use crate::crdt::{CRDT, CrdtBehavior};
+use crate::message_data::NodeUpdateMsg;
+use crate::error::CrdtError;
+use crate::constants::{REPAIR_MAX_BATCH_COUNT, REPAIR_MAX_BATCH_BYTES, REPAIR_BUDGET_BYTES_PER_SEC};
+
+#[derive(Debug, Clone, Default)]
+pub struct RepairConfig {
+    pub max_batch_count: Option<usize>,
+    pub max_batch_bytes: Option<usize>,
+    pub budget_bytes_per_sec: Option<u64>
+}
+
+impl RepairConfig {
+    pub fn new(max_batch_count: Option<usize>, max_batch_bytes: Option<usize>, budget_bytes_per_sec: Option<u64>) -> Self {
+        Self{max_batch_count, max_batch_bytes, budget_bytes_per_sec}
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(limit(*REPAIR_MAX_BATCH_COUNT).map(|count| count as usize),
+                  limit(*REPAIR_MAX_BATCH_BYTES).map(|bytes| bytes as usize),
+                  limit(*REPAIR_BUDGET_BYTES_PER_SEC))
+    }
+
+    pub fn is_unlimited(&self) -> bool {
+        self.max_batch_count.is_none() && self.max_batch_bytes.is_none() && self.budget_bytes_per_sec.is_none()
+    }
+}
+
+fn limit(value: u64) -> Option<u64> {
+    match value {
+        0     => None,
+        value => Some(value)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct PeerRepair {
+    sent_list: HashMap<NodeType, LCType>,
+    tokens: i64
+}
+
+#[derive(Debug)]
+pub struct RepairLimiter {
+    pub config: RepairConfig,
+    peer_list: HashMap<NodeType, PeerRepair>,
+    last_tick: Option<u64>
+}
+
+impl RepairLimiter {
+    pub fn new(config: RepairConfig) -> Self {
+        Self{config, peer_list: HashMap::new(), last_tick: None}
+    }
+
+    // the bucket holds at most one second of budget and is refilled by elapsed time
+    pub fn refill(&mut self, now: u64) {
+        let elapsed_ms = self.last_tick.map_or(0, |last_tick| now.saturating_sub(last_tick));
+        self.last_tick = Some(now);
+        if let Some(budget) = self.config.budget_bytes_per_sec {
+            let budget = budget as i64;
+            for prepair in self.peer_list.values_mut() {
+                prepair.tokens = (prepair.tokens + budget*elapsed_ms as i64/1000).min(budget);
+            }
+        }
+    }
+
+    pub fn reset_cursor(&mut self, pnode: NodeType) {
+        if let Some(prepair) = self.peer_list.get_mut(&pnode) {
+            prepair.sent_list.clear();
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for prepair in self.peer_list.values_mut() {
+            prepair.sent_list.clear();
+        }
+    }
+
+    pub fn tokens(&self, pnode: &NodeType) -> Option<i64> {
+        self.config.budget_bytes_per_sec.map(|budget| self.peer_list.get(pnode).map_or(budget as i64, |prepair| prepair.tokens))
+    }
+
+    // missing_list is causally sorted, so any prefix of it holds a contiguous run per origin
+    // and the cursor can be kept as the highest lc sent per origin; the budget may go into
+    // debt by one message so an oversized message cannot stall repair
+    pub fn take_page<OpsValue: Clone+PartialEq+Serialize>(&mut self, pnode: NodeType, missing_list: Vec<NodeUpdateMsg<OpsValue>>) ->
+        Vec<NodeUpdateMsg<OpsValue>> {
+        if self.config.is_unlimited() {
+            return missing_list;
+        }
+
+        let budget = self.config.budget_bytes_per_sec;
+        let prepair = self.peer_list.entry(pnode)
+                                    .or_insert_with(|| PeerRepair{sent_list: HashMap::new(), tokens: budget.unwrap_or(0) as i64});
+        let mut page = Vec::new();
+        let mut page_bytes = 0;
+        for msg in missing_list {
+            let lc = msg.node_vector_clock.vcmap.get(&msg.node).copied().unwrap_or(0);
+            if prepair.sent_list.get(&msg.node).is_some_and(|sent_lc| lc <= *sent_lc) {
+                continue;
+            }
+            if self.config.max_batch_count.is_some_and(|max_count| page.len() >= max_count) {
+                break;
+            }
+            let msg_bytes = serde_json::to_vec(&msg).map_or(0, |bytes| bytes.len());
+            if !page.is_empty() && self.config.max_batch_bytes.is_some_and(|max_bytes| page_bytes + msg_bytes > max_bytes) {
+                break;
+            }
+            if budget.is_some() {
+                if prepair.tokens <= 0 {
+                    break;
+                }
+                prepair.tokens -= msg_bytes as i64;
+            }
+            page_bytes += msg_bytes;
+            prepair.sent_list.insert(msg.node, lc);
+            page.push(msg);
+        }
+        page
+    }
+}
+
+impl <CrdtValue: Clone+Debug,
+      OpsValue: Clone+PartialEq+Debug+Serialize+DeserializeOwned,
+      State: Debug+CrdtBehavior<CrdtValue, OpsValue>> CRDT<CrdtValue, OpsValue, State> {
+    pub fn set_repair_config(&mut self, config: RepairConfig) {
+        self.repair_limiter = RepairLimiter::new(config);
+    }
+
+    // pushes continue from the cursor; a digest request states what the peer really holds,
+    // so it resets the cursor and lost pages are sent again
+    pub fn repair_msg_list(&mut self, pnode: NodeType, missing_list: Vec<NodeUpdateMsg<OpsValue>>, reset_cursor: bool) ->
+        Result<Vec<NodeUpdateMsg<OpsValue>>, CrdtError> {
+        if !self.trcb.node_trcb.contains_key(&pnode) {
+            return Err(CrdtError::UnknownNode(pnode));
+        }
+        if reset_cursor {
+            self.repair_limiter.reset_cursor(pnode);
+        }
+        Ok(self.repair_limiter.take_page(pnode, missing_list))
+    }
+}
@@ -0,0 +1,72 @@
+use std::collections::HashSet;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use anyhow::Result;
+
+use crate::{NodeType,
+            IntMultCrdtValue, IntMultOpsValue,
+            EDFlagCrdtValue, EDFlagOpsValue,
+            ARSetOpsValue,
+            PNCntOpsValue};
+use crate::crdt::CRDT;
+use crate::add_mult_crdt::AddMult;
+use crate::edflag_crdt::{EWFlag, DWFlag};
+use crate::arset_crdt::{AWSet, RWSet};
+use crate::pncnt_crdt::{PNCounter, PNCounterData};
+use crate::message_data::{PeerMsgMap, PeerNodeMsg, UserUpdateMsg};
+use crate::error::CrdtError;
+
+// the per-type calls drivers like the actor and the cli need, which the crdt types only
+// have as inherent methods
+pub trait Replica: Send+'static {
+    type Value: Clone+Serialize+Send+'static;
+    type Ops: Clone+PartialEq+Serialize+DeserializeOwned+Send+'static;
+
+    fn node(&self) -> NodeType;
+    fn update(&mut self, umsg: UserUpdateMsg<Self::Ops>) -> Result<PeerMsgMap<Self::Ops>, CrdtError>;
+    fn peer_update(&mut self, pmsg_list: Vec<PeerNodeMsg<Self::Ops>>) -> Result<PeerMsgMap<Self::Ops>, CrdtError>;
+    fn tick(&mut self, now: u64) -> Result<PeerMsgMap<Self::Ops>, CrdtError>;
+    fn value(&self) -> Self::Value;
+    fn take_rejection_list(&mut self) -> Vec<CrdtError>;
+}
+
+macro_rules! impl_replica {
+    ($crdt_value:ty, $ops_value:ty, $state:ty) => {
+        impl Replica for CRDT<$crdt_value, $ops_value, $state> {
+            type Value = $crdt_value;
+            type Ops = $ops_value;
+
+            fn node(&self) -> NodeType {
+                self.get_node()
+            }
+
+            fn update(&mut self, umsg: UserUpdateMsg<$ops_value>) -> Result<PeerMsgMap<$ops_value>, CrdtError> {
+                let msg = self.create_local_msg(umsg)?;
+                self.process_local_msg(msg)
+            }
+
+            fn peer_update(&mut self, pmsg_list: Vec<PeerNodeMsg<$ops_value>>) -> Result<PeerMsgMap<$ops_value>, CrdtError> {
+                self.process_peer_msg(pmsg_list)
+            }
+
+            fn tick(&mut self, now: u64) -> Result<PeerMsgMap<$ops_value>, CrdtError> {
+                self.on_tick(now)
+            }
+
+            fn value(&self) -> $crdt_value {
+                self.query()
+            }
+
+            fn take_rejection_list(&mut self) -> Vec<CrdtError> {
+                CRDT::take_rejection_list(self)
+            }
+        }
+    };
+}
+
+impl_replica!(IntMultCrdtValue, IntMultOpsValue, AddMult);
+impl_replica!(EDFlagCrdtValue, EDFlagOpsValue, EWFlag);
+impl_replica!(EDFlagCrdtValue, EDFlagOpsValue, DWFlag);
+impl_replica!(HashSet<ARSetOpsValue>, ARSetOpsValue, AWSet);
+impl_replica!(HashSet<ARSetOpsValue>, ARSetOpsValue, RWSet);
+impl_replica!(PNCounterData, PNCntOpsValue, PNCounter);
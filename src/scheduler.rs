@@ -0,0 +1,208 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use rand::{Rng, SeedableRng};
+use rand::rngs::SmallRng;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use anyhow::Result;
+
+use crate::NodeType;
+use crate::crdt::{CRDT, CrdtBehavior};
+use crate::message_data::PeerNodeMsg;
+use crate::error::CrdtError;
+use crate::constants::{TICK_VC_INTERVAL_MS, TICK_REPAIR_INTERVAL_MS, TICK_JITTER_PCT};
+
+pub trait Clock {
+    fn now_ms(&self) -> u64;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_ms(&self) -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SimClock {
+    now_ms: AtomicU64
+}
+
+impl SimClock {
+    pub fn new(now_ms: u64) -> Self {
+        Self{now_ms: AtomicU64::new(now_ms)}
+    }
+
+    pub fn advance(&self, ms: u64) -> u64 {
+        self.now_ms.fetch_add(ms, Ordering::SeqCst) + ms
+    }
+
+    pub fn set(&self, now_ms: u64) {
+        self.now_ms.store(now_ms, Ordering::SeqCst);
+    }
+}
+
+impl Clock for SimClock {
+    fn now_ms(&self) -> u64 {
+        self.now_ms.load(Ordering::SeqCst)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TickTask {
+    VectorClock,
+    Repair
+}
+
+#[derive(Debug, Clone)]
+pub struct TickConfig {
+    pub vc_interval_ms: u64,
+    pub repair_interval_ms: u64,
+    pub jitter_pct: u16
+}
+
+impl TickConfig {
+    pub fn new(vc_interval_ms: u64, repair_interval_ms: u64, jitter_pct: u16) -> Self {
+        Self{vc_interval_ms, repair_interval_ms, jitter_pct: jitter_pct.min(100)}
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(*TICK_VC_INTERVAL_MS, *TICK_REPAIR_INTERVAL_MS, *TICK_JITTER_PCT)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Timer {
+    interval_ms: u64,
+    next_at: Option<u64>
+}
+
+impl Timer {
+    // the first deadline is drawn from the whole interval so replicas started
+    // together do not fire in lock step; later ones are interval +/- jitter
+    fn fire(&mut self, now: u64, jitter_pct: u64, rng: &mut SmallRng) -> bool {
+        if self.interval_ms == 0 {
+            return false;
+        }
+
+        match self.next_at {
+            None                          => {
+                self.next_at = Some(now.saturating_add(rng.gen_range(0..=self.interval_ms)));
+                false
+            },
+            Some(next_at) if now < next_at => false,
+            Some(_)                       => {
+                let jitter_ms = self.interval_ms*jitter_pct/100;
+                self.next_at = Some(now.saturating_add(self.interval_ms - jitter_ms + rng.gen_range(0..=2*jitter_ms)));
+                true
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Scheduler {
+    pub jitter_pct: u16,
+    pub unanswered_set: HashSet<NodeType>,
+    vc_timer: Timer,
+    repair_timer: Timer,
+    rng: SmallRng
+}
+
+impl Scheduler {
+    pub fn new(config: TickConfig) -> Self {
+        Self::new_with_rng(config, SmallRng::from_entropy())
+    }
+
+    pub fn new_with_seed(config: TickConfig, seed: u64) -> Self {
+        Self::new_with_rng(config, SmallRng::seed_from_u64(seed))
+    }
+
+    fn new_with_rng(config: TickConfig, rng: SmallRng) -> Self {
+        Self{jitter_pct: config.jitter_pct,
+             unanswered_set: HashSet::new(),
+             vc_timer: Timer{interval_ms: config.vc_interval_ms, next_at: None},
+             repair_timer: Timer{interval_ms: config.repair_interval_ms, next_at: None},
+             rng}
+    }
+
+    pub fn set_interval(&mut self, task: TickTask, interval_ms: u64) {
+        let timer = match task {
+                        TickTask::VectorClock => &mut self.vc_timer,
+                        TickTask::Repair      => &mut self.repair_timer
+                    };
+        timer.interval_ms = interval_ms;
+        timer.next_at = None;
+    }
+
+    pub fn interval(&self, task: TickTask) -> u64 {
+        match task {
+            TickTask::VectorClock => self.vc_timer.interval_ms,
+            TickTask::Repair      => self.repair_timer.interval_ms
+        }
+    }
+
+    pub fn is_due(&mut self, task: TickTask, now: u64) -> bool {
+        let timer = match task {
+                        TickTask::VectorClock => &mut self.vc_timer,
+                        TickTask::Repair      => &mut self.repair_timer
+                    };
+        timer.fire(now, self.jitter_pct as u64, &mut self.rng)
+    }
+}
+
+impl <CrdtValue: Clone+Debug,
+      OpsValue: Clone+PartialEq+Debug+Serialize+DeserializeOwned,
+      State: Debug+CrdtBehavior<CrdtValue, OpsValue>> CRDT<CrdtValue, OpsValue, State> {
+    pub fn set_scheduler(&mut self, scheduler: Scheduler) {
+        self.scheduler = scheduler;
+        if let Some(gossip) = self.gossip.as_ref() {
+            self.scheduler.set_interval(TickTask::Repair, gossip.config.period_ms);
+        }
+    }
+
+    // drives failure detection, repair and clock advertisement from time rather than
+    // from incoming traffic, so an idle replica still advertises and repairs
+    pub fn on_tick(&mut self, now: u64) -> Result<HashMap<NodeType, Vec<PeerNodeMsg<OpsValue>>>, CrdtError> {
+        self.check_peers(now)?;
+        self.check_epoch_reset_timeout(now)?;
+        self.repair_limiter.refill(now);
+
+        let mut msg_map = HashMap::new();
+        if self.scheduler.is_due(TickTask::Repair, now) {
+            if self.gossip.is_some() {
+                msg_map = self.create_gossip_msg_list()?;
+            } else {
+                // a peer answering digest requests pulls what it misses, so operations are only
+                // pushed to one that left the last request unanswered, being lost or too old
+                msg_map = self.create_digest_request();
+                for pnode in std::mem::take(&mut self.scheduler.unanswered_set) {
+                    if let Some(msg_list) = msg_map.get_mut(&pnode) {
+                        msg_list.splice(0..0, self.create_repair_msg_list_for(pnode)?);
+                    }
+                }
+                self.scheduler.unanswered_set = msg_map.keys().copied().collect();
+            }
+            if self.merkle.depth > 0 {
+                for msg_list in msg_map.values_mut() {
+                    msg_list.push(self.create_merkle_request_msg());
+                }
+            }
+        }
+
+        // peers sent a digest request above already have our clock
+        if self.scheduler.is_due(TickTask::VectorClock, now) {
+            self.msg_count_vc = 0;
+            for pnode in self.trcb.node_trcb.keys() {
+                if !self.trcb.is_evicted(pnode) && !msg_map.contains_key(pnode) {
+                    msg_map.insert(*pnode, vec![PeerNodeMsg::VectorClockNodeMsg(self.create_vc_msg())]);
+                }
+            }
+        }
+        Ok(msg_map)
+    }
+}
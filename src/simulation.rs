@@ -0,0 +1,335 @@
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use rand::{Rng, SeedableRng};
+use rand::rngs::SmallRng;
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use anyhow::Result;
+
+use crate::NodeType;
+use crate::crdt::{CRDT, CrdtBehavior, CrdtInstance, CrdtType};
+use crate::add_mult_crdt::AddMult;
+use crate::edflag_crdt::{EDFlag, EWFlag, DWFlag};
+use crate::arset_crdt::{AWSet, RWSet};
+use crate::pncnt_crdt::{PNCounter, PNCounterData};
+use crate::message_data::{OpsInstance, PeerNodeMsg, SDPOpsType, UserUpdateMsg, sorted_msg_list};
+use crate::replica::Replica;
+use crate::scheduler::{Scheduler, TickConfig};
+use crate::vector_clock::VCOrdering;
+use crate::constants::{TICK_VC_INTERVAL_MS, TICK_REPAIR_INTERVAL_MS, TICK_JITTER_PCT};
+use crate::error::CrdtError;
+
+// delays are counted in rounds, each round being tick_ms of simulated time
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum DelayDist {
+    Fixed(u64),
+    Uniform{min_rounds: u64, max_rounds: u64},
+    Exponential{mean_rounds: f64}
+}
+
+impl DelayDist {
+    pub fn sample(&self, rng: &mut SmallRng) -> u64 {
+        match self {
+            DelayDist::Fixed(rounds)                    => *rounds,
+            DelayDist::Uniform{min_rounds, max_rounds}  => rng.gen_range(*min_rounds..=*max_rounds),
+            DelayDist::Exponential{mean_rounds}         => (-mean_rounds*(1.0 - rng.gen::<f64>()).ln()).round() as u64
+        }
+    }
+}
+
+// while a partition lasts two nodes only talk if some group holds both of them
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Partition {
+    pub from_round: u64,
+    pub to_round: u64,
+    pub group_list: Vec<Vec<NodeType>>
+}
+
+impl Partition {
+    pub fn cuts(&self, round: u64, node: NodeType, pnode: NodeType) -> bool {
+        round >= self.from_round && round < self.to_round &&
+            !self.group_list.iter().any(|group| group.contains(&node) && group.contains(&pnode))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Scenario {
+    pub node_count: NodeType,
+    pub crdt_list: Vec<CrdtType>,
+    pub op_rounds: u64,
+    pub op_rate_pct: u16,
+    pub add_pct: u16,
+    pub value_min: i64,
+    pub value_max: i64,
+    pub loss_pct: u16,
+    pub delay: DelayDist,
+    pub partition_list: Vec<Partition>,
+    pub tick_ms: u64,
+    pub vc_interval_ms: u64,
+    pub repair_interval_ms: u64,
+    pub max_rounds: u64,
+    pub seed: u64
+}
+
+impl Default for Scenario {
+    fn default() -> Self {
+        Self{node_count: 5,
+             crdt_list: vec![CrdtType::PNCounterCrdt],
+             op_rounds: 50,
+             op_rate_pct: 30,
+             add_pct: 50,
+             value_min: 1,
+             value_max: 10,
+             loss_pct: 0,
+             delay: DelayDist::Fixed(0),
+             partition_list: Vec::new(),
+             tick_ms: 100,
+             vc_interval_ms: TICK_VC_INTERVAL_MS.to_owned(),
+             repair_interval_ms: TICK_REPAIR_INTERVAL_MS.to_owned(),
+             max_rounds: 1000,
+             seed: 0}
+    }
+}
+
+impl Scenario {
+    pub fn from_json(json: &str) -> Result<Self, CrdtError> {
+        let scenario: Self = serde_json::from_str(json).map_err(|e| CrdtError::DecodeError(e.to_string()))?;
+        scenario.check()?;
+        Ok(scenario)
+    }
+
+    pub fn check(&self) -> Result<(), CrdtError> {
+        let config_error = |param: &str, value: String| Err(CrdtError::ConfigError(param.to_owned(), value));
+        if self.node_count == 0 {
+            return config_error("node_count", self.node_count.to_string());
+        }
+        if self.crdt_list.is_empty() {
+            return config_error("crdt_list", "[]".to_owned());
+        }
+        if self.value_min > self.value_max {
+            return config_error("value_min", self.value_min.to_string());
+        }
+        for crdt_type in self.crdt_list.iter() {
+            let value_ok = match crdt_type {
+                               CrdtType::PNCounterCrdt                     => u32::try_from(self.value_min).is_ok() && u32::try_from(self.value_max).is_ok(),
+                               CrdtType::AWSetCrdt | CrdtType::RWSetCrdt   => i32::try_from(self.value_min).is_ok() && i32::try_from(self.value_max).is_ok(),
+                               _                                           => true
+                           };
+            if !value_ok {
+                return config_error("value_min", format!("{}..={} for {:?}", self.value_min, self.value_max, crdt_type));
+            }
+        }
+        if self.op_rate_pct > 100 || self.add_pct > 100 || self.loss_pct > 100 {
+            return config_error("pct", format!("{} {} {}", self.op_rate_pct, self.add_pct, self.loss_pct));
+        }
+        match self.delay {
+            DelayDist::Uniform{min_rounds, max_rounds} if min_rounds > max_rounds => return config_error("delay", format!("{:?}", self.delay)),
+            DelayDist::Exponential{mean_rounds} if mean_rounds.is_nan() || mean_rounds < 0.0 => return config_error("delay", format!("{:?}", self.delay)),
+            _                                                                     => ()
+        }
+        for partition in self.partition_list.iter() {
+            if partition.group_list.iter().flatten().any(|node| *node >= self.node_count) {
+                return config_error("partition_list", format!("{:?}", partition.group_list));
+            }
+        }
+        Ok(())
+    }
+
+    // convergence is timed from the last operation or the end of the last partition
+    pub fn settle_round(&self) -> u64 {
+        self.partition_list.iter().map(|partition| partition.to_round).fold(self.op_rounds, u64::max)
+    }
+
+    fn is_cut(&self, round: u64, node: NodeType, pnode: NodeType) -> bool {
+        self.partition_list.iter().any(|partition| partition.cuts(round, node, pnode))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CrdtSimReport {
+    pub crdt_type: CrdtType,
+    pub op_count: u64,
+    pub converged: bool,
+    pub converge_round: Option<u64>,
+    pub converge_ms: Option<u64>,
+    pub msg_count: u64,
+    pub byte_count: u64,
+    pub dropped_count: u64,
+    pub refused_count: u64,
+    pub final_value_list: Vec<Value>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SimReport {
+    pub scenario: Scenario,
+    pub crdt_report_list: Vec<CrdtSimReport>
+}
+
+type CreateOpsFn<CrdtValue, OpsValue, State> = fn(&CRDT<CrdtValue, OpsValue, State>, SDPOpsType, i64) -> Result<OpsInstance<OpsValue>, CrdtError>;
+
+// check has already held the range against each type, so a failed conversion is a bug here
+fn ops_value<T: TryFrom<i64>>(value: i64) -> Result<T, CrdtError> {
+    T::try_from(value).map_err(|_| CrdtError::Overflow("simulation value"))
+}
+
+// every crdt type runs on its own network drawn from the same seed, so their reports compare
+pub fn simulate(scenario: &Scenario) -> Result<SimReport, CrdtError> {
+    scenario.check()?;
+    let mut crdt_report_list = Vec::new();
+    for crdt_type in scenario.crdt_list.iter() {
+        let crdt_report = match crdt_type {
+            CrdtType::AddMultCrdt   => simulate_crdt::<i64, i64, AddMult>(scenario, 0,
+                                           |crdt, ops_type, value| match ops_type {
+                                               SDPOpsType::SDPAdd  => Ok(crdt.get_add_ops(value)),
+                                               SDPOpsType::SDPMult => Ok(crdt.get_mult_ops(value))
+                                           })?,
+            CrdtType::EWFlagCrdt    => simulate_crdt::<EDFlag, EDFlag, EWFlag>(scenario, EDFlag::Enabled,
+                                           |crdt, ops_type, _| match ops_type {
+                                               SDPOpsType::SDPAdd  => Ok(crdt.get_add_ops()),
+                                               SDPOpsType::SDPMult => Ok(crdt.get_mult_ops())
+                                           })?,
+            CrdtType::DWFlagCrdt    => simulate_crdt::<EDFlag, EDFlag, DWFlag>(scenario, EDFlag::Disabled,
+                                           |crdt, ops_type, _| match ops_type {
+                                               SDPOpsType::SDPAdd  => Ok(crdt.get_add_ops()),
+                                               SDPOpsType::SDPMult => Ok(crdt.get_mult_ops())
+                                           })?,
+            CrdtType::AWSetCrdt     => simulate_crdt::<_, i32, AWSet>(scenario, Default::default(),
+                                           |crdt, ops_type, value| match ops_type {
+                                               SDPOpsType::SDPAdd  => Ok(crdt.get_add_ops(ops_value(value)?)),
+                                               SDPOpsType::SDPMult => Ok(crdt.get_mult_ops(ops_value(value)?))
+                                           })?,
+            CrdtType::RWSetCrdt     => simulate_crdt::<_, i32, RWSet>(scenario, Default::default(),
+                                           |crdt, ops_type, value| match ops_type {
+                                               SDPOpsType::SDPAdd  => Ok(crdt.get_add_ops(ops_value(value)?)),
+                                               SDPOpsType::SDPMult => Ok(crdt.get_mult_ops(ops_value(value)?))
+                                           })?,
+            CrdtType::PNCounterCrdt => simulate_crdt::<PNCounterData, u32, PNCounter>(scenario, PNCounterData::new(),
+                                           |crdt, ops_type, value| match ops_type {
+                                               SDPOpsType::SDPAdd  => Ok(crdt.get_add_ops(ops_value(value)?)),
+                                               SDPOpsType::SDPMult => Ok(crdt.get_mult_ops(ops_value(value)?))
+                                           })?
+        };
+        crdt_report_list.push(crdt_report);
+    }
+    Ok(SimReport{scenario: scenario.clone(), crdt_report_list})
+}
+
+struct InFlight<OpsValue: Clone+PartialEq> {
+    pnode: NodeType,
+    pmsg_list: Vec<PeerNodeMsg<OpsValue>>
+}
+
+fn simulate_crdt<CrdtValue: Clone+Debug+PartialEq+Serialize,
+                 OpsValue: Clone+PartialEq+Debug+Serialize+DeserializeOwned,
+                 State: Debug+CrdtBehavior<CrdtValue, OpsValue>>
+    (scenario: &Scenario, crdt_value: CrdtValue, create_ops: CreateOpsFn<CrdtValue, OpsValue, State>) -> Result<CrdtSimReport, CrdtError>
+    where CRDT<CrdtValue, OpsValue, State>: Replica<Value = CrdtValue, Ops = OpsValue> {
+    let node_list: Vec<NodeType> = (0..scenario.node_count).collect();
+    let mut rng = SmallRng::seed_from_u64(scenario.seed);
+    let tick_config = TickConfig::new(scenario.vc_interval_ms, scenario.repair_interval_ms, TICK_JITTER_PCT.to_owned());
+    let mut node_crdt_list = Vec::new();
+    for node in node_list.iter() {
+        let mut crdt: CRDT<CrdtValue, OpsValue, State> = CRDT::new_with_node_list(*node, node_list.clone(), crdt_value.clone())?;
+        crdt.set_scheduler(Scheduler::new_with_seed(tick_config.clone(), scenario.seed.wrapping_add(*node as u64)));
+        node_crdt_list.push(crdt);
+    }
+
+    let crdt_instance = CrdtInstance::new_default(State::CRDT_TYPE);
+    let settle_round = scenario.settle_round();
+    let mut report = CrdtSimReport{crdt_type: State::CRDT_TYPE, op_count: 0, converged: false, converge_round: None, converge_ms: None,
+                                   msg_count: 0, byte_count: 0, dropped_count: 0, refused_count: 0, final_value_list: Vec::new()};
+    // keyed by delivery round, then by send order
+    let mut in_flight: BTreeMap<(u64, u64), InFlight<OpsValue>> = BTreeMap::new();
+    let mut seq = 0;
+
+    for round in 0..scenario.max_rounds {
+        let now = round.saturating_mul(scenario.tick_ms);
+        let mut out_list = Vec::new();
+        for crdt in node_crdt_list.iter_mut() {
+            if round < scenario.op_rounds && rng.gen_range(0..100) < scenario.op_rate_pct {
+                let ops_type = if rng.gen_range(0..100) < scenario.add_pct {SDPOpsType::SDPAdd} else {SDPOpsType::SDPMult};
+                let ops_instance = create_ops(crdt, ops_type, rng.gen_range(scenario.value_min..=scenario.value_max))?;
+                // a replica refusing an operation, such as one that would overflow, is part of the run
+                match crdt.update(UserUpdateMsg::new(crdt_instance.clone(), ops_instance)) {
+                    Ok(msg_map) => {
+                        out_list.push((crdt.get_node(), msg_map));
+                        report.op_count += 1;
+                    }
+                    Err(_)      => report.refused_count += 1
+                }
+            }
+            out_list.push((crdt.get_node(), crdt.tick(now)?));
+        }
+
+        // replies sent with no delay arrive within the same round
+        loop {
+            for (node, msg_map) in out_list.drain(..) {
+                for (pnode, pmsg_list) in sorted_msg_list(msg_map) {
+                    report.msg_count += pmsg_list.len() as u64;
+                    report.byte_count += msg_bytes(&pmsg_list);
+                    if scenario.is_cut(round, node, pnode) || rng.gen_range(0..100) < scenario.loss_pct {
+                        report.dropped_count += pmsg_list.len() as u64;
+                        continue;
+                    }
+                    in_flight.insert((round.saturating_add(scenario.delay.sample(&mut rng)), seq), InFlight{pnode, pmsg_list});
+                    seq += 1;
+                }
+            }
+            let due_key = match in_flight.keys().next() {
+                Some(key) if key.0 <= round => *key,
+                _                           => break
+            };
+            if let Some(msg) = in_flight.remove(&due_key) {
+                out_list.push((msg.pnode, node_crdt_list[msg.pnode as usize].peer_update(msg.pmsg_list)?));
+            }
+        }
+
+        if round >= settle_round && converged(&node_crdt_list)? {
+            report.converged = true;
+            report.converge_round = Some(round - settle_round);
+            report.converge_ms = Some((round - settle_round).saturating_mul(scenario.tick_ms));
+            break;
+        }
+    }
+
+    report.final_value_list = node_crdt_list.iter()
+                                            .map(|crdt| serde_json::to_value(crdt.query()).map(sort_set)
+                                                                                            .map_err(|e| CrdtError::EncodeError(e.to_string())))
+                                            .collect::<Result<_, CrdtError>>()?;
+    Ok(report)
+}
+
+// sets serialize in hash order, which would make equal runs report differently
+fn sort_set(value: Value) -> Value {
+    match value {
+        Value::Array(mut value_list) => {
+            value_list.sort_by(|a, b| match (a.as_i64(), b.as_i64()) {
+                                          (Some(a), Some(b)) => a.cmp(&b),
+                                          _                  => a.to_string().cmp(&b.to_string())
+                                      });
+            Value::Array(value_list)
+        }
+        value                        => value
+    }
+}
+
+fn msg_bytes<OpsValue: Clone+PartialEq+Serialize>(pmsg_list: &[PeerNodeMsg<OpsValue>]) -> u64 {
+    pmsg_list.iter()
+             .map(|pmsg| serde_json::to_vec(pmsg).map_or(0, |bytes| bytes.len() as u64))
+             .sum()
+}
+
+fn converged<CrdtValue: Clone+Debug+PartialEq,
+             OpsValue: Clone+PartialEq+Debug+Serialize+DeserializeOwned,
+             State: Debug+CrdtBehavior<CrdtValue, OpsValue>>(node_crdt_list: &[CRDT<CrdtValue, OpsValue, State>]) -> Result<bool, CrdtError> {
+    let first = &node_crdt_list[0];
+    for crdt in node_crdt_list.iter().skip(1) {
+        if crdt.trcb.node_vector_clock.cmp_vc(&first.trcb.node_vector_clock)? != VCOrdering::VCEQ ||
+            crdt.query() != first.query() {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
@@ -0,0 +1,143 @@
+use std::collections::BTreeMap;
+use std::fmt::{self, Debug};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use anyhow::Result;
+
+use crate::NodeType;
+use crate::crdt::{CRDT, CrdtBehavior, CrdtInstance};
+use crate::message_data::NodeUpdateMsg;
+use crate::vector_clock::VectorClock;
+use crate::history::ProcessMsgFn;
+use crate::error::CrdtError;
+
+pub type SubscriptionId = u64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValueChange<OpsValue> {
+    Added(OpsValue),
+    Removed(OpsValue)
+}
+
+#[derive(Debug, Clone)]
+pub struct ChangeEvent<CrdtValue, OpsValue> {
+    pub crdt_instance: CrdtInstance,
+    pub old_value: CrdtValue,
+    pub new_value: CrdtValue,
+    pub node: NodeType,
+    pub node_vector_clock: VectorClock,
+    pub change_list: Vec<ValueChange<OpsValue>>
+}
+
+pub type Listener<CrdtValue, OpsValue> = Box<dyn FnMut(&ChangeEvent<CrdtValue, OpsValue>)+Send>;
+
+pub type ValueEqFn<CrdtValue> = fn(&CrdtValue, &CrdtValue) -> bool;
+
+// value_eq is kept by subscribe, so code without a PartialEq bound can still tell a change
+pub struct SubscriptionState<CrdtValue, OpsValue> {
+    pub next_id: SubscriptionId,
+    pub listener_list: BTreeMap<SubscriptionId, (CrdtInstance, Listener<CrdtValue, OpsValue>)>,
+    pub value_eq: Option<ValueEqFn<CrdtValue>>
+}
+
+impl <CrdtValue, OpsValue> SubscriptionState<CrdtValue, OpsValue> {
+    pub fn new() -> Self {
+        Self{next_id: 0, listener_list: BTreeMap::new(), value_eq: None}
+    }
+
+    pub fn is_watched(&self, crdt_instance: &CrdtInstance) -> bool {
+        self.listener_list.values().any(|(instance, _)| instance == crdt_instance)
+    }
+}
+
+impl <CrdtValue, OpsValue> Default for SubscriptionState<CrdtValue, OpsValue> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl <CrdtValue, OpsValue> Debug for SubscriptionState<CrdtValue, OpsValue> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SubscriptionState")
+         .field("next_id", &self.next_id)
+         .field("listener_list", &self.listener_list.iter().map(|(id, (instance, _))| (id, instance)).collect::<Vec<_>>())
+         .finish()
+    }
+}
+
+impl <CrdtValue: Clone+Debug+PartialEq,
+      OpsValue: Clone+PartialEq+Debug+Serialize+DeserializeOwned,
+      State: Debug+CrdtBehavior<CrdtValue, OpsValue>> CRDT<CrdtValue, OpsValue, State> {
+    pub fn subscribe(&mut self, crdt_instance: CrdtInstance, listener: Listener<CrdtValue, OpsValue>) -> SubscriptionId {
+        let id = self.subscription.next_id;
+        self.subscription.next_id += 1;
+        self.subscription.listener_list.insert(id, (crdt_instance, listener));
+        self.subscription.value_eq = Some(CrdtValue::eq);
+        id
+    }
+
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        self.subscription.listener_list.remove(&id).is_some()
+    }
+
+    // listeners run after the operation is applied and only when it changed the value,
+    // so a remove losing to a concurrent add or a duplicate add stays silent
+    pub fn process_msg_with(&mut self, msg: &NodeUpdateMsg<OpsValue>, process_msg: ProcessMsgFn<CrdtValue, OpsValue, State>) ->
+        Result<(), CrdtError> {
+        let crdt_instance = &msg.user_update_msg.crdt_instance;
+        if !self.subscription.is_watched(crdt_instance) {
+            return process_msg(self, msg);
+        }
+        let old_value = self.crdt_value.clone();
+        process_msg(self, msg)?;
+        if old_value == self.crdt_value {
+            return Ok(());
+        }
+        let change_list = State::change_list(&old_value, &self.crdt_value);
+        let event = ChangeEvent{crdt_instance: crdt_instance.clone(),
+                                old_value,
+                                new_value: self.crdt_value.clone(),
+                                node: msg.node,
+                                node_vector_clock: msg.node_vector_clock.clone(),
+                                change_list};
+        for (instance, listener) in self.subscription.listener_list.values_mut() {
+            if *instance == event.crdt_instance {
+                listener(&event);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl <CrdtValue: Clone+Debug,
+      OpsValue: Clone+PartialEq+Debug+Serialize+DeserializeOwned,
+      State: Debug+CrdtBehavior<CrdtValue, OpsValue>> CRDT<CrdtValue, OpsValue, State> {
+    // the value before a wholesale replace, copied only when someone listens
+    pub fn watched_value(&self) -> Option<CrdtValue> {
+        match self.subscription.listener_list.is_empty() {
+            true  => None,
+            false => Some(self.crdt_value.clone())
+        }
+    }
+
+    // a state transfer or an epoch reset replaces the value without an operation and covers
+    // every instance of this replica, so each listener hears about it
+    pub fn notify_replaced_value(&mut self, old_value: Option<CrdtValue>, node: NodeType) {
+        let (old_value, value_eq) = match (old_value, self.subscription.value_eq) {
+                                        (Some(old_value), Some(value_eq)) => (old_value, value_eq),
+                                        _                                 => return
+                                    };
+        if value_eq(&old_value, &self.crdt_value) {
+            return;
+        }
+        let change_list = State::change_list(&old_value, &self.crdt_value);
+        for (instance, listener) in self.subscription.listener_list.values_mut() {
+            listener(&ChangeEvent{crdt_instance: instance.clone(),
+                                  old_value: old_value.clone(),
+                                  new_value: self.crdt_value.clone(),
+                                  node,
+                                  node_vector_clock: self.trcb.node_vector_clock.clone(),
+                                  change_list: change_list.clone()});
+        }
+    }
+}
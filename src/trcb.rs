@@ -1,21 +1,25 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::cmp;
 use anyhow::Result;
 
 use crate::{LCType, NodeType};
-use crate::vector_clock::{VectorClock, VectorClockError, VCStatus};
+use crate::vector_clock::{VectorClock, VCOrdering, VCStatus, INC_LC, INITIAL_LC};
+use crate::error::CrdtError;
 
 #[derive(Debug)]
 pub struct TRCBData {
     pub node: NodeType,
     pub node_vector_clock: VectorClock,
-    pub node_trcb: HashMap<NodeType, VectorClock>
+    pub node_trcb: HashMap<NodeType, VectorClock>,
+    pub stable_vector_clock: VectorClock,
+    pub stable_dots: Vec<(NodeType, LCType)>,
+    pub evicted: HashSet<NodeType>
 }
 
 impl TRCBData {
-    pub fn new(node: NodeType, node_list: Vec<NodeType>) -> Result<Self, VectorClockError> {
+    pub fn new(node: NodeType, node_list: Vec<NodeType>) -> Result<Self, CrdtError> {
         if !&node_list.contains(&node) {
-            return Err(VectorClockError::InconsistentInputTRBC(node, node_list));
+            return Err(CrdtError::InconsistentInputTRBC(node, node_list));
         }
 
         let node_vector_clock = VectorClock::new(node_list.clone())?;
@@ -27,49 +31,155 @@ impl TRCBData {
                 node_trcb.insert(pnode, node_vector_clock.clone());
             }
         }
+
+        let stable_vector_clock = node_vector_clock.clone();
         
         Ok(Self {
             node,
             node_vector_clock,
-            node_trcb
+            node_trcb,
+            stable_vector_clock,
+            stable_dots: Vec::new(),
+            evicted: HashSet::new()
         })
     }
 
-    pub fn next_vc(&mut self) -> Result<VectorClock, VectorClockError> {
-        self.node_vector_clock.next_vc(&self.node)?;
+    pub fn next_vc(&mut self) -> Result<VectorClock, CrdtError> {
+        let node = self.node;
+        let old_lc = get_lc(&self.node_vector_clock, &node)?;
+        self.node_vector_clock.next_vc(&node)?;
+        self.update_stable(vec![(node, old_lc)])?;
         Ok(self.node_vector_clock.clone())
     }
 
-    pub fn add_peer_vc(&mut self, peer_node: NodeType, peer_vc: VectorClock) -> Result<VCStatus, VectorClockError> {
+    pub fn add_peer_vc(&mut self, peer_node: NodeType, peer_vc: VectorClock) -> Result<VCStatus, CrdtError> {
         let peer_vc_status = self.node_vector_clock.is_next_vc(&peer_node, &peer_vc)?;
 
         if peer_vc_status == VCStatus::INORDER {
+            let old_lc = get_lc(&self.node_vector_clock, &peer_node)?;
             self.node_vector_clock.next_vc(&peer_node)?;
-            self.node_trcb.insert(peer_node, peer_vc);
+            let mut changes = vec![(peer_node, old_lc)];
+            changes.extend(self.merge_peer_vc(peer_node, &peer_vc)?);
+            self.update_stable(changes)?;
         }
     
         Ok(peer_vc_status)
     }
 
-    pub fn add_peer_vcmsg(&mut self, peer_node: NodeType, peer_vc: VectorClock) -> Result<(), VectorClockError> {
-        let cvc = self.node_trcb.get(&peer_node).ok_or(VectorClockError::NodeNotFound)?;
-        let mvc = cvc.max_vc(&peer_vc)?;
-        self.node_trcb.insert(peer_node, mvc);
+    pub fn add_peer_vcmsg(&mut self, peer_node: NodeType, peer_vc: VectorClock) -> Result<(), CrdtError> {
+        let changes = self.merge_peer_vc(peer_node, &peer_vc)?;
+        self.update_stable(changes)
+    }
+
+    pub fn causally_stable(&self) -> Result<VectorClock, CrdtError> {
+        Ok(self.stable_vector_clock.clone())
+    }
+
+    pub fn take_stable_dots(&mut self) -> Vec<(NodeType, LCType)> {
+        std::mem::take(&mut self.stable_dots)
+    }
+
+    pub fn is_evicted(&self, peer_node: &NodeType) -> bool {
+        self.evicted.contains(peer_node)
+    }
+
+    // no retained-or-pruned operation can be concurrent with a clock at or above the stable clock
+    pub fn is_after_stable(&self, vc: &VectorClock) -> Result<bool, CrdtError> {
+        let vc_ord = self.stable_vector_clock.cmp_vc(vc)?;
+        Ok(vc_ord == VCOrdering::VCLE || vc_ord == VCOrdering::VCEQ)
+    }
+
+    pub fn evict_peer(&mut self, peer_node: NodeType) -> Result<(), CrdtError> {
+        if !self.node_trcb.contains_key(&peer_node) {
+            return Err(CrdtError::UnknownNode(peer_node));
+        }
+
+        if self.evicted.insert(peer_node) {
+            let node_list: Vec<NodeType> = self.stable_vector_clock.vcmap.keys().copied().collect();
+            for nnode in node_list {
+                self.recompute_stable(nnode)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn readmit_peer(&mut self, peer_node: NodeType, peer_vc: VectorClock) -> Result<(), CrdtError> {
+        if !self.node_trcb.contains_key(&peer_node) {
+            return Err(CrdtError::UnknownNode(peer_node));
+        }
+
+        let pvc = peer_vc.max_vc(&self.stable_vector_clock)?;
+        self.node_trcb.insert(peer_node, pvc);
+        self.evicted.remove(&peer_node);
+        Ok(())
+    }
+
+    pub fn reset_from_state(&mut self, donor_node: NodeType, donor_vc: VectorClock, stable_vc: VectorClock) -> Result<(), CrdtError> {
+        if donor_vc.len() != self.node_vector_clock.len() || stable_vc.len() != self.node_vector_clock.len() {
+            return Err(CrdtError::NonCompatibleVC);
+        }
+
+        for (pnode, pvc) in self.node_trcb.iter_mut() {
+            *pvc = if *pnode == donor_node {donor_vc.clone()} else {stable_vc.clone()};
+        }
+        self.node_vector_clock = donor_vc;
+        self.stable_vector_clock = stable_vc;
+        self.stable_dots.clear();
+        self.evicted.clear();
         Ok(())
     }
 
-    pub fn causally_stable(&self) -> Result<VectorClock, VectorClockError> {
-        let mut cs_map: HashMap<NodeType, LCType> = HashMap::new();
+    // evicted peers stay evicted, their entries restart like every other one
+    pub fn reset_epoch(&mut self) {
+        let zero_vc = |vc: &mut VectorClock| vc.vcmap.values_mut().for_each(|lc| *lc = INITIAL_LC);
+        zero_vc(&mut self.node_vector_clock);
+        zero_vc(&mut self.stable_vector_clock);
+        self.node_trcb.values_mut().for_each(zero_vc);
+        self.stable_dots.clear();
+    }
+
+    fn merge_peer_vc(&mut self, peer_node: NodeType, peer_vc: &VectorClock) -> Result<Vec<(NodeType, LCType)>, CrdtError> {
+        let cvc = self.node_trcb.get_mut(&peer_node).ok_or(CrdtError::UnknownNode(peer_node))?;
+        let mut changes = Vec::new();
+
+        for (pnode, plc) in peer_vc.vcmap.iter() {
+            let clc = cvc.vcmap.get_mut(pnode).ok_or(CrdtError::NonCompatibleVC)?;
+            if *plc > *clc {
+                changes.push((*pnode, *clc));
+                *clc = *plc;
+            }
+        }
+
+        Ok(changes)
+    }
+
+    // an entry only moves the stable clock when it was holding the minimum of its column
+    fn update_stable(&mut self, changes: Vec<(NodeType, LCType)>) -> Result<(), CrdtError> {
+        for (nnode, old_lc) in changes {
+            if old_lc == get_lc(&self.stable_vector_clock, &nnode)? {
+                self.recompute_stable(nnode)?;
+            }
+        }
+        Ok(())
+    }
 
-        for (nnode, nlc) in self.node_vector_clock.vcmap.iter() {
-            let mut mlc = nlc;
-            for (_, pvc) in &self.node_trcb {
-                let plc = pvc.vcmap.get(nnode).ok_or(VectorClockError::UnexpectedError("trcb.causally_stable 67".to_owned()))?;
-                mlc = cmp::min(mlc, plc);
+    fn recompute_stable(&mut self, nnode: NodeType) -> Result<(), CrdtError> {
+        let slc = get_lc(&self.stable_vector_clock, &nnode)?;
+        let mut mlc = get_lc(&self.node_vector_clock, &nnode)?;
+        for (pnode, pvc) in self.node_trcb.iter() {
+            if !self.evicted.contains(pnode) {
+                mlc = cmp::min(mlc, get_lc(pvc, &nnode)?);
             }
-            cs_map.insert(*nnode, *mlc);
-        };
+        }
 
-        Ok(VectorClock{vcmap: cs_map})
+        if mlc > slc {
+            self.stable_dots.extend((slc+INC_LC..=mlc).map(|lc| (nnode, lc)));
+            self.stable_vector_clock.vcmap.insert(nnode, mlc);
+        }
+        Ok(())
     }
-}
\ No newline at end of file
+}
+
+fn get_lc(vc: &VectorClock, node: &NodeType) -> Result<LCType, CrdtError> {
+    vc.vcmap.get(node).copied().ok_or(CrdtError::NonCompatibleVC)
+}
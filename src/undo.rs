@@ -0,0 +1,141 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Debug;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use anyhow::Result;
+
+use crate::{EpochType, LCType};
+use crate::crdt::{CRDT, CrdtBehavior, CrdtInstance};
+use crate::message_data::{NodeUpdateMsg, OpsInstance, PeerMsgMap, UserUpdateMsg};
+use crate::error::CrdtError;
+
+pub type UndoKey = (EpochType, LCType);
+
+pub type LocalMsgFn<CrdtValue, OpsValue, State> =
+    fn(&mut CRDT<CrdtValue, OpsValue, State>, NodeUpdateMsg<OpsValue>) -> Result<PeerMsgMap<OpsValue>, CrdtError>;
+
+// the inverse is worked out against the value just before the local operation, which is
+// the only moment it is known; the same goes for the redo, worked out just before the undo.
+// compensations are kept on the entry they undo or redo rather than as entries of their own
+#[derive(Debug)]
+pub struct UndoEntry<OpsValue: Clone+PartialEq> {
+    pub crdt_instance: CrdtInstance,
+    pub inverse: Compensation<OpsValue>,
+    pub undone: bool,
+    pub redo: Compensation<OpsValue>
+}
+
+pub type PendingUndo<OpsValue> = Option<(UndoKey, UndoEntry<OpsValue>)>;
+
+pub type Compensation<OpsValue> = Result<Option<OpsInstance<OpsValue>>, CrdtError>;
+
+#[derive(Debug)]
+pub struct UndoState<OpsValue: Clone+PartialEq> {
+    pub max_len: usize,
+    pub entry_list: BTreeMap<UndoKey, UndoEntry<OpsValue>>,
+    pub compensating: bool
+}
+
+impl <OpsValue: Clone+PartialEq> UndoState<OpsValue> {
+    pub fn new(max_len: usize) -> Self {
+        Self{max_len, entry_list: BTreeMap::new(), compensating: false}
+    }
+
+    pub fn insert(&mut self, key: UndoKey, entry: UndoEntry<OpsValue>) {
+        self.entry_list.insert(key, entry);
+        while self.entry_list.len() > self.max_len {
+            self.entry_list.pop_first();
+        }
+    }
+}
+
+impl <CrdtValue: Clone+Debug,
+      OpsValue: Clone+PartialEq+Debug+Serialize+DeserializeOwned,
+      State: Debug+CrdtBehavior<CrdtValue, OpsValue>> CRDT<CrdtValue, OpsValue, State> {
+    pub fn set_undo_history_len(&mut self, max_len: usize) {
+        self.undo.max_len = max_len;
+        while self.undo.entry_list.len() > max_len {
+            self.undo.entry_list.pop_first();
+        }
+    }
+
+    pub fn undo_key(&self, msg: &NodeUpdateMsg<OpsValue>) -> Result<UndoKey, CrdtError> {
+        let lc = *msg.node_vector_clock.vcmap.get(&msg.node).ok_or(CrdtError::UnknownNode(msg.node))?;
+        match msg.node == self.get_node() {
+            true  => Ok((msg.epoch, lc)),
+            false => Err(CrdtError::NoUndoEntry(msg.node, lc))
+        }
+    }
+
+    // called by process_local_msg before the operation is applied; the entry is only kept
+    // by record_undo once the operation went through
+    pub fn prepare_undo(&self, msg: &NodeUpdateMsg<OpsValue>) -> Result<PendingUndo<OpsValue>, CrdtError> {
+        if self.undo.max_len == 0 || self.undo.compensating {
+            return Ok(None);
+        }
+        let key = self.undo_key(msg)?;
+        let inverse = State::inverse_ops(&self.crdt_value, &msg.user_update_msg.ops_instance);
+        Ok(Some((key, UndoEntry{crdt_instance: msg.user_update_msg.crdt_instance.clone(), inverse, undone: false, redo: Ok(None)})))
+    }
+
+    pub fn record_undo(&mut self, pending: PendingUndo<OpsValue>) {
+        if let Some((key, entry)) = pending {
+            self.undo.insert(key, entry);
+        }
+    }
+
+    pub fn undo_with(&mut self, msg: &NodeUpdateMsg<OpsValue>, process_local_msg: LocalMsgFn<CrdtValue, OpsValue, State>) ->
+        Result<PeerMsgMap<OpsValue>, CrdtError> {
+        let key = self.undo_key(msg)?;
+        let (crdt_instance, inverse) = match self.undo.entry_list.get(&key) {
+            Some(entry) if !entry.undone => (entry.crdt_instance.clone(), entry.inverse.clone()?),
+            _                            => return Err(CrdtError::NoUndoEntry(msg.node, key.1))
+        };
+        let (redo, msg_map) = self.compensate(crdt_instance, inverse, process_local_msg)?;
+        if let Some(entry) = self.undo.entry_list.get_mut(&key) {
+            entry.undone = true;
+            entry.redo = redo;
+        }
+        Ok(msg_map)
+    }
+
+    // the redo is a new compensation, so it follows whatever happened concurrently just like
+    // the undo did, and undoing again works against the value it leaves
+    pub fn redo_with(&mut self, msg: &NodeUpdateMsg<OpsValue>, process_local_msg: LocalMsgFn<CrdtValue, OpsValue, State>) ->
+        Result<PeerMsgMap<OpsValue>, CrdtError> {
+        let key = self.undo_key(msg)?;
+        let (crdt_instance, redo) = match self.undo.entry_list.get(&key) {
+            Some(entry) if entry.undone => (entry.crdt_instance.clone(), entry.redo.clone()?),
+            _                           => return Err(CrdtError::NoUndoEntry(msg.node, key.1))
+        };
+        let (inverse, msg_map) = self.compensate(crdt_instance, redo, process_local_msg)?;
+        if let Some(entry) = self.undo.entry_list.get_mut(&key) {
+            entry.undone = false;
+            entry.inverse = inverse;
+            entry.redo = Ok(None);
+        }
+        Ok(msg_map)
+    }
+
+    pub fn can_undo(&self, msg: &NodeUpdateMsg<OpsValue>) -> bool {
+        self.undo_key(msg).ok()
+                          .and_then(|key| self.undo.entry_list.get(&key))
+                          .is_some_and(|entry| !entry.undone && entry.inverse.is_ok())
+    }
+
+    // applies ops_instance as a local operation and returns what would take it back
+    fn compensate(&mut self, crdt_instance: CrdtInstance, ops_instance: Option<OpsInstance<OpsValue>>,
+                  process_local_msg: LocalMsgFn<CrdtValue, OpsValue, State>) ->
+        Result<(Compensation<OpsValue>, PeerMsgMap<OpsValue>), CrdtError> {
+        let ops_instance = match ops_instance {
+            Some(ops_instance) => ops_instance,
+            None               => return Ok((Ok(None), HashMap::new()))
+        };
+        let inverse = State::inverse_ops(&self.crdt_value, &ops_instance);
+        let msg = self.create_local_msg(UserUpdateMsg::new(crdt_instance, ops_instance))?;
+        self.undo.compensating = true;
+        let result = process_local_msg(self, msg);
+        self.undo.compensating = false;
+        Ok((inverse, result?))
+    }
+}
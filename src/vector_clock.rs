@@ -4,19 +4,11 @@ use serde::{Serialize, Deserialize};
 use anyhow::Result;
 
 use crate::{LCType, NodeType};
+use crate::error::CrdtError;
 
 pub const INITIAL_LC: LCType = 0;
 pub const INC_LC:     LCType = 1;
 
-#[derive(Debug)]
-pub enum VectorClockError {
-    EmptyNodeList,
-    NodeNotFound,
-    NonCompatibleVC,
-    InconsistentInputTRBC(NodeType, Vec<NodeType>),
-    UnexpectedError(String)
-}
-
 #[derive(Debug, Clone, PartialEq)]
 pub enum VCOrdering {
     VCLE,
@@ -29,7 +21,8 @@ pub enum VCOrdering {
 pub enum VCStatus {
     DUPLICATE,
     INORDER,
-    OUTOFORDER
+    OUTOFORDER,
+    EVICTED
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -38,9 +31,9 @@ pub struct VectorClock {
 }
 
 impl VectorClock {
-    pub fn new(node_list: Vec<NodeType>) -> Result<Self, VectorClockError> {
+    pub fn new(node_list: Vec<NodeType>) -> Result<Self, CrdtError> {
         if node_list.is_empty() {
-            return Err(VectorClockError::EmptyNodeList);
+            return Err(CrdtError::EmptyNodeList);
         }
         
         let mut vcmap = HashMap::new();
@@ -56,28 +49,42 @@ impl VectorClock {
         self.vcmap.len()
     }
 
-    pub fn next_vc(&mut self, node: &NodeType) -> Result<(), VectorClockError> {
-        let lc = self.vcmap.get_mut(node).ok_or(VectorClockError::NodeNotFound)?;
-        *lc += INC_LC;
+    pub fn is_empty(&self) -> bool {
+        self.vcmap.is_empty()
+    }
+
+    pub fn next_vc(&mut self, node: &NodeType) -> Result<(), CrdtError> {
+        let lc = self.vcmap.get_mut(node).ok_or(CrdtError::UnknownNode(*node))?;
+        *lc = lc.checked_add(INC_LC).ok_or(CrdtError::ClockOverflow(*node))?;
         Ok(())
 
     }
 
-    pub fn is_next_vc(&self, node: &NodeType, peer_vc: &VectorClock) -> Result<VCStatus, VectorClockError> {
-        let nlc = self.vcmap.get(node).ok_or(VectorClockError::NodeNotFound)?;
-        let plc = peer_vc.vcmap.get(node).ok_or(VectorClockError::NodeNotFound)?;
-        let vc_status = cmp_lc(*nlc+INC_LC, *plc);
+    pub fn is_next_vc(&self, node: &NodeType, peer_vc: &VectorClock) -> Result<VCStatus, CrdtError> {
+        let nlc = self.vcmap.get(node).ok_or(CrdtError::UnknownNode(*node))?;
+        let plc = peer_vc.vcmap.get(node).ok_or(CrdtError::UnknownNode(*node))?;
+        let vc_status = cmp_next_lc(*nlc, *plc);
         Ok(peer_vc_status(vc_status))
     }
 
-    pub fn cmp_vc(&self, other: &VectorClock) -> Result<VCOrdering, VectorClockError> {
+    pub fn check_deps(&self, node: NodeType, other: &VectorClock) -> Result<bool, CrdtError> {
+        for (onode, olc) in other.vcmap.iter() {
+            let lc = self.vcmap.get(onode).ok_or(CrdtError::NonCompatibleVC)?;
+            if *onode != node && olc > lc {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    pub fn cmp_vc(&self, other: &VectorClock) -> Result<VCOrdering, CrdtError> {
         if self.len() != other.len() {
-            return Err(VectorClockError::NonCompatibleVC);
+            return Err(CrdtError::NonCompatibleVC);
         }
         
         let mut vcords = VCOrdering::VCEQ;
         for (node, lc1) in self.vcmap.iter() {
-            let lc2 = other.vcmap.get(node).ok_or(VectorClockError::NonCompatibleVC)?;
+            let lc2 = other.vcmap.get(node).ok_or(CrdtError::NonCompatibleVC)?;
             let vcordo = cmp_lc(*lc1, *lc2);
             vcords = vc_order(vcords, vcordo);
         }
@@ -85,17 +92,17 @@ impl VectorClock {
         Ok(vcords)
     }
 
-    pub fn check_vc(&self, node: NodeType, other: &VectorClock) -> Result<VCOrdering, VectorClockError> {
-        let lc1 = self.vcmap.get(&node).ok_or(VectorClockError::NodeNotFound)?+1;
-        let lc2 = other.vcmap.get(&node).ok_or(VectorClockError::NodeNotFound)?+0;
+    pub fn check_vc(&self, node: NodeType, other: &VectorClock) -> Result<VCOrdering, CrdtError> {
+        let lc1 = *self.vcmap.get(&node).ok_or(CrdtError::UnknownNode(node))?;
+        let lc2 = *other.vcmap.get(&node).ok_or(CrdtError::UnknownNode(node))?;
 
-        Ok(cmp_lc(lc1, lc2))
+        Ok(cmp_next_lc(lc1, lc2))
     }
 
-    pub fn min_max_vc(&self, other: &VectorClock, f: fn(LCType, LCType) -> LCType) -> Result<VectorClock, VectorClockError> {
+    pub fn min_max_vc(&self, other: &VectorClock, f: fn(LCType, LCType) -> LCType) -> Result<VectorClock, CrdtError> {
         let mut vcmap = HashMap::new();
         for (node, lc1) in self.vcmap.iter() {
-            let lc2 = other.vcmap.get(node).ok_or(VectorClockError::NonCompatibleVC)?;
+            let lc2 = other.vcmap.get(node).ok_or(CrdtError::NonCompatibleVC)?;
             let flc = f(*lc1, *lc2);
             vcmap.insert(*node, flc);
         }
@@ -103,11 +110,11 @@ impl VectorClock {
         Ok(VectorClock{vcmap})
     }
 
-    pub fn max_vc(&self, other: &VectorClock) -> Result<VectorClock, VectorClockError> {
+    pub fn max_vc(&self, other: &VectorClock) -> Result<VectorClock, CrdtError> {
         self.min_max_vc(other, max)
     }
 
-    pub fn min_vc(&self, other: &VectorClock) -> Result<VectorClock, VectorClockError> {
+    pub fn min_vc(&self, other: &VectorClock) -> Result<VectorClock, CrdtError> {
         self.min_max_vc(other, min)
     }    
 }
@@ -134,6 +141,20 @@ pub fn cmp_lc(lc1: LCType, lc2: LCType) -> VCOrdering {
     }
 }
 
+// no clock follows the largest one, so every peer clock is behind it
+pub fn cmp_next_lc(lc1: LCType, lc2: LCType) -> VCOrdering {
+    match lc1.checked_add(INC_LC) {
+        Some(next_lc) => cmp_lc(next_lc, lc2),
+        None          => VCOrdering::VCGR
+    }
+}
+
+// the conversion is the identity when LCType is u64
+#[allow(clippy::useless_conversion)]
+pub fn lc_u64(lc: LCType) -> u64 {
+    u64::from(lc)
+}
+
 pub fn peer_vc_status(pord: VCOrdering) -> VCStatus {
     match pord {
         VCOrdering::VCGR => VCStatus::DUPLICATE,
@@ -0,0 +1,829 @@
+use std::collections::{BTreeSet, HashMap};
+use anyhow::Result;
+
+use crate::{CRDTNumType, EpochType, LCType, NodeType};
+use crate::crdt::{CrdtInstance, CrdtType};
+use crate::edflag_crdt::EDFlag;
+use crate::message_data::{DeltaNodeUpdateMsg,
+                          MerklePath,
+                          OpHash,
+                          PredHash,
+                          MerkleReplyMsg,
+                          MerkleRequestMsg,
+                          NodeUpdateMsg,
+                          NodeVectorClockMsg,
+                          OpsInstance,
+                          PeerNodeMsg,
+                          SDPOpsType,
+                          UserUpdateMsg};
+use crate::vector_clock::{VectorClock, lc_u64};
+use crate::error::CrdtError;
+
+// frame: version, flags, kind, node dictionary, messages
+// clocks are written against the dictionary, so node ids appear once per frame
+// version 2 prefixes each message with its length so a reader can skip message kinds
+// it does not know and fields appended to ones it does; a new message kind must not
+// take part in the delta clock chain, since older readers skip it
+// version 3 may set FLAG_SIGNED, then update and clock messages end with a signature
+// version 4 may set FLAG_HASH_CHAIN, then updates end with their predecessor hashes
+// version 5 may set FLAG_EPOCH, then update and clock messages end with their epoch and
+// clock messages with the reset pending byte
+pub const WIRE_VERSION: u8     = 5;
+pub const WIRE_MIN_VERSION: u8 = 1;
+
+const FLAG_DELTA_CLOCK: u8 = 0x01;
+const FLAG_SIGNED: u8      = 0x02;
+const FLAG_HASH_CHAIN: u8  = 0x04;
+const FLAG_EPOCH: u8       = 0x08;
+
+const FRAME_PEER_MSG_LIST: u8 = 0;
+const FRAME_VC_MSG: u8        = 1;
+
+const CLOCK_DENSE: u8  = 0;
+const CLOCK_DELTA: u8  = 1;
+const CLOCK_SPARSE: u8 = 2;
+
+const MSG_VECTOR_CLOCK: u8   = 0;
+const MSG_UPDATE: u8         = 1;
+const MSG_DIGEST_REQUEST: u8 = 2;
+const MSG_DIGEST_REPLY: u8   = 3;
+const MSG_DIGEST_ACK: u8     = 4;
+const MSG_MERKLE_REQUEST: u8 = 5;
+const MSG_MERKLE_REPLY: u8   = 6;
+const MSG_DELTA_UPDATE: u8   = 7;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WireOptions {
+    pub delta_clock: bool,
+    pub version: u8
+}
+
+impl Default for WireOptions {
+    fn default() -> Self {
+        Self::new(false)
+    }
+}
+
+impl WireOptions {
+    pub fn new(delta_clock: bool) -> Self {
+        Self{delta_clock, version: WIRE_VERSION}
+    }
+
+    pub fn with_version(self, version: u8) -> Self {
+        Self{version, ..self}
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DecodedFrame<OpsValue: Clone+PartialEq> {
+    pub version: u8,
+    pub msg_list: Vec<PeerNodeMsg<OpsValue>>,
+    pub skipped_count: usize
+}
+
+pub trait WireValue: Sized+Clone+PartialEq {
+    fn encode_wire(&self, writer: &mut WireWriter);
+    fn decode_wire(reader: &mut WireReader) -> Result<Self, CrdtError>;
+}
+
+impl WireValue for i64 {
+    fn encode_wire(&self, writer: &mut WireWriter) {
+        writer.put_varint(zigzag(*self));
+    }
+
+    fn decode_wire(reader: &mut WireReader) -> Result<Self, CrdtError> {
+        Ok(unzigzag(reader.get_varint()?))
+    }
+}
+
+impl WireValue for i32 {
+    fn encode_wire(&self, writer: &mut WireWriter) {
+        writer.put_varint(zigzag(*self as i64));
+    }
+
+    fn decode_wire(reader: &mut WireReader) -> Result<Self, CrdtError> {
+        i32::try_from(unzigzag(reader.get_varint()?)).map_err(wire_error)
+    }
+}
+
+impl WireValue for u32 {
+    fn encode_wire(&self, writer: &mut WireWriter) {
+        writer.put_varint(*self as u64);
+    }
+
+    fn decode_wire(reader: &mut WireReader) -> Result<Self, CrdtError> {
+        u32::try_from(reader.get_varint()?).map_err(wire_error)
+    }
+}
+
+impl WireValue for EDFlag {
+    fn encode_wire(&self, writer: &mut WireWriter) {
+        writer.put_u8(match self {
+                          EDFlag::Enabled  => 0,
+                          EDFlag::Disabled => 1
+                      });
+    }
+
+    fn decode_wire(reader: &mut WireReader) -> Result<Self, CrdtError> {
+        match reader.get_u8()? {
+            0   => Ok(EDFlag::Enabled),
+            1   => Ok(EDFlag::Disabled),
+            tag => Err(wire_error(format!("unknown flag tag {}", tag)))
+        }
+    }
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn unzigzag(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+fn wire_error<E: std::fmt::Display>(e: E) -> CrdtError {
+    CrdtError::DecodeError(e.to_string())
+}
+
+#[derive(Debug, Default)]
+pub struct WireWriter {
+    buf: Vec<u8>,
+    dictionary: HashMap<NodeType, u64>,
+    node_list: Vec<NodeType>,
+    prev_clock: Option<Vec<LCType>>,
+    delta_clock: bool,
+    signed: bool,
+    hash_chain: bool,
+    epoch: bool
+}
+
+impl WireWriter {
+    fn new(node_list: Vec<NodeType>, delta_clock: bool, flags: u8) -> Self {
+        let dictionary = node_list.iter().enumerate().map(|(index, node)| (*node, index as u64)).collect();
+        Self{buf: Vec::new(), dictionary, node_list, prev_clock: None, delta_clock,
+             signed: flags & FLAG_SIGNED != 0, hash_chain: flags & FLAG_HASH_CHAIN != 0, epoch: flags & FLAG_EPOCH != 0}
+    }
+
+    pub fn put_u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    pub fn put_u64(&mut self, value: u64) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn put_varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.buf.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        self.buf.push(value as u8);
+    }
+
+    fn put_node(&mut self, node: NodeType) -> Result<(), CrdtError> {
+        let index = *self.dictionary.get(&node).ok_or(CrdtError::UnknownNode(node))?;
+        self.put_varint(index);
+        Ok(())
+    }
+
+    fn put_dictionary(&mut self) {
+        self.put_varint(self.node_list.len() as u64);
+        let mut prev_node = 0;
+        for node in self.node_list.clone() {
+            self.put_varint((node - prev_node) as u64);
+            prev_node = node;
+        }
+    }
+
+    // clocks over the whole dictionary are dense, and with delta_clock each one is
+    // written as the zigzag difference from the previous clock in the same frame
+    fn put_clock(&mut self, vc: &VectorClock) -> Result<(), CrdtError> {
+        if vc.len() != self.node_list.len() || !vc.vcmap.keys().all(|node| self.dictionary.contains_key(node)) {
+            self.put_u8(CLOCK_SPARSE);
+            let mut entry_list: Vec<(&NodeType, &LCType)> = vc.vcmap.iter().collect();
+            entry_list.sort();
+            self.put_varint(entry_list.len() as u64);
+            for (node, lc) in entry_list {
+                self.put_node(*node)?;
+                self.put_varint(lc_u64(*lc));
+            }
+            return Ok(());
+        }
+
+        let clock: Vec<LCType> = self.node_list.iter().map(|node| vc.vcmap[node]).collect();
+        match self.prev_clock.take() {
+            Some(prev_clock) if self.delta_clock => {
+                self.put_u8(CLOCK_DELTA);
+                for (lc, prev_lc) in clock.iter().zip(prev_clock.iter()) {
+                    self.put_varint(zigzag((*lc as i64).wrapping_sub(*prev_lc as i64)));
+                }
+            },
+            _                                    => {
+                self.put_u8(CLOCK_DENSE);
+                for lc in clock.iter() {
+                    self.put_varint(lc_u64(*lc));
+                }
+            }
+        }
+        self.prev_clock = Some(clock);
+        Ok(())
+    }
+
+    // an empty signature stands for an unsigned message
+    fn put_signature(&mut self, signature: &Option<Vec<u8>>) {
+        if self.signed {
+            let signature = signature.as_deref().unwrap_or_default();
+            self.put_varint(signature.len() as u64);
+            self.buf.extend_from_slice(signature);
+        }
+    }
+
+    fn put_pred_hash_list(&mut self, pred_hash_list: &[PredHash]) -> Result<(), CrdtError> {
+        if self.hash_chain {
+            self.put_varint(pred_hash_list.len() as u64);
+            for (node, lc, hash) in pred_hash_list {
+                self.put_node(*node)?;
+                self.put_varint(lc_u64(*lc));
+                self.buf.extend_from_slice(hash);
+            }
+        }
+        Ok(())
+    }
+
+    fn put_epoch(&mut self, epoch: EpochType) {
+        if self.epoch {
+            self.put_varint(epoch as u64);
+        }
+    }
+
+    fn put_vc_msg(&mut self, msg: &NodeVectorClockMsg) -> Result<(), CrdtError> {
+        self.put_node(msg.node)?;
+        self.put_clock(&msg.node_vector_clock)?;
+        self.put_signature(&msg.signature);
+        self.put_epoch(msg.epoch);
+        if self.epoch {
+            self.put_u8(msg.reset_pending as u8);
+        }
+        Ok(())
+    }
+
+    fn put_path(&mut self, (level, index): &MerklePath) {
+        self.put_u8(*level);
+        self.put_varint(*index);
+    }
+
+    fn put_user_update_msg<OpsValue: WireValue>(&mut self, msg: &UserUpdateMsg<OpsValue>) {
+        self.put_varint(msg.crdt_instance.instance_node_id as u64);
+        self.put_varint(msg.crdt_instance.instance_num as u64);
+        self.put_u8(crdt_type_tag(&msg.crdt_instance.instance_type));
+        self.put_u8(match msg.ops_instance.ops_type {
+                        SDPOpsType::SDPAdd  => 0,
+                        SDPOpsType::SDPMult => 1
+                    });
+        msg.ops_instance.ops_value.encode_wire(self);
+    }
+
+    fn put_peer_msg<OpsValue: WireValue>(&mut self, msg: &PeerNodeMsg<OpsValue>) -> Result<(), CrdtError> {
+        match msg {
+            PeerNodeMsg::VectorClockNodeMsg(vmsg) => {
+                self.put_u8(MSG_VECTOR_CLOCK);
+                self.put_vc_msg(vmsg)?;
+            },
+            PeerNodeMsg::UpdateNodeMsg(umsg)      => {
+                self.put_u8(MSG_UPDATE);
+                self.put_node(umsg.node)?;
+                self.put_clock(&umsg.node_vector_clock)?;
+                self.put_user_update_msg(&umsg.user_update_msg);
+                self.put_signature(&umsg.signature);
+                self.put_pred_hash_list(&umsg.pred_hash_list)?;
+                self.put_epoch(umsg.epoch);
+            },
+            PeerNodeMsg::DeltaUpdateNodeMsg(dmsg) => {
+                self.put_u8(MSG_DELTA_UPDATE);
+                self.put_node(dmsg.node)?;
+                self.put_varint(lc_u64(dmsg.lc));
+                self.put_varint(dmsg.entry_list.len() as u64);
+                for (node, lc) in dmsg.entry_list.iter() {
+                    self.put_node(*node)?;
+                    self.put_varint(lc_u64(*lc));
+                }
+                self.put_user_update_msg(&dmsg.user_update_msg);
+                self.put_signature(&dmsg.signature);
+                self.put_pred_hash_list(&dmsg.pred_hash_list)?;
+                self.put_epoch(dmsg.epoch);
+            },
+            PeerNodeMsg::DigestRequestMsg(vmsg)   => {
+                self.put_u8(MSG_DIGEST_REQUEST);
+                self.put_vc_msg(vmsg)?;
+            },
+            PeerNodeMsg::DigestReplyMsg(vmsg)     => {
+                self.put_u8(MSG_DIGEST_REPLY);
+                self.put_vc_msg(vmsg)?;
+            },
+            PeerNodeMsg::DigestAckMsg(vmsg)       => {
+                self.put_u8(MSG_DIGEST_ACK);
+                self.put_vc_msg(vmsg)?;
+            },
+            PeerNodeMsg::MerkleRequestMsg(rmsg)   => {
+                self.put_u8(MSG_MERKLE_REQUEST);
+                self.put_node(rmsg.node)?;
+                self.put_u8(rmsg.depth);
+                self.put_clock(&rmsg.low_vector_clock)?;
+                self.put_clock(&rmsg.high_vector_clock)?;
+                self.put_varint(rmsg.path_list.len() as u64);
+                for path in rmsg.path_list.iter() {
+                    self.put_path(path);
+                }
+            },
+            PeerNodeMsg::MerkleReplyMsg(rmsg)     => {
+                self.put_u8(MSG_MERKLE_REPLY);
+                self.put_node(rmsg.node)?;
+                self.put_u8(rmsg.depth);
+                self.put_clock(&rmsg.low_vector_clock)?;
+                self.put_clock(&rmsg.high_vector_clock)?;
+                self.put_varint(rmsg.path_list.len() as u64);
+                for path in rmsg.path_list.iter() {
+                    self.put_path(path);
+                }
+                self.put_varint(rmsg.hash_list.len() as u64);
+                for (path, hash) in rmsg.hash_list.iter() {
+                    self.put_path(path);
+                    self.put_u64(*hash);
+                }
+                self.put_varint(rmsg.leaf_list.len() as u64);
+                for (node, lc, hash) in rmsg.leaf_list.iter() {
+                    self.put_node(*node)?;
+                    self.put_varint(lc_u64(*lc));
+                    self.put_u64(*hash);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct WireReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    version: u8,
+    signed: bool,
+    hash_chain: bool,
+    epoch: bool,
+    node_list: Vec<NodeType>,
+    prev_clock: Option<Vec<LCType>>
+}
+
+impl <'a> WireReader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self{buf, pos: 0, version: WIRE_VERSION, signed: false, hash_chain: false, epoch: false, node_list: Vec::new(), prev_clock: None}
+    }
+
+    pub fn get_u8(&mut self) -> Result<u8, CrdtError> {
+        let value = *self.buf.get(self.pos).ok_or(wire_error("unexpected end of frame"))?;
+        self.pos += 1;
+        Ok(value)
+    }
+
+    pub fn get_u64(&mut self) -> Result<u64, CrdtError> {
+        let bytes = self.buf.get(self.pos..self.pos+8).ok_or(wire_error("unexpected end of frame"))?;
+        self.pos += 8;
+        Ok(u64::from_le_bytes(bytes.try_into().map_err(wire_error)?))
+    }
+
+    pub fn get_varint(&mut self) -> Result<u64, CrdtError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.get_u8()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(wire_error("varint overflow"))
+    }
+
+    fn get_len(&mut self) -> Result<usize, CrdtError> {
+        let len = self.get_varint()? as usize;
+        if len > self.buf.len() - self.pos {
+            return Err(wire_error(format!("length {} exceeds frame", len)));
+        }
+        Ok(len)
+    }
+
+    fn get_lc(&mut self) -> Result<LCType, CrdtError> {
+        LCType::try_from(self.get_varint()?).map_err(wire_error)
+    }
+
+    fn get_node(&mut self) -> Result<NodeType, CrdtError> {
+        let index = self.get_varint()? as usize;
+        self.node_list.get(index).copied().ok_or(wire_error(format!("node index {} not in dictionary", index)))
+    }
+
+    fn get_dictionary(&mut self) -> Result<(), CrdtError> {
+        let len = self.get_len()?;
+        let mut node: NodeType = 0;
+        for _ in 0..len {
+            let step = NodeType::try_from(self.get_varint()?).map_err(wire_error)?;
+            node = node.checked_add(step).ok_or(wire_error("node id overflow"))?;
+            self.node_list.push(node);
+        }
+        Ok(())
+    }
+
+    fn get_clock(&mut self) -> Result<VectorClock, CrdtError> {
+        let clock: Vec<LCType> = match self.get_u8()? {
+            CLOCK_SPARSE => {
+                let len = self.get_len()?;
+                let mut vcmap = HashMap::new();
+                for _ in 0..len {
+                    let node = self.get_node()?;
+                    vcmap.insert(node, self.get_lc()?);
+                }
+                return Ok(VectorClock{vcmap});
+            },
+            CLOCK_DENSE  => (0..self.node_list.len()).map(|_| self.get_lc()).collect::<Result<_, _>>()?,
+            CLOCK_DELTA  => {
+                let prev_clock = self.prev_clock.take().ok_or(wire_error("delta clock without a previous clock"))?;
+                let mut clock = Vec::with_capacity(prev_clock.len());
+                for prev_lc in prev_clock {
+                    let lc = (prev_lc as i64).wrapping_add(unzigzag(self.get_varint()?)) as u64;
+                    clock.push(LCType::try_from(lc).map_err(wire_error)?);
+                }
+                clock
+            },
+            tag          => return Err(wire_error(format!("unknown clock tag {}", tag)))
+        };
+        let vcmap = self.node_list.iter().copied().zip(clock.iter().copied()).collect();
+        self.prev_clock = Some(clock);
+        Ok(VectorClock{vcmap})
+    }
+
+    fn get_signature(&mut self) -> Result<Option<Vec<u8>>, CrdtError> {
+        if !self.signed {
+            return Ok(None);
+        }
+        let len = self.get_len()?;
+        let signature = self.buf[self.pos..self.pos+len].to_vec();
+        self.pos += len;
+        Ok((!signature.is_empty()).then_some(signature))
+    }
+
+    fn get_pred_hash_list(&mut self) -> Result<Vec<PredHash>, CrdtError> {
+        if !self.hash_chain {
+            return Ok(Vec::new());
+        }
+        let mut pred_hash_list = Vec::new();
+        for _ in 0..self.get_len()? {
+            let node = self.get_node()?;
+            let lc = self.get_lc()?;
+            let hash: OpHash = self.buf.get(self.pos..self.pos+32)
+                                       .ok_or(wire_error("unexpected end of frame"))?
+                                       .try_into()
+                                       .map_err(wire_error)?;
+            self.pos += 32;
+            pred_hash_list.push((node, lc, hash));
+        }
+        Ok(pred_hash_list)
+    }
+
+    fn get_epoch(&mut self) -> Result<EpochType, CrdtError> {
+        match self.epoch {
+            true  => EpochType::try_from(self.get_varint()?).map_err(wire_error),
+            false => Ok(0)
+        }
+    }
+
+    fn get_vc_msg(&mut self) -> Result<NodeVectorClockMsg, CrdtError> {
+        let node = self.get_node()?;
+        let mut msg = NodeVectorClockMsg::new(node, self.get_clock()?);
+        msg.signature = self.get_signature()?;
+        msg.epoch = self.get_epoch()?;
+        if self.epoch {
+            msg.reset_pending = self.get_u8()? != 0;
+        }
+        Ok(msg)
+    }
+
+    fn get_path(&mut self) -> Result<MerklePath, CrdtError> {
+        let level = self.get_u8()?;
+        Ok((level, self.get_varint()?))
+    }
+
+    fn get_path_list(&mut self) -> Result<Vec<MerklePath>, CrdtError> {
+        let len = self.get_len()?;
+        (0..len).map(|_| self.get_path()).collect()
+    }
+
+    fn get_user_update_msg<OpsValue: WireValue>(&mut self) -> Result<UserUpdateMsg<OpsValue>, CrdtError> {
+        let instance_node_id = NodeType::try_from(self.get_varint()?).map_err(wire_error)?;
+        let instance_num = CRDTNumType::try_from(self.get_varint()?).map_err(wire_error)?;
+        let instance_type = crdt_type_from_tag(self.get_u8()?)?;
+        let ops_type = match self.get_u8()? {
+                           0   => SDPOpsType::SDPAdd,
+                           1   => SDPOpsType::SDPMult,
+                           tag => return Err(wire_error(format!("unknown ops tag {}", tag)))
+                       };
+        let ops_value = OpsValue::decode_wire(self)?;
+        Ok(UserUpdateMsg::new(CrdtInstance::new(instance_node_id, instance_num, instance_type),
+                              OpsInstance::new(ops_type, ops_value)))
+    }
+
+    fn get_peer_msg<OpsValue: WireValue>(&mut self) -> Result<PeerNodeMsg<OpsValue>, CrdtError> {
+        match self.get_u8()? {
+            MSG_VECTOR_CLOCK   => Ok(PeerNodeMsg::VectorClockNodeMsg(self.get_vc_msg()?)),
+            MSG_UPDATE         => {
+                let node = self.get_node()?;
+                let node_vector_clock = self.get_clock()?;
+                let user_update_msg = self.get_user_update_msg()?;
+                let mut umsg = NodeUpdateMsg::new(node, node_vector_clock, user_update_msg);
+                umsg.signature = self.get_signature()?;
+                umsg.pred_hash_list = self.get_pred_hash_list()?;
+                umsg.epoch = self.get_epoch()?;
+                Ok(PeerNodeMsg::UpdateNodeMsg(umsg))
+            },
+            MSG_DELTA_UPDATE   => {
+                let node = self.get_node()?;
+                let lc = self.get_lc()?;
+                let mut entry_list = Vec::new();
+                for _ in 0..self.get_len()? {
+                    let entry_node = self.get_node()?;
+                    entry_list.push((entry_node, self.get_lc()?));
+                }
+                let user_update_msg = self.get_user_update_msg()?;
+                let signature = self.get_signature()?;
+                let pred_hash_list = self.get_pred_hash_list()?;
+                let epoch = self.get_epoch()?;
+                Ok(PeerNodeMsg::DeltaUpdateNodeMsg(DeltaNodeUpdateMsg{node, lc, entry_list, user_update_msg, pred_hash_list, epoch, signature}))
+            },
+            MSG_DIGEST_REQUEST => Ok(PeerNodeMsg::DigestRequestMsg(self.get_vc_msg()?)),
+            MSG_DIGEST_REPLY   => Ok(PeerNodeMsg::DigestReplyMsg(self.get_vc_msg()?)),
+            MSG_DIGEST_ACK     => Ok(PeerNodeMsg::DigestAckMsg(self.get_vc_msg()?)),
+            MSG_MERKLE_REQUEST => {
+                let node = self.get_node()?;
+                let depth = self.get_u8()?;
+                let low_vector_clock = self.get_clock()?;
+                let high_vector_clock = self.get_clock()?;
+                let path_list = self.get_path_list()?;
+                Ok(PeerNodeMsg::MerkleRequestMsg(MerkleRequestMsg{node, depth, low_vector_clock, high_vector_clock, path_list}))
+            },
+            MSG_MERKLE_REPLY   => {
+                let node = self.get_node()?;
+                let depth = self.get_u8()?;
+                let low_vector_clock = self.get_clock()?;
+                let high_vector_clock = self.get_clock()?;
+                let path_list = self.get_path_list()?;
+                let mut hash_list = Vec::new();
+                for _ in 0..self.get_len()? {
+                    let path = self.get_path()?;
+                    hash_list.push((path, self.get_u64()?));
+                }
+                let mut leaf_list = Vec::new();
+                for _ in 0..self.get_len()? {
+                    let leaf_node = self.get_node()?;
+                    let lc = self.get_lc()?;
+                    leaf_list.push((leaf_node, lc, self.get_u64()?));
+                }
+                Ok(PeerNodeMsg::MerkleReplyMsg(MerkleReplyMsg{node, depth, low_vector_clock, high_vector_clock, path_list, hash_list, leaf_list}))
+            },
+            tag                => Err(wire_error(format!("unknown message tag {}", tag)))
+        }
+    }
+
+    fn get_frame_msg<OpsValue: WireValue>(&mut self) -> Result<Option<PeerNodeMsg<OpsValue>>, CrdtError> {
+        if self.version < 2 {
+            return Ok(Some(self.get_peer_msg()?));
+        }
+
+        let len = self.get_len()?;
+        let end = self.pos + len;
+        if len == 0 || !(MSG_VECTOR_CLOCK..=MSG_DELTA_UPDATE).contains(&self.buf[self.pos]) {
+            self.pos = end;
+            return Ok(None);
+        }
+        let msg = self.get_peer_msg()?;
+        if self.pos > end {
+            return Err(wire_error("message overruns its length"));
+        }
+        self.pos = end;
+        Ok(Some(msg))
+    }
+
+    fn finish(&self) -> Result<(), CrdtError> {
+        match self.buf.len() - self.pos {
+            0    => Ok(()),
+            rest => Err(wire_error(format!("{} trailing bytes", rest)))
+        }
+    }
+}
+
+fn crdt_type_tag(crdt_type: &CrdtType) -> u8 {
+    match crdt_type {
+        CrdtType::AddMultCrdt   => 0,
+        CrdtType::EWFlagCrdt    => 1,
+        CrdtType::DWFlagCrdt    => 2,
+        CrdtType::AWSetCrdt     => 3,
+        CrdtType::RWSetCrdt     => 4,
+        CrdtType::PNCounterCrdt => 5
+    }
+}
+
+fn crdt_type_from_tag(tag: u8) -> Result<CrdtType, CrdtError> {
+    match tag {
+        0 => Ok(CrdtType::AddMultCrdt),
+        1 => Ok(CrdtType::EWFlagCrdt),
+        2 => Ok(CrdtType::DWFlagCrdt),
+        3 => Ok(CrdtType::AWSetCrdt),
+        4 => Ok(CrdtType::RWSetCrdt),
+        5 => Ok(CrdtType::PNCounterCrdt),
+        _ => Err(wire_error(format!("unknown crdt type tag {}", tag)))
+    }
+}
+
+fn clock_node_list(vc: &VectorClock, node_set: &mut BTreeSet<NodeType>) {
+    node_set.extend(vc.vcmap.keys().copied());
+}
+
+fn peer_msg_node_list<OpsValue: Clone+PartialEq>(msg: &PeerNodeMsg<OpsValue>, node_set: &mut BTreeSet<NodeType>) {
+    match msg {
+        PeerNodeMsg::VectorClockNodeMsg(vmsg) |
+        PeerNodeMsg::DigestRequestMsg(vmsg)   |
+        PeerNodeMsg::DigestReplyMsg(vmsg)     |
+        PeerNodeMsg::DigestAckMsg(vmsg)       => {
+            node_set.insert(vmsg.node);
+            clock_node_list(&vmsg.node_vector_clock, node_set);
+        },
+        PeerNodeMsg::UpdateNodeMsg(umsg)      => {
+            node_set.insert(umsg.node);
+            clock_node_list(&umsg.node_vector_clock, node_set);
+            node_set.extend(umsg.pred_hash_list.iter().map(|(node, _, _)| *node));
+        },
+        PeerNodeMsg::DeltaUpdateNodeMsg(dmsg) => {
+            node_set.insert(dmsg.node);
+            node_set.extend(dmsg.entry_list.iter().map(|(node, _)| *node));
+            node_set.extend(dmsg.pred_hash_list.iter().map(|(node, _, _)| *node));
+        },
+        PeerNodeMsg::MerkleRequestMsg(rmsg)   => {
+            node_set.insert(rmsg.node);
+            clock_node_list(&rmsg.low_vector_clock, node_set);
+            clock_node_list(&rmsg.high_vector_clock, node_set);
+        },
+        PeerNodeMsg::MerkleReplyMsg(rmsg)     => {
+            node_set.insert(rmsg.node);
+            clock_node_list(&rmsg.low_vector_clock, node_set);
+            clock_node_list(&rmsg.high_vector_clock, node_set);
+            node_set.extend(rmsg.leaf_list.iter().map(|(node, _, _)| *node));
+        }
+    }
+}
+
+fn has_pred_hash<OpsValue: Clone+PartialEq>(msg: &PeerNodeMsg<OpsValue>) -> bool {
+    match msg {
+        PeerNodeMsg::UpdateNodeMsg(umsg)      => !umsg.pred_hash_list.is_empty(),
+        PeerNodeMsg::DeltaUpdateNodeMsg(dmsg) => !dmsg.pred_hash_list.is_empty(),
+        _                                     => false
+    }
+}
+
+fn has_epoch<OpsValue: Clone+PartialEq>(msg: &PeerNodeMsg<OpsValue>) -> bool {
+    match msg {
+        PeerNodeMsg::VectorClockNodeMsg(vmsg) |
+        PeerNodeMsg::DigestRequestMsg(vmsg)   |
+        PeerNodeMsg::DigestReplyMsg(vmsg)     |
+        PeerNodeMsg::DigestAckMsg(vmsg)       => vmsg.epoch != 0 || vmsg.reset_pending,
+        PeerNodeMsg::UpdateNodeMsg(umsg)      => umsg.epoch != 0,
+        PeerNodeMsg::DeltaUpdateNodeMsg(dmsg) => dmsg.epoch != 0,
+        PeerNodeMsg::MerkleRequestMsg(_)      |
+        PeerNodeMsg::MerkleReplyMsg(_)        => false
+    }
+}
+
+fn is_signed<OpsValue: Clone+PartialEq>(msg: &PeerNodeMsg<OpsValue>) -> bool {
+    match msg {
+        PeerNodeMsg::VectorClockNodeMsg(vmsg) |
+        PeerNodeMsg::DigestRequestMsg(vmsg)   |
+        PeerNodeMsg::DigestReplyMsg(vmsg)     |
+        PeerNodeMsg::DigestAckMsg(vmsg)       => vmsg.signature.is_some(),
+        PeerNodeMsg::UpdateNodeMsg(umsg)      => umsg.signature.is_some(),
+        PeerNodeMsg::DeltaUpdateNodeMsg(dmsg) => dmsg.signature.is_some(),
+        PeerNodeMsg::MerkleRequestMsg(_)      |
+        PeerNodeMsg::MerkleReplyMsg(_)        => false
+    }
+}
+
+fn known_flags(version: u8) -> u8 {
+    match version {
+        0..=2 => FLAG_DELTA_CLOCK,
+        3     => FLAG_DELTA_CLOCK | FLAG_SIGNED,
+        4     => FLAG_DELTA_CLOCK | FLAG_SIGNED | FLAG_HASH_CHAIN,
+        _     => FLAG_DELTA_CLOCK | FLAG_SIGNED | FLAG_HASH_CHAIN | FLAG_EPOCH
+    }
+}
+
+fn frame_writer(kind: u8, node_set: BTreeSet<NodeType>, options: WireOptions, flags: u8) -> Result<WireWriter, CrdtError> {
+    if !(WIRE_MIN_VERSION..=WIRE_VERSION).contains(&options.version) {
+        return Err(CrdtError::UnsupportedVersion(options.version as u16));
+    }
+    let flags = flags | if options.delta_clock { FLAG_DELTA_CLOCK } else { 0 };
+    if flags & !known_flags(options.version) != 0 {
+        return Err(CrdtError::EncodeError(format!("wire version {} cannot carry flags {:#x}", options.version, flags)));
+    }
+    let mut writer = WireWriter::new(node_set.into_iter().collect(), options.delta_clock, flags);
+    writer.put_u8(options.version);
+    writer.put_u8(flags);
+    writer.put_u8(kind);
+    writer.put_dictionary();
+    Ok(writer)
+}
+
+fn frame_reader(bytes: &[u8], kind: u8) -> Result<WireReader<'_>, CrdtError> {
+    let mut reader = WireReader::new(bytes);
+    let version = reader.get_u8()?;
+    if !(WIRE_MIN_VERSION..=WIRE_VERSION).contains(&version) {
+        return Err(CrdtError::UnsupportedVersion(version as u16));
+    }
+    reader.version = version;
+    let flags = reader.get_u8()?;
+    if flags & !known_flags(version) != 0 {
+        return Err(wire_error(format!("unknown flags {:#x}", flags)));
+    }
+    reader.signed = flags & FLAG_SIGNED != 0;
+    reader.hash_chain = flags & FLAG_HASH_CHAIN != 0;
+    reader.epoch = flags & FLAG_EPOCH != 0;
+    let frame_kind = reader.get_u8()?;
+    if frame_kind != kind {
+        return Err(wire_error(format!("expected frame kind {} found {}", kind, frame_kind)));
+    }
+    reader.get_dictionary()?;
+    Ok(reader)
+}
+
+pub fn encode_peer_msg_list<OpsValue: WireValue>(msg_list: &[PeerNodeMsg<OpsValue>], options: WireOptions) -> Result<Vec<u8>, CrdtError> {
+    let mut node_set = BTreeSet::new();
+    for msg in msg_list {
+        peer_msg_node_list(msg, &mut node_set);
+    }
+    let flags = if msg_list.iter().any(is_signed) { FLAG_SIGNED } else { 0 } |
+                if msg_list.iter().any(has_pred_hash) { FLAG_HASH_CHAIN } else { 0 } |
+                if msg_list.iter().any(has_epoch) { FLAG_EPOCH } else { 0 };
+    let mut writer = frame_writer(FRAME_PEER_MSG_LIST, node_set, options, flags)?;
+    writer.put_varint(msg_list.len() as u64);
+    for msg in msg_list {
+        let start = writer.buf.len();
+        writer.put_peer_msg(msg)?;
+        if options.version >= 2 {
+            let body = writer.buf.split_off(start);
+            writer.put_varint(body.len() as u64);
+            writer.buf.extend(body);
+        }
+    }
+    Ok(writer.buf)
+}
+
+pub fn decode_peer_frame<OpsValue: WireValue>(bytes: &[u8]) -> Result<DecodedFrame<OpsValue>, CrdtError> {
+    let mut reader = frame_reader(bytes, FRAME_PEER_MSG_LIST)?;
+    let len = reader.get_len()?;
+    let mut msg_list = Vec::with_capacity(len);
+    let mut skipped_count = 0;
+    for _ in 0..len {
+        match reader.get_frame_msg()? {
+            Some(msg) => msg_list.push(msg),
+            None      => skipped_count += 1
+        }
+    }
+    reader.finish()?;
+    Ok(DecodedFrame{version: reader.version, msg_list, skipped_count})
+}
+
+pub fn decode_peer_msg_list<OpsValue: WireValue>(bytes: &[u8]) -> Result<Vec<PeerNodeMsg<OpsValue>>, CrdtError> {
+    Ok(decode_peer_frame(bytes)?.msg_list)
+}
+
+pub fn encode_peer_msg<OpsValue: WireValue>(msg: &PeerNodeMsg<OpsValue>) -> Result<Vec<u8>, CrdtError> {
+    encode_peer_msg_list(std::slice::from_ref(msg), WireOptions::default())
+}
+
+pub fn decode_peer_msg<OpsValue: WireValue>(bytes: &[u8]) -> Result<PeerNodeMsg<OpsValue>, CrdtError> {
+    let mut msg_list = decode_peer_msg_list(bytes)?;
+    match msg_list.len() {
+        1   => Ok(msg_list.remove(0)),
+        len => Err(wire_error(format!("expected one message found {}", len)))
+    }
+}
+
+pub fn encode_vc_msg(msg: &NodeVectorClockMsg) -> Result<Vec<u8>, CrdtError> {
+    let mut node_set = BTreeSet::from([msg.node]);
+    clock_node_list(&msg.node_vector_clock, &mut node_set);
+    let flags = if msg.signature.is_some() { FLAG_SIGNED } else { 0 } |
+                if msg.epoch != 0 || msg.reset_pending { FLAG_EPOCH } else { 0 };
+    let mut writer = frame_writer(FRAME_VC_MSG, node_set, WireOptions::default(), flags)?;
+    writer.put_vc_msg(msg)?;
+    Ok(writer.buf)
+}
+
+pub fn decode_vc_msg(bytes: &[u8]) -> Result<NodeVectorClockMsg, CrdtError> {
+    let mut reader = frame_reader(bytes, FRAME_VC_MSG)?;
+    let msg = reader.get_vc_msg()?;
+    reader.finish()?;
+    Ok(msg)
+}
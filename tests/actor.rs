@@ -0,0 +1,112 @@
+#![cfg(feature = "tokio")]
+
+mod common;
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use ops_crdt_rust::crdt::{CRDT, CrdtInstance, CrdtType};
+use ops_crdt_rust::actor::{spawn_replica, ChannelTransport, Command, ReplicaHandle};
+use ops_crdt_rust::arset_crdt::AWSet;
+use ops_crdt_rust::error::CrdtError;
+use ops_crdt_rust::message_data::{OpsInstance, PeerNodeMsg, SDPOpsType, UserUpdateMsg};
+use ops_crdt_rust::pncnt_crdt::PNCounterData;
+
+use common::Counter;
+
+type Set = CRDT<HashSet<i32>, i32, AWSet>;
+
+fn set_umsg(ops_type: SDPOpsType, value: i32) -> UserUpdateMsg<i32> {
+    UserUpdateMsg::new(CrdtInstance::new(0, 0, CrdtType::AWSetCrdt), OpsInstance::new(ops_type, value))
+}
+
+async fn wait_for<F: Fn(&HashSet<i32>) -> bool>(handle: &ReplicaHandle<HashSet<i32>, i32>, done: F) -> HashSet<i32> {
+    for _ in 0..200 {
+        let value = handle.query().await.unwrap();
+        if done(&value) {
+            return value;
+        }
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    handle.query().await.unwrap()
+}
+
+#[tokio::test]
+async fn actors_converge_over_channels() {
+    let node_list = vec![0, 1, 2];
+    let (handle_list, inbox_list): (Vec<_>, Vec<_>) = node_list.iter().map(|node| ReplicaHandle::channel(*node, 64)).unzip();
+    let mut join_list = Vec::new();
+    for (node, inbox) in node_list.iter().zip(inbox_list) {
+        let replica = Set::new_with_node_list(*node, node_list.clone(), HashSet::new()).unwrap();
+        join_list.push(spawn_replica(replica, inbox, ChannelTransport::new(&handle_list), 10));
+    }
+
+    handle_list[0].update(set_umsg(SDPOpsType::SDPMult, 1)).await.unwrap();
+    handle_list[1].update(set_umsg(SDPOpsType::SDPMult, 2)).await.unwrap();
+    handle_list[2].update(set_umsg(SDPOpsType::SDPMult, 3)).await.unwrap();
+    wait_for(&handle_list[0], |value| value.contains(&3)).await;
+    handle_list[0].update(set_umsg(SDPOpsType::SDPAdd, 3)).await.unwrap();
+
+    let expected = HashSet::from([1, 2]);
+    for handle in handle_list.iter() {
+        assert_eq!(wait_for(handle, |value| *value == expected).await, expected);
+    }
+
+    for handle in handle_list.iter() {
+        handle.stop().await.unwrap();
+    }
+    for join in join_list {
+        let replica = join.await.unwrap();
+        assert_eq!(replica.query(), expected);
+    }
+}
+
+#[tokio::test]
+async fn errors_reach_the_caller() {
+    let (handle, inbox) = ReplicaHandle::channel(0, 8);
+    let replica = Counter::new_with_node_list(0, vec![0, 1], PNCounterData::new()).unwrap();
+    let join = spawn_replica(replica, inbox, ChannelTransport::new(std::slice::from_ref(&handle)), 0);
+
+    // an update naming another crdt type is refused and the actor carries on
+    let umsg = UserUpdateMsg::new(CrdtInstance::new(0, 0, CrdtType::AWSetCrdt), OpsInstance::new(SDPOpsType::SDPAdd, 4));
+    assert!(matches!(handle.update(umsg).await, Err(CrdtError::UnknownInstance(_))));
+    let umsg = UserUpdateMsg::new(CrdtInstance::new(0, 0, CrdtType::PNCounterCrdt), OpsInstance::new(SDPOpsType::SDPAdd, 4));
+    handle.update(umsg).await.unwrap();
+    assert_eq!(handle.query().await.unwrap(), serde_json::from_value(serde_json::json!({"pcount": 4, "ncount": 0})).unwrap());
+
+    handle.stop().await.unwrap();
+    join.await.unwrap();
+    assert!(matches!(handle.query().await, Err(CrdtError::ActorStopped(0))));
+}
+
+#[tokio::test]
+async fn rejected_peer_batch_is_reported() {
+    let (handle, inbox) = ReplicaHandle::channel(0, 8);
+    let (peer_handle, mut peer_inbox) = ReplicaHandle::channel(1, 8);
+    let replica = Set::new_with_node_list(0, vec![0, 1], HashSet::new()).unwrap();
+    let join = spawn_replica(replica, inbox, ChannelTransport::new(&[handle.clone(), peer_handle]), 0);
+    handle.update(set_umsg(SDPOpsType::SDPMult, 1)).await.unwrap();
+    while peer_inbox.rx.try_recv().is_ok() {}
+
+    // a clock from an epoch the actor cannot reach, then a digest request it answers
+    let peer = Set::new_with_node_list(1, vec![0, 1], HashSet::new()).unwrap();
+    let mut vc_msg = peer.create_vc_msg();
+    vc_msg.epoch = 2;
+    let mut pmsg_list = vec![PeerNodeMsg::VectorClockNodeMsg(vc_msg)];
+    pmsg_list.extend(peer.create_digest_request().remove(&0).unwrap());
+    handle.deliver(pmsg_list).await.unwrap();
+
+    // the reply to the digest request still goes out and the error is kept for the caller
+    match tokio::time::timeout(Duration::from_secs(1), peer_inbox.rx.recv()).await {
+        Ok(Some(Command::Peer(reply_list, None))) => assert!(!reply_list.is_empty()),
+        _                                         => panic!("no reply to the digest request")
+    }
+    let error_list = handle.take_errors().await.unwrap();
+    assert_eq!(error_list.len(), 1);
+    assert!(matches!(error_list[0], CrdtError::EpochMismatch(0, 2)));
+    assert!(handle.take_errors().await.unwrap().is_empty());
+    assert_eq!(handle.query().await.unwrap(), HashSet::from([1]));
+
+    handle.stop().await.unwrap();
+    join.await.unwrap();
+}
@@ -0,0 +1,65 @@
+mod common;
+
+use ops_crdt_rust::message_data::PeerNodeMsg;
+use ops_crdt_rust::vector_clock::VCStatus;
+
+use common::{counter_list, increment_msg, pcount};
+
+fn kind_list(msg_list: &[PeerNodeMsg<u32>]) -> Vec<&'static str> {
+    msg_list.iter().map(|msg| match msg {
+        PeerNodeMsg::UpdateNodeMsg(_)      |
+        PeerNodeMsg::DeltaUpdateNodeMsg(_) => "update",
+        PeerNodeMsg::DigestReplyMsg(_)     => "reply",
+        PeerNodeMsg::DigestAckMsg(_)       => "ack",
+        _                                  => "other"
+    }).collect()
+}
+
+#[test]
+fn next_op_of_a_node_waits_for_its_dependencies() {
+    let mut node_list = counter_list(3);
+    let (msg_a, _) = increment_msg(&mut node_list[0], 0, 1);
+    node_list[1].process_peer_msg(vec![PeerNodeMsg::UpdateNodeMsg(msg_a.clone())]).unwrap();
+    let (msg_b, _) = increment_msg(&mut node_list[1], 0, 2);
+
+    // b is the next op of node 1 but depends on a, which node 2 has not seen
+    assert_eq!(node_list[2].general_process_peer_msg(msg_b.clone()).unwrap(), VCStatus::OUTOFORDER);
+    node_list[2].process_peer_msg(vec![PeerNodeMsg::UpdateNodeMsg(msg_b.clone())]).unwrap();
+    assert_eq!((pcount(&node_list[2]), node_list[2].msg_list_len()), (0, 0));
+
+    node_list[2].process_peer_msg(vec![PeerNodeMsg::UpdateNodeMsg(msg_a)]).unwrap();
+    assert_eq!(node_list[2].general_process_peer_msg(msg_b.clone()).unwrap(), VCStatus::INORDER);
+    assert_eq!(node_list[2].general_process_peer_msg(msg_b).unwrap(), VCStatus::DUPLICATE);
+}
+
+#[test]
+fn digest_exchange_repairs_and_acknowledges() {
+    let mut node_list = counter_list(3);
+    for value in 1..=3 {
+        let (msg, _) = increment_msg(&mut node_list[0], 0, value);
+        node_list[1].process_peer_msg(vec![PeerNodeMsg::UpdateNodeMsg(msg)]).unwrap();
+    }
+
+    // node 2 missed every update; its request names what it has
+    let mut request_map = node_list[2].create_digest_request_for(0).unwrap();
+    assert!(node_list[2].create_digest_request_for(7).is_err());
+    let mut reply_map = node_list[0].process_peer_msg(request_map.remove(&0).unwrap()).unwrap();
+    let reply_list = reply_map.remove(&2).unwrap();
+    assert_eq!(kind_list(&reply_list), vec!["update", "update", "update", "reply"]);
+
+    let mut ack_map = node_list[2].process_peer_msg(reply_list).unwrap();
+    assert_eq!(pcount(&node_list[2]), 6);
+    let ack_list = ack_map.remove(&0).unwrap();
+    assert_eq!(kind_list(&ack_list), vec!["ack"]);
+
+    // the ack tells node 0 what node 2 now holds, so nothing is resent
+    node_list[0].process_peer_msg(ack_list).unwrap();
+    assert_eq!(node_list[0].trcb.node_trcb[&2].vcmap, node_list[2].trcb.node_vector_clock.vcmap);
+    let msg_map = node_list[0].create_peer_msg_list(true).unwrap();
+    assert!(msg_map[&2].is_empty());
+
+    // a second request finds nothing missing and gets only the reply
+    let mut request_map = node_list[2].create_digest_request();
+    let reply_map = node_list[0].process_peer_msg(request_map.remove(&0).unwrap()).unwrap();
+    assert_eq!(kind_list(&reply_map[&2]), vec!["reply"]);
+}
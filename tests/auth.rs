@@ -0,0 +1,92 @@
+#![cfg(any(feature = "hmac", feature = "ed25519"))]
+mod common;
+
+use std::collections::HashMap;
+
+use ops_crdt_rust::auth::MsgAuth;
+use ops_crdt_rust::message_data::{NodeVectorClockMsg, PeerNodeMsg};
+use ops_crdt_rust::vector_clock::VectorClock;
+use ops_crdt_rust::error::CrdtError;
+
+use common::{increment, Counter};
+
+fn counter(node: u16, auth: Box<dyn MsgAuth>) -> Counter {
+    let mut crdt = common::counter(node, vec![0, 1, 2]);
+    crdt.set_auth(Some(auth));
+    crdt
+}
+
+fn update_list(msg_map: &HashMap<u16, Vec<PeerNodeMsg<u32>>>, pnode: u16) -> Vec<PeerNodeMsg<u32>> {
+    msg_map[&pnode].iter().filter(|msg| !matches!(msg, PeerNodeMsg::VectorClockNodeMsg(_))).cloned().collect()
+}
+
+fn tamper(msg_list: &[PeerNodeMsg<u32>], ops_value: Option<u32>) -> Vec<PeerNodeMsg<u32>> {
+    let mut msg_list = msg_list.to_vec();
+    let (user_update_msg, signature) = match &mut msg_list[0] {
+        PeerNodeMsg::UpdateNodeMsg(umsg)      => (&mut umsg.user_update_msg, &mut umsg.signature),
+        PeerNodeMsg::DeltaUpdateNodeMsg(dmsg) => (&mut dmsg.user_update_msg, &mut dmsg.signature),
+        msg                                   => panic!("expected an update found {:?}", msg)
+    };
+    match ops_value {
+        Some(ops_value) => user_update_msg.ops_instance.ops_value = ops_value,
+        None            => *signature = None
+    }
+    msg_list
+}
+
+fn value(crdt: &Counter) -> serde_json::Value {
+    serde_json::to_value(&crdt.crdt_value).unwrap()
+}
+
+// a replica accepts its peers, rejects a forged origin or a tampered update, and accepts
+// an update relayed by a third node since the signature belongs to the origin
+fn check_forgery_rejected(auth: impl Fn(u16) -> Box<dyn MsgAuth>, forger: Box<dyn MsgAuth>) {
+    let mut node0 = counter(0, auth(0));
+    let mut node1 = counter(1, auth(1));
+    let mut node2 = counter(2, auth(2));
+
+    let msg_list = update_list(&increment(&mut node0, 5), 1);
+    node1.process_peer_msg(msg_list.clone()).unwrap();
+    assert_eq!(value(&node1), serde_json::json!({"pcount": 5, "ncount": 0}));
+
+    node2.process_peer_msg(tamper(&msg_list, Some(500))).unwrap();
+    assert!(matches!(node2.take_rejection_list().as_slice(), [CrdtError::AuthRejected(0)]));
+
+    let mut mallory = counter(0, forger);
+    let forged = update_list(&increment(&mut mallory, 1000), 2);
+    node2.process_peer_msg(forged.clone()).unwrap();
+    node2.process_peer_msg(tamper(&msg_list, None)).unwrap();
+    assert!(matches!(node2.take_rejection_list().as_slice(), [CrdtError::AuthRejected(0), CrdtError::AuthRejected(0)]));
+    assert_eq!(value(&node2), serde_json::json!({"pcount": 0, "ncount": 0}));
+
+    let forged_vc = NodeVectorClockMsg::new(1, VectorClock{vcmap: HashMap::from([(0, 9), (1, 9), (2, 9)])});
+    node2.process_peer_msg(vec![PeerNodeMsg::VectorClockNodeMsg(forged_vc)]).unwrap();
+    assert!(matches!(node2.take_rejection_list().as_slice(), [CrdtError::AuthRejected(1)]));
+
+    // a forged message in the middle of a batch does not hold back the rest of it
+    node2.process_peer_msg(forged.into_iter().chain(msg_list).collect()).unwrap();
+    assert!(matches!(node2.take_rejection_list().as_slice(), [CrdtError::AuthRejected(0)]));
+    assert_eq!(value(&node2), serde_json::json!({"pcount": 5, "ncount": 0}));
+    node2.process_peer_msg(vec![PeerNodeMsg::VectorClockNodeMsg(node1.create_vc_msg())]).unwrap();
+}
+
+#[cfg(feature = "hmac")]
+#[test]
+fn hmac_rejects_forged_updates() {
+    use ops_crdt_rust::auth::HmacAuth;
+
+    let secret_list: HashMap<u16, Vec<u8>> = (0..3).map(|node| (node, format!("secret-{}", node).into_bytes())).collect();
+    let forger = HmacAuth::new(0, HashMap::from([(0, b"guessed".to_vec())])).unwrap();
+    check_forgery_rejected(|node| Box::new(HmacAuth::new(node, secret_list.clone()).unwrap()), Box::new(forger));
+}
+
+#[cfg(feature = "ed25519")]
+#[test]
+fn ed25519_rejects_forged_updates() {
+    use ops_crdt_rust::auth::Ed25519Auth;
+
+    let secret_key = |node: u16| [node as u8 + 1; 32];
+    let public_key_list: HashMap<u16, [u8; 32]> = (0..3).map(|node| (node, Ed25519Auth::public_key(&secret_key(node)))).collect();
+    let forger = Ed25519Auth::new(&[42; 32], public_key_list.clone()).unwrap();
+    check_forgery_rejected(|node| Box::new(Ed25519Auth::new(&secret_key(node), public_key_list.clone()).unwrap()), Box::new(forger));
+}
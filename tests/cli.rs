@@ -0,0 +1,124 @@
+use std::collections::{BTreeMap, HashSet};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use serde_json::{json, Value};
+
+use ops_crdt_rust::cli::{parse_args, request, CliCommand, CliRequest, CliResponse, ReplicaServer};
+use ops_crdt_rust::crdt::CrdtType;
+use ops_crdt_rust::error::CrdtError;
+use ops_crdt_rust::message_data::SDPOpsType;
+
+fn args(line: &str) -> Vec<String> {
+    line.split_whitespace().map(|arg| arg.to_owned()).collect()
+}
+
+fn data_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("ops_crdt_cli_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+fn wait_for<F: Fn(&Value) -> bool>(addr: &str, crdt_type: CrdtType, done: F) -> Value {
+    let mut value = Value::Null;
+    for _ in 0..100 {
+        value = request(addr, &CliRequest::Query{crdt_type: crdt_type.clone(), instance_num: 0}).unwrap();
+        if done(&value) {
+            break;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    value
+}
+
+#[test]
+fn args_parse_into_commands() {
+    let command = parse_args(&args("start --node 1 --peers 0=127.0.0.1:7000,1=127.0.0.1:7001 --data-dir data/1")).unwrap();
+    assert_eq!(command, CliCommand::Start{node: 1,
+                                          peer_list: BTreeMap::from([(0, "127.0.0.1:7000".to_owned()), (1, "127.0.0.1:7001".to_owned())]),
+                                          data_dir: PathBuf::from("data/1")});
+
+    let command = parse_args(&args("submit --addr 127.0.0.1:7000 --crdt ewflag --op add --value Disabled")).unwrap();
+    assert_eq!(command, CliCommand::Submit{addr: "127.0.0.1:7000".to_owned(), crdt_type: CrdtType::EWFlagCrdt, instance_num: 0,
+                                           ops_type: SDPOpsType::SDPAdd, value: json!("Disabled")});
+    let command = parse_args(&args("dump --crdt pncnt --addr 127.0.0.1:7000")).unwrap();
+    assert_eq!(command, CliCommand::Dump{addr: "127.0.0.1:7000".to_owned(), crdt_type: CrdtType::PNCounterCrdt, instance_num: 0});
+    let command = parse_args(&args("query --crdt awset --instance 2 --addr 127.0.0.1:7000")).unwrap();
+    assert_eq!(command, CliCommand::Query{addr: "127.0.0.1:7000".to_owned(), crdt_type: CrdtType::AWSetCrdt, instance_num: 2});
+
+    assert!(matches!(parse_args(&args("submit --addr a --crdt awset --op del --value 1")), Err(CrdtError::ConfigError(_, _))));
+    assert!(matches!(parse_args(&args("query --addr a --crdt list")), Err(CrdtError::ConfigError(_, _))));
+    assert!(matches!(parse_args(&args("query --addr a --crdt awset --instance x")), Err(CrdtError::ConfigError(_, _))));
+    assert!(matches!(parse_args(&args("query --addr")), Err(CrdtError::ConfigError(_, _))));
+    assert!(matches!(parse_args(&[]), Err(CrdtError::ConfigError(_, _))));
+}
+
+#[test]
+fn replicas_converge_over_tcp_and_survive_restart() {
+    let listener_list: Vec<TcpListener> = (0..2).map(|_| TcpListener::bind("127.0.0.1:0").unwrap()).collect();
+    let peer_list: BTreeMap<u16, String> = listener_list.iter()
+                                                        .enumerate()
+                                                        .map(|(node, listener)| (node as u16, listener.local_addr().unwrap().to_string()))
+                                                        .collect();
+    let dir_list = [data_dir("node0"), data_dir("node1")];
+    for (node, listener) in listener_list.into_iter().enumerate() {
+        let server = Arc::new(ReplicaServer::new(node as u16, peer_list.clone(), dir_list[node].clone()).unwrap());
+        thread::spawn(move || server.serve(listener));
+    }
+
+    let submit = |node: u16, crdt_type: CrdtType, ops_type: SDPOpsType, value: Value| {
+        request(&peer_list[&node], &CliRequest::Submit{crdt_type, instance_num: 0, ops_type, value})
+    };
+    assert_eq!(submit(0, CrdtType::AWSetCrdt, SDPOpsType::SDPMult, json!(3)).unwrap(), json!([3]));
+    submit(1, CrdtType::AWSetCrdt, SDPOpsType::SDPMult, json!(5)).unwrap();
+    submit(1, CrdtType::PNCounterCrdt, SDPOpsType::SDPAdd, json!(4)).unwrap();
+    assert!(matches!(submit(0, CrdtType::PNCounterCrdt, SDPOpsType::SDPAdd, json!("four")), Err(CrdtError::RemoteError(_))));
+
+    let counter = json!({"pcount": 4, "ncount": 0});
+    let set = |value: &Value| serde_json::from_value::<HashSet<i32>>(value.clone()).unwrap();
+    for node in 0..2 {
+        let value = wait_for(&peer_list[&node], CrdtType::AWSetCrdt, |value| set(value) == HashSet::from([3, 5]));
+        assert_eq!(set(&value), HashSet::from([3, 5]));
+        assert_eq!(wait_for(&peer_list[&node], CrdtType::PNCounterCrdt, |value| *value == counter), counter);
+    }
+
+    let dump = request(&peer_list[&0], &CliRequest::Dump{crdt_type: CrdtType::PNCounterCrdt, instance_num: 0}).unwrap();
+    assert_eq!(dump["node"], json!(0));
+    assert_eq!(dump["node_vector_clock"]["vcmap"]["1"], json!(1));
+
+    // a replica started again on the same data directory picks up where it stopped
+    let restarted = ReplicaServer::new(1, peer_list.clone(), dir_list[1].clone()).unwrap();
+    match restarted.handle_request(CliRequest::Query{crdt_type: CrdtType::PNCounterCrdt, instance_num: 0}) {
+        CliResponse::Ok(value) => assert_eq!(value, counter),
+        CliResponse::Err(e)    => panic!("{}", e)
+    }
+    for dir in dir_list {
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
+
+#[test]
+fn instances_of_one_type_are_kept_apart() {
+    let dir = data_dir("instances");
+    let peer_list = BTreeMap::from([(0, "127.0.0.1:1".to_owned())]);
+    let server = ReplicaServer::new(0, peer_list.clone(), dir.clone()).unwrap();
+    let value = |server: &ReplicaServer, request: CliRequest| match server.handle_request(request) {
+        CliResponse::Ok(value) => Ok(value),
+        CliResponse::Err(e)    => Err(e)
+    };
+
+    let submit = |instance_num, value| CliRequest::Submit{crdt_type: CrdtType::AWSetCrdt, instance_num, ops_type: SDPOpsType::SDPMult, value};
+    value(&server, submit(0, json!(1))).unwrap();
+    value(&server, submit(3, json!(2))).unwrap();
+    assert_eq!(value(&server, CliRequest::Query{crdt_type: CrdtType::AWSetCrdt, instance_num: 0}).unwrap(), json!([1]));
+    assert_eq!(value(&server, CliRequest::Query{crdt_type: CrdtType::AWSetCrdt, instance_num: 3}).unwrap(), json!([2]));
+    assert!(value(&server, CliRequest::Query{crdt_type: CrdtType::AWSetCrdt, instance_num: 4}).is_err());
+    assert!(server.tick().is_empty());
+
+    // every saved instance comes back on restart
+    let restarted = ReplicaServer::new(0, peer_list, dir.clone()).unwrap();
+    assert_eq!(value(&restarted, CliRequest::Query{crdt_type: CrdtType::AWSetCrdt, instance_num: 3}).unwrap(), json!([2]));
+    let _ = std::fs::remove_dir_all(dir);
+}
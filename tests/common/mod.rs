@@ -0,0 +1,38 @@
+// counter fixtures shared by the integration tests; each file uses only some of them
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+
+use ops_crdt_rust::crdt::{CRDT, CrdtInstance, CrdtType};
+use ops_crdt_rust::message_data::{NodeUpdateMsg, OpsInstance, PeerNodeMsg, SDPOpsType, UserUpdateMsg};
+use ops_crdt_rust::pncnt_crdt::{PNCounter, PNCounterData};
+
+pub type Counter = CRDT<PNCounterData, u32, PNCounter>;
+pub type MsgMap = HashMap<u16, Vec<PeerNodeMsg<u32>>>;
+
+pub fn counter(node: u16, node_list: Vec<u16>) -> Counter {
+    Counter::new_with_node_list(node, node_list, PNCounterData::new()).unwrap()
+}
+
+pub fn counter_list(len: u16) -> Vec<Counter> {
+    (0..len).map(|node| counter(node, (0..len).collect())).collect()
+}
+
+pub fn add_msg(instance_num: u16, value: u32) -> UserUpdateMsg<u32> {
+    UserUpdateMsg::new(CrdtInstance::new(0, instance_num, CrdtType::PNCounterCrdt), OpsInstance::new(SDPOpsType::SDPAdd, value))
+}
+
+// the local op as well as what it sends, for tests that deliver or undo it later
+pub fn increment_msg(crdt: &mut Counter, instance_num: u16, value: u32) -> (NodeUpdateMsg<u32>, MsgMap) {
+    let msg = crdt.create_local_msg(add_msg(instance_num, value)).unwrap();
+    let msg_map = crdt.process_local_msg(msg.clone()).unwrap();
+    (msg, msg_map)
+}
+
+pub fn increment(crdt: &mut Counter, value: u32) -> MsgMap {
+    increment_msg(crdt, 0, value).1
+}
+
+pub fn pcount(crdt: &Counter) -> u64 {
+    serde_json::to_value(crdt.query()).unwrap()["pcount"].as_u64().unwrap()
+}
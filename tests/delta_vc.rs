@@ -0,0 +1,45 @@
+mod common;
+
+use ops_crdt_rust::message_data::PeerNodeMsg;
+
+use common::{increment, pcount, Counter};
+
+fn counter(node: u16) -> Counter {
+    let mut crdt = common::counter(node, vec![0, 1, 2]);
+    crdt.set_delta_vc(true);
+    crdt
+}
+
+#[test]
+fn updates_travel_as_deltas_of_the_previous_op() {
+    let mut node0 = counter(0);
+    let mut node1 = counter(1);
+    let mut node2 = counter(2);
+
+    let mut msg_map = increment(&mut node0, 1);
+    node1.process_peer_msg(msg_map.remove(&1).unwrap()).unwrap();
+    let msg_list = increment(&mut node0, 2).remove(&1).unwrap();
+
+    // only the origin entry changed since the previous op of node 0
+    match msg_list.last() {
+        Some(PeerNodeMsg::DeltaUpdateNodeMsg(dmsg)) => assert_eq!((dmsg.lc, dmsg.entry_list.len()), (2, 0)),
+        msg                                         => panic!("expected a delta, got {:?}", msg)
+    }
+    node1.process_peer_msg(msg_list).unwrap();
+    assert_eq!(pcount(&node1), 3);
+
+    // node 2 lacks the base of the second delta, drops it, and is repaired with full clocks
+    let delta_list = node0.create_peer_msg_list(true).unwrap().remove(&2).unwrap();
+    node2.process_peer_msg(delta_list[1..].to_vec()).unwrap();
+    assert_eq!((pcount(&node2), node2.msg_list_len()), (0, 0));
+    node2.process_peer_msg(delta_list).unwrap();
+    assert_eq!(pcount(&node2), 3);
+
+    // node 1 relays node 0 operations as deltas node 2 can expand
+    let mut msg_map = increment(&mut node0, 4);
+    node1.process_peer_msg(msg_map.remove(&1).unwrap()).unwrap();
+    let relay_list = node1.create_peer_msg_list(true).unwrap().remove(&2).unwrap();
+    assert!(matches!(relay_list.last(), Some(PeerNodeMsg::DeltaUpdateNodeMsg(_))));
+    node2.process_peer_msg(relay_list).unwrap();
+    assert_eq!(pcount(&node2), 7);
+}
@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use ops_crdt_rust::LCType;
+use ops_crdt_rust::dense_vector_clock::{DenseVectorClock, Membership, VCSerdeVersion};
+use ops_crdt_rust::vector_clock::{VectorClock, VCOrdering};
+
+fn vector_clock(lc_list: &[LCType]) -> VectorClock {
+    VectorClock{vcmap: lc_list.iter().enumerate().map(|(node, lc)| (node as u16, *lc)).collect::<HashMap<_, _>>()}
+}
+
+fn clock_list() -> Vec<VectorClock> {
+    [[0, 0, 0, 0], [1, 0, 2, 0], [1, 1, 2, 0], [2, 0, 2, 0], [0, 3, 0, 1], [2, 1, 2, 0]].iter().map(|lc_list| vector_clock(lc_list)).collect()
+}
+
+#[test]
+fn dense_clock_agrees_with_vector_clock() {
+    // a shuffled node order, so slots differ from node numbers
+    let membership = Membership::new(vec![2, 0, 3, 1]).unwrap();
+    let other_membership = Membership::new(vec![0, 1, 2, 3]).unwrap();
+    for vc1 in clock_list() {
+        let dvc1 = DenseVectorClock::from_vector_clock(&membership, &vc1).unwrap();
+        assert_eq!(dvc1.to_vector_clock().vcmap, vc1.vcmap);
+        for vc2 in clock_list() {
+            for dvc2 in [DenseVectorClock::from_vector_clock(&membership, &vc2).unwrap(),
+                         DenseVectorClock::from_vector_clock(&other_membership, &vc2).unwrap()] {
+                assert_eq!(dvc1.cmp_vc(&dvc2).unwrap(), vc1.cmp_vc(&vc2).unwrap());
+                assert_eq!(dvc1.max_vc(&dvc2).unwrap().to_vector_clock().vcmap, vc1.max_vc(&vc2).unwrap().vcmap);
+                assert_eq!(dvc1.min_vc(&dvc2).unwrap().to_vector_clock().vcmap, vc1.min_vc(&vc2).unwrap().vcmap);
+                for node in 0..4 {
+                    assert_eq!(dvc1.check_deps(node, &dvc2).unwrap(), vc1.check_deps(node, &vc2).unwrap());
+                    assert_eq!(dvc1.check_vc(node, &dvc2).unwrap(), vc1.check_vc(node, &vc2).unwrap());
+                    assert_eq!(dvc1.is_next_vc(&node, &dvc2).unwrap(), vc1.is_next_vc(&node, &vc2).unwrap());
+                }
+            }
+        }
+
+        let (mut dvc1, mut vc1) = (dvc1, vc1);
+        dvc1.next_vc(&3).unwrap();
+        vc1.next_vc(&3).unwrap();
+        assert_eq!(dvc1.to_vector_clock().vcmap, vc1.vcmap);
+    }
+}
+
+#[test]
+fn serde_round_trips_and_rebinds_membership() {
+    let vc = vector_clock(&[1, 0, 2, 5]);
+
+    // v1 is the VectorClock encoding itself
+    let membership = Membership::new(vec![0, 1, 2, 3]).unwrap();
+    let dvc = DenseVectorClock::from_vector_clock(&membership, &vc).unwrap();
+    let json = serde_json::to_string(&dvc).unwrap();
+    assert_eq!(serde_json::from_str::<VectorClock>(&json).unwrap().vcmap, vc.vcmap);
+    let mut dvc1: DenseVectorClock = serde_json::from_str(&json).unwrap();
+    let dvc2: DenseVectorClock = serde_json::from_str(&serde_json::to_string(&vc).unwrap()).unwrap();
+    assert_eq!(dvc1.to_vector_clock().vcmap, vc.vcmap);
+    assert_eq!(dvc1.membership().serde_version(), VCSerdeVersion::V1);
+    assert_eq!(dvc1.cmp_vc(&dvc2).unwrap(), VCOrdering::VCEQ);
+    assert!(!Arc::ptr_eq(dvc1.membership(), &membership));
+    dvc1.rebind(&membership).unwrap();
+    assert!(Arc::ptr_eq(dvc1.membership(), &membership));
+    assert_eq!(dvc1.to_vector_clock().vcmap, vc.vcmap);
+
+    let membership = Membership::new_with_version(vec![3, 1, 0, 2], VCSerdeVersion::V2).unwrap();
+    let dvc = DenseVectorClock::from_vector_clock(&membership, &vc).unwrap();
+    let json = serde_json::to_value(&dvc).unwrap();
+    assert_eq!(json, serde_json::json!({"version": 2, "nodes": [3, 1, 0, 2], "counters": [5, 0, 1, 2]}));
+    let mut dvc1: DenseVectorClock = serde_json::from_value(json).unwrap();
+    assert_eq!(dvc1.to_vector_clock().vcmap, vc.vcmap);
+    assert_eq!(dvc1.membership().serde_version(), VCSerdeVersion::V2);
+    dvc1.rebind(&membership).unwrap();
+    assert!(Arc::ptr_eq(dvc1.membership(), &membership));
+
+    let bad_json = serde_json::json!({"version": 2, "nodes": [0, 1], "counters": [1]});
+    assert!(serde_json::from_value::<DenseVectorClock>(bad_json).is_err());
+    assert!(serde_json::from_value::<DenseVectorClock>(serde_json::json!({"version": 3, "counters": [1]})).is_err());
+}
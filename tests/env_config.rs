@@ -0,0 +1,10 @@
+use ops_crdt_rust::constants::check_env;
+use ops_crdt_rust::error::CrdtError;
+
+// the env config is read once per process, so this file holds a single test
+#[test]
+fn merkle_depth_out_of_range_is_refused() {
+    std::env::set_var("MERKLE_DEPTH", "256");
+    assert!(matches!(check_env(), Err(CrdtError::ConfigError(param, value)) if param == "MERKLE_DEPTH" && value == "256"));
+    assert_eq!(*ops_crdt_rust::constants::MERKLE_DEPTH, 0);
+}
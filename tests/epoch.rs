@@ -0,0 +1,157 @@
+mod common;
+
+use ops_crdt_rust::LCType;
+use ops_crdt_rust::crdt::{CrdtInstance, CrdtType};
+use ops_crdt_rust::error::CrdtError;
+use ops_crdt_rust::failure_detector::PeerStatus;
+use ops_crdt_rust::message_data::{NodeUpdateMsg, NodeVectorClockMsg, OpsInstance, PeerNodeMsg, SDPOpsType, UserUpdateMsg};
+use ops_crdt_rust::vector_clock::{VectorClock, VCStatus};
+
+use common::{counter_list, pcount, Counter, MsgMap};
+
+fn add(node: &mut Counter, value: u32) -> Result<(NodeUpdateMsg<u32>, MsgMap), CrdtError> {
+    let umsg = UserUpdateMsg::new(CrdtInstance::new(0, 0, CrdtType::PNCounterCrdt), OpsInstance::new(SDPOpsType::SDPAdd, value));
+    let msg = node.create_local_msg(umsg)?;
+    let msg_map = node.process_local_msg(msg.clone())?;
+    Ok((msg, msg_map))
+}
+
+fn deliver(node_list: &mut [Counter], msg_map: MsgMap) {
+    for (pnode, pmsg_list) in msg_map {
+        node_list[pnode as usize].process_peer_msg(pmsg_list).unwrap();
+    }
+}
+
+fn exchange_vc(node_list: &mut [Counter]) {
+    let vc_msg_list: Vec<NodeVectorClockMsg> = node_list.iter().map(|node| node.create_vc_msg()).collect();
+    for vc_msg in vc_msg_list {
+        for node in node_list.iter_mut().filter(|node| node.get_node() != vc_msg.node) {
+            node.process_peer_msg(vec![PeerNodeMsg::VectorClockNodeMsg(vc_msg.clone())]).unwrap();
+        }
+    }
+}
+
+#[test]
+fn clock_overflow_is_an_error() {
+    let mut vc = VectorClock::new(vec![0, 1]).unwrap();
+    vc.vcmap.insert(0, LCType::MAX);
+    assert!(matches!(vc.next_vc(&0), Err(CrdtError::ClockOverflow(0))));
+
+    // no clock follows the largest one, so a peer clock can never be its successor
+    let mut peer_vc = vc.clone();
+    peer_vc.vcmap.insert(0, 0);
+    assert_eq!(vc.is_next_vc(&0, &peer_vc).unwrap(), VCStatus::DUPLICATE);
+}
+
+#[test]
+fn epoch_reset_restarts_clocks_and_keeps_value() {
+    let mut node_list = counter_list(3);
+    node_list[0].set_epoch_reset_lc(2);
+
+    let (_, msg_map) = add(&mut node_list[1], 10).unwrap();
+    deliver(&mut node_list, msg_map);
+    let (_, msg_map) = add(&mut node_list[0], 1).unwrap();
+    deliver(&mut node_list, msg_map);
+    let (old_msg, msg_map) = add(&mut node_list[0], 2).unwrap();
+    deliver(&mut node_list, msg_map);
+
+    // node 0 reached its reset clock and stops creating operations of epoch 0
+    assert!(node_list[0].is_epoch_reset_pending());
+    assert!(matches!(add(&mut node_list[0], 4), Err(CrdtError::EpochResetPending(0))));
+
+    exchange_vc(&mut node_list);
+    assert!(node_list.iter().all(|node| node.is_epoch_reset_pending() || node.get_epoch() == 1));
+    assert!(matches!(add(&mut node_list[2], 4), Err(CrdtError::EpochResetPending(0))));
+    exchange_vc(&mut node_list);
+    exchange_vc(&mut node_list);
+
+    for node in node_list.iter() {
+        assert_eq!((node.get_epoch(), node.is_epoch_reset_pending()), (1, false));
+        assert!(node.trcb.node_vector_clock.vcmap.values().all(|lc| *lc == 0));
+        assert_eq!(pcount(node), 13);
+        assert_eq!(node.msg_list_len(), 0);
+    }
+
+    // a late operation of epoch 0 was already delivered everywhere before the reset
+    node_list[1].process_peer_msg(vec![PeerNodeMsg::UpdateNodeMsg(old_msg)]).unwrap();
+    assert_eq!(pcount(&node_list[1]), 13);
+
+    let (msg, msg_map) = add(&mut node_list[2], 4).unwrap();
+    assert_eq!((msg.epoch, msg.node_vector_clock.vcmap[&2]), (1, 1));
+    deliver(&mut node_list, msg_map);
+    assert!(node_list.iter().all(|node| pcount(node) == 17));
+}
+
+#[test]
+fn pending_node_follows_the_next_epoch() {
+    let mut node_list = counter_list(3);
+    node_list[0].start_epoch_reset().unwrap();
+    exchange_vc(&mut node_list);
+    exchange_vc(&mut node_list);
+    assert!(node_list.iter().all(|node| node.get_epoch() == 1));
+
+    // an update of the next epoch ends the reset of a node still waiting for clock messages
+    let mut node_list = counter_list(3);
+    node_list[0].start_epoch_reset().unwrap();
+    let vc_msg = node_list[0].create_vc_msg();
+    for node in node_list[1..].iter_mut() {
+        node.process_peer_msg(vec![PeerNodeMsg::VectorClockNodeMsg(vc_msg.clone())]).unwrap();
+    }
+    let vc_msg = node_list[2].create_vc_msg();
+    node_list[1].process_peer_msg(vec![PeerNodeMsg::VectorClockNodeMsg(vc_msg)]).unwrap();
+    assert_eq!(node_list[1].get_epoch(), 1);
+    assert!(node_list[2].is_epoch_reset_pending());
+
+    let (_, msg_map) = add(&mut node_list[1], 5).unwrap();
+    node_list[2].process_peer_msg(msg_map[&2].clone()).unwrap();
+    assert_eq!((node_list[2].get_epoch(), pcount(&node_list[2])), (1, 5));
+
+    // a node outside any reset cannot jump two epochs ahead, and the rest of the batch
+    // still applies
+    let mut vc_msg = node_list[1].create_vc_msg();
+    vc_msg.epoch = 3;
+    let (_, msg_map) = add(&mut node_list[1], 2).unwrap();
+    let msg_list = std::iter::once(PeerNodeMsg::VectorClockNodeMsg(vc_msg)).chain(msg_map[&2].clone()).collect();
+    node_list[2].process_peer_msg(msg_list).unwrap();
+    assert!(matches!(node_list[2].take_rejection_list().as_slice(), [CrdtError::EpochMismatch(1, 3)]));
+    assert_eq!((node_list[2].get_epoch(), pcount(&node_list[2])), (1, 7));
+}
+
+#[test]
+fn silent_peer_is_evicted_when_the_reset_times_out() {
+    let mut node_list = counter_list(3);
+    let (_, msg_map) = add(&mut node_list[0], 3).unwrap();
+    deliver(&mut node_list[..2], msg_map.into_iter().filter(|(pnode, _)| *pnode == 1).collect());
+    for node in node_list.iter_mut() {
+        node.set_epoch_reset_timeout(500);
+    }
+
+    // node 2 never answers, so nodes 0 and 1 wait for its final entry
+    node_list[0].start_epoch_reset().unwrap();
+    exchange_vc(&mut node_list[..2]);
+    exchange_vc(&mut node_list[..2]);
+    for node in node_list[..2].iter_mut() {
+        assert!(node.is_epoch_reset_pending());
+        assert_eq!(node.epoch_reset_blocker_list(), vec![2]);
+        node.on_tick(1_000).unwrap();
+        node.on_tick(1_499).unwrap();
+        assert!(node.is_epoch_reset_pending());
+    }
+
+    for node in node_list[..2].iter_mut() {
+        node.on_tick(1_500).unwrap();
+        assert_eq!(node.peer_status(&2), Some(PeerStatus::Evicted));
+        assert_eq!((node.get_epoch(), node.is_epoch_reset_pending(), pcount(node)), (1, false, 3));
+    }
+    let (_, msg_map) = add(&mut node_list[1], 4).unwrap();
+    assert!(!msg_map.contains_key(&2));
+    deliver(&mut node_list[..2], msg_map);
+    assert_eq!(pcount(&node_list[0]), 7);
+
+    // without a timeout the reset waits
+    let mut node_list = counter_list(3);
+    node_list[0].start_epoch_reset().unwrap();
+    node_list[0].on_tick(1_000).unwrap();
+    node_list[0].on_tick(1_000_000).unwrap();
+    assert!(node_list[0].is_epoch_reset_pending());
+}
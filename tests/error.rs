@@ -0,0 +1,105 @@
+mod common;
+
+use ops_crdt_rust::crdt::{CRDT, CrdtInstance, CrdtType};
+use ops_crdt_rust::add_mult_crdt::AddMult;
+use ops_crdt_rust::error::CrdtError;
+use ops_crdt_rust::message_data::{OpsInstance, PeerNodeMsg, SDPOpsType, UserUpdateMsg};
+use ops_crdt_rust::node_state::NodeState;
+
+use common::Counter;
+
+fn counter(node: u16) -> Counter {
+    common::counter(node, vec![0, 1])
+}
+
+fn user_update_msg<T: Clone+PartialEq>(crdt_type: CrdtType, ops_type: SDPOpsType, value: T) -> UserUpdateMsg<T> {
+    UserUpdateMsg::new(CrdtInstance::new(0, 0, crdt_type), OpsInstance::new(ops_type, value))
+}
+
+#[test]
+fn local_msg_is_processed_once() {
+    let mut node0 = counter(0);
+    let msg = node0.create_local_msg(user_update_msg(CrdtType::PNCounterCrdt, SDPOpsType::SDPAdd, 5)).unwrap();
+    node0.process_local_msg(msg.clone()).unwrap();
+    assert!(matches!(node0.process_local_msg(msg), Err(CrdtError::StaleMessage(0, 1))));
+    assert_eq!(serde_json::to_value(&node0.crdt_value).unwrap(), serde_json::json!({"pcount": 5, "ncount": 0}));
+
+    // an older message once a newer one was created is out of turn
+    let old_msg = node0.create_local_msg(user_update_msg(CrdtType::PNCounterCrdt, SDPOpsType::SDPAdd, 1)).unwrap();
+    node0.create_local_msg(user_update_msg(CrdtType::PNCounterCrdt, SDPOpsType::SDPAdd, 1)).unwrap();
+    assert!(matches!(node0.process_local_msg(old_msg), Err(CrdtError::StaleMessage(0, 2))));
+}
+
+#[test]
+fn update_for_another_crdt_type_is_rejected() {
+    let mut node0 = counter(0);
+    let mut node1 = counter(1);
+    let result = node0.create_local_msg(user_update_msg(CrdtType::AWSetCrdt, SDPOpsType::SDPAdd, 5));
+    assert!(matches!(result, Err(CrdtError::UnknownInstance(_))));
+    assert_eq!(node0.trcb.node_vector_clock.vcmap[&0], 0);
+
+    let mut msg = node0.create_local_msg(user_update_msg(CrdtType::PNCounterCrdt, SDPOpsType::SDPAdd, 5)).unwrap();
+    msg.user_update_msg.crdt_instance.instance_type = CrdtType::AWSetCrdt;
+    let result = node1.process_peer_msg(vec![PeerNodeMsg::UpdateNodeMsg(msg)]);
+    assert!(matches!(result, Err(CrdtError::UnknownInstance(CrdtInstance{instance_type: CrdtType::AWSetCrdt, ..}))));
+}
+
+#[test]
+fn overflow_is_an_error() {
+    let mut node0 = counter(0);
+    let msg = node0.create_local_msg(user_update_msg(CrdtType::PNCounterCrdt, SDPOpsType::SDPAdd, u32::MAX)).unwrap();
+    node0.process_local_msg(msg).unwrap();
+    let result = node0.create_local_msg(user_update_msg(CrdtType::PNCounterCrdt, SDPOpsType::SDPAdd, 1));
+    assert!(matches!(result, Err(CrdtError::Overflow(_))));
+    assert_eq!(node0.trcb.node_vector_clock.vcmap[&0], 1);
+
+    let mut mult: CRDT<i64, i64, AddMult> = CRDT::new_with_node_list(0, vec![0, 1], i64::MAX / 2).unwrap();
+    let result = mult.create_local_msg(user_update_msg(CrdtType::AddMultCrdt, SDPOpsType::SDPMult, 3));
+    assert!(matches!(result, Err(CrdtError::Overflow(_))));
+}
+
+#[test]
+fn overflow_between_replicas_wraps_and_leaves_no_clock_gap() {
+    let mut node0 = counter(0);
+    let mut node1 = counter(1);
+    let msg0 = node0.create_local_msg(user_update_msg(CrdtType::PNCounterCrdt, SDPOpsType::SDPAdd, u32::MAX)).unwrap();
+    node0.process_local_msg(msg0.clone()).unwrap();
+    let msg1 = node1.create_local_msg(user_update_msg(CrdtType::PNCounterCrdt, SDPOpsType::SDPAdd, 2)).unwrap();
+    node1.process_local_msg(msg1.clone()).unwrap();
+
+    // each operation was fine where it was made and only overflows once both meet
+    node0.process_peer_msg(vec![PeerNodeMsg::UpdateNodeMsg(msg1)]).unwrap();
+    node1.process_peer_msg(vec![PeerNodeMsg::UpdateNodeMsg(msg0)]).unwrap();
+    assert_eq!(node0.crdt_value, node1.crdt_value);
+    assert_eq!(serde_json::to_value(&node0.crdt_value).unwrap(), serde_json::json!({"pcount": 1, "ncount": 0}));
+
+    // a refused local operation takes no clock, so the next one is delivered in order
+    node0.create_local_msg(user_update_msg(CrdtType::PNCounterCrdt, SDPOpsType::SDPAdd, u32::MAX)).unwrap_err();
+    let msg0 = node0.create_local_msg(user_update_msg(CrdtType::PNCounterCrdt, SDPOpsType::SDPMult, 3)).unwrap();
+    node0.process_local_msg(msg0.clone()).unwrap();
+    node1.process_peer_msg(vec![PeerNodeMsg::UpdateNodeMsg(msg0)]).unwrap();
+    assert_eq!(node0.crdt_value, node1.crdt_value);
+    assert_eq!(node0.trcb.node_vector_clock.vcmap, node1.trcb.node_vector_clock.vcmap);
+
+    let mut mult0: CRDT<i64, i64, AddMult> = CRDT::new_with_node_list(0, vec![0, 1], i64::MAX / 2).unwrap();
+    let mut mult1: CRDT<i64, i64, AddMult> = CRDT::new_with_node_list(1, vec![0, 1], i64::MAX / 2).unwrap();
+    let msg0 = mult0.create_local_msg(user_update_msg(CrdtType::AddMultCrdt, SDPOpsType::SDPAdd, i64::MAX / 2)).unwrap();
+    mult0.process_local_msg(msg0.clone()).unwrap();
+    let msg1 = mult1.create_local_msg(user_update_msg(CrdtType::AddMultCrdt, SDPOpsType::SDPMult, 2)).unwrap();
+    mult1.process_local_msg(msg1.clone()).unwrap();
+    mult0.process_peer_msg(vec![PeerNodeMsg::UpdateNodeMsg(msg1)]).unwrap();
+    mult1.process_peer_msg(vec![PeerNodeMsg::UpdateNodeMsg(msg0)]).unwrap();
+    assert_eq!(mult0.crdt_value, mult1.crdt_value);
+}
+
+#[test]
+fn unknown_node_instance_is_an_error() {
+    let node_state = NodeState::new().unwrap();
+    assert!(node_state.get_node_instance(0).is_ok());
+    let error = node_state.get_node_instance(999).unwrap_err();
+    assert!(matches!(error, CrdtError::UnknownNode(999)));
+    assert_eq!(error.to_string(), "unknown node 999");
+
+    let error: Box<dyn std::error::Error> = Box::new(CrdtError::ConfigError("NODE_LIST".to_owned(), "a,b".to_owned()));
+    assert_eq!(error.to_string(), "invalid value \"a,b\" for NODE_LIST");
+}
@@ -0,0 +1,80 @@
+mod common;
+
+use ops_crdt_rust::failure_detector::{EvictionPolicy, FailureDetector, PeerStatus};
+use ops_crdt_rust::message_data::PeerNodeMsg;
+use ops_crdt_rust::vector_clock::VCStatus;
+
+use common::{counter_list, increment_msg, pcount};
+
+// peers are kept in a map, so changes come back in any order
+fn check(detector: &mut FailureDetector, now: u64) -> Vec<(u16, PeerStatus)> {
+    let mut status_list = detector.check(now);
+    status_list.sort_by_key(|(peer, _)| *peer);
+    status_list
+}
+
+#[test]
+fn silence_leads_to_suspicion_then_eviction() {
+    let mut detector = FailureDetector::new(vec![1, 2], 100, EvictionPolicy::AfterSuspectedMs(50));
+    assert!(check(&mut detector, 0).is_empty());
+
+    detector.heartbeat(1);
+    assert_eq!(check(&mut detector, 150), vec![(2, PeerStatus::Suspected)]);
+    assert!(check(&mut detector, 199).is_empty());
+
+    // a heartbeat clears a suspicion, and silence after it counts from that check
+    detector.heartbeat(2);
+    assert_eq!(check(&mut detector, 199), vec![(2, PeerStatus::Alive)]);
+    assert_eq!(check(&mut detector, 260), vec![(1, PeerStatus::Suspected)]);
+    assert_eq!(check(&mut detector, 310), vec![(1, PeerStatus::Evicted), (2, PeerStatus::Suspected)]);
+    assert_eq!(check(&mut detector, 360), vec![(2, PeerStatus::Evicted)]);
+
+    // an evicted peer is only readmitted explicitly
+    detector.heartbeat(1);
+    assert!(check(&mut detector, 400).is_empty());
+    detector.readmit(1).unwrap();
+    assert_eq!(detector.status(&1), Some(PeerStatus::Alive));
+    assert!(detector.readmit(9).is_err());
+
+    let mut manual = FailureDetector::new(vec![1], 100, EvictionPolicy::Manual);
+    check(&mut manual, 0);
+    assert_eq!(check(&mut manual, 10_000), vec![(1, PeerStatus::Suspected)]);
+    assert!(check(&mut manual, 1_000_000).is_empty());
+}
+
+#[test]
+fn evicted_peer_rejoins_through_a_state_transfer() {
+    let mut node_list = counter_list(3);
+    let (lost_msg, _) = increment_msg(&mut node_list[2], 0, 100);
+    for value in 1..=3 {
+        let (msg, _) = increment_msg(&mut node_list[0], 0, value);
+        node_list[1].process_peer_msg(vec![PeerNodeMsg::UpdateNodeMsg(msg)]).unwrap();
+    }
+    let vc_msg = node_list[1].create_vc_msg();
+    node_list[0].process_peer_msg(vec![PeerNodeMsg::VectorClockNodeMsg(vc_msg)]).unwrap();
+
+    // node 2 is silent, so nothing becomes stable until it is evicted
+    assert_eq!(node_list[0].msg_list_len(), 3);
+    node_list[0].evict_peer(2).unwrap();
+    assert_eq!(node_list[0].peer_status(&2), Some(PeerStatus::Evicted));
+    assert_eq!(node_list[0].msg_list_len(), 0);
+
+    // an old operation of the evicted node could be concurrent with pruned ones and is refused
+    assert_eq!(node_list[0].general_process_peer_msg(lost_msg).unwrap(), VCStatus::EVICTED);
+    assert_eq!(pcount(&node_list[0]), 6);
+
+    // node 2 adopts the state of node 0 and gets back the operation nobody delivered
+    let state = node_list[0].create_state_transfer().unwrap();
+    let lost_list = node_list[2].apply_state_transfer(state).unwrap();
+    assert_eq!(lost_list.len(), 1);
+    assert_eq!(pcount(&node_list[2]), 6);
+    let vc = node_list[2].trcb.node_vector_clock.clone();
+    node_list[0].readmit_peer(2, vc).unwrap();
+    assert_eq!(node_list[0].peer_status(&2), Some(PeerStatus::Alive));
+
+    let msg = node_list[2].create_local_msg(lost_list[0].clone()).unwrap();
+    let mut msg_map = node_list[2].process_local_msg(msg).unwrap();
+    node_list[0].process_peer_msg(msg_map.remove(&0).unwrap()).unwrap();
+    node_list[1].process_peer_msg(msg_map.remove(&1).unwrap()).unwrap();
+    assert!(node_list.iter().all(|node| pcount(node) == 106));
+}
@@ -0,0 +1,43 @@
+use std::collections::BTreeSet;
+
+use ops_crdt_rust::gossip::{GossipConfig, GossipMode, GossipState};
+use ops_crdt_rust::rand_gossip::{self, GossipSimConfig};
+
+#[test]
+fn round_robin_reaches_every_peer_in_turn() {
+    let mut gossip = GossipState::new_with_seed(GossipConfig::new(2, 1000, GossipMode::RoundRobin), 1);
+    let peer_list = vec![4, 1, 3, 2, 5];
+    let round_list: Vec<Vec<u16>> = (0..3).map(|_| gossip.select_peers(peer_list.clone())).collect();
+    assert_eq!(round_list, vec![vec![1, 2], vec![3, 4], vec![5, 1]]);
+
+    // a fanout above the peer count selects each peer once
+    let mut gossip = GossipState::new_with_seed(GossipConfig::new(9, 1000, GossipMode::RoundRobin), 1);
+    assert_eq!(gossip.select_peers(vec![2, 1]), vec![1, 2]);
+    assert!(gossip.select_peers(Vec::new()).is_empty());
+}
+
+#[test]
+fn random_mode_picks_distinct_peers() {
+    let mut gossip = GossipState::new_with_seed(GossipConfig::new(3, 1000, GossipMode::Random), 1);
+    let mut seen_set = BTreeSet::new();
+    for _ in 0..50 {
+        let selected = gossip.select_peers(vec![1, 2, 3, 4, 5, 6]);
+        assert_eq!(selected.len(), 3);
+        assert_eq!(selected.iter().collect::<BTreeSet<_>>().len(), 3);
+        seen_set.extend(selected);
+    }
+    assert_eq!(seen_set.len(), 6);
+}
+
+#[test]
+fn gossip_converges_despite_loss() {
+    let sim_config = GossipSimConfig{node_count: 8, op_rounds: 10, op_rate_pct: 30, loss_pct: 10, max_rounds: 200, seed: 3};
+    for mode in [GossipMode::Random, GossipMode::RoundRobin] {
+        let narrow = rand_gossip::simulate(GossipConfig::new(1, 1000, mode), &sim_config).unwrap();
+        let wide = rand_gossip::simulate(GossipConfig::new(7, 1000, mode), &sim_config).unwrap();
+        let (narrow_round, wide_round) = (narrow.converge_round.unwrap(), wide.converge_round.unwrap());
+        assert_eq!(narrow.converge_ms, Some(narrow_round*1000));
+        assert!(wide_round <= narrow_round, "{:?} {} {}", mode, wide_round, narrow_round);
+        assert!(wide.msg_count > narrow.msg_count);
+    }
+}
@@ -0,0 +1,136 @@
+mod common;
+
+use ops_crdt_rust::crdt::{CrdtInstance, CrdtType};
+use ops_crdt_rust::hash_chain::op_hash;
+use ops_crdt_rust::message_data::{OpsInstance, PeerNodeMsg, SDPOpsType, UserUpdateMsg};
+use ops_crdt_rust::wire::{self, WireOptions};
+
+use common::{increment, Counter};
+
+fn counter(node: u16) -> Counter {
+    let mut crdt = common::counter(node, vec![0, 1, 2]);
+    crdt.set_hash_chain(true);
+    crdt
+}
+
+#[test]
+fn updates_carry_predecessor_hashes() {
+    let mut node0 = counter(0);
+    let mut node1 = counter(1);
+    node1.process_peer_msg(increment(&mut node0, 1).remove(&1).unwrap()).unwrap();
+    node1.process_peer_msg(increment(&mut node0, 2).remove(&1).unwrap()).unwrap();
+    increment(&mut node1, 3);
+
+    let msg = &node1.msg_list[&(1, 1)];
+    let mut pred_list: Vec<_> = msg.pred_hash_list.iter().map(|(node, lc, _)| (*node, *lc)).collect();
+    pred_list.sort();
+    assert_eq!(pred_list, vec![(0, 2)]);
+    assert_eq!(msg.pred_hash_list[0].2, op_hash(&node0.msg_list[&(0, 2)]).unwrap());
+    assert_eq!(node0.msg_list[&(0, 2)].pred_hash_list, vec![(0, 1, op_hash(&node0.msg_list[&(0, 1)]).unwrap())]);
+
+    let msg_list = vec![PeerNodeMsg::UpdateNodeMsg(msg.clone())];
+    let decoded: Vec<PeerNodeMsg<u32>> = wire::decode_peer_msg_list(&wire::encode_peer_msg_list(&msg_list, WireOptions::new(true)).unwrap()).unwrap();
+    assert_eq!(serde_json::to_value(&decoded).unwrap(), serde_json::to_value(&msg_list).unwrap());
+    assert!(wire::encode_peer_msg_list(&msg_list, WireOptions::new(true).with_version(3)).is_err());
+}
+
+fn update_origin(msg: &PeerNodeMsg<u32>) -> Option<u16> {
+    match msg {
+        PeerNodeMsg::UpdateNodeMsg(umsg)      => Some(umsg.node),
+        PeerNodeMsg::DeltaUpdateNodeMsg(dmsg) => Some(dmsg.node),
+        _                                     => None
+    }
+}
+
+// node 0 sends one increment to node 1 and a different one with the same clock to node 2
+#[test]
+fn equivocation_is_reported() {
+    let mut node0 = counter(0);
+    let mut twin0 = counter(0);
+    let mut node1 = counter(1);
+    let mut node2 = counter(2);
+
+    let honest_list = increment(&mut node0, 1).remove(&1).unwrap();
+    let forked_list = increment(&mut twin0, 100).remove(&2).unwrap();
+    node1.process_peer_msg(honest_list.clone()).unwrap();
+    node2.process_peer_msg(forked_list).unwrap();
+    assert!(node1.take_equivocation_list().is_empty());
+    assert!(node2.take_equivocation_list().is_empty());
+
+    // node 1 builds on the version it saw, so its update names that version's hash
+    let witness_list: Vec<PeerNodeMsg<u32>> = increment(&mut node1, 5).remove(&2).unwrap()
+                                                                        .into_iter()
+                                                                        .filter(|msg| update_origin(msg) == Some(1))
+                                                                        .collect();
+    node2.process_peer_msg(witness_list).unwrap();
+    let report_list = node2.take_equivocation_list();
+    assert_eq!(report_list.len(), 1);
+    assert_eq!((report_list[0].node, report_list[0].lc, report_list[0].witness), (0, 1, Some((1, 1))));
+    assert_eq!(report_list[0].peer_hash, op_hash(&node0.msg_list[&(0, 1)]).unwrap());
+    assert_eq!(report_list[0].local_hash, op_hash(&twin0.msg_list[&(0, 1)]).unwrap());
+    assert!(node2.equivocator_list().is_empty());
+    assert_eq!(node2.suspect_list(), vec![0, 1]);
+
+    // anti-entropy later hands node 2 the other version itself, and again after the
+    // report was taken
+    node2.process_peer_msg(honest_list.clone()).unwrap();
+    let report_list = node2.take_equivocation_list();
+    assert_eq!(report_list.len(), 1);
+    assert_eq!((report_list[0].node, report_list[0].lc, report_list[0].witness, report_list[0].signed), (0, 1, None, false));
+    node2.process_peer_msg(honest_list).unwrap();
+    assert!(node2.take_equivocation_list().is_empty());
+
+    // without signatures a relay could have forged either version, so node 0 stays a suspect
+    assert!(node2.equivocator_list().is_empty());
+    assert!(node1.equivocator_list().is_empty());
+}
+
+// node 1 names a made up hash for the operation honest node 0 sent everyone
+#[test]
+fn forged_pred_hash_does_not_frame_its_origin() {
+    let mut node0 = counter(0);
+    let mut node1 = counter(1);
+    let mut node2 = counter(2);
+    let mut msg_map = increment(&mut node0, 1);
+    node1.process_peer_msg(msg_map.remove(&1).unwrap()).unwrap();
+    node2.process_peer_msg(msg_map.remove(&2).unwrap()).unwrap();
+
+    let user_update_msg = UserUpdateMsg::new(CrdtInstance::new(0, 0, CrdtType::PNCounterCrdt), OpsInstance::new(SDPOpsType::SDPAdd, 5));
+    let mut msg = node1.create_local_msg(user_update_msg).unwrap();
+    assert_eq!(msg.pred_hash_list.len(), 1);
+    msg.pred_hash_list[0].2 = [7; 32];
+    node1.sign_update_msg(&mut msg).unwrap();
+    node2.process_peer_msg(node1.process_local_msg(msg).unwrap().remove(&2).unwrap()).unwrap();
+
+    let report_list = node2.take_equivocation_list();
+    assert_eq!(report_list.len(), 1);
+    assert_eq!((report_list[0].node, report_list[0].witness), (0, Some((1, 1))));
+    assert!(node2.equivocator_list().is_empty());
+    assert_eq!(node2.suspect_list(), vec![0, 1]);
+    assert_eq!(serde_json::to_value(&node2.crdt_value).unwrap(), serde_json::json!({"pcount": 6, "ncount": 0}));
+}
+
+// with signatures a second version can only come from the origin itself
+#[cfg(feature = "hmac")]
+#[test]
+fn signed_equivocation_names_the_origin() {
+    use ops_crdt_rust::auth::HmacAuth;
+
+    let secret_list: std::collections::HashMap<u16, Vec<u8>> = (0..3).map(|node| (node, format!("secret-{}", node).into_bytes())).collect();
+    let signed_counter = |node: u16| {
+        let mut crdt = counter(node);
+        crdt.set_auth(Some(Box::new(HmacAuth::new(node, secret_list.clone()).unwrap())));
+        crdt
+    };
+    let mut node0 = signed_counter(0);
+    let mut twin0 = signed_counter(0);
+    let mut node2 = signed_counter(2);
+
+    let honest_list = increment(&mut node0, 1).remove(&2).unwrap();
+    node2.process_peer_msg(increment(&mut twin0, 100).remove(&2).unwrap()).unwrap();
+    node2.process_peer_msg(honest_list).unwrap();
+    let report_list = node2.take_equivocation_list();
+    assert_eq!((report_list[0].node, report_list[0].witness, report_list[0].signed), (0, None, true));
+    assert_eq!(node2.equivocator_list(), vec![0]);
+    assert!(node2.suspect_list().is_empty());
+}
@@ -0,0 +1,131 @@
+mod common;
+
+use std::collections::{HashMap, HashSet};
+
+use ops_crdt_rust::crdt::{CRDT, CrdtInstance, CrdtType};
+use ops_crdt_rust::arset_crdt::AWSet;
+use ops_crdt_rust::error::CrdtError;
+use ops_crdt_rust::message_data::{OpsInstance, PeerNodeMsg, SDPOpsType, UserUpdateMsg};
+use ops_crdt_rust::pncnt_crdt::PNCounterData;
+use ops_crdt_rust::vector_clock::VectorClock;
+
+use common::Counter;
+
+type Set = CRDT<HashSet<i32>, i32, AWSet>;
+type MsgMap<T> = HashMap<u16, Vec<PeerNodeMsg<T>>>;
+
+fn counter_add(node: &mut Counter, value: u32) -> MsgMap<u32> {
+    let umsg = UserUpdateMsg::new(CrdtInstance::new(0, 0, CrdtType::PNCounterCrdt), OpsInstance::new(SDPOpsType::SDPAdd, value));
+    let msg = node.create_local_msg(umsg).unwrap();
+    node.process_local_msg(msg).unwrap()
+}
+
+fn set_op(node: &mut Set, ops_type: SDPOpsType, value: i32) -> MsgMap<i32> {
+    let umsg = UserUpdateMsg::new(CrdtInstance::new(0, 0, CrdtType::AWSetCrdt), OpsInstance::new(ops_type, value));
+    let msg = node.create_local_msg(umsg).unwrap();
+    node.process_local_msg(msg).unwrap()
+}
+
+fn peer_list<T: Clone+PartialEq>(msg_map: &MsgMap<T>, pnode: u16) -> Vec<PeerNodeMsg<T>> {
+    msg_map.get(&pnode).cloned().unwrap_or_default()
+}
+
+fn pcount(value: PNCounterData) -> u64 {
+    serde_json::to_value(value).unwrap()["pcount"].as_u64().unwrap()
+}
+
+#[test]
+fn counter_as_seen_by_another_node() {
+    let mut node_list: Vec<Counter> = (0..3).map(|node| Counter::new_with_node_list(node, vec![0, 1, 2], PNCounterData::new()).unwrap())
+                                            .collect();
+    node_list[0].set_history(true).unwrap();
+
+    let mut snapshot = None;
+    for step in 1..=6 {
+        let origin = step % 3;
+        let msg_map = counter_add(&mut node_list[origin as usize], step);
+        for (pnode, pmsg_list) in msg_map {
+            node_list[pnode as usize].process_peer_msg(pmsg_list).unwrap();
+        }
+        if step == 3 {
+            snapshot = Some((node_list[2].trcb.node_vector_clock.clone(), node_list[2].query()));
+        }
+    }
+
+    let (vc, value) = snapshot.unwrap();
+    assert_eq!(node_list[0].history_len(), 6);
+    assert_eq!(node_list[0].query_at(&vc).unwrap(), value);
+    assert_eq!(node_list[0].query_at(&node_list[0].trcb.node_vector_clock).unwrap(), node_list[0].query());
+    assert_eq!(pcount(node_list[0].query_at(&VectorClock::new(vec![0, 1, 2]).unwrap()).unwrap()), 0);
+
+    // the point node 1 had seen up to its own second operation, which followed steps 1 to 3
+    let mut vc = VectorClock::new(vec![0, 1, 2]).unwrap();
+    vc.vcmap.extend([(0, 1), (1, 2), (2, 1)]);
+    assert_eq!(pcount(node_list[0].query_at(&vc).unwrap()), 1+2+3+4);
+}
+
+#[test]
+fn replay_keeps_concurrent_semantics() {
+    let mut node0 = Set::new_with_node_list(0, vec![0, 1], HashSet::new()).unwrap();
+    let mut node1 = Set::new_with_node_list(1, vec![0, 1], HashSet::new()).unwrap();
+    node0.set_history(true).unwrap();
+
+    let msg_map = set_op(&mut node0, SDPOpsType::SDPMult, 7);
+    node1.process_peer_msg(peer_list(&msg_map, 1)).unwrap();
+    let before_vc = node0.trcb.node_vector_clock.clone();
+
+    // node 0 removes 7 while node 1 adds it again, the add wins
+    let remove_map = set_op(&mut node0, SDPOpsType::SDPAdd, 7);
+    let removed_vc = node0.trcb.node_vector_clock.clone();
+    let add_map = set_op(&mut node1, SDPOpsType::SDPMult, 7);
+    node0.process_peer_msg(peer_list(&add_map, 0)).unwrap();
+    node1.process_peer_msg(peer_list(&remove_map, 1)).unwrap();
+    assert_eq!(node0.query(), HashSet::from([7]));
+
+    assert_eq!(node0.query_at(&before_vc).unwrap(), HashSet::from([7]));
+    assert_eq!(node0.query_at(&removed_vc).unwrap(), HashSet::new());
+    assert_eq!(node0.query_at(&node0.trcb.node_vector_clock).unwrap(), HashSet::from([7]));
+}
+
+#[test]
+fn history_starts_when_enabled() {
+    let mut node0 = Counter::new_with_node_list(0, vec![0, 1], PNCounterData::new()).unwrap();
+    let zero_vc = node0.trcb.node_vector_clock.clone();
+    counter_add(&mut node0, 5);
+    assert!(matches!(node0.query_at(&node0.trcb.node_vector_clock), Err(CrdtError::HistoryUnavailable)));
+
+    node0.set_history(true).unwrap();
+    let enabled_vc = node0.trcb.node_vector_clock.clone();
+    counter_add(&mut node0, 2);
+    assert_eq!(pcount(node0.query_at(&enabled_vc).unwrap()), 5);
+    assert_eq!(pcount(node0.query_at(&node0.trcb.node_vector_clock).unwrap()), 7);
+    assert!(matches!(node0.query_at(&zero_vc), Err(CrdtError::HistoryUnavailable)));
+}
+
+// the replay replica reads no env config and keeps nothing on disk
+#[test]
+fn bare_replica_has_every_optional_part_off() {
+    let mut bare = Counter::new_bare(0, vec![0, 1], PNCounterData::new()).unwrap();
+    assert!(bare.gossip.is_none() && bare.auth.is_none() && !bare.history.enabled && !bare.hash_chain.enabled);
+    assert_eq!((bare.undo.max_len, bare.merkle.depth, bare.memory_policy.max_msg_count), (0, 0, None));
+    counter_add(&mut bare, 4);
+    assert_eq!(pcount(bare.query()), 4);
+    assert!(bare.spill_store.path().is_none());
+}
+
+#[test]
+fn earlier_epochs_replay_in_full() {
+    let mut node0 = Counter::new_with_node_list(0, vec![0], PNCounterData::new()).unwrap();
+    node0.set_history(true).unwrap();
+    counter_add(&mut node0, 5);
+    let epoch0_vc = node0.trcb.node_vector_clock.clone();
+    node0.start_epoch_reset().unwrap();
+    assert_eq!(node0.get_epoch(), 1);
+
+    let zero_vc = node0.trcb.node_vector_clock.clone();
+    counter_add(&mut node0, 2);
+    assert_eq!(pcount(node0.query_at(&zero_vc).unwrap()), 5);
+    assert_eq!(pcount(node0.query_at(&node0.trcb.node_vector_clock).unwrap()), 7);
+    assert_eq!(pcount(node0.query_at_epoch(0, &epoch0_vc).unwrap()), 5);
+    assert_eq!(pcount(node0.query_at_epoch(0, &zero_vc).unwrap()), 0);
+}
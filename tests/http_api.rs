@@ -0,0 +1,132 @@
+#![cfg(feature = "http")]
+
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use serde_json::{json, Value};
+
+use ops_crdt_rust::crdt::CrdtType;
+use ops_crdt_rust::error::CrdtError;
+use ops_crdt_rust::http_api::{read_request, ApiServer, ApiState, HttpRequest, MAX_HEADER_COUNT, MAX_LINE_BYTES};
+
+fn start_server(node_list: Vec<u16>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let server = Arc::new(ApiServer::new(node_list).unwrap());
+    thread::spawn(move || server.serve(listener));
+    addr
+}
+
+fn http(addr: &str, method: &str, path: &str, body: Option<Value>) -> (u16, Value) {
+    let body = body.map(|body| body.to_string()).unwrap_or_default();
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(stream, "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+           method, path, addr, body.len(), body).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let status = head.split_whitespace().nth(1).unwrap().parse().unwrap();
+    (status, serde_json::from_str(body).unwrap())
+}
+
+#[test]
+fn instances_are_created_updated_and_queried_over_http() {
+    let addr = start_server(vec![0, 1, 2]);
+
+    let (status, body) = http(&addr, "POST", "/instances", Some(json!({"crdt_type": "AWSetCrdt"})));
+    assert_eq!((status, body), (201, json!({"instance_num": 0, "crdt_type": "AWSetCrdt"})));
+    let (status, _) = http(&addr, "POST", "/instances", Some(json!({"crdt_type": "PNCounterCrdt"})));
+    assert_eq!(status, 201);
+    assert_eq!(http(&addr, "GET", "/instances", None).1.as_array().unwrap().len(), 2);
+
+    for (node, value) in [(0, 3), (1, 5), (2, 7)] {
+        let (status, _) = http(&addr, "POST", "/instances/0/ops", Some(json!({"node": node, "ops_type": "SDPMult", "value": value})));
+        assert_eq!(status, 200);
+    }
+    let (status, body) = http(&addr, "POST", "/instances/0/ops", Some(json!({"node": 2, "ops_type": "SDPAdd", "value": 5})));
+    assert_eq!(status, 200);
+    assert_eq!(serde_json::from_value::<HashSet<i32>>(body["value"].clone()).unwrap(), HashSet::from([3, 7]));
+
+    let (_, body) = http(&addr, "GET", "/instances/0", None);
+    assert_eq!(body["converged"], json!(true));
+    for node in ["0", "1", "2"] {
+        assert_eq!(serde_json::from_value::<HashSet<i32>>(body["value_list"][node].clone()).unwrap(), HashSet::from([3, 7]));
+    }
+
+    http(&addr, "POST", "/instances/1/ops", Some(json!({"node": 1, "ops_type": "SDPAdd", "value": 4})));
+    let (_, body) = http(&addr, "GET", "/instances/1?node=2", None);
+    assert_eq!(body, json!({"node": 2, "value": {"pcount": 4, "ncount": 0}}));
+
+    let (_, body) = http(&addr, "GET", "/instances/1/state", None);
+    assert_eq!(body["0"]["node_vector_clock"]["vcmap"]["1"], json!(1));
+
+    let (_, body) = http(&addr, "GET", "/cluster", None);
+    assert_eq!(body["node_list"], json!([0, 1, 2]));
+    assert_eq!(body["instance_list"][0]["crdt_type"], json!("AWSetCrdt"));
+    assert_eq!(body["instance_list"][1]["converged"], json!(true));
+    assert_eq!(body["instance_list"][1]["node_vector_clock_list"]["2"]["vcmap"]["1"], json!(1));
+}
+
+#[test]
+fn bad_requests_get_error_statuses() {
+    let addr = start_server(vec![0, 1]);
+    http(&addr, "POST", "/instances", Some(json!({"crdt_type": "PNCounterCrdt"})));
+
+    assert_eq!(http(&addr, "POST", "/instances", Some(json!({"crdt_type": "NoSuchCrdt"}))).0, 400);
+    assert_eq!(http(&addr, "POST", "/instances/0/ops", Some(json!({"node": 0, "ops_type": "SDPAdd", "value": "four"}))).0, 400);
+    assert_eq!(http(&addr, "POST", "/instances/0/ops", Some(json!({"node": 9, "ops_type": "SDPAdd", "value": 4}))).0, 404);
+    assert_eq!(http(&addr, "GET", "/instances/7", None).0, 404);
+    assert_eq!(http(&addr, "GET", "/instances/0?node=x", None).0, 400);
+    let (status, body) = http(&addr, "DELETE", "/instances/0", None);
+    assert_eq!(status, 404);
+    assert!(body["error"].as_str().unwrap().contains("route DELETE"));
+}
+
+#[test]
+fn requests_parse_and_dispatch_without_a_socket() {
+    let raw = "POST /instances?x=1 HTTP/1.1\r\ncontent-length: 29\r\n\r\n{\"crdt_type\": \"DWFlagCrdt\"}  ";
+    let request = read_request(&mut raw.as_bytes()).unwrap();
+    assert_eq!(request.path, "/instances");
+    assert_eq!(request.query["x"], "1");
+
+    let mut state = ApiState::new(vec![0, 1]).unwrap();
+    assert_eq!(state.handle(&request).status, 201);
+    let request = HttpRequest{method: "GET".to_owned(), path: "/instances/0".to_owned(), query: Default::default(), body: String::new()};
+    assert_eq!(state.handle(&request).body, json!({"converged": true, "value_list": {"0": "Disabled", "1": "Disabled"}}));
+    assert!(ApiState::new(Vec::new()).is_err());
+}
+
+#[test]
+fn oversized_lines_and_header_floods_are_refused() {
+    let raw = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(MAX_LINE_BYTES));
+    assert!(matches!(read_request(&mut raw.as_bytes()), Err(CrdtError::DecodeError(_))));
+    let raw = format!("GET /cluster HTTP/1.1\r\nx-pad: {}\r\n\r\n", "a".repeat(MAX_LINE_BYTES));
+    assert!(matches!(read_request(&mut raw.as_bytes()), Err(CrdtError::DecodeError(_))));
+    let raw = format!("GET /cluster HTTP/1.1\r\n{}\r\n", "x-pad: 1\r\n".repeat(MAX_HEADER_COUNT + 1));
+    assert!(matches!(read_request(&mut raw.as_bytes()), Err(CrdtError::DecodeError(_))));
+
+    let raw = format!("GET /cluster HTTP/1.1\r\n{}\r\n", "x-pad: 1\r\n".repeat(MAX_HEADER_COUNT));
+    assert_eq!(read_request(&mut raw.as_bytes()).unwrap().path, "/cluster");
+}
+
+// the operation stays applied where it got to, so the failed delivery comes back with a 200
+#[test]
+fn partial_delivery_is_reported_with_the_value() {
+    let mut state = ApiState::new(vec![0, 1, 2]).unwrap();
+    state.create_instance(CrdtType::PNCounterCrdt).unwrap();
+    state.instance_list.get_mut(&0).unwrap().node_state.node_instance_list.remove(&2);
+
+    let request = HttpRequest{method: "POST".to_owned(), path: "/instances/0/ops".to_owned(), query: HashMap::new(),
+                              body: json!({"node": 0, "ops_type": "SDPAdd", "value": 4}).to_string()};
+    let response = state.handle(&request);
+    assert_eq!(response.status, 200);
+    assert_eq!(response.body["value"], json!({"pcount": 4, "ncount": 0}));
+    let error_list = response.body["delivery_error_list"].as_array().unwrap();
+    assert!(!error_list.is_empty() && error_list.iter().all(|e| e.as_str().unwrap().starts_with("node 2:")));
+
+    let request = HttpRequest{method: "GET".to_owned(), path: "/instances/0".to_owned(), query: HashMap::from([("node".to_owned(), "1".to_owned())]),
+                              body: String::new()};
+    assert_eq!(state.handle(&request).body["value"], json!({"pcount": 4, "ncount": 0}));
+}
@@ -0,0 +1,115 @@
+mod common;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use ops_crdt_rust::LCType;
+use ops_crdt_rust::failure_detector::PeerStatus;
+use ops_crdt_rust::hash_chain::op_hash;
+use ops_crdt_rust::memory_policy::{MemoryPolicy, OverflowAction};
+use ops_crdt_rust::message_data::{NodeVectorClockMsg, NodeUpdateMsg, PeerNodeMsg};
+use ops_crdt_rust::vector_clock::VectorClock;
+
+use common::{increment_msg, Counter};
+
+fn spill_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("ops_crdt_spill_{}_{}", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+fn counter(node: u16, overflow_action: OverflowAction, dir: &Path) -> Counter {
+    let mut crdt = common::counter(node, vec![0, 1, 2]);
+    crdt.set_memory_policy(MemoryPolicy::new(Some(2), None, overflow_action, dir.to_path_buf())).unwrap();
+    crdt
+}
+
+fn vc_msg(node: u16, lc: LCType) -> Vec<PeerNodeMsg<u32>> {
+    let vcmap = HashMap::from([(0, lc), (1, 0), (2, 0)]);
+    vec![PeerNodeMsg::VectorClockNodeMsg(NodeVectorClockMsg::new(node, VectorClock{vcmap}))]
+}
+
+#[test]
+fn spilled_operations_reload_and_compact() {
+    let dir = spill_dir("reload");
+    let mut node0 = counter(0, OverflowAction::SpillToDisk, &dir);
+    let mut other0 = counter(0, OverflowAction::SpillToDisk, &dir);
+    for value in 1..=5 {
+        increment_msg(&mut node0, 7, value);
+        increment_msg(&mut other0, 8, value);
+    }
+
+    let metrics = node0.memory_metrics();
+    assert_eq!((metrics.msg_count, metrics.spilled_count), (2, 3));
+    assert!(metrics.msg_bytes > 2*100);
+    // a second instance on the same node spills to its own file
+    assert_eq!(node0.spill_store.path(), Some(dir.join("msg_list_0_pncountercrdt_0_7.jsonl").as_path()));
+    assert_eq!(other0.spill_store.path(), Some(dir.join("msg_list_0_pncountercrdt_0_8.jsonl").as_path()));
+    assert_eq!(other0.memory_metrics().spilled_count, 3);
+
+    let msg_list = node0.all_msg_list().unwrap();
+    assert_eq!(msg_list.len(), 5);
+    let spilled: NodeUpdateMsg<u32> = node0.spill_store.get(&(0, 2)).unwrap().unwrap();
+    assert_eq!(spilled.user_update_msg.ops_instance.ops_value, 2);
+    assert_eq!(spilled.user_update_msg.crdt_instance.instance_num, 7);
+
+    // two of the three spilled lines become dead, more than half the file, so it is rewritten
+    let file_len = node0.spill_store.file_len();
+    node0.process_peer_msg(vc_msg(1, 2)).unwrap();
+    node0.process_peer_msg(vc_msg(2, 2)).unwrap();
+    assert_eq!(node0.memory_metrics().spilled_count, 1);
+    assert!(node0.spill_store.file_len() < file_len / 2);
+    assert_eq!(std::fs::metadata(node0.spill_store.path().unwrap()).unwrap().len(), node0.spill_store.file_len());
+    let spilled: NodeUpdateMsg<u32> = node0.spill_store.get(&(0, 3)).unwrap().unwrap();
+    assert_eq!(spilled.user_update_msg.ops_instance.ops_value, 3);
+    assert_eq!(serde_json::to_value(&node0.crdt_value).unwrap(), serde_json::json!({"pcount": 15, "ncount": 0}));
+
+    node0.process_peer_msg(vc_msg(1, 5)).unwrap();
+    node0.process_peer_msg(vc_msg(2, 5)).unwrap();
+    assert_eq!(node0.memory_metrics().spilled_count, 0);
+    assert!(!node0.spill_store.path().unwrap().exists());
+    assert!(other0.spill_store.path().unwrap().exists());
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+// lookups by key or by clock find the spilled operations as well as the retained ones
+#[test]
+fn spilled_operations_stay_visible() {
+    let dir = spill_dir("visible");
+    let mut node0 = counter(0, OverflowAction::SpillToDisk, &dir);
+    for value in 1..=5 {
+        increment_msg(&mut node0, 0, value);
+    }
+    assert_eq!(node0.memory_metrics().spilled_count, 3);
+
+    let spilled: NodeUpdateMsg<u32> = node0.spill_store.get(&(0, 1)).unwrap().unwrap();
+    assert_eq!(node0.known_op_hash(0, 1).unwrap(), Some(op_hash(&spilled).unwrap()));
+    assert_eq!(node0.delta_base_vc(0, 2).map(|vc| vc.vcmap.clone()), Some(spilled.node_vector_clock.vcmap));
+
+    let peer_vc = VectorClock{vcmap: HashMap::from([(0, 0), (1, 1), (2, 0)])};
+    assert_eq!(node0.spill_store.load_concurrent::<u32>(&peer_vc).unwrap().len(), 3);
+    let peer_vc = VectorClock{vcmap: HashMap::from([(0, 2), (1, 1), (2, 0)])};
+    assert_eq!(node0.spill_store.load_concurrent::<u32>(&peer_vc).unwrap().into_keys().collect::<Vec<_>>(), vec![(0, 3)]);
+
+    node0.set_history(true).unwrap();
+    assert_eq!(node0.history.base_msg_list.len(), 5);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn lagging_peer_is_evicted_to_free_memory() {
+    let dir = spill_dir("evict");
+    let mut node0 = counter(0, OverflowAction::StateTransfer, &dir);
+    let mut node1 = counter(1, OverflowAction::StateTransfer, &dir);
+    for value in 1..=2 {
+        node1.process_peer_msg(increment_msg(&mut node0, 0, value).1.remove(&1).unwrap()).unwrap();
+    }
+    node0.process_peer_msg(vec![PeerNodeMsg::VectorClockNodeMsg(node1.create_vc_msg())]).unwrap();
+
+    // node 2 never answered, so it holds every operation in memory and goes first
+    increment_msg(&mut node0, 0, 3);
+    assert_eq!(node0.peer_status(&2), Some(PeerStatus::Evicted));
+    assert_eq!(node0.peer_status(&1), Some(PeerStatus::Alive));
+    let metrics = node0.memory_metrics();
+    assert_eq!((metrics.msg_count, metrics.evicted_count, metrics.spilled_count), (1, 1, 0));
+}
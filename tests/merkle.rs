@@ -0,0 +1,80 @@
+mod common;
+
+use std::collections::HashMap;
+
+use ops_crdt_rust::merkle::{self, DivergenceKind, MerkleDivergence, MerkleTree, MERKLE_ROOT};
+use ops_crdt_rust::message_data::PeerNodeMsg;
+
+use common::{increment, Counter};
+
+fn counter(node: u16, depth: u8) -> Counter {
+    let mut crdt = common::counter(node, vec![0, 1, 2]);
+    crdt.set_merkle_depth(depth);
+    crdt
+}
+
+fn merkle_msg_list(mut msg_map: HashMap<u16, Vec<PeerNodeMsg<u32>>>, pnode: u16) -> Vec<PeerNodeMsg<u32>> {
+    msg_map.remove(&pnode)
+           .unwrap_or_default()
+           .into_iter()
+           .filter(|msg| matches!(msg, PeerNodeMsg::MerkleRequestMsg(_) | PeerNodeMsg::MerkleReplyMsg(_)))
+           .collect()
+}
+
+// node 0 asks node 1 and each side answers the other until no path differs; returns the
+// number of messages exchanged
+fn descend(node0: &mut Counter, node1: &mut Counter) -> usize {
+    let mut msg_list = merkle_msg_list(node0.create_merkle_request(1).unwrap(), 1);
+    let mut msg_count = 0;
+    let mut to_node1 = true;
+    while !msg_list.is_empty() {
+        msg_count += msg_list.len();
+        msg_list = match to_node1 {
+            true  => merkle_msg_list(node1.process_peer_msg(msg_list).unwrap(), 0),
+            false => merkle_msg_list(node0.process_peer_msg(msg_list).unwrap(), 1)
+        };
+        to_node1 = !to_node1;
+    }
+    msg_count
+}
+
+fn synced_pair(depth: u8) -> (Counter, Counter) {
+    let mut node0 = counter(0, depth);
+    let mut node1 = counter(1, depth);
+    for value in 1..=20 {
+        node1.process_peer_msg(increment(&mut node0, value).remove(&1).unwrap()).unwrap();
+    }
+    (node0, node1)
+}
+
+#[test]
+fn equal_replicas_stop_at_the_root() {
+    let (mut node0, mut node1) = synced_pair(2);
+    assert_eq!(descend(&mut node0, &mut node1), 2);
+    assert!(node0.take_merkle_divergence().is_empty());
+}
+
+#[test]
+fn descent_finds_a_mismatched_operation() {
+    let (mut node0, mut node1) = synced_pair(2);
+    node1.msg_list.get_mut(&(0, 7)).unwrap().user_update_msg.ops_instance.ops_value = 700;
+
+    // one request and reply per level: root, level 1, then the leaves
+    assert_eq!(descend(&mut node0, &mut node1), 6);
+    assert_eq!(node0.take_merkle_divergence(), vec![MerkleDivergence{peer: 1, node: 0, lc: 7, kind: DivergenceKind::Mismatch}]);
+    assert!(node1.take_merkle_divergence().is_empty());
+}
+
+#[test]
+fn depth_zero_is_a_single_root() {
+    assert_eq!(merkle::leaf_index(3, 9, 0), 0);
+    assert!(merkle::leaf_index(3, 9, 1) < 16);
+
+    let (node0, _) = synced_pair(0);
+    let low_vc = node0.trcb.stable_vector_clock.clone();
+    let high_vc = node0.trcb.node_vector_clock.clone();
+    let tree = MerkleTree::new(0, &low_vc, &high_vc, &node0.msg_list).unwrap();
+    assert_ne!(tree.root_hash(), 0);
+    assert_eq!(tree.leaf_entry_list(&MERKLE_ROOT).len(), 20);
+    assert!(node0.create_merkle_request(1).unwrap().is_empty());
+}
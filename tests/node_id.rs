@@ -0,0 +1,102 @@
+mod common;
+
+use ops_crdt_rust::error::CrdtError;
+use ops_crdt_rust::failure_detector::PeerStatus;
+use ops_crdt_rust::node_id::{NodeIdMap, NodeIdMsg, NODE_ID_BLOCK};
+use ops_crdt_rust::pncnt_crdt::PNCounterData;
+
+use common::Counter;
+
+fn host_list() -> Vec<String> {
+    ["db-c", "db-a", "db-b", "db-a"].iter().map(|host| host.to_string()).collect()
+}
+
+#[test]
+fn id_list_maps_to_compact_nodes() {
+    let id_map = NodeIdMap::from_id_list(host_list()).unwrap();
+    assert_eq!(id_map.node(&"db-a".to_string()).unwrap(), 0);
+    assert_eq!(id_map.id(2).unwrap(), "db-c");
+    assert_eq!(id_map.live_node_list(), vec![0, 1, 2]);
+    assert!(matches!(id_map.node(&"db-x".to_string()), Err(CrdtError::UnknownNodeId(_))));
+
+    // the same ids in any order give the same map
+    let mut reversed = host_list();
+    reversed.reverse();
+    assert_eq!(NodeIdMap::from_id_list(reversed).unwrap(), id_map);
+
+    let mut id_map = id_map;
+    assert_eq!(id_map.add(0, "db-b".to_string()).unwrap(), 1);
+    assert_eq!(id_map.add(0, "db-d".to_string()).unwrap(), 3);
+    assert!(matches!(id_map.insert(4, "db-a".to_string()), Err(CrdtError::NodeIdConflict(_, 4))));
+    assert!(matches!(id_map.insert(0, "db-e".to_string()), Err(CrdtError::NodeIdConflict(_, 0))));
+
+    // the env default leaves replicas on plain nodes
+    assert_eq!(NodeIdMap::from_env().unwrap(), None);
+}
+
+#[test]
+fn uuid_like_ids_work_too() {
+    let id_map = NodeIdMap::from_pair_list(vec![(7, (0x9f2c_u64, 1u64)), (3, (0x11aa, 2))]).unwrap();
+    let node = Counter::new_with_node_id_map(&(0x9f2c, 1), &id_map, PNCounterData::new()).unwrap();
+    assert_eq!(node.get_node(), 7);
+    assert_eq!(node.trcb.node_trcb.keys().copied().collect::<Vec<_>>(), vec![3]);
+}
+
+#[test]
+fn removal_propagates_as_eviction() {
+    let mut id_map0 = NodeIdMap::from_id_list(host_list()).unwrap();
+    let mut id_map1 = id_map0.clone();
+    let mut node0 = Counter::new_with_node_id_map(&"db-a".to_string(), &id_map0, PNCounterData::new()).unwrap();
+    let mut node1 = Counter::new_with_node_id_map(&"db-b".to_string(), &id_map1, PNCounterData::new()).unwrap();
+
+    let msg = node0.remove_node_id(&mut id_map0, &"db-c".to_string()).unwrap();
+    assert_eq!(node0.peer_status(&2), Some(PeerStatus::Evicted));
+
+    let msg: NodeIdMsg<String> = serde_json::from_str(&serde_json::to_string(&msg).unwrap()).unwrap();
+    assert_eq!(node1.apply_node_id_msg(&mut id_map1, msg.clone()).unwrap(), vec![2]);
+    assert_eq!(node1.peer_status(&2), Some(PeerStatus::Evicted));
+    assert_eq!(id_map1, id_map0);
+    assert_eq!(id_map1.live_node_list(), vec![0, 1]);
+
+    // merging is idempotent
+    assert_eq!(node1.apply_node_id_msg(&mut id_map1, msg).unwrap(), Vec::<u16>::new());
+
+    // a message naming a node after another id changes nothing
+    let mut bad_msg = id_map0.create_msg(0);
+    bad_msg.id_list.push((1, "db-z".to_string()));
+    bad_msg.removed_list.push(0);
+    assert!(matches!(node1.apply_node_id_msg(&mut id_map1, bad_msg), Err(CrdtError::NodeIdConflict(_, 1))));
+    assert!(!id_map1.is_removed(&0));
+}
+
+#[test]
+fn concurrent_adds_never_share_a_node() {
+    let mut id_map0 = NodeIdMap::from_id_list(host_list()).unwrap();
+    let mut id_map1 = id_map0.clone();
+
+    // each allocator draws from its own block
+    assert_eq!(id_map0.add(0, "db-d".to_string()).unwrap(), 3);
+    assert_eq!(id_map1.add(1, "db-e".to_string()).unwrap(), NODE_ID_BLOCK as u16);
+
+    // both also add the same id; the lower node keeps it on every replica
+    assert_eq!(id_map0.add(0, "db-f".to_string()).unwrap(), 4);
+    assert_eq!(id_map1.add(1, "db-f".to_string()).unwrap(), NODE_ID_BLOCK as u16 + 1);
+
+    let msg0 = id_map0.create_msg(0);
+    let msg1 = id_map1.create_msg(1);
+    assert_eq!(id_map0.apply_msg(msg1.clone()).unwrap(), vec![NODE_ID_BLOCK as u16 + 1]);
+    assert_eq!(id_map1.apply_msg(msg0.clone()).unwrap(), vec![NODE_ID_BLOCK as u16 + 1]);
+    assert_eq!(id_map0, id_map1);
+    assert_eq!(id_map0.node(&"db-f".to_string()).unwrap(), 4);
+    assert_eq!(id_map0.live_node_list(), vec![0, 1, 2, 3, 4, NODE_ID_BLOCK as u16]);
+
+    // the superseded node is never handed out again, and old messages still merge
+    assert_eq!(id_map1.add(1, "db-g".to_string()).unwrap(), NODE_ID_BLOCK as u16 + 2);
+    let mut id_map2 = NodeIdMap::from_id_list(host_list()).unwrap();
+    id_map2.apply_msg(msg1).unwrap();
+    id_map2.apply_msg(id_map1.create_msg(1)).unwrap();
+    id_map2.apply_msg(msg0).unwrap();
+    assert_eq!(id_map2, id_map1);
+
+    assert!(matches!(id_map0.add(u16::MAX, "db-h".to_string()), Err(CrdtError::Overflow(_))));
+}
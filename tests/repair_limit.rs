@@ -0,0 +1,81 @@
+mod common;
+
+use ops_crdt_rust::LCType;
+use ops_crdt_rust::message_data::PeerNodeMsg;
+use ops_crdt_rust::repair_limit::RepairConfig;
+
+use common::{increment, Counter};
+
+fn counter(node: u16) -> Counter {
+    let mut crdt = common::counter(node, vec![0, 1]);
+    crdt.set_delta_vc(false);
+    crdt
+}
+
+fn lc_list(msg_list: &[PeerNodeMsg<u32>]) -> Vec<LCType> {
+    msg_list.iter().filter_map(|msg| match msg {
+        PeerNodeMsg::UpdateNodeMsg(umsg) => Some(umsg.node_vector_clock.vcmap[&umsg.node]),
+        _                                => None
+    }).collect()
+}
+
+#[test]
+fn local_pushes_bypass_pages_and_budget() {
+    let mut node0 = counter(0);
+    node0.set_repair_config(RepairConfig::new(Some(2), None, Some(1)));
+    node0.on_tick(0).unwrap();
+
+    // each local operation reaches the peer alone and at once, however small the budget
+    for value in 1..=5 {
+        let msg_map = increment(&mut node0, value);
+        assert_eq!(lc_list(&msg_map[&1]), vec![value as LCType]);
+    }
+    assert_eq!(node0.repair_limiter.tokens(&1), Some(1));
+}
+
+#[test]
+fn repair_pages_resume_from_the_cursor() {
+    let mut node0 = counter(0);
+    let mut node1 = counter(1);
+    for value in 1..=5 {
+        increment(&mut node0, value);
+    }
+    node0.set_repair_config(RepairConfig::new(Some(2), None, None));
+
+    // node 1 received nothing; repair rounds page through what it lacks
+    let page_list: Vec<Vec<LCType>> = (0..4).map(|_| lc_list(&node0.create_peer_msg_list(true).unwrap()[&1])).collect();
+    assert_eq!(page_list, vec![vec![1, 2], vec![3, 4], vec![5], vec![]]);
+
+    // the first page was lost; a digest request states what node 1 holds and restarts there
+    let page = node0.create_peer_msg_list(true).unwrap().remove(&1).unwrap();
+    assert!(page.is_empty());
+    let mut request_map = node1.create_digest_request();
+    let reply_list = node0.process_peer_msg(request_map.remove(&0).unwrap()).unwrap().remove(&1).unwrap();
+    assert_eq!(lc_list(&reply_list), vec![1, 2]);
+    node1.process_peer_msg(reply_list).unwrap();
+
+    let page = node0.create_peer_msg_list(true).unwrap().remove(&1).unwrap();
+    assert_eq!(lc_list(&page), vec![3, 4]);
+    node1.process_peer_msg(page).unwrap();
+    let page = node0.create_peer_msg_list(true).unwrap().remove(&1).unwrap();
+    node1.process_peer_msg(page).unwrap();
+    assert_eq!(serde_json::to_value(node1.query()).unwrap()["pcount"], 15);
+}
+
+#[test]
+fn budget_limits_each_round_until_refilled() {
+    let mut node0 = counter(0);
+    for value in 1..=4 {
+        increment(&mut node0, value);
+    }
+    let msg_bytes = serde_json::to_vec(&node0.msg_list[&(0, 1)]).unwrap().len() as u64;
+    node0.set_repair_config(RepairConfig::new(None, None, Some(msg_bytes+1)));
+    node0.repair_limiter.refill(0);
+
+    // the second message overdraws the bucket, then rounds stop until it refills
+    assert_eq!(lc_list(&node0.create_peer_msg_list(true).unwrap()[&1]), vec![1, 2]);
+    assert!(node0.repair_limiter.tokens(&1).unwrap() < 0);
+    assert!(lc_list(&node0.create_peer_msg_list(true).unwrap()[&1]).is_empty());
+    node0.repair_limiter.refill(1_000);
+    assert_eq!(lc_list(&node0.create_peer_msg_list(true).unwrap()[&1]), vec![3]);
+}
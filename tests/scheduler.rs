@@ -0,0 +1,123 @@
+mod common;
+
+use ops_crdt_rust::message_data::PeerNodeMsg;
+use ops_crdt_rust::pncnt_crdt::PNCounterData;
+use ops_crdt_rust::scheduler::{Clock, Scheduler, SimClock, TickConfig, TickTask};
+
+use common::Counter;
+
+const STEP_MS: u64 = 10;
+
+fn run_until(scheduler: &mut Scheduler, task: TickTask, clock: &SimClock, until_ms: u64) -> Vec<u64> {
+    let mut fire_list = Vec::new();
+    while clock.now_ms() < until_ms {
+        if scheduler.is_due(task, clock.now_ms()) {
+            fire_list.push(clock.now_ms());
+        }
+        clock.advance(STEP_MS);
+    }
+    fire_list
+}
+
+fn gap_list(fire_list: &[u64]) -> Vec<u64> {
+    fire_list.windows(2).map(|pair| pair[1] - pair[0]).collect()
+}
+
+#[test]
+fn timers_fire_at_interval_within_jitter() {
+    let clock = SimClock::new(5_000);
+    let mut scheduler = Scheduler::new_with_seed(TickConfig::new(1000, 0, 20), 1);
+    let fire_list = run_until(&mut scheduler, TickTask::VectorClock, &clock, 105_000);
+
+    assert!(fire_list[0] <= 5_000 + 1000 + STEP_MS);
+    assert!(gap_list(&fire_list).iter().all(|gap| (800..=1200+STEP_MS).contains(gap)));
+    assert!((85..=125).contains(&fire_list.len()));
+    assert!(gap_list(&fire_list).iter().any(|gap| *gap != 1000));
+
+    // an interval of 0 disables the task
+    clock.set(0);
+    assert!(run_until(&mut scheduler, TickTask::Repair, &clock, 100_000).is_empty());
+}
+
+#[test]
+fn without_jitter_only_the_first_deadline_is_random() {
+    let clock = SimClock::new(0);
+    let mut scheduler = Scheduler::new_with_seed(TickConfig::new(0, 500, 0), 2);
+    let fire_list = run_until(&mut scheduler, TickTask::Repair, &clock, 10_000);
+    assert!(gap_list(&fire_list).iter().all(|gap| *gap == 500));
+
+    // replicas started together spread their first rounds over the interval
+    let first_list: Vec<u64> = (0..8).map(|seed| {
+                                         let clock = SimClock::new(0);
+                                         let mut scheduler = Scheduler::new_with_seed(TickConfig::new(0, 500, 0), seed);
+                                         run_until(&mut scheduler, TickTask::Repair, &clock, 1_000)[0]
+                                     })
+                                     .collect();
+    assert!(first_list.iter().all(|first| *first <= 500 + STEP_MS));
+    assert!(first_list.iter().any(|first| *first != first_list[0]));
+
+    // a new interval restarts the timer
+    scheduler.set_interval(TickTask::Repair, 2000);
+    assert_eq!(scheduler.interval(TickTask::Repair), 2000);
+    let fire_list = run_until(&mut scheduler, TickTask::Repair, &clock, 20_000);
+    assert!(gap_list(&fire_list).iter().all(|gap| *gap == 2000));
+}
+
+#[test]
+fn idle_replica_advertises_and_repairs_on_tick() {
+    let clock = SimClock::new(0);
+    let mut node0 = Counter::new_with_node_list(0, vec![0, 1, 2], PNCounterData::new()).unwrap();
+    node0.set_scheduler(Scheduler::new_with_seed(TickConfig::new(100, 1000, 0), 3));
+
+    let (mut vc_count, mut digest_count) = (0, 0);
+    while clock.advance(STEP_MS) <= 10_000 {
+        for (_, msg_list) in node0.on_tick(clock.now_ms()).unwrap() {
+            for msg in msg_list {
+                match msg {
+                    PeerNodeMsg::VectorClockNodeMsg(_) => vc_count += 1,
+                    PeerNodeMsg::DigestRequestMsg(_)   => digest_count += 1,
+                    msg                                => panic!("idle replica sent {:?}", msg)
+                }
+            }
+        }
+    }
+    // both peers get a clock message every 100 ms and a digest request every second; a
+    // tick doing both sends only the request, which carries the clock too
+    assert!((18..=22).contains(&digest_count), "{}", digest_count);
+    assert!((180..=200).contains(&vc_count), "{}", vc_count);
+}
+
+fn next_repair(node0: &mut Counter, clock: &SimClock) -> Vec<PeerNodeMsg<u32>> {
+    loop {
+        if let Some(msg_list) = node0.on_tick(clock.advance(STEP_MS)).unwrap().remove(&1) {
+            return msg_list;
+        }
+    }
+}
+
+fn update_count(msg_list: &[PeerNodeMsg<u32>]) -> usize {
+    msg_list.iter().filter(|msg| matches!(msg, PeerNodeMsg::UpdateNodeMsg(_))).count()
+}
+
+// a repair round asks with a digest request and pushes the gap only to a peer that left
+// the previous request unanswered
+#[test]
+fn repair_pushes_only_when_the_digest_goes_unanswered() {
+    let clock = SimClock::new(0);
+    let mut node0 = common::counter(0, vec![0, 1]);
+    let mut node1 = common::counter(1, vec![0, 1]);
+    node0.set_scheduler(Scheduler::new_with_seed(TickConfig::new(0, 1000, 0), 5));
+    common::increment(&mut node0, 3);
+
+    let msg_list = next_repair(&mut node0, &clock);
+    assert!(matches!(msg_list.as_slice(), [PeerNodeMsg::DigestRequestMsg(_)]));
+    let msg_list = next_repair(&mut node0, &clock);
+    assert_eq!(update_count(&msg_list), 1);
+    assert!(matches!(msg_list.last(), Some(PeerNodeMsg::DigestRequestMsg(_))));
+
+    let reply_list = node1.process_peer_msg(msg_list).unwrap().remove(&0).unwrap();
+    node0.process_peer_msg(reply_list).unwrap();
+    assert_eq!(common::pcount(&node1), 3);
+    let msg_list = next_repair(&mut node0, &clock);
+    assert!(matches!(msg_list.as_slice(), [PeerNodeMsg::DigestRequestMsg(_)]));
+}
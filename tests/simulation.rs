@@ -0,0 +1,108 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use ops_crdt_rust::cli::{parse_args, CliCommand};
+use ops_crdt_rust::crdt::CrdtType;
+use ops_crdt_rust::error::CrdtError;
+use ops_crdt_rust::simulation::{simulate, DelayDist, Partition, Scenario};
+
+const SCENARIO: &str = r#"{
+    "node_count": 4,
+    "crdt_list": ["PNCounterCrdt", "AWSetCrdt", "EWFlagCrdt"],
+    "op_rounds": 30,
+    "op_rate_pct": 40,
+    "loss_pct": 10,
+    "delay": {"Uniform": {"min_rounds": 0, "max_rounds": 3}},
+    "partition_list": [{"from_round": 10, "to_round": 40, "group_list": [[0, 1], [2, 3]]}],
+    "repair_interval_ms": 500,
+    "seed": 11
+}"#;
+
+#[test]
+fn scenario_converges_after_partition_heals() {
+    let scenario = Scenario::from_json(SCENARIO).unwrap();
+    assert_eq!(scenario.tick_ms, 100);
+    assert_eq!(scenario.settle_round(), 40);
+
+    let report = simulate(&scenario).unwrap();
+    assert_eq!(report.crdt_report_list.len(), 3);
+    for crdt_report in report.crdt_report_list.iter() {
+        assert!(crdt_report.converged, "{:?} did not converge", crdt_report.crdt_type);
+        assert!(crdt_report.op_count > 0 && crdt_report.dropped_count > 0);
+        assert!(crdt_report.msg_count > crdt_report.dropped_count && crdt_report.byte_count > crdt_report.msg_count);
+        assert_eq!(crdt_report.converge_ms, crdt_report.converge_round.map(|round| round*100));
+        assert_eq!(crdt_report.final_value_list.len(), 4);
+    }
+
+    let set_report = &report.crdt_report_list[1];
+    assert_eq!(set_report.crdt_type, CrdtType::AWSetCrdt);
+    let set_list: Vec<HashSet<i32>> = set_report.final_value_list.iter().map(|value| serde_json::from_value(value.clone()).unwrap()).collect();
+    assert!(set_list.iter().all(|set| *set == set_list[0]));
+
+    // the same seed gives the same run
+    assert_eq!(simulate(&scenario).unwrap(), report);
+}
+
+#[test]
+fn lasting_partition_never_converges() {
+    let scenario = Scenario{node_count: 3,
+                            op_rounds: 10,
+                            partition_list: vec![Partition{from_round: 0, to_round: 1000, group_list: vec![vec![0, 1]]}],
+                            max_rounds: 100,
+                            delay: DelayDist::Exponential{mean_rounds: 1.5},
+                            seed: 3,
+                            ..Default::default()};
+    let report = simulate(&scenario).unwrap();
+    let crdt_report = &report.crdt_report_list[0];
+    assert!(!crdt_report.converged);
+    assert_eq!(crdt_report.converge_round, None);
+}
+
+#[test]
+fn bad_scenarios_are_refused() {
+    assert!(matches!(Scenario::from_json(r#"{"node_count": 2, "partition_list": [{"from_round": 0, "to_round": 5, "group_list": [[0, 2]]}]}"#),
+                     Err(CrdtError::ConfigError(_, _))));
+    assert!(matches!(Scenario::from_json(r#"{"delay": {"Uniform": {"min_rounds": 4, "max_rounds": 1}}}"#), Err(CrdtError::ConfigError(_, _))));
+    assert!(matches!(Scenario::from_json(r#"{"crdt_list": ["NoSuchCrdt"]}"#), Err(CrdtError::DecodeError(_))));
+
+    let args: Vec<String> = ["simulate", "--scenario", "net.json"].iter().map(|arg| arg.to_string()).collect();
+    assert_eq!(parse_args(&args).unwrap(), CliCommand::Simulate{scenario_path: PathBuf::from("net.json"), report_path: None});
+}
+
+#[test]
+fn every_crdt_type_converges() {
+    let scenario = Scenario::from_json(r#"{"crdt_list": ["AddMultCrdt", "EWFlagCrdt", "DWFlagCrdt", "AWSetCrdt", "RWSetCrdt", "PNCounterCrdt"],
+                                          "loss_pct": 10, "seed": 1}"#).unwrap();
+    let report = simulate(&scenario).unwrap();
+    assert_eq!(report.crdt_report_list.len(), 6);
+    for crdt_report in report.crdt_report_list.iter() {
+        assert!(crdt_report.converged, "{:?} did not converge", crdt_report.crdt_type);
+        assert!(crdt_report.op_count > 0);
+        assert!(crdt_report.final_value_list.iter().all(|value| *value == crdt_report.final_value_list[0]));
+    }
+
+    // repeated mults overflow i64 long before the run ends; those operations are refused
+    // where they are made and the rest still converge
+    let mult_report = &report.crdt_report_list[0];
+    assert_eq!(mult_report.crdt_type, CrdtType::AddMultCrdt);
+    assert!(mult_report.refused_count > 0);
+}
+
+#[test]
+fn negative_values_only_for_signed_types() {
+    let scenario = Scenario::from_json(r#"{"crdt_list": ["AddMultCrdt", "AWSetCrdt"], "value_min": -3, "seed": 2}"#).unwrap();
+    assert!(simulate(&scenario).unwrap().crdt_report_list.iter().all(|crdt_report| crdt_report.converged));
+
+    assert!(matches!(Scenario::from_json(r#"{"crdt_list": ["PNCounterCrdt"], "value_min": -3}"#), Err(CrdtError::ConfigError(_, _))));
+    assert!(matches!(Scenario::from_json(r#"{"crdt_list": ["RWSetCrdt"], "value_max": 3000000000}"#), Err(CrdtError::ConfigError(_, _))));
+}
+
+#[test]
+fn extreme_scenario_values_do_not_overflow() {
+    for delay in [r#"{"Fixed": 18446744073709551615}"#, r#"{"Exponential": {"mean_rounds": 1e300}}"#] {
+        let scenario = Scenario::from_json(&format!(r#"{{"node_count": 3, "max_rounds": 20, "seed": 18446744073709551615,
+                                                         "tick_ms": 18446744073709551615, "delay": {}}}"#, delay)).unwrap();
+        let report = simulate(&scenario).unwrap();
+        assert!(!report.crdt_report_list[0].converged);
+    }
+}
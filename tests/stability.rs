@@ -0,0 +1,81 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use ops_crdt_rust::LCType;
+use ops_crdt_rust::crdt::{CRDT, CrdtBehavior, CrdtInstance, CrdtType};
+use ops_crdt_rust::error::CrdtError;
+use ops_crdt_rust::message_data::{NodeUpdateMsg, OpsInstance, PeerNodeMsg, SDPOpsType, UserUpdateMsg};
+
+// a set that keeps a tombstone per remove until the remove is stable everywhere
+#[derive(Debug, Clone, Default, PartialEq)]
+struct TombstoneSetData {
+    element_set: BTreeSet<i32>,
+    tombstone_list: BTreeMap<(u16, LCType), i32>
+}
+
+#[derive(Debug)]
+struct TombstoneSet;
+
+impl CrdtBehavior<TombstoneSetData, i32> for TombstoneSet {
+    const CRDT_TYPE: CrdtType = CrdtType::AWSetCrdt;
+
+    fn on_causally_stable(crdt_value: &mut TombstoneSetData, msg: &NodeUpdateMsg<i32>) {
+        if msg.user_update_msg.ops_instance.ops_type == SDPOpsType::SDPMult {
+            crdt_value.tombstone_list.remove(&(msg.node, msg.node_vector_clock.vcmap[&msg.node]));
+        }
+    }
+}
+
+type Replica = CRDT<TombstoneSetData, i32, TombstoneSet>;
+
+fn process_msg(crdt: &mut Replica, msg: &NodeUpdateMsg<i32>) -> Result<(), CrdtError> {
+    let value = msg.user_update_msg.ops_instance.ops_value;
+    match msg.user_update_msg.ops_instance.ops_type {
+        SDPOpsType::SDPAdd  => { crdt.crdt_value.element_set.insert(value); },
+        SDPOpsType::SDPMult => { crdt.crdt_value.element_set.remove(&value);
+                                 crdt.crdt_value.tombstone_list.insert((msg.node, msg.node_vector_clock.vcmap[&msg.node]), value); }
+    }
+    Ok(())
+}
+
+fn update(crdt: &mut Replica, ops_type: SDPOpsType, value: i32) -> HashMap<u16, Vec<PeerNodeMsg<i32>>> {
+    let user_update_msg = UserUpdateMsg::new(CrdtInstance::new(0, 0, CrdtType::AWSetCrdt), OpsInstance::new(ops_type, value));
+    let msg = crdt.create_local_msg(user_update_msg).unwrap();
+    crdt.check_local_msg(&msg).unwrap();
+    crdt.process_msg_with(&msg, process_msg).unwrap();
+    crdt.general_process_local_msg(msg).unwrap()
+}
+
+fn deliver(crdt: &mut Replica, pmsg_list: Vec<PeerNodeMsg<i32>>) {
+    for pmsg in pmsg_list {
+        match crdt.accept_peer_msg(pmsg).unwrap() {
+            Some(PeerNodeMsg::UpdateNodeMsg(umsg)) => {
+                crdt.general_process_peer_msg(umsg.clone()).unwrap();
+                crdt.process_msg_with(&umsg, process_msg).unwrap();
+            },
+            Some(PeerNodeMsg::VectorClockNodeMsg(vmsg)) => crdt.general_process_vc_msg(vmsg).unwrap(),
+            _                                           => ()
+        }
+    }
+}
+
+#[test]
+fn stable_removes_drop_their_tombstones() {
+    let mut node0 = Replica::new_with_node_list(0, vec![0, 1], TombstoneSetData::default()).unwrap();
+    let mut node1 = Replica::new_with_node_list(1, vec![0, 1], TombstoneSetData::default()).unwrap();
+
+    for (ops_type, value) in [(SDPOpsType::SDPAdd, 1), (SDPOpsType::SDPAdd, 2), (SDPOpsType::SDPMult, 1)] {
+        let mut msg_map = update(&mut node0, ops_type, value);
+        deliver(&mut node1, msg_map.remove(&1).unwrap());
+    }
+    assert_eq!(node0.crdt_value.tombstone_list, BTreeMap::from([((0, 3), 1)]));
+    assert_eq!(node1.crdt_value, node0.crdt_value);
+
+    // node 0 learns that node 1 holds the remove, node 1 that node 0 knows it
+    deliver(&mut node0, vec![PeerNodeMsg::VectorClockNodeMsg(node1.create_vc_msg())]);
+    deliver(&mut node1, vec![PeerNodeMsg::VectorClockNodeMsg(node0.create_vc_msg())]);
+    for node in [&node0, &node1] {
+        assert!(node.crdt_value.tombstone_list.is_empty());
+        assert_eq!(node.crdt_value.element_set, BTreeSet::from([2]));
+        assert_eq!(node.msg_list_len(), 0);
+    }
+}
@@ -0,0 +1,122 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use ops_crdt_rust::crdt::{CRDT, CrdtInstance, CrdtType};
+use ops_crdt_rust::arset_crdt::AWSet;
+use ops_crdt_rust::edflag_crdt::{EDFlag, EWFlag};
+use ops_crdt_rust::message_data::{OpsInstance, PeerNodeMsg, SDPOpsType, UserUpdateMsg};
+use ops_crdt_rust::subscription::{ChangeEvent, Listener, ValueChange};
+
+type Set = CRDT<HashSet<i32>, i32, AWSet>;
+type Flag = CRDT<EDFlag, EDFlag, EWFlag>;
+type MsgMap<T> = HashMap<u16, Vec<PeerNodeMsg<T>>>;
+type EventList<CrdtValue, OpsValue> = Arc<Mutex<Vec<ChangeEvent<CrdtValue, OpsValue>>>>;
+
+fn set_op(node: &mut Set, ops_type: SDPOpsType, value: i32) -> MsgMap<i32> {
+    let umsg = UserUpdateMsg::new(CrdtInstance::new(0, 0, CrdtType::AWSetCrdt), OpsInstance::new(ops_type, value));
+    let msg = node.create_local_msg(umsg).unwrap();
+    node.process_local_msg(msg).unwrap()
+}
+
+fn peer_list<T: Clone+PartialEq>(msg_map: &MsgMap<T>, pnode: u16) -> Vec<PeerNodeMsg<T>> {
+    msg_map.get(&pnode).cloned().unwrap_or_default()
+}
+
+fn recorder<CrdtValue: Clone+Send+'static, OpsValue: Clone+Send+'static>() ->
+    (EventList<CrdtValue, OpsValue>, Listener<CrdtValue, OpsValue>) {
+    let event_list = Arc::new(Mutex::new(Vec::new()));
+    let sink = event_list.clone();
+    (event_list, Box::new(move |event: &ChangeEvent<CrdtValue, OpsValue>| sink.lock().unwrap().push(event.clone())))
+}
+
+#[test]
+fn set_listener_sees_elements_from_any_node() {
+    let mut node0 = Set::new_with_node_list(0, vec![0, 1], HashSet::new()).unwrap();
+    let mut node1 = Set::new_with_node_list(1, vec![0, 1], HashSet::new()).unwrap();
+    let (event_list, listener) = recorder();
+    node1.subscribe(CrdtInstance::new(0, 0, CrdtType::AWSetCrdt), listener);
+
+    let msg_map = set_op(&mut node0, SDPOpsType::SDPMult, 7);
+    node1.process_peer_msg(peer_list(&msg_map, 1)).unwrap();
+    set_op(&mut node1, SDPOpsType::SDPMult, 3);
+    // adding a value already present changes nothing
+    set_op(&mut node1, SDPOpsType::SDPMult, 3);
+    set_op(&mut node1, SDPOpsType::SDPAdd, 7);
+
+    let event_list = event_list.lock().unwrap();
+    let change_list: Vec<_> = event_list.iter().map(|event| (event.node, event.change_list.clone())).collect();
+    assert_eq!(change_list, vec![(0, vec![ValueChange::Added(7)]),
+                                 (1, vec![ValueChange::Added(3)]),
+                                 (1, vec![ValueChange::Removed(7)])]);
+    assert_eq!(event_list[0].old_value, HashSet::new());
+    assert_eq!(event_list[0].node_vector_clock.vcmap.get(&0), Some(&1));
+    assert_eq!(event_list[2].new_value, HashSet::from([3]));
+}
+
+#[test]
+fn concurrent_add_brings_element_back() {
+    let mut node0 = Set::new_with_node_list(0, vec![0, 1], HashSet::new()).unwrap();
+    let mut node1 = Set::new_with_node_list(1, vec![0, 1], HashSet::new()).unwrap();
+    let msg_map = set_op(&mut node0, SDPOpsType::SDPMult, 7);
+    node1.process_peer_msg(peer_list(&msg_map, 1)).unwrap();
+
+    let (event_list, listener) = recorder();
+    node0.subscribe(CrdtInstance::new(0, 0, CrdtType::AWSetCrdt), listener);
+    let add_map = set_op(&mut node1, SDPOpsType::SDPMult, 7);
+    let remove_map = set_op(&mut node0, SDPOpsType::SDPAdd, 7);
+    node0.process_peer_msg(peer_list(&add_map, 0)).unwrap();
+    node1.process_peer_msg(peer_list(&remove_map, 1)).unwrap();
+
+    let event_list = event_list.lock().unwrap();
+    let change_list: Vec<_> = event_list.iter().map(|event| (event.node, event.change_list.clone())).collect();
+    assert_eq!(change_list, vec![(0, vec![ValueChange::Removed(7)]), (1, vec![ValueChange::Added(7)])]);
+    assert_eq!(node1.query(), HashSet::from([7]));
+}
+
+#[test]
+fn listeners_follow_their_instance() {
+    let mut node0 = Flag::new_with_node_list(0, vec![0, 1], EDFlag::Enabled).unwrap();
+    let (event_list, listener) = recorder();
+    let (other_list, other_listener) = recorder();
+    let id = node0.subscribe(CrdtInstance::new(0, 0, CrdtType::EWFlagCrdt), listener);
+    node0.subscribe(CrdtInstance::new(0, 1, CrdtType::EWFlagCrdt), other_listener);
+
+    let umsg = UserUpdateMsg::new(CrdtInstance::new(0, 0, CrdtType::EWFlagCrdt), OpsInstance::new(SDPOpsType::SDPAdd, EDFlag::Disabled));
+    let msg = node0.create_local_msg(umsg).unwrap();
+    node0.process_local_msg(msg).unwrap();
+    {
+        let event_list = event_list.lock().unwrap();
+        assert_eq!(event_list.len(), 1);
+        assert_eq!((&event_list[0].old_value, &event_list[0].new_value), (&EDFlag::Enabled, &EDFlag::Disabled));
+        assert!(event_list[0].change_list.is_empty());
+    }
+    assert!(other_list.lock().unwrap().is_empty());
+
+    assert!(node0.unsubscribe(id));
+    assert!(!node0.unsubscribe(id));
+    let umsg = UserUpdateMsg::new(CrdtInstance::new(0, 0, CrdtType::EWFlagCrdt), OpsInstance::new(SDPOpsType::SDPMult, EDFlag::Enabled));
+    let msg = node0.create_local_msg(umsg).unwrap();
+    node0.process_local_msg(msg).unwrap();
+    assert_eq!(event_list.lock().unwrap().len(), 1);
+}
+
+#[test]
+fn state_transfer_reports_the_replaced_value() {
+    let mut node0 = Set::new_with_node_list(0, vec![0, 1], HashSet::new()).unwrap();
+    let mut node1 = Set::new_with_node_list(1, vec![0, 1], HashSet::new()).unwrap();
+    let (event_list, listener) = recorder();
+    node1.subscribe(CrdtInstance::new(0, 0, CrdtType::AWSetCrdt), listener);
+
+    set_op(&mut node0, SDPOpsType::SDPMult, 7);
+    node1.apply_state_transfer(node0.create_state_transfer().unwrap()).unwrap();
+    {
+        let event_list = event_list.lock().unwrap();
+        assert_eq!(event_list.len(), 1);
+        assert_eq!((event_list[0].node, event_list[0].old_value.clone(), event_list[0].new_value.clone()), (0, HashSet::new(), HashSet::from([7])));
+        assert_eq!(event_list[0].change_list, vec![ValueChange::Added(7)]);
+    }
+
+    // the same state again leaves the value as it is
+    node1.apply_state_transfer(node0.create_state_transfer().unwrap()).unwrap();
+    assert_eq!(event_list.lock().unwrap().len(), 1);
+}
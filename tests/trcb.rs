@@ -0,0 +1,86 @@
+use std::collections::BTreeSet;
+use rand::{Rng, SeedableRng};
+use rand::rngs::SmallRng;
+
+use ops_crdt_rust::{LCType, PNCntOpsValue};
+use ops_crdt_rust::crdt::{CRDT, CrdtInstance, CrdtType};
+use ops_crdt_rust::message_data::UserUpdateMsg;
+use ops_crdt_rust::pncnt_crdt::{PNCounter, PNCounterData};
+use ops_crdt_rust::trcb::TRCBData;
+use ops_crdt_rust::vector_clock::{VectorClock, VCStatus};
+
+// the stable clock by definition: the least entry over the own clock and every live peer
+fn full_stable_vc(trcb: &TRCBData) -> VectorClock {
+    let mut stable_vc = trcb.node_vector_clock.clone();
+    for (pnode, pvc) in trcb.node_trcb.iter() {
+        if !trcb.is_evicted(pnode) {
+            stable_vc = stable_vc.min_vc(pvc).unwrap();
+        }
+    }
+    stable_vc
+}
+
+fn dot_set(from_vc: &VectorClock, to_vc: &VectorClock) -> BTreeSet<(u16, LCType)> {
+    to_vc.vcmap.iter().flat_map(|(node, lc)| (from_vc.vcmap[node]+1..=*lc).map(|dlc| (*node, dlc))).collect()
+}
+
+#[test]
+fn incremental_stability_matches_full_recompute() {
+    let node_list = vec![0, 1, 2, 3];
+    let mut rng = SmallRng::seed_from_u64(7);
+    let mut clock_list: Vec<VectorClock> = node_list.iter().map(|_| VectorClock::new(node_list.clone()).unwrap()).collect();
+    let mut trcb = TRCBData::new(0, node_list.clone()).unwrap();
+    let mut stable_vc = trcb.stable_vector_clock.clone();
+    let mut dot_list = BTreeSet::new();
+
+    for step in 0..2000 {
+        let node = rng.gen_range(0..4usize);
+        match rng.gen_range(0..3) {
+            // a local operation
+            0 if node == 0 => { trcb.next_vc().unwrap(); },
+            // a peer operation that is next for node 0, depending on part of what node 0 holds
+            0 | 1 if node != 0 => {
+                let pnode = node as u16;
+                let mut peer_vc = clock_list[node].min_vc(&trcb.node_vector_clock).unwrap();
+                peer_vc.vcmap.insert(pnode, trcb.node_vector_clock.vcmap[&pnode]+1);
+                assert_eq!(trcb.add_peer_vc(pnode, peer_vc.clone()).unwrap(), VCStatus::INORDER);
+                clock_list[node] = clock_list[node].max_vc(&peer_vc).unwrap();
+            },
+            // a clock message: the peer has caught up with part of what node 0 holds
+            2 if node != 0 => {
+                let mut peer_vc = trcb.node_vector_clock.clone();
+                peer_vc.vcmap.values_mut().for_each(|lc| *lc -= rng.gen_range(0..=(*lc).min(2)));
+                clock_list[node] = clock_list[node].max_vc(&peer_vc).unwrap();
+                trcb.add_peer_vcmsg(node as u16, peer_vc).unwrap();
+            },
+            _ => ()
+        }
+        if step == 1000 {
+            trcb.evict_peer(3).unwrap();
+        }
+
+        let expected_vc = full_stable_vc(&trcb);
+        assert_eq!(trcb.stable_vector_clock.vcmap, expected_vc.vcmap, "step {}", step);
+        dot_list.extend(trcb.take_stable_dots());
+        assert!(trcb.take_stable_dots().is_empty());
+        assert_eq!(dot_list, dot_set(&stable_vc, &expected_vc), "step {}", step);
+        stable_vc = expected_vc;
+        dot_list.clear();
+    }
+    assert!(stable_vc.vcmap.values().all(|lc| *lc > 10));
+}
+
+#[test]
+#[allow(deprecated)]
+fn deprecated_stability_gate_is_ignored() {
+    let mut crdt = CRDT::<PNCounterData, PNCntOpsValue, PNCounter>::new(0, PNCounterData::new()).unwrap();
+    crdt.trcb = TRCBData::new(0, vec![0]).unwrap();
+    // the old gate is still accepted but no longer holds stability back
+    crdt.max_msg_count_cs = u16::MAX;
+
+    let user_update_msg = UserUpdateMsg::new(CrdtInstance::new_default(CrdtType::PNCounterCrdt), crdt.get_add_ops(1));
+    let node_update_msg = crdt.create_local_msg(user_update_msg).unwrap();
+    crdt.process_local_msg(node_update_msg).unwrap();
+    assert_eq!(crdt.msg_list_len(), 0);
+    assert_eq!(crdt.msg_count_cs, 0);
+}
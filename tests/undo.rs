@@ -0,0 +1,169 @@
+mod common;
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use ops_crdt_rust::crdt::{CRDT, CrdtBehavior, CrdtInstance, CrdtType};
+use ops_crdt_rust::add_mult_crdt::AddMult;
+use ops_crdt_rust::arset_crdt::AWSet;
+use ops_crdt_rust::edflag_crdt::{EDFlag, EWFlag};
+use ops_crdt_rust::error::CrdtError;
+use ops_crdt_rust::message_data::{NodeUpdateMsg, OpsInstance, PeerNodeMsg, SDPOpsType, UserUpdateMsg};
+use ops_crdt_rust::pncnt_crdt::PNCounterData;
+
+use common::Counter;
+
+type Set = CRDT<HashSet<i32>, i32, AWSet>;
+
+fn create_msg<CrdtValue: Clone+Debug,
+              OpsValue: Clone+PartialEq+Debug+Serialize+DeserializeOwned,
+              State: Debug+CrdtBehavior<CrdtValue, OpsValue>>
+    (crdt: &mut CRDT<CrdtValue, OpsValue, State>, crdt_type: CrdtType, ops_instance: OpsInstance<OpsValue>) -> NodeUpdateMsg<OpsValue> {
+    crdt.create_local_msg(UserUpdateMsg::new(CrdtInstance::new(0, 0, crdt_type), ops_instance)).unwrap()
+}
+
+fn peer_list<T: Clone+PartialEq>(msg_map: HashMap<u16, Vec<PeerNodeMsg<T>>>, pnode: u16) -> Vec<PeerNodeMsg<T>> {
+    msg_map.get(&pnode).cloned().unwrap_or_default()
+}
+
+fn counter_value(node: &Counter) -> serde_json::Value {
+    serde_json::to_value(node.query()).unwrap()
+}
+
+#[test]
+fn counter_undo_and_redo_reach_peers() {
+    let mut node0 = Counter::new_with_node_list(0, vec![0, 1], PNCounterData::new()).unwrap();
+    let mut node1 = Counter::new_with_node_list(1, vec![0, 1], PNCounterData::new()).unwrap();
+    node0.set_undo_history_len(8);
+
+    let msg = create_msg(&mut node0, CrdtType::PNCounterCrdt, OpsInstance::new(SDPOpsType::SDPAdd, 5));
+    node1.process_peer_msg(peer_list(node0.process_local_msg(msg.clone()).unwrap(), 1)).unwrap();
+    assert!(node0.can_undo(&msg));
+
+    node1.process_peer_msg(peer_list(node0.undo(&msg).unwrap(), 1)).unwrap();
+    assert_eq!(counter_value(&node1), serde_json::json!({"pcount": 5, "ncount": 5}));
+    assert!(matches!(node0.undo(&msg), Err(CrdtError::NoUndoEntry(0, 1))));
+
+    node1.process_peer_msg(peer_list(node0.redo(&msg).unwrap(), 1)).unwrap();
+    assert_eq!(counter_value(&node1), serde_json::json!({"pcount": 10, "ncount": 5}));
+    assert_eq!(counter_value(&node0), counter_value(&node1));
+    assert!(matches!(node0.redo(&msg), Err(CrdtError::NoUndoEntry(0, 1))));
+    assert!(node0.can_undo(&msg));
+}
+
+// compensations take no history slot of their own, so a history of one is enough to undo
+// and redo the same operation over and over
+#[test]
+fn redo_state_stays_on_the_undone_entry() {
+    let mut node0 = Counter::new_with_node_list(0, vec![0, 1], PNCounterData::new()).unwrap();
+    node0.set_undo_history_len(1);
+    let msg = create_msg(&mut node0, CrdtType::PNCounterCrdt, OpsInstance::new(SDPOpsType::SDPAdd, 5));
+    node0.process_local_msg(msg.clone()).unwrap();
+
+    node0.undo(&msg).unwrap();
+    node0.redo(&msg).unwrap();
+    assert!(matches!(node0.redo(&msg), Err(CrdtError::NoUndoEntry(0, 1))));
+    assert_eq!(counter_value(&node0), serde_json::json!({"pcount": 10, "ncount": 5}));
+
+    node0.undo(&msg).unwrap();
+    node0.redo(&msg).unwrap();
+    assert_eq!(counter_value(&node0), serde_json::json!({"pcount": 15, "ncount": 10}));
+    assert_eq!(node0.undo.entry_list.len(), 1);
+
+    // a newer operation still pushes the undone one out
+    node0.undo(&msg).unwrap();
+    let new_msg = create_msg(&mut node0, CrdtType::PNCounterCrdt, OpsInstance::new(SDPOpsType::SDPAdd, 1));
+    node0.process_local_msg(new_msg.clone()).unwrap();
+    assert!(matches!(node0.redo(&msg), Err(CrdtError::NoUndoEntry(0, 1))));
+    assert!(node0.can_undo(&new_msg));
+}
+
+#[test]
+fn undo_of_set_add_loses_to_concurrent_add() {
+    let mut node0 = Set::new_with_node_list(0, vec![0, 1], HashSet::new()).unwrap();
+    let mut node1 = Set::new_with_node_list(1, vec![0, 1], HashSet::new()).unwrap();
+    node0.set_undo_history_len(8);
+
+    let msg = create_msg(&mut node0, CrdtType::AWSetCrdt, OpsInstance::new(SDPOpsType::SDPMult, 7));
+    node1.process_peer_msg(peer_list(node0.process_local_msg(msg.clone()).unwrap(), 1)).unwrap();
+
+    // node 1 adds 7 again while node 0 undoes its add
+    let add_msg = create_msg(&mut node1, CrdtType::AWSetCrdt, OpsInstance::new(SDPOpsType::SDPMult, 7));
+    let add_map = node1.process_local_msg(add_msg).unwrap();
+    let undo_map = node0.undo(&msg).unwrap();
+    assert!(node0.query().is_empty());
+    node0.process_peer_msg(peer_list(add_map, 0)).unwrap();
+    node1.process_peer_msg(peer_list(undo_map, 1)).unwrap();
+    assert_eq!(node0.query(), HashSet::from([7]));
+    assert_eq!(node1.query(), HashSet::from([7]));
+
+    // adding a value already present changed nothing, so neither does its undo
+    let msg = create_msg(&mut node0, CrdtType::AWSetCrdt, OpsInstance::new(SDPOpsType::SDPMult, 7));
+    node0.process_local_msg(msg.clone()).unwrap();
+    assert!(node0.undo(&msg).unwrap().is_empty());
+    assert_eq!(node0.query(), HashSet::from([7]));
+    assert!(node0.redo(&msg).unwrap().is_empty());
+}
+
+#[test]
+fn flag_undo_restores_previous_value() {
+    let mut node0: CRDT<EDFlag, EDFlag, EWFlag> = CRDT::new_with_node_list(0, vec![0, 1], EDFlag::Enabled).unwrap();
+    node0.set_undo_history_len(8);
+    let msg = create_msg(&mut node0, CrdtType::EWFlagCrdt, OpsInstance::new(SDPOpsType::SDPAdd, EDFlag::Disabled));
+    node0.process_local_msg(msg.clone()).unwrap();
+    assert_eq!(node0.query(), EDFlag::Disabled);
+    node0.undo(&msg).unwrap();
+    assert_eq!(node0.query(), EDFlag::Enabled);
+    node0.redo(&msg).unwrap();
+    assert_eq!(node0.query(), EDFlag::Disabled);
+}
+
+#[test]
+fn undo_needs_local_history() {
+    let mut node0 = Counter::new_with_node_list(0, vec![0, 1], PNCounterData::new()).unwrap();
+    let mut node1 = Counter::new_with_node_list(1, vec![0, 1], PNCounterData::new()).unwrap();
+    let msg = create_msg(&mut node0, CrdtType::PNCounterCrdt, OpsInstance::new(SDPOpsType::SDPAdd, 5));
+    node0.process_local_msg(msg.clone()).unwrap();
+    assert!(matches!(node0.undo(&msg), Err(CrdtError::NoUndoEntry(0, 1))));
+
+    node1.set_undo_history_len(8);
+    node1.process_peer_msg(vec![PeerNodeMsg::UpdateNodeMsg(msg.clone())]).unwrap();
+    assert!(matches!(node1.undo(&msg), Err(CrdtError::NoUndoEntry(0, 1))));
+
+    // the oldest entries are dropped first
+    node1.set_undo_history_len(1);
+    let old_msg = create_msg(&mut node1, CrdtType::PNCounterCrdt, OpsInstance::new(SDPOpsType::SDPAdd, 1));
+    node1.process_local_msg(old_msg.clone()).unwrap();
+    let new_msg = create_msg(&mut node1, CrdtType::PNCounterCrdt, OpsInstance::new(SDPOpsType::SDPAdd, 1));
+    node1.process_local_msg(new_msg.clone()).unwrap();
+    assert!(!node1.can_undo(&old_msg) && node1.can_undo(&new_msg));
+
+    let mut mult: CRDT<i64, i64, AddMult> = CRDT::new_with_node_list(0, vec![0, 1], 2).unwrap();
+    mult.set_undo_history_len(8);
+    let msg = create_msg(&mut mult, CrdtType::AddMultCrdt, OpsInstance::new(SDPOpsType::SDPMult, 3));
+    mult.process_local_msg(msg.clone()).unwrap();
+    assert!(!mult.can_undo(&msg));
+    assert!(matches!(mult.undo(&msg), Err(CrdtError::UndoUnsupported(_))));
+    let msg = create_msg(&mut mult, CrdtType::AddMultCrdt, OpsInstance::new(SDPOpsType::SDPAdd, 4));
+    mult.process_local_msg(msg.clone()).unwrap();
+    mult.undo(&msg).unwrap();
+    assert_eq!(mult.query(), 6);
+}
+
+#[test]
+fn failed_apply_leaves_no_undo_entry() {
+    let mut mult: CRDT<i64, i64, AddMult> = CRDT::new_with_node_list(0, vec![0, 1], 2).unwrap();
+    mult.set_undo_history_len(8);
+    let msg = create_msg(&mut mult, CrdtType::AddMultCrdt, OpsInstance::new(SDPOpsType::SDPAdd, 4));
+    mult.process_local_msg(msg).unwrap();
+
+    // a clock the stored op can not be compared with makes the apply fail
+    let mut msg = create_msg(&mut mult, CrdtType::AddMultCrdt, OpsInstance::new(SDPOpsType::SDPAdd, 1));
+    msg.node_vector_clock.vcmap.insert(9, 0);
+    assert!(matches!(mult.process_local_msg(msg.clone()), Err(CrdtError::NonCompatibleVC)));
+    assert_eq!(mult.query(), 6);
+    assert!(!mult.can_undo(&msg));
+    assert!(matches!(mult.undo(&msg), Err(CrdtError::NoUndoEntry(0, 2))));
+}
@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+use serde::Serialize;
+
+use ops_crdt_rust::LCType;
+use ops_crdt_rust::crdt::{CrdtInstance, CrdtType};
+use ops_crdt_rust::edflag_crdt::EDFlag;
+use ops_crdt_rust::message_data::{DeltaNodeUpdateMsg, MerkleReplyMsg, MerkleRequestMsg, NodeUpdateMsg, NodeVectorClockMsg,
+                                  OpsInstance, PeerNodeMsg, SDPOpsType, UserUpdateMsg};
+use ops_crdt_rust::vector_clock::VectorClock;
+use ops_crdt_rust::wire::{self, WireOptions, WIRE_VERSION};
+use ops_crdt_rust::error::CrdtError;
+
+fn vector_clock(lc_list: &[(u16, LCType)]) -> VectorClock {
+    VectorClock{vcmap: lc_list.iter().copied().collect::<HashMap<_, _>>()}
+}
+
+fn update_msg<OpsValue: Clone+PartialEq>(node: u16, lc_list: &[(u16, LCType)], crdt_type: CrdtType,
+                                         ops_type: SDPOpsType, ops_value: OpsValue) -> PeerNodeMsg<OpsValue> {
+    let user_update_msg = UserUpdateMsg::new(CrdtInstance::new(node, 3, crdt_type), OpsInstance::new(ops_type, ops_value));
+    PeerNodeMsg::UpdateNodeMsg(NodeUpdateMsg::new(node, vector_clock(lc_list), user_update_msg))
+}
+
+fn json<T: Serialize>(value: &T) -> serde_json::Value {
+    serde_json::to_value(value).unwrap()
+}
+
+fn five_node_clock(lc: LCType) -> Vec<(u16, LCType)> {
+    vec![(0, lc), (1, lc+2), (2, 7), (3, 0), (4, lc*3)]
+}
+
+#[test]
+fn round_trip_every_message_kind() {
+    let vc_msg = NodeVectorClockMsg::new(2, vector_clock(&five_node_clock(40)));
+    let mut reset_msg = vc_msg.clone();
+    reset_msg.epoch = 300;
+    reset_msg.reset_pending = true;
+    let msg_list: Vec<PeerNodeMsg<i64>> = vec![
+        PeerNodeMsg::VectorClockNodeMsg(vc_msg.clone()),
+        update_msg(1, &five_node_clock(41), CrdtType::AddMultCrdt, SDPOpsType::SDPAdd, -12345),
+        update_msg(4, &five_node_clock(1_000_000), CrdtType::AddMultCrdt, SDPOpsType::SDPMult, i64::MAX),
+        PeerNodeMsg::DeltaUpdateNodeMsg(DeltaNodeUpdateMsg{node: 4, lc: 1_000_001, entry_list: vec![(0, 12), (3, 1)],
+                                                           user_update_msg: UserUpdateMsg::new(CrdtInstance::new(4, 3, CrdtType::AddMultCrdt),
+                                                                                               OpsInstance::new(SDPOpsType::SDPAdd, 0)),
+                                                           pred_hash_list: Vec::new(), epoch: 3, signature: None}),
+        PeerNodeMsg::VectorClockNodeMsg(reset_msg),
+        PeerNodeMsg::DigestRequestMsg(vc_msg.clone()),
+        PeerNodeMsg::DigestReplyMsg(vc_msg.clone()),
+        PeerNodeMsg::DigestAckMsg(vc_msg.clone()),
+        PeerNodeMsg::MerkleRequestMsg(MerkleRequestMsg{node: 3, depth: 2,
+                                                       low_vector_clock: vector_clock(&five_node_clock(5)),
+                                                       high_vector_clock: vector_clock(&five_node_clock(9)),
+                                                       path_list: vec![(0, 0), (1, 15)]}),
+        PeerNodeMsg::MerkleReplyMsg(MerkleReplyMsg{node: 0, depth: 2,
+                                                   low_vector_clock: vector_clock(&five_node_clock(5)),
+                                                   high_vector_clock: vector_clock(&five_node_clock(9)),
+                                                   path_list: vec![(1, 15), (2, 255)],
+                                                   hash_list: vec![((2, 241), u64::MAX), ((2, 250), 7)],
+                                                   leaf_list: vec![(4, 8, 0xdead_beef), (1, 6, 1)]})];
+
+    for options in [WireOptions::new(false), WireOptions::new(true)] {
+        let bytes = wire::encode_peer_msg_list(&msg_list, options).unwrap();
+        let decoded: Vec<PeerNodeMsg<i64>> = wire::decode_peer_msg_list(&bytes).unwrap();
+        assert_eq!(json(&decoded), json(&msg_list));
+    }
+
+    for msg in msg_list.iter() {
+        let decoded: PeerNodeMsg<i64> = wire::decode_peer_msg(&wire::encode_peer_msg(msg).unwrap()).unwrap();
+        assert_eq!(json(&decoded), json(msg));
+    }
+
+    let decoded = wire::decode_vc_msg(&wire::encode_vc_msg(&vc_msg).unwrap()).unwrap();
+    assert_eq!(json(&decoded), json(&vc_msg));
+}
+
+#[test]
+fn round_trip_every_ops_value_type() {
+    let counter_msg = update_msg(0, &five_node_clock(1), CrdtType::PNCounterCrdt, SDPOpsType::SDPAdd, u32::MAX);
+    let decoded: PeerNodeMsg<u32> = wire::decode_peer_msg(&wire::encode_peer_msg(&counter_msg).unwrap()).unwrap();
+    assert_eq!(json(&decoded), json(&counter_msg));
+
+    let set_msg = update_msg(1, &five_node_clock(2), CrdtType::RWSetCrdt, SDPOpsType::SDPMult, i32::MIN);
+    let decoded: PeerNodeMsg<i32> = wire::decode_peer_msg(&wire::encode_peer_msg(&set_msg).unwrap()).unwrap();
+    assert_eq!(json(&decoded), json(&set_msg));
+
+    let flag_msg = update_msg(2, &five_node_clock(3), CrdtType::DWFlagCrdt, SDPOpsType::SDPMult, EDFlag::Disabled);
+    let decoded: PeerNodeMsg<EDFlag> = wire::decode_peer_msg(&wire::encode_peer_msg(&flag_msg).unwrap()).unwrap();
+    assert_eq!(json(&decoded), json(&flag_msg));
+}
+
+#[test]
+fn round_trip_sparse_and_sparse_node_ids() {
+    let msg_list: Vec<PeerNodeMsg<u32>> = vec![
+        update_msg(1000, &[(1000, 5), (7, 2), (60000, 9)], CrdtType::PNCounterCrdt, SDPOpsType::SDPAdd, 1),
+        update_msg(7, &[(7, 3), (1000, 5)], CrdtType::PNCounterCrdt, SDPOpsType::SDPAdd, 1),
+        update_msg(60000, &[(1000, 5), (7, 3), (60000, 10)], CrdtType::PNCounterCrdt, SDPOpsType::SDPMult, 2)];
+    let bytes = wire::encode_peer_msg_list(&msg_list, WireOptions::new(true)).unwrap();
+    let decoded: Vec<PeerNodeMsg<u32>> = wire::decode_peer_msg_list(&bytes).unwrap();
+    assert_eq!(json(&decoded), json(&msg_list));
+}
+
+#[test]
+fn counter_increment_is_a_fraction_of_json() {
+    let msg = update_msg(1, &five_node_clock(120), CrdtType::PNCounterCrdt, SDPOpsType::SDPAdd, 1u32);
+    let binary_len = wire::encode_peer_msg(&msg).unwrap().len();
+    let json_len = serde_json::to_vec(&msg).unwrap().len();
+    assert!(binary_len <= 25, "binary counter increment grew to {} bytes", binary_len);
+    assert!(binary_len*8 <= json_len, "binary {} bytes vs json {} bytes", binary_len, json_len);
+
+    let vc_msg = NodeVectorClockMsg::new(1, vector_clock(&five_node_clock(120)));
+    let vc_len = wire::encode_vc_msg(&vc_msg).unwrap().len();
+    assert!(vc_len <= 20, "binary vector clock message grew to {} bytes", vc_len);
+}
+
+#[test]
+fn delta_clocks_shrink_repair_batches() {
+    let node_list: Vec<u16> = (0..50).collect();
+    let msg_list: Vec<PeerNodeMsg<u32>> = (0..100u32).map(|step| {
+        let lc_list: Vec<(u16, LCType)> = node_list.iter().map(|node| (*node, (100_000 + step + *node as u32) as LCType)).collect();
+        update_msg(3, &lc_list, CrdtType::PNCounterCrdt, SDPOpsType::SDPAdd, 1)
+    }).collect();
+
+    let dense_len = wire::encode_peer_msg_list(&msg_list, WireOptions::new(false)).unwrap().len();
+    let delta_len = wire::encode_peer_msg_list(&msg_list, WireOptions::new(true)).unwrap().len();
+    assert!(delta_len*2 <= dense_len, "delta {} bytes vs dense {} bytes", delta_len, dense_len);
+
+    let decoded: Vec<PeerNodeMsg<u32>> = wire::decode_peer_msg_list(&wire::encode_peer_msg_list(&msg_list, WireOptions::new(true)).unwrap()).unwrap();
+    assert_eq!(json(&decoded), json(&msg_list));
+}
+
+// a difference between 64 bit clocks can exceed i64, so it wraps on both sides
+#[cfg(feature = "lc64")]
+#[test]
+fn delta_clocks_round_trip_near_the_lc64_limit() {
+    let msg_list: Vec<PeerNodeMsg<u32>> = [(0, u64::MAX), (u64::MAX, 0), (u64::MAX-1, 1), (1, u64::MAX-1)].iter().map(|(lc0, lc1)| {
+        update_msg(0, &[(0, *lc0), (1, *lc1), (2, u64::MAX/2)], CrdtType::PNCounterCrdt, SDPOpsType::SDPAdd, 1)
+    }).collect();
+    let decoded: Vec<PeerNodeMsg<u32>> = wire::decode_peer_msg_list(&wire::encode_peer_msg_list(&msg_list, WireOptions::new(true)).unwrap()).unwrap();
+    assert_eq!(json(&decoded), json(&msg_list));
+}
+
+#[test]
+fn malformed_frames_are_rejected() {
+    let msg = update_msg(1, &five_node_clock(3), CrdtType::PNCounterCrdt, SDPOpsType::SDPAdd, 1u32);
+    let bytes = wire::encode_peer_msg(&msg).unwrap();
+    assert_eq!(bytes[0], WIRE_VERSION);
+
+    for len in 0..bytes.len() {
+        assert!(matches!(wire::decode_peer_msg::<u32>(&bytes[..len]), Err(CrdtError::DecodeError(_))));
+    }
+
+    let mut bad_version = bytes.clone();
+    bad_version[0] = WIRE_VERSION+1;
+    assert!(matches!(wire::decode_peer_msg::<u32>(&bad_version), Err(CrdtError::UnsupportedVersion(_))));
+
+    let mut trailing = bytes.clone();
+    trailing.push(0);
+    assert!(matches!(wire::decode_peer_msg::<u32>(&trailing), Err(CrdtError::DecodeError(_))));
+
+    let vc_bytes = wire::encode_vc_msg(&NodeVectorClockMsg::new(1, vector_clock(&five_node_clock(3)))).unwrap();
+    assert!(matches!(wire::decode_peer_msg::<u32>(&vc_bytes), Err(CrdtError::DecodeError(_))));
+}